@@ -0,0 +1,50 @@
+//! Proving time of the emulated chain with the `Weight`-optimized limbs and `AdditionStrategy::Accumulated`,
+//! the limb products of the steps computed by the schoolbook method or Karatsuba's, see `limb_mul`.
+//! The number of constraints of each circuit is printed along.
+
+use ark_ec::CurveGroup;
+use ark_groth16::Groth16;
+use ark_std::UniformRand;
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use rand::rngs::OsRng;
+
+use snowball::bitmask::Bitmask;
+use snowball::chain::{Bls381Emulated, ChainCircuit};
+use snowball::config::{AdditionStrategy, Config, Multiplication, Optimization};
+use snowball::prover::setup;
+use snowball::witness::constraint_matrices;
+
+type C = Bls381Emulated;
+type S = Groth16<ark_bls12_381::Bls12_381>;
+
+fn prove(c: &mut Criterion) {
+    let rng = &mut OsRng;
+    let mut group = c.benchmark_group("prove");
+    group.sample_size(10);
+    for num_keys in [4, 16] {
+        let keys: Vec<_> = (0..num_keys).map(|_| ark_bls12_381::G1Projective::rand(rng).into_affine()).collect();
+        let seed = ark_bls12_381::G1Projective::rand(rng).into_affine();
+        let bitmask = Bitmask::from_bits(vec![true; num_keys]);
+        for multiplication in [Multiplication::Schoolbook, Multiplication::Karatsuba] {
+            let config = Config {
+                optimization: Optimization::Weight,
+                addition: AdditionStrategy::Accumulated,
+                multiplication,
+                labels: false,
+                ..Default::default()
+            };
+            let circuit = ChainCircuit::<C>::new(keys.clone(), seed, &bitmask).unwrap().with_config(config);
+            let m = constraint_matrices(circuit.clone()).unwrap();
+            println!("{} keys, {:?}: {} constraints, weight {}", num_keys, multiplication,
+                     m.num_constraints, m.a_num_non_zero + m.b_num_non_zero + m.c_num_non_zero);
+            let (prover, _) = setup::<C, S, _>(circuit.clone(), rng).unwrap();
+            group.bench_with_input(BenchmarkId::new(format!("{:?}", multiplication), num_keys), &circuit, |b, circuit| {
+                b.iter(|| prover.prove(circuit.clone(), &mut OsRng).unwrap())
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, prove);
+criterion_main!(benches);
@@ -0,0 +1,38 @@
+//! The apk circuit either fails to synthesize, or computes what `circuit_semantics` says it does,
+//! and whenever its constraints are satisfied, the aggregate key offset by the seed.
+//! The keysets that add a point to itself, that the incomplete additions accept with a wrong result, are a known
+//! divergence, see `circuit_semantics::aggregate`, and are skipped.
+
+#![no_main]
+
+use ark_r1cs_std::fields::FieldOpsBounds;
+use libfuzzer_sys::fuzz_target;
+
+use snowball::chain::{Bls377InBw6, Bls381Emulated, Chain, KeyBaseField};
+use snowball::circuit_semantics::{adds_to_itself, check_case};
+use snowball::config::{AdditionStrategy, Config};
+use snowball_fuzz::Case;
+
+fn run<C: Chain>(case: &Case)
+    where for<'a> &'a C::FieldVar: FieldOpsBounds<'a, KeyBaseField<C>, C::FieldVar>
+{
+    let keys = case.keys::<C::Curve>();
+    let bitmask = case.bitmask(keys.len());
+    let addition = if case.complete { AdditionStrategy::Complete } else { AdditionStrategy::Incomplete };
+    let config = Config { addition, labels: false, ..Default::default() };
+    if adds_to_itself::<C>(case.seed(), &keys, &bitmask, &config) {
+        return;
+    }
+    if let Ok(Some(divergence)) = check_case::<C>(case.seed(), keys, bitmask, &config) {
+        panic!("{:?}", divergence);
+    }
+}
+
+fuzz_target!(|input: (bool, Case)| {
+    let (emulated, case) = input;
+    if emulated {
+        run::<Bls381Emulated>(&case);
+    } else {
+        run::<Bls377InBw6>(&case);
+    }
+});
@@ -0,0 +1,45 @@
+//! An assignment of the apk circuit satisfies its constraints only for the bitmask it was synthesized with:
+//! claiming any other packed bitmask, including bits past the last key, with the same witness is rejected.
+
+#![no_main]
+
+use ark_ff::{One, PrimeField};
+use ark_r1cs_std::fields::FieldOpsBounds;
+use libfuzzer_sys::fuzz_target;
+
+use snowball::chain::{Bls377InBw6, Bls381Emulated, Chain, ChainCircuit, KeyBaseField};
+use snowball::witness::{constraint_matrices, extract_assignment};
+use snowball_fuzz::Case;
+
+fn run<C: Chain>(case: &Case, claim: u64)
+    where for<'a> &'a C::FieldVar: FieldOpsBounds<'a, KeyBaseField<C>, C::FieldVar>
+{
+    let keys = case.keys::<C::Curve>();
+    let bitmask = case.bitmask(keys.len());
+    let circuit = || ChainCircuit::<C>::new(keys.clone(), case.seed(), &bitmask).unwrap();
+    let (Ok(mut assignment), Ok(matrices)) = (extract_assignment(circuit()), constraint_matrices(circuit())) else {
+        return;
+    };
+    if assignment.first_unsatisfied(&matrices).is_some() {
+        // The keyset is one the incomplete additions can't aggregate: there's no honest proof to tamper with.
+        return;
+    }
+
+    // The packed bitmask is the last public input.
+    let honest = *assignment.instance.last().unwrap();
+    let mut claimed = C::ConstraintField::from(claim);
+    if claimed == honest {
+        claimed += C::ConstraintField::one();
+    }
+    *assignment.instance.last_mut().unwrap() = claimed;
+    assert!(assignment.first_unsatisfied(&matrices).is_some(), "claimed bitmask {} accepted for {}", claimed.into_bigint(), honest.into_bigint());
+}
+
+fuzz_target!(|input: (bool, Case, u64)| {
+    let (emulated, case, claim) = input;
+    if emulated {
+        run::<Bls381Emulated>(&case, claim);
+    } else {
+        run::<Bls377InBw6>(&case, claim);
+    }
+});
@@ -0,0 +1,70 @@
+//! Inputs of the fuzz targets, decoded from the fuzzer's bytes into the keysets the incomplete additions
+//! of the apk circuit are the most likely to get wrong.
+
+use ark_ec::short_weierstrass::{Affine, SWCurveConfig};
+use ark_ec::{AffineRepr, CurveGroup};
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+
+use snowball::bitmask::Bitmask;
+
+/// Enough keys for the collisions between them and with the partial sums, few enough to keep
+/// the emulated syntheses fast.
+pub const MAX_KEYS: usize = 8;
+
+/// How a key is derived from the seed and the keys before it.
+#[derive(Arbitrary, Clone, Copy, Debug)]
+pub enum KeyChoice {
+    /// `s * G`, `s = 0` giving the point at infinity.
+    Multiple(u64),
+    /// The `i`-th key so far, modulo their number.
+    Repeat(u8),
+    /// The inverse of the `i`-th key so far.
+    Inverse(u8),
+    Seed,
+    InverseSeed,
+}
+
+#[derive(Arbitrary, Clone, Debug)]
+pub struct Case {
+    /// The seed is `(seed + 1) * G`, never the point at infinity.
+    pub seed: u64,
+    pub keys: Vec<KeyChoice>,
+    /// Padded with unset bits, or truncated, to one per key.
+    pub bits: Vec<bool>,
+    /// `AdditionStrategy::Complete` rather than the default.
+    pub complete: bool,
+}
+
+impl Case {
+    pub fn seed<P: SWCurveConfig>(&self) -> Affine<P> {
+        mul_generator(self.seed.saturating_add(1))
+    }
+
+    /// At least one key, at most `MAX_KEYS`.
+    pub fn keys<P: SWCurveConfig>(&self) -> Vec<Affine<P>> {
+        let seed = self.seed::<P>();
+        let mut keys: Vec<Affine<P>> = Vec::new();
+        for choice in self.keys.iter().take(MAX_KEYS) {
+            let key = match *choice {
+                KeyChoice::Multiple(s) => mul_generator(s),
+                KeyChoice::Repeat(i) if !keys.is_empty() => keys[i as usize % keys.len()],
+                KeyChoice::Inverse(i) if !keys.is_empty() => -keys[i as usize % keys.len()],
+                KeyChoice::Repeat(_) | KeyChoice::Inverse(_) | KeyChoice::Seed => seed,
+                KeyChoice::InverseSeed => -seed,
+            };
+            keys.push(key);
+        }
+        if keys.is_empty() {
+            keys.push(seed);
+        }
+        keys
+    }
+
+    pub fn bitmask(&self, num_keys: usize) -> Bitmask {
+        Bitmask::from_bits((0..num_keys).map(|i| self.bits.get(i).copied().unwrap_or(false)).collect())
+    }
+}
+
+fn mul_generator<P: SWCurveConfig>(s: u64) -> Affine<P> {
+    (Affine::<P>::generator() * P::ScalarField::from(s)).into_affine()
+}
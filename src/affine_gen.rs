@@ -1,17 +1,25 @@
 use std::borrow::Borrow;
+use std::fmt;
 use std::marker::PhantomData;
 
 use ark_ec::short_weierstrass::{Affine, SWCurveConfig};
 use ark_r1cs_std::alloc::{AllocationMode, AllocVar};
 use ark_r1cs_std::boolean::Boolean;
+use ark_r1cs_std::eq::EqGadget;
 use ark_r1cs_std::fields::{FieldOpsBounds, FieldVar};
+use ark_r1cs_std::fields::fp::FpVar;
 use ark_r1cs_std::R1CSVar;
 use ark_r1cs_std::select::CondSelectGadget;
+use ark_ff::{BigInteger, BitIteratorBE, PrimeField, Zero};
 use ark_relations::r1cs::{ConstraintSystemRef, Field, Namespace, SynthesisError};
 use derivative::Derivative;
 
+use crate::fragments::{LimbVar, VarKind};
+use crate::labels::labeled;
+use crate::scalar_mul::CofactorConfig;
+
 #[derive(Derivative)]
-#[derivative(Debug, Clone)]
+#[derivative(Clone)]
 #[must_use]
 pub struct NonZeroAffineVarGeneric<P, F, CF>
     where
@@ -21,12 +29,115 @@ pub struct NonZeroAffineVarGeneric<P, F, CF>
 {
     pub x: F,
     pub y: F,
-    #[derivative(Debug = "ignore")]
     _p: PhantomData<P>,
-    #[derivative(Debug = "ignore")]
     _cf: PhantomData<CF>,
 }
 
+/// The value of the variable, as `0x` and the first and last 4 hex digits, or `?` if it isn't assigned.
+pub(crate) fn short_hex<TF: PrimeField, CF: Field, F: FieldVar<TF, CF>>(x: &F) -> String {
+    let Ok(value) = x.value() else {
+        return "?".to_string();
+    };
+    let hex: String = value.into_bigint().to_bytes_be().iter().map(|b| format!("{:02x}", b)).collect();
+    let hex = hex.trim_start_matches('0');
+    match hex.len() {
+        0 => "0x0".to_string(),
+        1..=8 => format!("0x{}", hex),
+        n => format!("0x{}…{}", &hex[..4], &hex[n - 4..]),
+    }
+}
+
+// `x^3 + a * x + b`, with no `a * x` term for the curves with `a = 0`.
+fn curve_rhs<P, F, CF>(x: &F) -> Result<F, SynthesisError>
+    where P: SWCurveConfig,
+          CF: Field,
+          F: FieldVar<P::BaseField, CF>,
+{
+    let x3 = x.square()? * x;
+    Ok(if P::COEFF_A.is_zero() { x3 + P::COEFF_B } else { x3 + x.clone() * P::COEFF_A + P::COEFF_B })
+}
+
+/// The inverse of `x`, enforcing `x` to be non-zero, with a constraint for `x * inverse = 1`.
+/// `Unsatisfiable` if `x` is the constant `0`, that `FieldVar::inverse` maps to `0`.
+pub(crate) fn nonzero_inverse<TF: Field, CF: Field, F: FieldVar<TF, CF>>(x: &F) -> Result<F, SynthesisError> {
+    if x.is_constant() && x.value()?.is_zero() {
+        return Err(SynthesisError::Unsatisfiable);
+    }
+    x.inverse()
+}
+
+// Whether `y > (p - 1) / 2`, comparing the canonical bits of `y` to those of the bound, least significant first:
+// a `1` of `y` over a `0` of the bound makes it larger, and a `0` over a `1` smaller, unless higher bits decide.
+fn is_larger_half<TF: PrimeField, CF: PrimeField, F: FieldVar<TF, CF>>(y: &F) -> Result<Boolean<CF>, SynthesisError> {
+    let half = TF::MODULUS_MINUS_ONE_DIV_TWO.to_bits_le();
+    y.to_bits_le()?.iter().zip(half).try_fold(Boolean::FALSE, |larger, (b, h)| if h { b.and(&larger) } else { b.or(&larger) })
+}
+
+impl<P, F, CF> NonZeroAffineVarGeneric<P, F, CF>
+    where
+        P: SWCurveConfig,
+        P::BaseField: PrimeField,
+        CF: PrimeField,
+        F: LimbVar<P::BaseField, CF>,
+{
+    /// The kind of both coordinates, `Derived` if they differ.
+    pub fn kind(&self) -> VarKind {
+        match (self.x.kind(), self.y.kind()) {
+            (x, y) if x == y => x,
+            _ => VarKind::Derived,
+        }
+    }
+
+    /// The compressed point: the limbs of `x`, as `LimbVar::limbs` has them, and the sign of `y`,
+    /// set if `y > (p - 1) / 2`, as the flag of the Zcash encoding of BLS12 points, see `bls_keys`.
+    /// The sign decomposes `y` into its canonical bits, about `2 log(p)` constraints, labeled `compress`.
+    pub fn compress(&self) -> Result<(Vec<FpVar<CF>>, Boolean<CF>), SynthesisError> {
+        labeled(&self.cs(), "compress", || Ok((self.x.limbs()?, is_larger_half(&self.y)?)))
+    }
+
+    /// Allocates the points as witnesses enforced to be on the curve, as `new_witness_on_curve` does, labeled `i`,
+    /// with the curve equations checked by `LimbVar::enforce_curve_equation`: a reduction fewer per point for the emulated fields,
+    /// about a fifth of the constraints, the range checks of the limbs being the same. The equations can't be merged into one
+    /// without a random challenge to combine them with, that a circuit doesn't have before its witness, so each point keeps its own.
+    pub fn new_witness_batch(cs: ConstraintSystemRef<CF>, points: &[Affine<P>]) -> Result<Vec<Self>, SynthesisError> {
+        points.iter().enumerate().map(|(i, p)| {
+            labeled(&cs, i, || {
+                let p = Self::new_witness(ark_relations::ns!(cs, "point"), || Ok(*p))?;
+                labeled(&cs, "on_curve", || F::enforce_curve_equation(&p.x, &p.y, P::COEFF_A, P::COEFF_B))?;
+                Ok(p)
+            })
+        }).collect()
+    }
+}
+
+impl<P, F, CF> fmt::Debug for NonZeroAffineVarGeneric<P, F, CF>
+    where
+        P: SWCurveConfig,
+        P::BaseField: PrimeField,
+        CF: PrimeField,
+        F: LimbVar<P::BaseField, CF>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NonZeroAffineVarGeneric")
+            .field("x", &format_args!("{} ({})", short_hex(&self.x), self.x.kind()))
+            .field("y", &format_args!("{} ({})", short_hex(&self.y), self.y.kind()))
+            .finish()
+    }
+}
+
+/// `(x, y) kind`, with the values as `short_hex`es.
+impl<P, F, CF> fmt::Display for NonZeroAffineVarGeneric<P, F, CF>
+    where
+        P: SWCurveConfig,
+        P::BaseField: PrimeField,
+        CF: PrimeField,
+        F: LimbVar<P::BaseField, CF>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}, {}) {}", short_hex(&self.x), short_hex(&self.y), self.kind())
+    }
+}
+
 impl<P, F, CF> AllocVar<Affine<P>, CF> for NonZeroAffineVarGeneric<P, F, CF>
     where
         P: SWCurveConfig,
@@ -37,8 +148,8 @@ impl<P, F, CF> AllocVar<Affine<P>, CF> for NonZeroAffineVarGeneric<P, F, CF>
         let ns = cs.into();
         let cs = ns.cs();
         let point = f().map(|b| *b.borrow());
-        let x = F::new_variable(ark_relations::ns!(cs, "x"), || point.map(|p| p.x), mode)?;
-        let y = F::new_variable(ark_relations::ns!(cs, "y"), || point.map(|p| p.y), mode)?;
+        let x = labeled(&cs, "x", || F::new_variable(ark_relations::ns!(cs, "x"), || point.map(|p| p.x), mode))?;
+        let y = labeled(&cs, "y", || F::new_variable(ark_relations::ns!(cs, "y"), || point.map(|p| p.y), mode))?;
         let point_var = NonZeroAffineVarGeneric { x, y, _p: PhantomData, _cf: PhantomData };
         Ok(point_var)
     }
@@ -91,11 +202,292 @@ impl<P, F, CF> NonZeroAffineVarGeneric<P, F, CF>
         let (x2, y2) = (&other.x, &other.y);
         let numerator = y2 - y1;
         let denominator = x2 - x1;
-        let lambda = numerator.mul_by_inverse_unchecked(&denominator)?;
-        let x3 = lambda.square()? - x1 - x2;
+        let lambda = labeled(&self.cs().or(other.cs()), "lambda", || numerator.mul_by_inverse_unchecked(&denominator))?;
+        self.add_with_slope(other, &lambda)
+    }
+
+    /// Enforces `y^2 = x^3 + a * x + b`, that nothing else checks of a witness point:
+    /// the additions take any coordinates, and would add off-curve points as well.
+    pub fn enforce_on_curve(&self) -> Result<(), SynthesisError>
+        where for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>
+    {
+        self.y.square_equals(&curve_rhs::<P, F, CF>(&self.x)?)
+    }
+
+    /// The point of the curve with the abscissa `x` and the sign `sign` of its ordinate, as `compress` has it.
+    /// `y` is a witness, the square root the prover picks by the sign, enforced by `y^2 = x^3 + a * x + b`
+    /// and the canonical decomposition of `y`. For an `x` of no point there's no `y`, and the system can't be satisfied:
+    /// the allocation fails with `Unsatisfiable`, or assigns `0` for the emulated fields, that ignore the error.
+    pub fn new_decompressed(x: F, sign: &Boolean<CF>) -> Result<Self, SynthesisError>
+        where P::BaseField: PrimeField,
+              CF: PrimeField,
+    {
+        let cs = x.cs().or(sign.cs());
+        labeled(&cs, "decompress", || {
+            let y = labeled(&cs, "y", || F::new_witness(ark_relations::ns!(cs, "y"), || {
+                let p = Affine::<P>::get_point_from_x_unchecked(x.value()?, sign.value()?).ok_or(SynthesisError::Unsatisfiable)?;
+                Ok(p.y)
+            }))?;
+            y.square_equals(&curve_rhs::<P, F, CF>(&x)?)?;
+            is_larger_half(&y)?.enforce_equal(sign)?;
+            Ok(Self::new(x, y))
+        })
+    }
+
+    /// Allocates a witness point enforced to be on the curve, see `enforce_on_curve`.
+    pub fn new_witness_on_curve(cs: impl Into<Namespace<CF>>, f: impl FnOnce() -> Result<Affine<P>, SynthesisError>) -> Result<Self, SynthesisError>
+        where for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>
+    {
+        let p = Self::new_witness(cs, f)?;
+        labeled(&p.cs(), "on_curve", || p.enforce_on_curve())?;
+        Ok(p)
+    }
+
+    /// `2 * self`, enforcing `y != 0`, so the slope of the tangent is determined.
+    pub fn double_checked(&self) -> Result<Self, SynthesisError>
+        where for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>
+    {
+        let numerator = self.x.square()? * P::BaseField::from(3u8) + P::COEFF_A;
+        let lambda = labeled(&self.cs(), "lambda", || Ok::<_, SynthesisError>(numerator * nonzero_inverse(&self.y.double()?)?))?;
+        self.add_with_slope(self, &lambda)
+    }
+
+    /// Enforces the point to be on the curve, `enforce_on_curve`, and in the subgroup of order `r`, the modulus
+    /// of the scalar field, as `[r - 1] P = -P`. The multiplication is double-and-add with checked operations: for a point of order `r`,
+    /// none of the partial multiples `[k] P` is `O` or `±P` where it's doubled or added to, so they're all satisfiable,
+    /// while any other point either fails a check or ends elsewhere. A doubling and an addition per bit of `r`:
+    /// costly for emulated fields, but the only check there is for curves with a cofactor.
+    /// The formulas don't involve `b`, so without the curve equation, a point of a curve with another `b`,
+    /// and maybe of order `r` there, would pass.
+    pub fn enforce_in_prime_subgroup(&self) -> Result<(), SynthesisError>
+        where for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>
+    {
+        let r_minus_1 = (-P::ScalarField::from(1u8)).into_bigint();
+        labeled(&self.cs(), "subgroup", || {
+            self.enforce_on_curve()?;
+            let acc = self.mul_by_constant_checked(r_minus_1.as_ref())?;
+            acc.x.enforce_equal(&self.x)?;
+            acc.y.enforce_equal(&self.y.negate()?)
+        })
+    }
+
+    /// `[k] self` for a non-zero constant `k`, given by its limbs, little-endian, by double-and-add with checked operations,
+    /// a doubling per bit of `k` but the top one, and an addition per set one. Unsatisfiable if a partial multiple
+    /// is `O` or `±self` where it's doubled or added to, that only a point of small order can hit, as one of the torsion.
+    /// Fails with `SynthesisError::Unsatisfiable` for `k = 0`, the product `O` being no point of this type.
+    pub fn mul_by_constant_checked(&self, k: &[u64]) -> Result<Self, SynthesisError>
+        where for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>
+    {
+        let mut bits = BitIteratorBE::without_leading_zeros(k);
+        if bits.next() != Some(true) {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        let mut acc = self.clone();
+        for b in bits {
+            acc = acc.double_checked()?;
+            if b {
+                acc = acc.add_checked(self)?;
+            }
+        }
+        Ok(acc)
+    }
+
+    /// `[h] self` for the cofactor `h` of the curve, as `AffineRepr::mul_by_cofactor`, see `mul_by_constant_checked`.
+    /// A point of the prime-order subgroup for any point of the curve, but those of the torsion, that are unsatisfiable.
+    pub fn mul_by_cofactor(&self) -> Result<Self, SynthesisError>
+        where for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>
+    {
+        labeled(&self.cs(), "mul_by_cofactor", || self.mul_by_constant_checked(P::COFACTOR))
+    }
+
+    /// The point of the prime-order subgroup `SWCurveConfig::clear_cofactor` maps `self` to, the multiple by `CofactorConfig::effective_cofactor`,
+    /// the cofactor itself but for the curves with a shorter scalar that clears it, as BLS12 ones, see `mul_by_cofactor`.
+    pub fn clear_cofactor(&self) -> Result<Self, SynthesisError>
+        where P: CofactorConfig,
+              for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>
+    {
+        labeled(&self.cs(), "clear_cofactor", || self.mul_by_constant_checked(P::effective_cofactor()))
+    }
+
+    /// `-self`, free: a linear combination.
+    pub fn negate(&self) -> Result<Self, SynthesisError> {
+        Ok(Self::new(self.x.clone(), self.y.negate()?))
+    }
+
+    /// `-self` if `cond` is set, `self` otherwise, for signed digits or signed selections of points: a selection of `y`.
+    pub fn conditionally_negate(&self, cond: &Boolean<CF>) -> Result<Self, SynthesisError> {
+        Ok(Self::new(self.x.clone(), cond.select(&self.y.negate()?, &self.y)?))
+    }
+
+    /// `self - other`, as `add_unchecked` of `-other`: unsatisfiable or wrong on points with the same `x`.
+    /// Subtracting the seed from the sum of the keys gives the aggregate key itself, so it can be exposed.
+    pub fn sub_unchecked(&self, other: &Self) -> Result<Self, SynthesisError>
+        where for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>
+    {
+        self.add_unchecked(&other.negate()?)
+    }
+
+    /// `add_unchecked` enforcing `x1 != x2` in the constraint system, for the inverse of `x2 - x1`.
+    /// Otherwise, with `x1 = x2` and `y1 = y2`, any slope satisfies `add_unchecked`, and the prover chooses the sum.
+    pub fn add_checked(&self, other: &Self) -> Result<Self, SynthesisError>
+        where for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>
+    {
+        let (numerator, denominator) = self.slope_fraction(other);
+        let lambda = labeled(&self.cs().or(other.cs()), "lambda", || Ok::<_, SynthesisError>(numerator * nonzero_inverse(&denominator)?))?;
+        self.add_with_slope(other, &lambda)
+    }
+
+    /// `self + other` given the slope `lambda` of the line through them, already enforced by the caller.
+    pub fn add_with_slope(&self, other: &Self, lambda: &F) -> Result<Self, SynthesisError>
+        where for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>
+    {
+        let (x1, y1) = (&self.x, &self.y);
+        let x3 = lambda.square()? - x1 - &other.x;
         let y3 = lambda * &(x1 - &x3) - y1;
         Ok(Self::new(x3, y3))
     }
+
+    /// The numerator and the denominator of the slope of the line through `self` and `other`.
+    pub fn slope_fraction(&self, other: &Self) -> (F, F)
+        where for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>
+    {
+        (&other.y - &self.y, &other.x - &self.x)
+    }
+}
+
+/// A point of the curve or the identity, the coordinates of which are then meaningless, flagged by `infinity`.
+/// The additions are complete, at the cost of two equality checks and the selections of the special cases,
+/// so the sums can start from the identity and hit it, see `aggregate_complete`.
+#[derive(Derivative)]
+#[derivative(Clone)]
+#[must_use]
+pub struct AffineVarGeneric<P, F, CF>
+    where
+        P: SWCurveConfig,
+        CF: Field,
+        F: FieldVar<P::BaseField, CF>,
+{
+    pub x: F,
+    pub y: F,
+    pub infinity: Boolean<CF>,
+    _p: PhantomData<P>,
+}
+
+impl<P, F, CF> AffineVarGeneric<P, F, CF>
+    where
+        P: SWCurveConfig,
+        CF: Field,
+        F: FieldVar<P::BaseField, CF>,
+{
+    pub fn new(x: F, y: F, infinity: Boolean<CF>) -> Self {
+        Self { x, y, infinity, _p: PhantomData }
+    }
+
+    /// The identity, a constant, with the coordinates `(0, 0)` as arkworks has them.
+    pub fn zero() -> Self {
+        Self::new(F::zero(), F::zero(), Boolean::TRUE)
+    }
+
+    pub fn is_zero(&self) -> &Boolean<CF> {
+        &self.infinity
+    }
+
+    /// `-self` if `cond` is set, `self` otherwise, see `NonZeroAffineVarGeneric::conditionally_negate`. The identity stays `(0, 0)`.
+    pub fn conditionally_negate(&self, cond: &Boolean<CF>) -> Result<Self, SynthesisError> {
+        Ok(Self::new(self.x.clone(), cond.select(&self.y.negate()?, &self.y)?, self.infinity.clone()))
+    }
+
+    /// Enforces the point to be on the curve unless it's the identity, see `NonZeroAffineVarGeneric::enforce_on_curve`.
+    pub fn enforce_on_curve(&self) -> Result<(), SynthesisError>
+        where for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>
+    {
+        (self.y.square()? - curve_rhs::<P, F, CF>(&self.x)?).conditional_enforce_equal(&F::zero(), &self.infinity.not())
+    }
+
+    /// `self + other`, for any points of the curve, the identity and the points of order 2 included.
+    /// The sum is the identity for `y1 = -y2` with `x1 = x2`, which covers the doubling of a point with `y = 0`.
+    /// The slope is that of the tangent for `x1 = x2`, and is pinned to `1` where the result doesn't depend on it,
+    /// so it's always determined: the prover has no choice of the result.
+    pub fn add(&self, other: &Self) -> Result<Self, SynthesisError>
+        where for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>
+    {
+        let cs = self.cs().or(other.cs());
+        labeled(&cs, "complete_add", || {
+            let same_x = self.x.is_eq(&other.x)?;
+            let opposite = same_x.and(&self.y.is_eq(&other.y.negate()?)?)?;
+            let special = self.infinity.or(&other.infinity)?.or(&opposite)?;
+            let numerator = same_x.select(&(self.x.square()? * P::BaseField::from(3u8) + P::COEFF_A), &(&other.y - &self.y))?;
+            let denominator = special.select(&F::one(), &same_x.select(&self.y.double()?, &(&other.x - &self.x))?)?;
+            let lambda = labeled(&cs, "lambda", || numerator.mul_by_inverse_unchecked(&denominator))?;
+            let x3 = lambda.square()? - &self.x - &other.x;
+            let y3 = &lambda * &(&self.x - &x3) - &self.y;
+            let sum = Self::conditionally_select(&opposite, &Self::zero(), &Self::new(x3, y3, Boolean::FALSE))?;
+            let sum = Self::conditionally_select(&other.infinity, self, &sum)?;
+            Self::conditionally_select(&self.infinity, other, &sum)
+        })
+    }
+}
+
+impl<P, F, CF> From<NonZeroAffineVarGeneric<P, F, CF>> for AffineVarGeneric<P, F, CF>
+    where
+        P: SWCurveConfig,
+        CF: Field,
+        F: FieldVar<P::BaseField, CF>,
+{
+    fn from(p: NonZeroAffineVarGeneric<P, F, CF>) -> Self {
+        Self::new(p.x, p.y, Boolean::FALSE)
+    }
+}
+
+impl<P, F, CF> AllocVar<Affine<P>, CF> for AffineVarGeneric<P, F, CF>
+    where
+        P: SWCurveConfig,
+        CF: Field,
+        F: FieldVar<P::BaseField, CF>,
+{
+    fn new_variable<T: Borrow<Affine<P>>>(cs: impl Into<Namespace<CF>>, f: impl FnOnce() -> Result<T, SynthesisError>, mode: AllocationMode) -> Result<Self, SynthesisError> {
+        let ns = cs.into();
+        let cs = ns.cs();
+        let point = f().map(|b| *b.borrow());
+        let x = labeled(&cs, "x", || F::new_variable(ark_relations::ns!(cs, "x"), || point.map(|p| p.x), mode))?;
+        let y = labeled(&cs, "y", || F::new_variable(ark_relations::ns!(cs, "y"), || point.map(|p| p.y), mode))?;
+        let infinity = labeled(&cs, "infinity", || Boolean::new_variable(ark_relations::ns!(cs, "infinity"), || point.map(|p| p.infinity), mode))?;
+        Ok(Self::new(x, y, infinity))
+    }
+}
+
+impl<P, F, CF> CondSelectGadget<CF> for AffineVarGeneric<P, F, CF>
+    where
+        P: SWCurveConfig,
+        CF: Field,
+        F: FieldVar<P::BaseField, CF>,
+{
+    fn conditionally_select(cond: &Boolean<CF>, true_value: &Self, false_value: &Self) -> Result<Self, SynthesisError> {
+        let x = cond.select(&true_value.x, &false_value.x)?;
+        let y = cond.select(&true_value.y, &false_value.y)?;
+        let infinity = cond.select(&true_value.infinity, &false_value.infinity)?;
+        Ok(Self::new(x, y, infinity))
+    }
+}
+
+impl<P, F, CF> R1CSVar<CF> for AffineVarGeneric<P, F, CF>
+    where
+        P: SWCurveConfig,
+        CF: Field,
+        F: FieldVar<P::BaseField, CF>,
+{
+    type Value = Affine<P>;
+
+    fn cs(&self) -> ConstraintSystemRef<CF> {
+        self.x.cs().or(self.y.cs()).or(self.infinity.cs())
+    }
+
+    fn value(&self) -> Result<Self::Value, SynthesisError> {
+        if self.infinity.value()? {
+            return Ok(Affine::identity());
+        }
+        Ok(Affine::<P>::new(self.x.value()?, self.y.value()?))
+    }
 }
 
 #[cfg(test)]
@@ -104,7 +496,7 @@ mod tests {
     use ark_r1cs_std::fields::fp::FpVar;
     use ark_relations::ns;
     use ark_relations::r1cs::ConstraintSystem;
-    use ark_std::{test_rng, UniformRand};
+    use ark_std::{test_rng, One, UniformRand};
 
     use crate::tests::{BlsInBls, Tracker};
 
@@ -145,4 +537,383 @@ mod tests {
         assert_eq!(sum.value().unwrap(), keys.iter().sum::<ark_bls12_381::G1Projective>().into_affine());
         assert!(cs.is_satisfied().unwrap());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_display() {
+        use ark_ec::AffineRepr;
+        use ark_relations::r1cs::SynthesisMode;
+
+        let cs = ConstraintSystem::<ark_bls12_381::Fr>::new_ref();
+        let g = ark_bls12_381::G1Affine::generator();
+        let witness = NonZeroAffineVarGeneric::<_, BlsInBls, _>::new_witness(ns!(cs, "w"), || Ok(g)).unwrap();
+        let input = NonZeroAffineVarGeneric::<_, BlsInBls, _>::new_input(ns!(cs, "i"), || Ok(g)).unwrap();
+        let constant = NonZeroAffineVarGeneric::<_, BlsInBls, _>::new_constant(ns!(cs, "c"), g).unwrap();
+        // The x coordinate of the generator is `0x17f1d3a7...a6c6bb`.
+        assert_eq!(witness.to_string().split(',').next().unwrap(), "(0x17f1…c6bb");
+        assert!(witness.to_string().ends_with(") witness"));
+        assert_eq!(input.kind(), VarKind::Input);
+        assert_eq!(constant.kind(), VarKind::Constant);
+        assert_eq!(witness.add_unchecked(&input.add_unchecked(&input.add_unchecked(&witness).unwrap()).unwrap()).unwrap().kind(), VarKind::Derived);
+        assert!(format!("{:?}", witness).starts_with("NonZeroAffineVarGeneric { x: 0x17f1…c6bb (witness), y: 0x"));
+
+        let cs = ConstraintSystem::<ark_bw6_761::Fr>::new_ref();
+        cs.set_mode(SynthesisMode::Setup);
+        let missing = NonZeroAffineVarGeneric::<ark_bls12_377::g1::Config, FpVar<_>, _>::new_witness(ns!(cs, "m"), || Ok(ark_bls12_377::G1Affine::generator())).unwrap();
+        assert_eq!(missing.to_string(), "(?, ?) witness");
+    }
+
+    fn check_on_curve<P, F, CF>(rng: &mut impl ark_std::rand::Rng)
+        where P: SWCurveConfig,
+              CF: PrimeField,
+              F: FieldVar<P::BaseField, CF>,
+              for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+    {
+        use ark_ff::One;
+
+        let p = Affine::<P>::rand(rng);
+        let off = Affine::<P>::new_unchecked(p.x, p.y + P::BaseField::one());
+        let on_curve = |p: Affine<P>| {
+            let cs = ConstraintSystem::<CF>::new_ref();
+            let _ = NonZeroAffineVarGeneric::<P, F, CF>::new_witness_on_curve(ns!(cs, "p"), || Ok(p)).unwrap();
+            cs.is_satisfied().unwrap()
+        };
+        assert!(on_curve(p));
+        assert!(!on_curve(off));
+
+        let cs = ConstraintSystem::<CF>::new_ref();
+        // The coordinates of the identity don't matter.
+        let zero = AffineVarGeneric::<P, F, CF>::new_witness(ns!(cs, "zero"), || Ok(Affine::<P> { infinity: true, ..off })).unwrap();
+        zero.enforce_on_curve().unwrap();
+        assert!(cs.is_satisfied().unwrap());
+        AffineVarGeneric::<P, F, CF>::new_witness(ns!(cs, "off"), || Ok(off)).unwrap().enforce_on_curve().unwrap();
+        assert!(!cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn test_enforce_on_curve() {
+        let rng = &mut test_rng();
+        check_on_curve::<ark_bls12_377::g1::Config, FpVar<ark_bw6_761::Fr>, _>(rng);
+        check_on_curve::<ark_bls12_381::g1::Config, BlsInBls, _>(rng);
+    }
+
+    fn check_witness_batch<P, F, CF>(rng: &mut impl ark_std::rand::Rng) -> usize
+        where P: SWCurveConfig,
+              P::BaseField: PrimeField,
+              CF: PrimeField,
+              F: LimbVar<P::BaseField, CF>,
+              for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+    {
+        use ark_ff::One;
+
+        let points: Vec<Affine<P>> = (0..2).map(|_| Affine::rand(rng)).collect();
+        let cs = ConstraintSystem::<CF>::new_ref();
+        let mut tracker = Tracker::new(&cs);
+        let point_vars = NonZeroAffineVarGeneric::<P, F, CF>::new_witness_batch(cs.clone(), &points).unwrap();
+        let batch_cost = tracker.update(&cs).num_constraints;
+        assert_eq!(point_vars.value().unwrap(), points);
+        assert!(cs.is_satisfied().unwrap());
+        for p in &points {
+            let _ = NonZeroAffineVarGeneric::<P, F, CF>::new_witness_on_curve(ns!(cs, "p"), || Ok(*p)).unwrap();
+        }
+        let one_by_one_cost = tracker.update(&cs).num_constraints;
+        println!("{} points: batch {}, one by one {}", points.len(), batch_cost, one_by_one_cost);
+        assert!(batch_cost <= one_by_one_cost);
+
+        let off = Affine::<P>::new_unchecked(points[0].x, points[0].y + P::BaseField::one());
+        let cs = ConstraintSystem::<CF>::new_ref();
+        let _ = NonZeroAffineVarGeneric::<P, F, CF>::new_witness_batch(cs.clone(), &[points[1], off]).unwrap();
+        assert!(!cs.is_satisfied().unwrap());
+        batch_cost
+    }
+
+    #[test]
+    fn test_new_witness_batch() {
+        use ark_ed_on_bls12_381_bandersnatch::{Fq, SWConfig};
+        use ark_r1cs_std::fields::nonnative::NonNativeFieldVar;
+
+        let rng = &mut test_rng();
+        // `a != 0` for Bandersnatch.
+        assert_eq!(check_witness_batch::<SWConfig, FpVar<Fq>, _>(rng), 2 * 3);
+        check_witness_batch::<SWConfig, NonNativeFieldVar<Fq, ark_bw6_761::Fr>, _>(rng);
+        check_witness_batch::<ark_bls12_381::g1::Config, BlsInBls, _>(rng);
+    }
+
+    fn check_conditionally_negate<P, F, CF>(rng: &mut impl ark_std::rand::Rng)
+        where P: SWCurveConfig,
+              CF: PrimeField,
+              F: FieldVar<P::BaseField, CF>,
+              for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+    {
+        let (p, q) = (Affine::<P>::rand(rng), Affine::<P>::rand(rng));
+        for negate in [false, true] {
+            let cs = ConstraintSystem::<CF>::new_ref();
+            let p_var = NonZeroAffineVarGeneric::<P, F, CF>::new_witness(ns!(cs, "p"), || Ok(p)).unwrap();
+            let q_var = NonZeroAffineVarGeneric::<P, F, CF>::new_witness(ns!(cs, "q"), || Ok(q)).unwrap();
+            let cond = Boolean::new_witness(ns!(cs, "cond"), || Ok(negate)).unwrap();
+            let signed = if negate { -p } else { p };
+            // Operations on the result, of both branches, for the emulated fields.
+            let sum = p_var.conditionally_negate(&cond).unwrap().add_unchecked(&q_var).unwrap();
+            assert_eq!(sum.value().unwrap(), (signed + q).into_affine());
+            let zero = AffineVarGeneric::<P, F, CF>::zero().conditionally_negate(&cond).unwrap();
+            assert_eq!(zero.value().unwrap(), Affine::identity());
+            let sum = AffineVarGeneric::from(p_var).conditionally_negate(&cond).unwrap().add(&q_var.into()).unwrap();
+            assert_eq!(sum.value().unwrap(), (signed + q).into_affine());
+            assert!(cs.is_satisfied().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_conditionally_negate() {
+        let rng = &mut test_rng();
+        check_conditionally_negate::<ark_bls12_377::g1::Config, FpVar<ark_bw6_761::Fr>, _>(rng);
+        check_conditionally_negate::<ark_bls12_381::g1::Config, BlsInBls, _>(rng);
+    }
+
+    #[test]
+    fn test_enforce_in_prime_subgroup() {
+        use ark_bls12_377::{Fq, G1Affine};
+        use ark_ff::{One, UniformRand};
+
+        let rng = &mut test_rng();
+        let p = G1Affine::rand(rng);
+        // A point of the curve outside of the subgroup: the cofactor isn't cleared.
+        let outside = std::iter::repeat_with(|| G1Affine::get_point_from_x_unchecked(Fq::rand(rng), false))
+            .flatten()
+            .find(|q| !q.is_in_correct_subgroup_assuming_on_curve())
+            .unwrap();
+        let in_subgroup = |p: G1Affine| {
+            let cs = ConstraintSystem::<ark_bw6_761::Fr>::new_ref();
+            let p_var = NonZeroAffineVarGeneric::<_, FpVar<_>, _>::new_witness(ns!(cs, "p"), || Ok(p)).unwrap();
+            p_var.enforce_in_prime_subgroup().unwrap();
+            cs.is_satisfied().unwrap()
+        };
+        let cs = ConstraintSystem::<ark_bw6_761::Fr>::new_ref();
+        let p_var = NonZeroAffineVarGeneric::<_, FpVar<_>, _>::new_witness(ns!(cs, "p"), || Ok(p)).unwrap();
+        assert_eq!(p_var.double_checked().unwrap().value().unwrap(), (p + p).into_affine());
+        assert!(in_subgroup(p));
+        assert!(!in_subgroup(outside));
+        // Off the curve, on another with the same `a = 0`.
+        assert!(!in_subgroup(G1Affine::new_unchecked(p.x, p.y + Fq::one())));
+    }
+
+    #[cfg(any(feature = "bls12-377-bw6", feature = "bls12-381-emulated"))]
+    fn check_clear_cofactor<P, F, CF>(rng: &mut impl ark_std::rand::Rng, by_cofactor: bool)
+        where P: CofactorConfig,
+              P::BaseField: PrimeField,
+              CF: PrimeField,
+              F: FieldVar<P::BaseField, CF>,
+              for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+    {
+        use ark_ec::AffineRepr;
+
+        let outside = std::iter::repeat_with(|| Affine::<P>::get_point_from_x_unchecked(P::BaseField::rand(rng), false))
+            .flatten()
+            .find(|q| !q.is_in_correct_subgroup_assuming_on_curve())
+            .unwrap();
+        let cs = ConstraintSystem::<CF>::new_ref();
+        let p_var = NonZeroAffineVarGeneric::<P, F, CF>::new_witness(ns!(cs, "p"), || Ok(outside)).unwrap();
+        let mut tracker = Tracker::new(&cs);
+        assert_eq!(p_var.clear_cofactor().unwrap().value().unwrap(), P::clear_cofactor(&outside));
+        println!("clear_cofactor: {:?}", tracker.update(&cs));
+        if by_cofactor {
+            assert_eq!(p_var.mul_by_cofactor().unwrap().value().unwrap(), outside.mul_by_cofactor());
+            println!("mul_by_cofactor: {:?}", tracker.update(&cs));
+        }
+        assert!(cs.is_satisfied().unwrap());
+        assert!(matches!(p_var.mul_by_constant_checked(&[0, 0]), Err(SynthesisError::Unsatisfiable)));
+        assert!(matches!(p_var.mul_by_constant_checked(&[]), Err(SynthesisError::Unsatisfiable)));
+    }
+
+    #[cfg(any(feature = "bls12-377-bw6", feature = "bls12-381-emulated"))]
+    #[test]
+    fn test_clear_cofactor() {
+        let rng = &mut test_rng();
+        #[cfg(feature = "bls12-377-bw6")]
+        check_clear_cofactor::<ark_bls12_377::g1::Config, FpVar<ark_bw6_761::Fr>, _>(rng, true);
+        #[cfg(feature = "bls12-381-emulated")]
+        check_clear_cofactor::<ark_bls12_381::g1::Config, FpVar<ark_bls12_381::Fq>, _>(rng, false);
+    }
+
+    #[test]
+    fn test_sub_unchecked() {
+        use crate::bitmask::Bitmask;
+        use crate::config::Config;
+        use crate::fragments::aggregate;
+
+        let rng = &mut test_rng();
+        let keys: Vec<ark_bls12_381::G1Affine> = (0..3).map(|_| ark_bls12_381::G1Affine::rand(rng)).collect();
+        let seed = ark_bls12_381::G1Affine::rand(rng);
+        let bitmask = Bitmask::from_bits(vec![true, false, true]);
+        let cs = ConstraintSystem::<ark_bls12_381::Fr>::new_ref();
+        let key_vars = Vec::<NonZeroAffineVarGeneric<_, BlsInBls, _>>::new_witness(ns!(cs, "keys"), || Ok(keys.clone())).unwrap();
+        let seed_var = NonZeroAffineVarGeneric::new_constant(ns!(cs, "seed"), seed).unwrap();
+        let bit_vars = bitmask.bits().iter().map(|b| Boolean::constant(*b)).collect::<Vec<_>>();
+        let sum = aggregate(seed_var.clone(), &key_vars, &bit_vars, &Config::default()).unwrap();
+        let apk = sum.sub_unchecked(&seed_var).unwrap();
+        assert_eq!(apk.value().unwrap(), crate::aggregation::aggregate(&keys, &bitmask).unwrap());
+        assert_eq!(seed_var.negate().unwrap().value().unwrap(), -seed);
+        assert!(cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn test_add_checked() {
+        use ark_relations::r1cs::SynthesisMode;
+
+        let rng = &mut test_rng();
+        let (p, q) = (ark_bls12_377::G1Affine::rand(rng), ark_bls12_377::G1Affine::rand(rng));
+        let add = |a: ark_bls12_377::G1Affine, b: ark_bls12_377::G1Affine, mode| {
+            let cs = ConstraintSystem::<ark_bw6_761::Fr>::new_ref();
+            cs.set_mode(mode);
+            let a_var = NonZeroAffineVarGeneric::<_, FpVar<_>, _>::new_witness(ns!(cs, "a"), || Ok(a)).unwrap();
+            let b_var = NonZeroAffineVarGeneric::<_, FpVar<_>, _>::new_witness(ns!(cs, "b"), || Ok(b)).unwrap();
+            let sum = a_var.add_checked(&b_var).unwrap();
+            if !cs.is_in_setup_mode() && cs.is_satisfied().unwrap() {
+                assert_eq!(sum.value().unwrap(), (a + b).into_affine());
+            }
+            cs.is_in_setup_mode() || cs.is_satisfied().unwrap()
+        };
+        assert!(add(p, q, SynthesisMode::Prove { construct_matrices: true }));
+        // Doubling, that `add_unchecked` lets the prover choose the result of.
+        assert!(!add(p, p, SynthesisMode::Prove { construct_matrices: true }));
+        assert!(add(p, p, SynthesisMode::Setup));
+
+        let cs = ConstraintSystem::<ark_bls12_381::Fr>::new_ref();
+        let p = NonZeroAffineVarGeneric::<_, BlsInBls, _>::new_witness(ns!(cs, "p"), || Ok(ark_bls12_381::G1Affine::rand(rng))).unwrap();
+        let minus_p = NonZeroAffineVarGeneric::new(p.x.clone(), p.y.negate().unwrap());
+        let _ = p.add_checked(&minus_p).unwrap();
+        assert!(!cs.is_satisfied().unwrap());
+        let c = NonZeroAffineVarGeneric::<_, BlsInBls, _>::new_constant(ns!(cs, "c"), ark_bls12_381::G1Affine::rand(rng)).unwrap();
+        assert!(matches!(c.add_checked(&c), Err(SynthesisError::Unsatisfiable)));
+    }
+
+    fn check_complete_add<P, F, CF>(rng: &mut impl ark_std::rand::Rng)
+        where P: SWCurveConfig,
+              P::BaseField: PrimeField,
+              CF: PrimeField,
+              F: LimbVar<P::BaseField, CF>,
+              for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+    {
+        let (p, q) = (Affine::<P>::rand(rng), Affine::<P>::rand(rng));
+        let zero = Affine::<P>::identity();
+        for (a, b) in [(p, q), (p, p), (p, -p), (zero, p), (p, zero), (zero, zero)] {
+            let cs = ConstraintSystem::<CF>::new_ref();
+            let a_var = AffineVarGeneric::<P, F, CF>::new_witness(ns!(cs, "a"), || Ok(a)).unwrap();
+            let b_var = AffineVarGeneric::<P, F, CF>::new_witness(ns!(cs, "b"), || Ok(b)).unwrap();
+            assert_eq!(a_var.add(&b_var).unwrap().value().unwrap(), (a + b).into_affine());
+            assert_eq!(AffineVarGeneric::zero().add(&a_var).unwrap().value().unwrap(), a);
+            assert!(cs.is_satisfied().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_complete_add() {
+        let rng = &mut test_rng();
+        check_complete_add::<ark_bls12_377::g1::Config, FpVar<ark_bw6_761::Fr>, _>(rng);
+        check_complete_add::<ark_bls12_381::g1::Config, BlsInBls, _>(rng);
+
+        // `(-1, 0)`, of order 2 on `y^2 = x^3 + 1`, is its own opposite: its double is the identity.
+        let t = ark_bls12_377::G1Affine::new_unchecked(-ark_bls12_377::Fq::one(), ark_bls12_377::Fq::zero());
+        assert!(t.is_on_curve());
+        let p = ark_bls12_377::G1Affine::rand(rng);
+        let cs = ConstraintSystem::<ark_bw6_761::Fr>::new_ref();
+        let t_var = AffineVarGeneric::<_, FpVar<ark_bw6_761::Fr>, _>::new_witness(ns!(cs, "t"), || Ok(t)).unwrap();
+        let p_var = AffineVarGeneric::<_, FpVar<ark_bw6_761::Fr>, _>::new_witness(ns!(cs, "p"), || Ok(p)).unwrap();
+        assert!(t_var.add(&t_var).unwrap().is_zero().value().unwrap());
+        // Off the prime-order subgroup, so compared by coordinates, that `value` would reject.
+        let sum = t_var.add(&p_var).unwrap();
+        let expected = (t + p).into_affine();
+        assert_eq!((sum.x.value().unwrap(), sum.y.value().unwrap()), (expected.x, expected.y));
+        assert!(cs.is_satisfied().unwrap());
+    }
+
+    fn check_compress<P, F, CF>(p: Affine<P>) -> bool
+        where P: SWCurveConfig,
+              P::BaseField: PrimeField,
+              CF: PrimeField,
+              F: LimbVar<P::BaseField, CF>,
+    {
+        let cs = ConstraintSystem::<CF>::new_ref();
+        let p_var = NonZeroAffineVarGeneric::<P, F, CF>::new_witness(ns!(cs, "p"), || Ok(p)).unwrap();
+        let (limbs, sign) = p_var.compress().unwrap();
+        assert_eq!(limbs.value().unwrap(), p_var.x.limbs().unwrap().value().unwrap());
+        assert_eq!(sign.value().unwrap(), p.y > -p.y);
+        assert!(cs.is_satisfied().unwrap());
+        sign.value().unwrap()
+    }
+
+    #[test]
+    fn test_compress() {
+        let rng = &mut test_rng();
+        let p = ark_bls12_377::G1Affine::rand(rng);
+        assert_ne!(check_compress::<_, FpVar<ark_bw6_761::Fr>, _>(p), check_compress::<_, FpVar<ark_bw6_761::Fr>, _>(-p));
+        #[cfg(feature = "bls12-381-emulated")]
+        for p in [ark_bls12_381::G1Affine::rand(rng), -ark_bls12_381::G1Affine::rand(rng)] {
+            // The flag of the compressed encoding, `0x20` of the first byte.
+            assert_eq!(check_compress::<_, BlsInBls, _>(p), crate::bls_keys::public_key_to_bytes(&p)[0] & 0x20 != 0);
+        }
+    }
+
+    fn check_decompressed<P, F, CF>(x: P::BaseField, sign: bool) -> bool
+        where P: SWCurveConfig,
+              P::BaseField: PrimeField,
+              CF: PrimeField,
+              F: FieldVar<P::BaseField, CF>,
+              for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+    {
+        let cs = ConstraintSystem::<CF>::new_ref();
+        let x_var = F::new_witness(ns!(cs, "x"), || Ok(x)).unwrap();
+        let sign_var = Boolean::new_witness(ns!(cs, "sign"), || Ok(sign)).unwrap();
+        match NonZeroAffineVarGeneric::<P, F, CF>::new_decompressed(x_var, &sign_var) {
+            Ok(p) if cs.is_satisfied().unwrap() => {
+                assert_eq!(Some(p.value().unwrap()), Affine::<P>::get_point_from_x_unchecked(x, sign));
+                true
+            }
+            Ok(_) => false,
+            Err(e) => {
+                assert!(matches!(e, SynthesisError::Unsatisfiable));
+                false
+            }
+        }
+    }
+
+    #[test]
+    fn test_new_decompressed() {
+        use ark_bls12_381::{Fq, G1Affine};
+
+        let rng = &mut test_rng();
+        let p = G1Affine::rand(rng);
+        let sign = p.y > -p.y;
+        assert!(check_decompressed::<ark_bls12_381::g1::Config, BlsInBls, _>(p.x, sign));
+        assert!(check_decompressed::<ark_bls12_381::g1::Config, BlsInBls, _>(p.x, !sign));
+        // An abscissa of no point.
+        let x = std::iter::repeat_with(|| Fq::rand(rng)).find(|x| G1Affine::get_point_from_x_unchecked(*x, false).is_none()).unwrap();
+        assert!(!check_decompressed::<ark_bls12_381::g1::Config, BlsInBls, _>(x, false));
+
+        let q = ark_bls12_377::G1Affine::rand(rng);
+        assert!(check_decompressed::<ark_bls12_377::g1::Config, FpVar<ark_bw6_761::Fr>, _>(q.x, q.y > -q.y));
+        let x = std::iter::repeat_with(|| ark_bls12_377::Fq::rand(rng))
+            .find(|x| ark_bls12_377::G1Affine::get_point_from_x_unchecked(*x, false).is_none())
+            .unwrap();
+        assert!(!check_decompressed::<ark_bls12_377::g1::Config, FpVar<ark_bw6_761::Fr>, _>(x, false));
+    }
+
+    // The aggregation step selects between the sum and the accumulator, i.e. both coordinates.
+    // In the emulated case that's one native select per limb, negligible next to the addition,
+    // so selecting over fewer values (e.g. the slope and one coordinate, re-deriving the other) doesn't pay off.
+    #[test]
+    fn test_select_cost() {
+        let rng = &mut test_rng();
+        let cs = ConstraintSystem::<ark_bls12_381::Fr>::new_ref();
+        let points = Vec::<NonZeroAffineVarGeneric<_, BlsInBls, _>>::new_witness(ns!(cs, "points"), || {
+            Ok(vec![ark_bls12_381::G1Affine::rand(rng), ark_bls12_381::G1Affine::rand(rng)])
+        }).unwrap();
+        let b = Boolean::new_witness(ns!(cs, "b"), || Ok(true)).unwrap();
+        let mut tracker = Tracker::new(&cs);
+        let sum = points[0].add_unchecked(&points[1]).unwrap();
+        let add = tracker.update(&cs);
+        let _selected = NonZeroAffineVarGeneric::conditionally_select(&b, &sum, &points[0]).unwrap();
+        let select = tracker.update(&cs);
+        println!("emulated addition: {:?}, selection: {:?}", add, select);
+        assert!(20 * select.num_constraints < add.num_constraints);
+    }
+}
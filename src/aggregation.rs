@@ -0,0 +1,63 @@
+use ark_ec::AffineRepr;
+use ark_ec::CurveGroup;
+use ark_ec::short_weierstrass::{Affine, Projective, SWCurveConfig};
+
+use crate::bitmask::Bitmask;
+use crate::error::SnowballError;
+
+fn check_lengths<P: SWCurveConfig>(keys: &[Affine<P>], bitmask: &Bitmask) -> Result<(), SnowballError> {
+    if keys.len() != bitmask.len() {
+        return Err(SnowballError::LengthMismatch { keys: keys.len(), bits: bitmask.len() });
+    }
+    Ok(())
+}
+
+/// Sum of the keys selected by the bitmask.
+pub fn aggregate<P: SWCurveConfig>(keys: &[Affine<P>], bitmask: &Bitmask) -> Result<Affine<P>, SnowballError> {
+    check_lengths(keys, bitmask)?;
+    let apk: Projective<P> = keys.iter()
+        .zip(bitmask.bits())
+        .filter(|(_, b)| **b)
+        .map(|(k, _)| k)
+        .sum();
+    Ok(apk.into_affine())
+}
+
+/// Same as `aggregate`, but without branching on the bits, for when the bitmask is confidential.
+///
+/// Every key is added to the accumulator, and the result is then selected arithmetically,
+/// `acc' = acc + b * (acc + key - acc)`. The accumulator starts from the generator rather than from
+/// the identity, so that the point additions never hit their identity special case depending on the bits.
+/// Timing still depends on the keys, as arkworks field arithmetic isn't constant-time.
+pub fn aggregate_ct<P: SWCurveConfig>(keys: &[Affine<P>], bitmask: &Bitmask) -> Result<Affine<P>, SnowballError> {
+    check_lengths(keys, bitmask)?;
+    let seed = Affine::<P>::generator();
+    let mut acc = seed.into_group();
+    for (key, bit) in keys.iter().zip(bitmask.bits()) {
+        let b = P::BaseField::from(*bit);
+        let next = acc + key;
+        acc.x += b * (next.x - acc.x);
+        acc.y += b * (next.y - acc.y);
+        acc.z += b * (next.z - acc.z);
+    }
+    Ok((acc - seed).into_affine())
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_std::{test_rng, UniformRand};
+
+    use super::*;
+
+    #[test]
+    fn test_aggregate_ct() {
+        let rng = &mut test_rng();
+        let n = 10;
+        let keys: Vec<ark_bls12_381::G1Affine> = (0..n).map(|_| ark_bls12_381::G1Affine::rand(rng)).collect();
+        let bitmask = Bitmask::from_bits((0..n).map(|_| bool::rand(rng)).collect());
+        assert_eq!(aggregate_ct(&keys, &bitmask).unwrap(), aggregate(&keys, &bitmask).unwrap());
+
+        let empty = Bitmask::from_bits(vec![false; n]);
+        assert!(aggregate_ct(&keys, &empty).unwrap().is_zero());
+    }
+}
@@ -1,90 +1,270 @@
 use std::marker::PhantomData;
 
+use ark_ec::hashing::curve_maps::wb::WBConfig;
 use ark_ec::short_weierstrass::{Affine, SWCurveConfig};
-use ark_ff::{Field, PrimeField};
+use ark_ff::{Field, PrimeField, Zero};
 use ark_r1cs_std::alloc::AllocVar;
 use ark_r1cs_std::fields::{FieldOpsBounds, FieldVar};
 use ark_r1cs_std::fields::fp::FpVar;
 use ark_r1cs_std::fields::nonnative::AllocatedNonNativeFieldVar;
-use ark_r1cs_std::fields::nonnative::params::OptimizationType;
-use ark_r1cs_std::select::CondSelectGadget;
-use ark_r1cs_std::ToBitsGadget;
-use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef};
+use ark_r1cs_std::fields::nonnative::params::{get_params, OptimizationType};
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystem, ConstraintSystemRef, SynthesisError};
 use derivative::Derivative;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use zeroize::Zeroize;
 
 use crate::affine_gen::NonZeroAffineVarGeneric;
+use crate::bitmask::Bitmask;
+use crate::chain::{Chain, ChainCircuit, KeyBaseField};
+use crate::config::{AdditionStrategy, CommitmentScheme, Config};
+use crate::error::SnowballError;
+use crate::fragments::{aggregate, aggregate_complete, allocate_compressed_input_keys, allocate_input_keys, allocate_packed_input_keys, BitmaskVar, constant_point, LimbVar};
+use crate::hash_to_curve::hash_to_curve;
+use crate::labels::labeled;
+use crate::profiling::phase;
+use crate::sum_acc::{AccumulatorField, PointSummation};
 
 #[derive(Derivative)]
 #[derivative(Debug, Clone)]
 pub struct ApkCircuit<P: SWCurveConfig, CF: Field, F: FieldVar<P::BaseField, CF>> {
-    keys: Vec<Affine<P>>,
-    seed: Affine<P>,
-    packed_bits: CF,
+    pub(crate) keys: Vec<Affine<P>>,
+    pub(crate) seed: Affine<P>,
+    pub(crate) packed_bits: CF,
+    pub(crate) config: Config,
+    // `fn() -> F` keeps the circuit `Send` even though field vars hold an `Rc` to their constraint system.
     #[derivative(Debug = "ignore")]
-    _f: PhantomData<F>,
+    _f: PhantomData<fn() -> F>,
+}
+
+impl<P: SWCurveConfig, CF: PrimeField, F: FieldVar<P::BaseField, CF>> ApkCircuit<P, CF, F> {
+    pub fn new(keys: Vec<Affine<P>>, seed: Affine<P>, bitmask: &Bitmask) -> Result<Self, SnowballError> {
+        if keys.len() != bitmask.len() {
+            return Err(SnowballError::LengthMismatch { keys: keys.len(), bits: bitmask.len() });
+        }
+        Ok(Self { keys, seed, packed_bits: bitmask.pack()?, config: Config::default(), _f: PhantomData })
+    }
+
+    /// `new` with the seed hashed from the domain separation tag `domain`, `hash_to_curve` of the empty message,
+    /// rather than a point the caller trusts to be of unknown discrete logarithm. The seed is hashed on the host:
+    /// `hash_to_curve::hash_to_curve_var` of the constant elements would fold into the same constant, at no cost,
+    /// and the verifying key fixes it either way.
+    pub fn new_with_domain(keys: Vec<Affine<P>>, domain: &[u8], bitmask: &Bitmask) -> Result<Self, SnowballError>
+        where P: WBConfig,
+    {
+        Self::new(keys, hash_to_curve(domain, &[]), bitmask)
+    }
+
+    /// The circuit summing the keys as the `PointSummation` `S` does, such as `SumAccumulator`: `Config::addition`
+    /// set to `S::ADDITION`, that the host-side replicas and the keys of the circuit go by as well.
+    pub fn with_summation<S: PointSummation<P, F, CF>>(self) -> Self {
+        let config = Config { addition: S::ADDITION, ..self.config.clone() };
+        self.with_config(config)
+    }
+
+    pub fn with_config(self, config: Config) -> Self {
+        Self { config, ..self }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn num_keys(&self) -> usize {
+        self.keys.len()
+    }
 }
 
 impl<P, CF, F> ConstraintSynthesizer<CF> for ApkCircuit<P, CF, F>
     where P: SWCurveConfig,
+          P::BaseField: PrimeField,
           CF: PrimeField,
-          F: FieldVar<P::BaseField, CF>,
+          F: LimbVar<P::BaseField, CF> + AccumulatorField<P, CF>,
           for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
 {
     fn generate_constraints(self, cs: ConstraintSystemRef<CF>) -> ark_relations::r1cs::Result<()> {
-        let seed_const = NonZeroAffineVarGeneric::<P, F, CF>::new_constant(ark_relations::ns!(cs, "seed"), &self.seed)?;
-        let key_vars = Vec::<NonZeroAffineVarGeneric::<P, F, CF>>::new_input(ark_relations::ns!(cs, "keys"), || Ok(self.keys))?;
-        let packed_bits_var = FpVar::new_input(ark_relations::ns!(cs, "bitmask_packed"), || Ok(&self.packed_bits))?;
-        let bit_vars = packed_bits_var.to_bits_le()?;
-
-        let mut curr_sum = seed_const;
-        for (b, key) in bit_vars.iter().zip(key_vars) {
-            let next_sum = curr_sum.add_unchecked(&key)?;
-            curr_sum = NonZeroAffineVarGeneric::<P, F, CF>::conditionally_select(b, &next_sum, &curr_sum)?;
-        }
-        Ok(())
+        self.config.apply(&cs);
+        let (seed_const, key_vars, bitmask_var) = phase(&cs, "allocation", || {
+            let seed_const = constant_point::<P, CF, F>(cs.clone(), self.seed)?;
+            let (key_vars, bitmask_var) = allocate_inputs::<P, CF, F>(cs.clone(), self.keys, self.packed_bits, &self.config)?;
+            bitmask_var.enforce_unused_zero()?;
+            Ok::<_, SynthesisError>((seed_const, key_vars, bitmask_var))
+        })?;
+
+        phase(&cs, "aggregation", || {
+            let _apk = if self.config.addition == AdditionStrategy::Complete {
+                aggregate_complete(&key_vars, bitmask_var.bits())?
+            } else {
+                aggregate(seed_const, &key_vars, bitmask_var.bits(), &self.config)?.into()
+            };
+            Ok(())
+        })
     }
 }
 
-pub fn keys_to_limbs<F: PrimeField, CF: PrimeField, P: SWCurveConfig<BaseField=F>>(keys: &[Affine<P>]) -> Vec<CF> {
-    keys.iter()
-        .flat_map(|p| vec![p.x, p.y])
-        .flat_map(|c| AllocatedNonNativeFieldVar::<F, CF>::get_limbs_representations(&c, OptimizationType::Constraints).unwrap())
-        .collect()
+// Public key variables and the packed bitmask variable, in the order they're allocated.
+pub(crate) type InputVars<P, F, CF> = (Vec<NonZeroAffineVarGeneric<P, F, CF>>, BitmaskVar<CF>);
+
+pub(crate) fn allocate_inputs<P, CF, F>(cs: ConstraintSystemRef<CF>, keys: Vec<Affine<P>>, packed_bits: CF, config: &Config) -> Result<InputVars<P, F, CF>, SynthesisError>
+    where P: SWCurveConfig,
+          P::BaseField: PrimeField,
+          CF: PrimeField,
+          F: LimbVar<P::BaseField, CF>,
+{
+    let num_keys = keys.len();
+    let key_vars = match config.commitment {
+        CommitmentScheme::PublicInputs => allocate_input_keys(cs.clone(), &keys)?,
+        CommitmentScheme::PackedLimbs => allocate_packed_input_keys(cs.clone(), &keys)?,
+        CommitmentScheme::CompressedInputs => allocate_compressed_input_keys(cs.clone(), &keys)?,
+        #[cfg(feature = "sha256")]
+        CommitmentScheme::Sha256 => crate::digest::allocate_digest_keys(cs.clone(), keys)?,
+    };
+    let packed_bits_var = labeled(&cs, "bitmask_packed", || FpVar::new_input(ark_relations::ns!(cs, "bitmask_packed"), || Ok(packed_bits)))?;
+    Ok((key_vars, BitmaskVar::new(packed_bits_var, num_keys)?))
 }
 
-#[cfg(test)]
+/// The instance vector of `ApkCircuit`, obtained by allocating the inputs exactly as the circuit does.
+pub fn public_inputs<P, CF, F>(keys: &[Affine<P>], bitmask: &Bitmask, config: &Config) -> Result<Vec<CF>, SnowballError>
+    where P: SWCurveConfig,
+          P::BaseField: PrimeField,
+          CF: PrimeField,
+          F: LimbVar<P::BaseField, CF>,
+{
+    if keys.len() != bitmask.len() {
+        return Err(SnowballError::LengthMismatch { keys: keys.len(), bits: bitmask.len() });
+    }
+    let cs = ConstraintSystem::<CF>::new_ref();
+    config.apply(&cs);
+    let _inputs = allocate_inputs::<P, CF, F>(cs.clone(), keys.to_vec(), bitmask.pack()?, config)?;
+    let cs = cs.borrow().ok_or(SynthesisError::MissingCS)?;
+    // The first instance variable is the constant `1`.
+    Ok(cs.instance_assignment[1..].to_vec())
+}
+
+// Not `ZeroizeOnDrop`: `generate_constraints` consumes the circuit by value and moves the witness out.
+impl<P: SWCurveConfig, CF: Field, F: FieldVar<P::BaseField, CF>> Zeroize for ApkCircuit<P, CF, F> {
+    fn zeroize(&mut self) {
+        self.keys.zeroize();
+        self.seed.zeroize();
+        self.packed_bits.zeroize();
+    }
+}
+
+/// `ApkCircuit` for a number of keys known at compile time.
+///
+/// Instantiating it for more keys than the packed bitmask can hold fails to compile.
+#[derive(Derivative)]
+#[derivative(Debug, Clone)]
+pub struct ApkCircuitFixed<const N: usize, C: Chain> {
+    keys: [Affine<C::Curve>; N],
+    seed: Affine<C::Curve>,
+    bits: [bool; N],
+    config: Config,
+}
+
+impl<const N: usize, C: Chain> ApkCircuitFixed<N, C> {
+    const FITS: () = assert!(N < <C::ConstraintField as PrimeField>::MODULUS_BIT_SIZE as usize, "the bitmask doesn't fit into a field element");
+
+    pub fn new(keys: [Affine<C::Curve>; N], seed: Affine<C::Curve>, bits: [bool; N]) -> Self {
+        let () = Self::FITS;
+        Self { keys, seed, bits, config: Config::default() }
+    }
+
+    pub fn with_config(self, config: Config) -> Self {
+        Self { config, ..self }
+    }
+
+    /// The packed bitmask public input, computed without allocating.
+    pub fn packed_bits(&self) -> C::ConstraintField {
+        let () = Self::FITS;
+        self.bits.iter().rev().fold(C::ConstraintField::zero(), |acc, b| acc.double() + C::ConstraintField::from(*b))
+    }
+
+    pub fn keys(&self) -> &[Affine<C::Curve>; N] {
+        &self.keys
+    }
+}
+
+impl<const N: usize, C: Chain> ConstraintSynthesizer<C::ConstraintField> for ApkCircuitFixed<N, C>
+    where for<'a> &'a C::FieldVar: FieldOpsBounds<'a, KeyBaseField<C>, C::FieldVar>
+{
+    fn generate_constraints(self, cs: ConstraintSystemRef<C::ConstraintField>) -> ark_relations::r1cs::Result<()> {
+        let circuit = ChainCircuit::<C> {
+            keys: self.keys.to_vec(),
+            seed: self.seed,
+            packed_bits: self.packed_bits(),
+            config: self.config,
+            _f: PhantomData,
+        };
+        circuit.generate_constraints(cs)
+    }
+}
+
+/// Limb decomposition used by the non-native field gadgets to represent elements of `F` in `CF`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LimbParams {
+    pub num_limbs: usize,
+    pub bits_per_limb: usize,
+}
+
+pub fn limb_params<F: PrimeField, CF: PrimeField>(opt: OptimizationType) -> LimbParams {
+    let params = get_params(F::MODULUS_BIT_SIZE as usize, CF::MODULUS_BIT_SIZE as usize, opt);
+    LimbParams { num_limbs: params.num_limbs, bits_per_limb: params.bits_per_limb }
+}
+
+/// Limbs of the key coordinates, decomposed in parallel with the `parallel` feature.
+pub fn keys_to_limbs<F: PrimeField, CF: PrimeField, P: SWCurveConfig<BaseField=F>>(keys: &[Affine<P>], config: &Config) -> Result<Vec<CF>, SnowballError> {
+    let opt = config.optimization.into();
+    let limbs = ark_std::cfg_iter!(keys)
+        .map(|p| {
+            let mut limbs = AllocatedNonNativeFieldVar::<F, CF>::get_limbs_representations(&p.x, opt)?;
+            limbs.extend(AllocatedNonNativeFieldVar::<F, CF>::get_limbs_representations(&p.y, opt)?);
+            Ok(limbs)
+        })
+        .collect::<Result<Vec<_>, SynthesisError>>()?;
+    Ok(limbs.concat())
+}
+
+#[cfg(all(test, feature = "bls12-377-bw6"))]
 mod tests {
+    #[cfg(feature = "bls12-381-emulated")]
     use ark_bls12_381::Bls12_381;
     use ark_bw6_761::BW6_761;
     use ark_groth16::{Groth16, PreparedVerifyingKey};
     use ark_r1cs_std::boolean::Boolean;
+    use ark_r1cs_std::ToBitsGadget;
     use ark_r1cs_std::fields::nonnative::NonNativeFieldVar;
     use ark_r1cs_std::R1CSVar;
-    use ark_relations::r1cs::ConstraintSystem;
     use ark_snark::SNARK;
     use ark_std::{test_rng, UniformRand};
     use rand::Rng;
     use rand::rngs::OsRng;
 
+    use crate::chain::Bls377InBw6;
+    #[cfg(feature = "bls12-381-emulated")]
+    use crate::chain::Bls381Emulated;
+    use crate::schema::point_to_instance;
+
     use super::*;
 
+    #[cfg(feature = "bls12-381-emulated")]
     #[test]
     fn apk_foreign() {
         let rng = &mut OsRng;
         let n = 3;
         let keys: Vec<ark_bls12_381::G1Affine> = (0..n).map(|_| ark_bls12_381::G1Affine::rand(rng)).collect();
         let bits: Vec<bool> = (0..n).map(|_| rng.gen_bool(0.9)).collect();
-        let seed = ark_bls12_381::G1Affine::rand(rng); // TODO
+        let seed = hash_to_curve::<ark_bls12_381::g1::Config>(b"snowball", &[]);
 
-        let cs = ConstraintSystem::<ark_bls12_381::Fr>::new_ref();
-        let bit_vars = Vec::<Boolean<ark_bls12_381::Fr>>::new_constant(cs, bits.clone()).unwrap();
-        let packed_bits = Boolean::le_bits_to_fp_var(&bit_vars).unwrap().value().unwrap();
+        let packed_bits = Bitmask::from_bits(bits).pack::<ark_bls12_381::Fr>().unwrap();
 
         let circuit = ApkCircuit {
             keys: keys.clone(),
             seed,
             packed_bits,
-            _f: PhantomData::<NonNativeFieldVar<ark_bls12_381::Fq, ark_bls12_381::Fr>>,
+            config: Config::default(),
+            _f: PhantomData::<fn() -> NonNativeFieldVar<ark_bls12_381::Fq, ark_bls12_381::Fr>>,
         };
 
         //TODO: circuit can be empty
@@ -92,7 +272,7 @@ mod tests {
         let proof = Groth16::<Bls12_381>::prove(&pk, circuit.clone(), rng).unwrap();
 
         let pvk: PreparedVerifyingKey<Bls12_381> = vk.into();
-        let mut pi = keys_to_limbs(&keys);
+        let mut pi: Vec<_> = keys.iter().flat_map(|p| point_to_instance::<Bls381Emulated>(p, &Config::default()).unwrap()).collect();
         pi.push(packed_bits);
         let pi = Groth16::<Bls12_381>::prepare_inputs(&pvk, &pi).unwrap();
         assert!(Groth16::<Bls12_381>::verify_proof_with_prepared_inputs(&pvk, &proof, &pi).unwrap());
@@ -104,26 +284,16 @@ mod tests {
         let n = 3;
         let keys: Vec<ark_bls12_377::G1Affine> = (0..n).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
         let bits: Vec<bool> = (0..n).map(|_| rng.gen_bool(0.9)).collect();
-        let seed = ark_bls12_377::G1Affine::rand(rng); // TODO
-
-        let cs = ConstraintSystem::<ark_bw6_761::Fr>::new_ref();
-        let bit_vars = Vec::<Boolean<ark_bw6_761::Fr>>::new_constant(cs, bits.clone()).unwrap();
-        let packed_bits = Boolean::le_bits_to_fp_var(&bit_vars).unwrap().value().unwrap();
-
-        let circuit = ApkCircuit {
-            keys: keys.clone(),
-            seed,
-            packed_bits,
-            _f: PhantomData::<FpVar<ark_bw6_761::Fr>>,
-        };
+        let bitmask = Bitmask::from_bits(bits);
+        let circuit = ApkCircuit::<_, _, FpVar<ark_bw6_761::Fr>>::new_with_domain(keys.clone(), b"snowball", &bitmask).unwrap();
 
         //TODO: circuit can be empty
         let (pk, vk) = Groth16::<BW6_761>::circuit_specific_setup(circuit.clone(), rng).unwrap();
         let proof = Groth16::<BW6_761>::prove(&pk, circuit.clone(), rng).unwrap();
 
         let pvk: PreparedVerifyingKey<BW6_761> = vk.into();
-        let mut pi: Vec<ark_bw6_761::Fr> = keys.iter().flat_map(|p| vec![p.x, p.y]).collect();
-        pi.push(packed_bits);
+        let mut pi: Vec<_> = keys.iter().flat_map(|p| point_to_instance::<Bls377InBw6>(p, &Config::default()).unwrap()).collect();
+        pi.push(bitmask.pack().unwrap());
         let pi = Groth16::<BW6_761>::prepare_inputs(&pvk, &pi).unwrap();
         assert!(Groth16::<BW6_761>::verify_proof_with_prepared_inputs(&pvk, &proof, &pi).unwrap());
     }
@@ -144,9 +314,175 @@ mod tests {
         assert_eq!(bits, bits_back);
     }
 
+    #[test]
+    fn test_public_inputs() {
+        let rng = &mut test_rng();
+        let keys: Vec<ark_bls12_381::G1Affine> = (0..3).map(|_| ark_bls12_381::G1Affine::rand(rng)).collect();
+        let bitmask = Bitmask::from_bits(vec![true, false, true]);
+        let pi = public_inputs::<_, _, NonNativeFieldVar<ark_bls12_381::Fq, ark_bls12_381::Fr>>(&keys, &bitmask, &Config::default()).unwrap();
+        let mut expected = keys_to_limbs(&keys, &Config::default()).unwrap();
+        expected.push(bitmask.pack().unwrap());
+        assert_eq!(pi, expected);
+    }
+
+    #[test]
+    fn test_length_mismatch() {
+        let rng = &mut test_rng();
+        let keys = vec![ark_bls12_377::G1Affine::rand(rng); 2];
+        let seed = ark_bls12_377::G1Affine::rand(rng);
+        let res = ApkCircuit::<_, ark_bw6_761::Fr, FpVar<_>>::new(keys, seed, &Bitmask::from_bits(vec![true]));
+        assert!(matches!(res, Err(SnowballError::LengthMismatch { keys: 2, bits: 1 })));
+    }
+
+    #[test]
+    fn test_zeroize() {
+        let rng = &mut test_rng();
+        let mut circuit = ApkCircuit {
+            keys: vec![ark_bls12_377::G1Affine::rand(rng)],
+            seed: ark_bls12_377::G1Affine::rand(rng),
+            packed_bits: ark_bw6_761::Fr::from(1u8),
+            config: Config::default(),
+            _f: PhantomData::<fn() -> FpVar<ark_bw6_761::Fr>>,
+        };
+        circuit.zeroize();
+        assert!(circuit.keys.is_empty());
+        assert!(circuit.packed_bits.is_zero());
+    }
+
     #[test]
     fn test_limbs_foreign() {
-        let limbs: Vec<ark_bls12_381::Fr> = keys_to_limbs(&[ark_bls12_381::G1Affine::rand(&mut test_rng())]);
+        let limbs: Vec<ark_bls12_381::Fr> = keys_to_limbs(&[ark_bls12_381::G1Affine::rand(&mut test_rng())], &Config::default()).unwrap();
         println!("bls12_381::G1Affine is represented with {} limbs in bls12_381::Fr", limbs.len());
+        let params = limb_params::<ark_bls12_381::Fq, ark_bls12_381::Fr>(OptimizationType::Constraints);
+        assert_eq!(limbs.len(), 2 * params.num_limbs);
+        assert!(params.num_limbs * params.bits_per_limb >= 381);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_fixed() {
+        let rng = &mut test_rng();
+        let keys: [ark_bls12_377::G1Affine; 4] = [(); 4].map(|_| ark_bls12_377::G1Affine::rand(rng));
+        let bits = [true, false, true, true];
+        let circuit = ApkCircuitFixed::<4, Bls377InBw6>::new(keys, ark_bls12_377::G1Affine::rand(rng), bits);
+        assert_eq!(circuit.packed_bits(), Bitmask::from_bits(bits.to_vec()).pack().unwrap());
+
+        let cs = ConstraintSystem::<ark_bw6_761::Fr>::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+        assert!(cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn test_with_summation() {
+        use ark_ec::CurveGroup;
+
+        use crate::fragments::aggregate_with;
+        use crate::sum_acc::SumAccumulator;
+
+        type P = ark_bls12_377::g1::Config;
+        type Fr = ark_bw6_761::Fr;
+
+        fn check<S: PointSummation<P, FpVar<Fr>, Fr>>(circuit: &ChainCircuit<Bls377InBw6>, bitmask: &Bitmask) {
+            let circuit = circuit.clone().with_config(Config::default()).with_summation::<S>();
+            assert_eq!(circuit.config().addition, S::ADDITION);
+            let cs = ConstraintSystem::<Fr>::new_ref();
+            circuit.clone().generate_constraints(cs.clone()).unwrap();
+            assert!(cs.is_satisfied().unwrap());
+
+            // The sum of `S`, the one the host-side replica computes by the config, and the actual one.
+            let cs = ConstraintSystem::<Fr>::new_ref();
+            let seed = NonZeroAffineVarGeneric::new_witness(cs.clone(), || Ok(circuit.seed)).unwrap();
+            let key_vars = Vec::new_witness(cs.clone(), || Ok(circuit.keys.clone())).unwrap();
+            let bit_vars = Vec::<Boolean<Fr>>::new_witness(cs.clone(), || Ok(bitmask.bits().to_vec())).unwrap();
+            let apk = aggregate_with::<S, _, _, _>(seed, &key_vars, &bit_vars).unwrap().value().unwrap();
+            assert_eq!(apk, crate::circuit_semantics::aggregate::<Bls377InBw6>(circuit.seed, &circuit.keys, bitmask, circuit.config()).0);
+            assert_eq!(apk, (crate::aggregation::aggregate(&circuit.keys, bitmask).unwrap() + circuit.seed).into_affine());
+        }
+
+        let rng = &mut test_rng();
+        let keys: Vec<_> = (0..4).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+        let bitmask = Bitmask::from_bits(vec![true, false, true, true]);
+        let circuit = ChainCircuit::<Bls377InBw6>::new(keys, ark_bls12_377::G1Affine::rand(rng), &bitmask).unwrap()
+            .with_config(Config { addition: AdditionStrategy::Complete, ..Default::default() });
+        check::<NonZeroAffineVarGeneric<P, FpVar<Fr>, Fr>>(&circuit, &bitmask);
+        check::<SumAccumulator<P, FpVar<Fr>, Fr>>(&circuit, &bitmask);
+    }
+
+    #[test]
+    fn test_complete() {
+        let rng = &mut test_rng();
+        let keys: Vec<ark_bls12_377::G1Affine> = (0..3).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+        let seed = ark_bls12_377::G1Affine::rand(rng);
+        // The sums hit the identity, double a key, and the last key cancels the seed the incomplete additions start from.
+        let keys = vec![keys[0], keys[1], -keys[0], keys[1], -seed];
+        let config = Config { addition: AdditionStrategy::Complete, ..Default::default() };
+        let is_satisfied = |bits: Vec<bool>, config: &Config| {
+            let circuit = ChainCircuit::<Bls377InBw6>::new(keys.clone(), seed, &Bitmask::from_bits(bits)).unwrap().with_config(config.clone());
+            let cs = ConstraintSystem::new_ref();
+            circuit.generate_constraints(cs.clone()).unwrap();
+            cs.is_satisfied().unwrap()
+        };
+        for bits in [vec![false; 5], vec![true, false, true, false, false], vec![false, true, false, true, false], vec![true; 5]] {
+            assert!(is_satisfied(bits, &config));
+        }
+        assert!(is_satisfied(vec![false, false, false, false, true], &config));
+        assert!(!is_satisfied(vec![false, false, false, false, true], &Config::default()));
+    }
+
+    #[cfg(feature = "bls12-381-emulated")]
+    #[test]
+    fn test_multiplication() {
+        use ark_ec::CurveGroup;
+        use ark_relations::r1cs::OptimizationGoal;
+
+        use crate::config::{Multiplication, Optimization};
+        use crate::fragments::aggregate;
+        use crate::tests::BlsInBls;
+
+        let rng = &mut test_rng();
+        let keys: Vec<_> = (0..3).map(|_| ark_bls12_381::G1Affine::rand(rng)).collect();
+        let seed = ark_bls12_381::G1Affine::rand(rng);
+        let bitmask = Bitmask::from_bits(vec![true, false, true]);
+        let expected = (crate::aggregation::aggregate(&keys, &bitmask).unwrap() + seed).into_affine();
+        let num_constraints = |multiplication| {
+            let config = Config { optimization: Optimization::Weight, addition: AdditionStrategy::Accumulated, multiplication, ..Default::default() };
+            let circuit = ChainCircuit::<Bls381Emulated>::new(keys.clone(), seed, &bitmask).unwrap().with_config(config.clone());
+            let cs = ConstraintSystem::new_ref();
+            circuit.generate_constraints(cs.clone()).unwrap();
+            assert!(cs.is_satisfied().unwrap());
+
+            let cs = ConstraintSystem::<ark_bls12_381::Fr>::new_ref();
+            cs.set_optimization_goal(OptimizationGoal::Weight);
+            let seed = NonZeroAffineVarGeneric::<_, BlsInBls, _>::new_witness(cs.clone(), || Ok(seed)).unwrap();
+            let key_vars = Vec::new_witness(cs.clone(), || Ok(keys.clone())).unwrap();
+            let bit_vars = Vec::<Boolean<_>>::new_witness(cs.clone(), || Ok(bitmask.bits().to_vec())).unwrap();
+            assert_eq!(aggregate(seed, &key_vars, &bit_vars, &config).unwrap().value().unwrap(), expected);
+            assert!(cs.is_satisfied().unwrap());
+            cs.num_constraints()
+        };
+        assert!(num_constraints(Multiplication::Karatsuba) < num_constraints(Multiplication::Schoolbook));
+    }
+
+    #[cfg(feature = "bls12-381-emulated")]
+    #[test]
+    fn test_labels() {
+        let rng = &mut test_rng();
+        let keys = (0..3).map(|_| ark_bls12_381::G1Affine::rand(rng)).collect();
+        let circuit = ChainCircuit::<Bls381Emulated>::new(keys, ark_bls12_381::G1Affine::rand(rng), &Bitmask::from_bits(vec![true; 3])).unwrap()
+            .with_config(Config { labels: true, ..Default::default() });
+        let cs = ConstraintSystem::<ark_bls12_381::Fr>::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+
+        let labels = crate::labels::labels(&cs).unwrap();
+        let num_limbs = limb_params::<ark_bls12_381::Fq, ark_bls12_381::Fr>(OptimizationType::Constraints).num_limbs;
+        assert_eq!(labels.instance(1 + 2 * num_limbs + 1).unwrap(), "keys/1/x/1");
+        assert_eq!(labels.instance(1 + 6 * num_limbs).unwrap(), "bitmask_packed");
+        assert_eq!(labels.constraint(cs.num_constraints() - 1).unwrap().split('/').take(3).collect::<Vec<_>>(), ["acc", "step", "2"]);
+
+        let cs = ConstraintSystem::<ark_bls12_381::Fr>::new_ref();
+        let keys = vec![ark_bls12_381::G1Affine::rand(rng)];
+        ChainCircuit::<Bls381Emulated>::new(keys, ark_bls12_381::G1Affine::rand(rng), &Bitmask::from_bits(vec![true])).unwrap()
+            .with_config(Config { labels: false, ..Default::default() })
+            .generate_constraints(cs.clone()).unwrap();
+        assert!(crate::labels::labels(&cs).is_none());
+    }
+}
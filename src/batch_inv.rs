@@ -0,0 +1,116 @@
+//! Quotients of independent pairs of field variables, with the witnesses computed by Montgomery's trick.
+//!
+//! In a circuit a quotient costs a multiplication to check whatever way its witness is computed, so chaining
+//! the checks through the prefix products as the trick does would only add `3(n - 1)` multiplications.
+//! What the trick saves is the inversions on the prover side: `n` quotients take a single inversion
+//! and `3(n - 1)` multiplications of field elements, each inversion being worth dozens of multiplications.
+
+use ark_ff::{batch_inversion, Field, PrimeField};
+use ark_r1cs_std::fields::FieldVar;
+use ark_relations::r1cs::{ConstraintSystemRef, SynthesisError};
+
+use crate::labels::labeled;
+
+/// `n / d` for each pair, enforced by `(n / d) * d = n`, with a zero `d` giving `0`, as `FieldVar::mul_by_inverse_unchecked` does.
+/// Costs the same constraints as the separate `mul_by_inverse_unchecked` calls, labeled `i`.
+pub fn batch_div<TF, CF, F>(pairs: &[(F, F)]) -> Result<Vec<F>, SynthesisError>
+    where TF: Field,
+          CF: PrimeField,
+          F: FieldVar<TF, CF>,
+{
+    let cs = pairs.iter().fold(ConstraintSystemRef::None, |cs, (n, d)| cs.or(n.cs()).or(d.cs()));
+    let quotients: Result<Vec<TF>, SynthesisError> = pairs.iter()
+        .map(|(_, d)| d.value())
+        .collect::<Result<Vec<_>, _>>()
+        .and_then(|mut inverses| {
+            batch_inversion(&mut inverses);
+            pairs.iter().zip(inverses).map(|((n, _), d_inv)| Ok(n.value()? * d_inv)).collect()
+        });
+
+    if cs.is_none() {
+        return quotients?.into_iter().map(F::constant).map(Ok).collect();
+    }
+    pairs.iter().enumerate().map(|(i, (n, d))| {
+        labeled(&cs, i, || {
+            let q = F::new_witness(ark_relations::ns!(cs, "n * d_inv"), || quotients.as_ref().map(|qs| qs[i]).map_err(|e| *e))?;
+            q.mul_equals(d, n)?;
+            Ok(q)
+        })
+    }).collect()
+}
+
+/// `1 / x` for each `x`, with a zero giving `0`. See `batch_div`.
+pub fn batch_inverse<TF, CF, F>(xs: &[F]) -> Result<Vec<F>, SynthesisError>
+    where TF: Field,
+          CF: PrimeField,
+          F: FieldVar<TF, CF>,
+{
+    let pairs: Vec<_> = xs.iter().map(|x| (F::one(), x.clone())).collect();
+    batch_div(&pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_r1cs_std::alloc::AllocVar;
+    use ark_r1cs_std::fields::fp::FpVar;
+    use ark_r1cs_std::R1CSVar;
+    use ark_relations::r1cs::ConstraintSystem;
+    use ark_std::{test_rng, UniformRand};
+
+    use crate::tests::BlsInBls;
+
+    use super::*;
+
+    fn test_batch_div<TF: Field, CF: PrimeField, F: FieldVar<TF, CF>>() {
+        let rng = &mut test_rng();
+        let mut values: Vec<(TF, TF)> = (0..5).map(|_| (TF::rand(rng), TF::rand(rng))).collect();
+        values.push((TF::rand(rng), TF::zero()));
+
+        let cs = ConstraintSystem::<CF>::new_ref();
+        let pairs: Vec<(F, F)> = values.iter()
+            .map(|(n, d)| (F::new_witness(cs.clone(), || Ok(*n)).unwrap(), F::new_witness(cs.clone(), || Ok(*d)).unwrap()))
+            .collect();
+        let before = cs.num_constraints();
+        let quotients = batch_div(&pairs).unwrap();
+        let batched = cs.num_constraints() - before;
+        for ((n, d), q) in values.iter().zip(&quotients) {
+            assert_eq!(q.value().unwrap(), *n * d.inverse().unwrap_or(TF::zero()));
+        }
+        // The zero denominator with a non-zero numerator is unsatisfiable, as with `mul_by_inverse_unchecked`.
+        assert!(!cs.is_satisfied().unwrap());
+
+        let before = cs.num_constraints();
+        for (n, d) in &pairs {
+            n.mul_by_inverse_unchecked(d).unwrap();
+        }
+        assert_eq!(cs.num_constraints() - before, batched);
+
+        let constants: Vec<(F, F)> = values[..5].iter().map(|(n, d)| (F::constant(*n), F::constant(*d))).collect();
+        let quotients = batch_div(&constants).unwrap();
+        assert!(quotients.iter().all(|q| q.is_constant()));
+        assert_eq!(quotients[0].value().unwrap(), values[0].0 / values[0].1);
+    }
+
+    #[test]
+    fn test_native() {
+        test_batch_div::<ark_bls12_381::Fr, ark_bls12_381::Fr, FpVar<ark_bls12_381::Fr>>();
+    }
+
+    #[test]
+    fn test_emulated() {
+        test_batch_div::<ark_bls12_381::Fq, ark_bls12_381::Fr, BlsInBls>();
+    }
+
+    #[test]
+    fn test_batch_inverse() {
+        let rng = &mut test_rng();
+        let cs = ConstraintSystem::<ark_bls12_381::Fr>::new_ref();
+        let values: Vec<_> = (0..4).map(|_| ark_bls12_381::Fq::rand(rng)).collect();
+        let xs: Vec<BlsInBls> = values.iter().map(|x| BlsInBls::new_witness(cs.clone(), || Ok(*x)).unwrap()).collect();
+        let inverses = batch_inverse(&xs).unwrap();
+        for (x, inv) in values.iter().zip(inverses) {
+            assert_eq!(inv.value().unwrap(), x.inverse().unwrap());
+        }
+        assert!(cs.is_satisfied().unwrap());
+    }
+}
@@ -0,0 +1,141 @@
+//! Runs the apk circuit end to end with Groth16, on keys and proofs serialized as the crate does.
+//!
+//! ```text
+//! snowball-cli setup  --chain <chain> --keys <file> --store <dir>
+//! snowball-cli prove  --chain <chain> --keys <file> --bitmask <bits> --store <dir> --out <file>
+//! snowball-cli verify --chain <chain> --keys <file> --store <dir> --proof <file>
+//! snowball-cli inputs --chain <chain> --keys <file> --bitmask <bits>
+//! ```
+//!
+//! The keys are a compressed `Vec` of affine points, the bitmask is a string of `0`s and `1`s, one per key,
+//! and the proof is a compressed `ProofEnvelope`. The seed is the generator of the curve of the keys.
+//! `setup` stores the keys of the circuit, that `prove` and `verify` fail without.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use ark_ec::AffineRepr;
+use ark_ec::short_weierstrass::Affine;
+use ark_groth16::Groth16;
+use ark_r1cs_std::fields::FieldOpsBounds;
+use rand::rngs::OsRng;
+
+use snowball::prelude::*;
+use snowball::chain::KeyBaseField;
+use snowball::serialization::{from_bytes, to_bytes};
+
+const USAGE: &str = "usage: snowball-cli <setup|prove|verify|inputs> --chain <chain> --keys <file> \
+[--bitmask <bits>] [--store <dir>] [--out <file>] [--proof <file>]";
+
+struct Command {
+    name: String,
+    args: HashMap<String, String>,
+}
+
+impl Command {
+    fn parse(mut args: impl Iterator<Item=String>) -> Result<Self, String> {
+        let name = args.next().filter(|name| ["setup", "prove", "verify", "inputs"].contains(&name.as_str())).ok_or(USAGE)?;
+        let mut parsed = HashMap::new();
+        while let Some(flag) = args.next() {
+            let key = flag.strip_prefix("--").ok_or_else(|| format!("unexpected argument '{}'", flag))?;
+            let value = args.next().ok_or_else(|| format!("missing value of '{}'", flag))?;
+            parsed.insert(key.to_string(), value);
+        }
+        Ok(Self { name, args: parsed })
+    }
+
+    fn arg(&self, key: &str) -> Result<&str, String> {
+        self.args.get(key).map(String::as_str).ok_or_else(|| format!("'{}' needs --{}", self.name, key))
+    }
+
+    fn read(&self, key: &str) -> Result<Vec<u8>, String> {
+        let path = self.arg(key)?;
+        fs::read(path).map_err(|e| format!("can't read {}: {}", path, e))
+    }
+
+    fn bitmask(&self) -> Result<Bitmask, String> {
+        self.arg("bitmask")?.chars()
+            .map(|c| match c {
+                '0' => Ok(false),
+                '1' => Ok(true),
+                _ => Err(format!("invalid bit '{}'", c)),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Bitmask::from_bits)
+    }
+
+    fn run<C: Chain>(&self) -> Result<(), String>
+        where for<'a> &'a C::FieldVar: FieldOpsBounds<'a, KeyBaseField<C>, C::FieldVar>
+    {
+        type Backend<C> = Groth16<<C as Chain>::Pairing>;
+        let err = |e: SnowballError| e.to_string();
+        let keys: Vec<Affine<C::Curve>> = from_bytes(&self.read("keys")?, SerializationMode::Compressed).map_err(err)?;
+        let seed = Affine::<C::Curve>::generator();
+        let store = || self.arg("store").map(KeyStore::new);
+
+        match self.name.as_str() {
+            "setup" => {
+                let circuit = ChainCircuit::<C>::new(keys.clone(), seed, &Bitmask::from_bits(vec![true; keys.len()])).map_err(err)?;
+                let id = CircuitId::of_circuit::<C, Backend<C>>(&circuit);
+                store()?.load_or_setup::<C, Backend<C>, _>(circuit, &mut OsRng).map_err(err)?;
+                println!("keys for {} stored", id);
+            }
+            "prove" => {
+                let bitmask = self.bitmask()?;
+                let circuit = ChainCircuit::<C>::new(keys.clone(), seed, &bitmask).map_err(err)?;
+                let id = CircuitId::of_circuit::<C, Backend<C>>(&circuit);
+                // The keys are `setup`'s to create, a fresh setup here would be one the verifier knows nothing of.
+                let (pk, _) = store()?.load::<C, Backend<C>>(&id).map_err(err)?
+                    .ok_or_else(|| format!("no keys for {}, run setup first", id))?;
+                let prover = Prover::<C, Backend<C>>::new(pk);
+                let proof = prover.prove(circuit, &mut OsRng).map_err(err)?;
+                let envelope = ProofEnvelope::new::<C, Backend<C>>(id, &proof, PublicData { bitmask }).map_err(err)?;
+                let out = PathBuf::from(self.arg("out")?);
+                fs::write(&out, to_bytes(&envelope, SerializationMode::Compressed).map_err(err)?)
+                    .map_err(|e| format!("can't write {}: {}", out.display(), e))?;
+            }
+            "verify" => {
+                let envelope: ProofEnvelope = from_bytes(&self.read("proof")?, SerializationMode::Compressed).map_err(err)?;
+                let id = CircuitId::new::<C, Backend<C>>(keys.len(), &seed, &Config::default());
+                let (_, vk) = store()?.load::<C, Backend<C>>(&id).map_err(err)?.ok_or_else(|| format!("no keys for {}", id))?;
+                let verifier = Verifier::<C, Backend<C>>::new(&vk).map_err(err)?.with_circuit_id(id);
+                if !verifier.verify_envelope(&keys, &envelope).map_err(err)? {
+                    return Err("invalid proof".to_string());
+                }
+                println!("valid proof, {} of {} keys", envelope.public_data.bitmask.count_ones(), keys.len());
+            }
+            "inputs" => {
+                let pi = public_inputs::<C::Curve, C::ConstraintField, C::FieldVar>(&keys, &self.bitmask()?, &Config::default()).map_err(err)?;
+                for x in pi {
+                    println!("{}", x);
+                }
+            }
+            _ => unreachable!("checked by `parse`"),
+        }
+        Ok(())
+    }
+}
+
+impl ChainVisitor for &Command {
+    type Output = Result<(), String>;
+
+    fn visit<C: Chain>(self) -> Self::Output
+        where for<'a> &'a C::FieldVar: FieldOpsBounds<'a, KeyBaseField<C>, C::FieldVar>
+    {
+        self.run::<C>()
+    }
+}
+
+fn main() -> ExitCode {
+    let res = Command::parse(std::env::args().skip(1))
+        .and_then(|command| command.arg("chain")?.parse::<AnyChain>()?.dispatch(&command));
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
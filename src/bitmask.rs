@@ -0,0 +1,125 @@
+use ark_ff::PrimeField;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+use crate::error::SnowballError;
+
+/// Host-side participation bitmask: bit `i` is set iff the `i`-th key of the keyset participates.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Zeroize, ZeroizeOnDrop, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bitmask {
+    bits: Vec<bool>,
+}
+
+impl Bitmask {
+    pub fn from_bits(bits: Vec<bool>) -> Self {
+        Self { bits }
+    }
+
+    pub fn bits(&self) -> &[bool] {
+        &self.bits
+    }
+
+    pub fn len(&self) -> usize {
+        self.bits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.is_empty()
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.bits.iter().filter(|b| **b).count()
+    }
+
+    /// The indices of the set bits, in increasing order, as `SignerIndicesVar` takes them.
+    pub fn signers(&self) -> Vec<usize> {
+        self.bits.iter().enumerate().filter(|(_, b)| **b).map(|(i, _)| i).collect()
+    }
+
+    /// Packs the bits little-endian into a single field element,
+    /// the way `Boolean::le_bits_to_fp_var` does it in-circuit.
+    pub fn pack<F: PrimeField>(&self) -> Result<F, SnowballError> {
+        let capacity = (F::MODULUS_BIT_SIZE - 1) as usize;
+        if self.bits.len() > capacity {
+            return Err(SnowballError::BitmaskTooLong { len: self.bits.len(), capacity });
+        }
+        let bytes: Vec<u8> = self.bits.chunks(8)
+            .map(|c| c.iter().enumerate().fold(0u8, |byte, (i, &bit)| byte | ((bit as u8) << i)))
+            .collect();
+        Ok(F::from_le_bytes_mod_order(&bytes))
+    }
+}
+
+impl From<Vec<bool>> for Bitmask {
+    fn from(bits: Vec<bool>) -> Self {
+        Self::from_bits(bits)
+    }
+}
+
+// Substrate-based consensus engines emit `BitVec<u8, Lsb0>`: bit `i` of the vector is bit `i % 8` of byte `i / 8`,
+// counting from the least significant one. Indices are preserved, so they match `to_bits_le` of the packed bitmask.
+#[cfg(feature = "bitvec")]
+impl From<&bitvec::vec::BitVec<u8, bitvec::order::Lsb0>> for Bitmask {
+    fn from(bv: &bitvec::vec::BitVec<u8, bitvec::order::Lsb0>) -> Self {
+        Self::from_bits(bv.iter().by_vals().collect())
+    }
+}
+
+#[cfg(feature = "bitvec")]
+impl From<&Bitmask> for bitvec::vec::BitVec<u8, bitvec::order::Lsb0> {
+    fn from(bitmask: &Bitmask) -> Self {
+        bitmask.bits.iter().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_r1cs_std::alloc::AllocVar;
+    use ark_r1cs_std::boolean::Boolean;
+    use ark_r1cs_std::R1CSVar;
+    use ark_relations::r1cs::ConstraintSystem;
+    use ark_std::{test_rng, UniformRand};
+
+    use super::*;
+
+    #[test]
+    fn test_pack() {
+        let rng = &mut test_rng();
+        let n = 100;
+        let bits: Vec<bool> = (0..n).map(|_| bool::rand(rng)).collect();
+
+        let cs = ConstraintSystem::<ark_bls12_381::Fr>::new_ref();
+        let bit_vars = Vec::<Boolean<ark_bls12_381::Fr>>::new_constant(cs, bits.clone()).unwrap();
+        let packed_var = Boolean::le_bits_to_fp_var(&bit_vars).unwrap();
+
+        let bitmask = Bitmask::from_bits(bits);
+        assert_eq!(bitmask.pack::<ark_bls12_381::Fr>().unwrap(), packed_var.value().unwrap());
+    }
+
+    #[test]
+    fn test_pack_too_long() {
+        let bitmask = Bitmask::from_bits(vec![true; 255]);
+        assert!(matches!(bitmask.pack::<ark_bls12_381::Fr>(), Err(SnowballError::BitmaskTooLong { len: 255, capacity: 254 })));
+    }
+
+    #[cfg(feature = "bitvec")]
+    #[test]
+    fn test_bitvec() {
+        use bitvec::prelude::*;
+
+        let bv = bitvec![u8, Lsb0; 1, 0, 1, 1, 0, 0, 0, 0, 1];
+        assert_eq!(bv.as_raw_slice(), &[0b0000_1101, 0b0000_0001]);
+        let bitmask = Bitmask::from(&bv);
+        assert_eq!(bitmask.pack::<ark_bls12_381::Fr>().unwrap(), ark_bls12_381::Fr::from(0b1_0000_1101u64));
+        assert_eq!(BitVec::<u8, Lsb0>::from(&bitmask), bv);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let bitmask = Bitmask::from_bits(vec![true, false, true]);
+        let json = serde_json::to_string(&bitmask).unwrap();
+        assert_eq!(serde_json::from_str::<Bitmask>(&json).unwrap(), bitmask);
+    }
+}
@@ -0,0 +1,83 @@
+//! BLS12-381 keys and signatures in the Zcash compressed encoding, the one used by the IETF BLS signature draft
+//! and Ethereum: big-endian `x` with the compression, infinity and sign-of-`y` flags in the 3 top bits.
+//! Public keys are in G1 and signatures in G2 (the "minimal-pubkey-size" variant).
+
+use ark_bls12_381::{G1Affine, G2Affine};
+use ark_ec::AffineRepr;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+
+use crate::error::SnowballError;
+
+pub const PUBLIC_KEY_SIZE: usize = 48;
+pub const SIGNATURE_SIZE: usize = 96;
+
+/// Parses a compressed public key, checking it's a non-identity point of the prime-order subgroup (`KeyValidate` of the draft).
+pub fn public_key_from_bytes(bytes: &[u8; PUBLIC_KEY_SIZE]) -> Result<G1Affine, SnowballError> {
+    let pk = G1Affine::deserialize_compressed(&bytes[..])?;
+    if pk.is_zero() {
+        return Err(SnowballError::InvalidKey);
+    }
+    Ok(pk)
+}
+
+pub fn public_key_to_bytes(pk: &G1Affine) -> [u8; PUBLIC_KEY_SIZE] {
+    let mut bytes = [0u8; PUBLIC_KEY_SIZE];
+    pk.serialize_compressed(&mut bytes[..]).expect("the buffer is of the compressed size");
+    bytes
+}
+
+/// Parses a compressed signature, checking it's a point of the prime-order subgroup.
+pub fn signature_from_bytes(bytes: &[u8; SIGNATURE_SIZE]) -> Result<G2Affine, SnowballError> {
+    Ok(G2Affine::deserialize_compressed(&bytes[..])?)
+}
+
+pub fn signature_to_bytes(sig: &G2Affine) -> [u8; SIGNATURE_SIZE] {
+    let mut bytes = [0u8; SIGNATURE_SIZE];
+    sig.serialize_compressed(&mut bytes[..]).expect("the buffer is of the compressed size");
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_std::{test_rng, UniformRand};
+
+    use super::*;
+
+    fn from_hex<const N: usize>(hex: &str) -> [u8; N] {
+        let mut bytes = [0u8; N];
+        for (i, b) in bytes.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_generator_encoding() {
+        let g1 = from_hex::<48>("97f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb");
+        assert_eq!(public_key_from_bytes(&g1).unwrap(), G1Affine::generator());
+        assert_eq!(public_key_to_bytes(&G1Affine::generator()), g1);
+        let g2 = from_hex::<96>("93e02b6052719f607dacd3a088274f65596bd0d09920b61ab5da61bbdc7f5049334cf11213945d57e5ac7d055d042b7e024aa2b2f08f0a91260805272dc51051c6e47ad4fa403b02b4510b647ae3d1770bac0326a805bbefd48056c8c121bdb8");
+        assert_eq!(signature_from_bytes(&g2).unwrap(), G2Affine::generator());
+    }
+
+    #[test]
+    fn test_validation() {
+        let mut identity = [0u8; 48];
+        identity[0] = 0xc0;
+        assert!(matches!(public_key_from_bytes(&identity), Err(SnowballError::InvalidKey)));
+
+        let rng = &mut test_rng();
+        let pk = G1Affine::rand(rng);
+        assert_eq!(public_key_from_bytes(&public_key_to_bytes(&pk)).unwrap(), pk);
+        let sig = G2Affine::rand(rng);
+        assert_eq!(signature_from_bytes(&signature_to_bytes(&sig)).unwrap(), sig);
+
+        // A point on the curve, but out of the prime-order subgroup.
+        let x = ark_bls12_381::Fq::from(4u8);
+        let p = G1Affine::get_point_from_x_unchecked(x, false).unwrap();
+        assert!(!p.is_in_correct_subgroup_assuming_on_curve());
+        let mut bytes = [0u8; 48];
+        p.serialize_compressed(&mut bytes[..]).unwrap();
+        assert!(public_key_from_bytes(&bytes).is_err());
+    }
+}
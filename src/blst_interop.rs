@@ -0,0 +1,81 @@
+//! Conversions between `blst` keys and signatures and the arkworks points the circuits consume,
+//! going through the Zcash compressed encoding both libraries implement.
+
+use ark_bls12_381::{G1Affine, G2Affine};
+use blst::min_pk::{PublicKey, Signature};
+use blst::BLST_ERROR;
+
+use crate::bitmask::Bitmask;
+use crate::bls_keys::{public_key_from_bytes, public_key_to_bytes, signature_from_bytes, signature_to_bytes};
+use crate::error::SnowballError;
+
+pub fn public_key_from_blst(pk: &PublicKey) -> Result<G1Affine, SnowballError> {
+    public_key_from_bytes(&pk.compress())
+}
+
+pub fn public_key_to_blst(pk: &G1Affine) -> Result<PublicKey, SnowballError> {
+    PublicKey::key_validate(&public_key_to_bytes(pk)).map_err(|_| SnowballError::InvalidKey)
+}
+
+pub fn signature_from_blst(sig: &Signature) -> Result<G2Affine, SnowballError> {
+    signature_from_bytes(&sig.compress())
+}
+
+pub fn signature_to_blst(sig: &G2Affine) -> Result<Signature, SnowballError> {
+    Signature::sig_validate(&signature_to_bytes(sig), false).map_err(|_| SnowballError::InvalidSignature)
+}
+
+/// Checks natively that `sig` is a valid signature of `msg` by the keys selected by the bitmask,
+/// which is much cheaper than finding it out by failing to prove.
+///
+/// The keys are assumed to have a proof of possession, as `FastAggregateVerify` of the IETF draft requires.
+pub fn pre_verify(keys: &[PublicKey], bitmask: &Bitmask, sig: &Signature, msg: &[u8], dst: &[u8]) -> Result<bool, SnowballError> {
+    if keys.len() != bitmask.len() {
+        return Err(SnowballError::LengthMismatch { keys: keys.len(), bits: bitmask.len() });
+    }
+    let signers: Vec<&PublicKey> = keys.iter()
+        .zip(bitmask.bits())
+        .filter(|(_, b)| **b)
+        .map(|(k, _)| k)
+        .collect();
+    Ok(sig.fast_aggregate_verify(true, msg, dst, &signers) == BLST_ERROR::BLST_SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use blst::min_pk::SecretKey;
+
+    use crate::aggregation::aggregate;
+
+    use super::*;
+
+    const DST: &[u8] = b"BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
+    #[test]
+    fn test_pre_verify() {
+        let n = 4;
+        let sks: Vec<SecretKey> = (0..n).map(|i| SecretKey::key_gen(&[i as u8 + 1; 32], &[]).unwrap()).collect();
+        let pks: Vec<PublicKey> = sks.iter().map(|sk| sk.sk_to_pk()).collect();
+        let bitmask = Bitmask::from_bits(vec![true, false, true, true]);
+
+        let msg = b"block";
+        let sigs: Vec<Signature> = sks.iter()
+            .zip(bitmask.bits())
+            .filter(|(_, b)| **b)
+            .map(|(sk, _)| sk.sign(msg, DST, &[]))
+            .collect();
+        let sig_refs: Vec<&Signature> = sigs.iter().collect();
+        let sig = blst::min_pk::AggregateSignature::aggregate(&sig_refs, true).unwrap().to_signature();
+
+        assert!(pre_verify(&pks, &bitmask, &sig, msg, DST).unwrap());
+        assert!(!pre_verify(&pks, &Bitmask::from_bits(vec![true, true, true, true]), &sig, msg, DST).unwrap());
+
+        let keys: Vec<G1Affine> = pks.iter().map(|pk| public_key_from_blst(pk).unwrap()).collect();
+        let apk = aggregate(&keys, &bitmask).unwrap();
+        let blst_apk = blst::min_pk::AggregatePublicKey::aggregate(&[&pks[0], &pks[2], &pks[3]], false).unwrap().to_public_key();
+        assert_eq!(public_key_from_blst(&blst_apk).unwrap(), apk);
+
+        assert_eq!(public_key_to_blst(&keys[1]).unwrap(), pks[1]);
+        assert_eq!(signature_to_blst(&signature_from_blst(&sig).unwrap()).unwrap(), sig);
+    }
+}
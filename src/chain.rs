@@ -0,0 +1,221 @@
+use std::fmt;
+use std::str::FromStr;
+
+use ark_ec::pairing::Pairing;
+use ark_ec::short_weierstrass::{Affine, SWCurveConfig};
+use ark_ff::PrimeField;
+use ark_r1cs_std::fields::FieldOpsBounds;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_snark::SNARK;
+use sha2::{Digest, Sha256};
+
+use crate::apk_circuits::ApkCircuit;
+use crate::config::Config;
+use crate::fragments::LimbVar;
+use crate::sum_acc::AccumulatorField;
+
+/// Base field of the curve the keys of the chain live on.
+pub type KeyBaseField<C> = <C as Chain>::BaseField;
+
+/// The apk circuit instantiated for the chain.
+pub type ChainCircuit<C> = ApkCircuit<<C as Chain>::Curve, <C as Chain>::ConstraintField, <C as Chain>::FieldVar>;
+
+/// Pairs the curve of the keys with the SNARK-friendly curve the apk circuit is proven over.
+pub trait Chain: 'static {
+    const NAME: &'static str;
+    /// Whether the base field of the keys is emulated in the constraint field.
+    const EMULATED: bool;
+
+    type BaseField: PrimeField;
+    type Curve: SWCurveConfig<BaseField=Self::BaseField>;
+    type ConstraintField: PrimeField;
+    type FieldVar: LimbVar<KeyBaseField<Self>, Self::ConstraintField> + AccumulatorField<Self::Curve, Self::ConstraintField>;
+    type Pairing: Pairing<ScalarField=Self::ConstraintField>;
+}
+
+/// BLS12-377 keys in BW6-761, the base field of the keys being the scalar field of the proving curve.
+#[cfg(feature = "bls12-377-bw6")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Bls377InBw6;
+
+#[cfg(feature = "bls12-377-bw6")]
+impl Chain for Bls377InBw6 {
+    const NAME: &'static str = "bls12-377-bw6";
+    const EMULATED: bool = false;
+
+    type BaseField = ark_bls12_377::Fq;
+    type Curve = ark_bls12_377::g1::Config;
+    type ConstraintField = ark_bw6_761::Fr;
+    type FieldVar = ark_r1cs_std::fields::fp::FpVar<ark_bw6_761::Fr>;
+    type Pairing = ark_bw6_761::BW6_761;
+}
+
+/// BLS12-381 keys in BLS12-381 itself, with the base field emulated.
+#[cfg(feature = "bls12-381-emulated")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Bls381Emulated;
+
+#[cfg(feature = "bls12-381-emulated")]
+impl Chain for Bls381Emulated {
+    const NAME: &'static str = "bls12-381-emulated";
+    const EMULATED: bool = true;
+
+    type BaseField = ark_bls12_381::Fq;
+    type Curve = ark_bls12_381::g1::Config;
+    type ConstraintField = ark_bls12_381::Fr;
+    type FieldVar = ark_r1cs_std::fields::nonnative::NonNativeFieldVar<ark_bls12_381::Fq, ark_bls12_381::Fr>;
+    type Pairing = ark_bls12_381::Bls12_381;
+}
+
+/// Identifies an apk circuit and the backend it's proven with, that together determine its proving and verifying keys.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CircuitId {
+    pub chain: String,
+    pub num_keys: usize,
+    /// Digest of the choices of the `Config` that change the constraints, empty for those of the default config.
+    pub config: String,
+    /// Digest of the compressed seed, that the circuit takes as a constant.
+    pub seed: String,
+    /// The type name of the SNARK.
+    pub backend: String,
+}
+
+impl CircuitId {
+    pub fn new<C: Chain, S: SNARK<C::ConstraintField>>(num_keys: usize, seed: &Affine<C::Curve>, config: &Config) -> Self {
+        let shape = |c: &Config| format!("{:?}/{:?}/{:?}/{:?}", c.optimization, c.addition, c.multiplication, c.commitment);
+        let config = if shape(config) == shape(&Config::default()) { String::new() } else { short_digest(shape(config).as_bytes()) };
+        let mut seed_bytes = Vec::new();
+        seed.serialize_compressed(&mut seed_bytes).expect("serializing into a vector can't fail");
+        Self {
+            chain: C::NAME.to_string(),
+            num_keys,
+            config,
+            seed: short_digest(&seed_bytes),
+            backend: std::any::type_name::<S>().to_string(),
+        }
+    }
+
+    /// The id of the circuit proven with the backend `S`.
+    pub fn of_circuit<C: Chain, S: SNARK<C::ConstraintField>>(circuit: &ChainCircuit<C>) -> Self {
+        Self::new::<C, S>(circuit.num_keys(), &circuit.seed, circuit.config())
+    }
+}
+
+// The first 4 bytes of the SHA-256, in hex.
+fn short_digest(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)[..4].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// `chain-nN-seed-backend`, then `-config` for a config other than the default, the backend as a digest of its name,
+/// so that the id makes a file name.
+impl fmt::Display for CircuitId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-n{}-{}-{}", self.chain, self.num_keys, self.seed, short_digest(self.backend.as_bytes()))?;
+        if !self.config.is_empty() {
+            write!(f, "-{}", self.config)?;
+        }
+        Ok(())
+    }
+}
+
+/// Generic code to run against the chain selected at runtime.
+pub trait ChainVisitor {
+    type Output;
+
+    fn visit<C: Chain>(self) -> Self::Output
+        where for<'a> &'a C::FieldVar: FieldOpsBounds<'a, KeyBaseField<C>, C::FieldVar>;
+}
+
+/// Runtime counterpart of the `Chain` implementations, those of the enabled features.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AnyChain {
+    #[cfg(feature = "bls12-377-bw6")]
+    Bls377InBw6,
+    #[cfg(feature = "bls12-381-emulated")]
+    Bls381Emulated,
+}
+
+impl AnyChain {
+    pub const ALL: &'static [AnyChain] = &[
+        #[cfg(feature = "bls12-377-bw6")]
+        AnyChain::Bls377InBw6,
+        #[cfg(feature = "bls12-381-emulated")]
+        AnyChain::Bls381Emulated,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "bls12-377-bw6")]
+            AnyChain::Bls377InBw6 => Bls377InBw6::NAME,
+            #[cfg(feature = "bls12-381-emulated")]
+            AnyChain::Bls381Emulated => Bls381Emulated::NAME,
+        }
+    }
+
+    pub fn dispatch<V: ChainVisitor>(&self, visitor: V) -> V::Output {
+        // Without a chain, `visitor` has nothing to visit.
+        #[cfg(not(any(feature = "bls12-377-bw6", feature = "bls12-381-emulated")))]
+        let _ = visitor;
+        match *self {
+            #[cfg(feature = "bls12-377-bw6")]
+            AnyChain::Bls377InBw6 => visitor.visit::<Bls377InBw6>(),
+            #[cfg(feature = "bls12-381-emulated")]
+            AnyChain::Bls381Emulated => visitor.visit::<Bls381Emulated>(),
+        }
+    }
+}
+
+impl fmt::Display for AnyChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for AnyChain {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        AnyChain::ALL.iter().copied()
+            .find(|c| c.name() == s)
+            .ok_or_else(|| format!("unknown chain '{}'", s))
+    }
+}
+
+#[cfg(all(test, any(feature = "bls12-377-bw6", feature = "bls12-381-emulated")))]
+mod tests {
+    use ark_ec::AffineRepr;
+    use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystem};
+    use ark_std::{test_rng, UniformRand};
+
+    use crate::bitmask::Bitmask;
+
+    use super::*;
+
+    struct IsSatisfied;
+
+    impl ChainVisitor for IsSatisfied {
+        type Output = bool;
+
+        fn visit<C: Chain>(self) -> bool
+            where for<'a> &'a C::FieldVar: FieldOpsBounds<'a, KeyBaseField<C>, C::FieldVar>
+        {
+            let rng = &mut test_rng();
+            let keys = (0..2).map(|_| ark_ec::short_weierstrass::Affine::<C::Curve>::rand(rng)).collect();
+            let seed = ark_ec::short_weierstrass::Affine::<C::Curve>::generator();
+            let circuit = ChainCircuit::<C>::new(keys, seed, &Bitmask::from_bits(vec![true, false])).unwrap();
+            let cs = ConstraintSystem::<C::ConstraintField>::new_ref();
+            circuit.generate_constraints(cs.clone()).unwrap();
+            cs.is_satisfied().unwrap()
+        }
+    }
+
+    #[test]
+    fn test_dispatch() {
+        for &chain in AnyChain::ALL {
+            assert_eq!(chain.to_string().parse::<AnyChain>().unwrap(), chain);
+            assert!(chain.dispatch(IsSatisfied));
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Churn between epochs: a circuit proving that at most `k` keys changed participation from one bitmask to the next,
+//! for protocols that rate-limit the churn of their committees.
+//!
+//! Both bitmasks are public inputs, packed as the apk circuit packs its own, so the previous one is the input
+//! of the proof of the previous epoch and the current one that of the current epoch. The bound is a constant of the circuit.
+
+use std::cmp::Ordering;
+
+use ark_ff::PrimeField;
+use ark_r1cs_std::alloc::AllocVar;
+use ark_r1cs_std::fields::FieldVar;
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError};
+
+use crate::bitmask::Bitmask;
+use crate::error::SnowballError;
+use crate::fragments::BitmaskVar;
+use crate::labels::labeled;
+use crate::profiling::phase;
+
+/// The number of keys whose bit differs between the bitmasks.
+pub fn count_changes(prev: &Bitmask, curr: &Bitmask) -> Result<usize, SnowballError> {
+    if prev.len() != curr.len() {
+        return Err(SnowballError::LengthMismatch { keys: prev.len(), bits: curr.len() });
+    }
+    Ok(prev.bits().iter().zip(curr.bits()).filter(|(a, b)| a != b).count())
+}
+
+/// Enforces at most `max_changes` of the keys to have a different bit in the bitmasks, a constraint per key for the xors.
+/// The bitmasks are over the same keys: the bits past the shorter one are ignored.
+pub fn enforce_max_changes<CF: PrimeField>(prev: &BitmaskVar<CF>, curr: &BitmaskVar<CF>, max_changes: usize) -> Result<(), SynthesisError> {
+    let changes = prev.bits().iter().zip(curr.bits())
+        .map(|(a, b)| a.xor(b))
+        .collect::<Result<Vec<_>, _>>()?;
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let count = changes.iter().fold(FpVar::zero(), |acc, c| acc + FpVar::from(c.clone()));
+    count.enforce_cmp(&FpVar::constant(CF::from(max_changes as u64)), Ordering::Less, true)
+}
+
+/// Proves that at most `max_changes` keys changed participation from the previous bitmask to the current one.
+#[derive(Clone, Debug)]
+pub struct ChurnCircuit<CF: PrimeField> {
+    prev: CF,
+    curr: CF,
+    num_keys: usize,
+    max_changes: usize,
+}
+
+impl<CF: PrimeField> ChurnCircuit<CF> {
+    pub fn new(prev: &Bitmask, curr: &Bitmask, max_changes: usize) -> Result<Self, SnowballError> {
+        count_changes(prev, curr)?;
+        Ok(Self { prev: prev.pack()?, curr: curr.pack()?, num_keys: prev.len(), max_changes })
+    }
+
+    pub fn num_keys(&self) -> usize {
+        self.num_keys
+    }
+
+    pub fn max_changes(&self) -> usize {
+        self.max_changes
+    }
+
+    /// The instance: the previous packed bitmask, then the current one.
+    pub fn public_inputs(&self) -> Vec<CF> {
+        vec![self.prev, self.curr]
+    }
+}
+
+impl<CF: PrimeField> ConstraintSynthesizer<CF> for ChurnCircuit<CF> {
+    fn generate_constraints(self, cs: ConstraintSystemRef<CF>) -> ark_relations::r1cs::Result<()> {
+        let (prev, curr) = phase(&cs, "allocation", || {
+            let bitmask = |label, packed| {
+                labeled(&cs, label, || {
+                    let bitmask = BitmaskVar::new(FpVar::new_input(cs.clone(), || Ok(packed))?, self.num_keys)?;
+                    bitmask.enforce_unused_zero()?;
+                    Ok::<_, SynthesisError>(bitmask)
+                })
+            };
+            Ok::<_, SynthesisError>((bitmask("prev_bitmask", self.prev)?, bitmask("bitmask", self.curr)?))
+        })?;
+
+        phase(&cs, "churn", || enforce_max_changes(&prev, &curr, self.max_changes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_relations::r1cs::ConstraintSystem;
+
+    use super::*;
+
+    type Fr = ark_bw6_761::Fr;
+
+    fn is_satisfied(prev: &[bool], curr: &[bool], max_changes: usize) -> bool {
+        let circuit = ChurnCircuit::<Fr>::new(&Bitmask::from_bits(prev.to_vec()), &Bitmask::from_bits(curr.to_vec()), max_changes).unwrap();
+        let inputs = circuit.public_inputs();
+        let cs = ConstraintSystem::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+        assert_eq!(cs.borrow().unwrap().instance_assignment[1..], inputs);
+        cs.is_satisfied().unwrap()
+    }
+
+    #[test]
+    fn test_churn_circuit() {
+        let prev = [true, true, false, false, true];
+        let curr = [true, false, true, false, true];
+        assert_eq!(count_changes(&Bitmask::from_bits(prev.to_vec()), &Bitmask::from_bits(curr.to_vec())).unwrap(), 2);
+        assert!(is_satisfied(&prev, &curr, 2));
+        assert!(is_satisfied(&prev, &curr, 3));
+        assert!(!is_satisfied(&prev, &curr, 1));
+        assert!(is_satisfied(&prev, &prev, 0));
+        assert!(is_satisfied(&[], &[], 0));
+        assert!(ChurnCircuit::<Fr>::new(&Bitmask::from_bits(vec![true]), &Bitmask::from_bits(vec![]), 0).is_err());
+    }
+}
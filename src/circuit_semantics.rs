@@ -0,0 +1,270 @@
+//! Host-side replica of what the apk circuit computes, quirks included,
+//! as opposed to `aggregation` that computes what it should.
+
+use ark_ec::{AffineRepr, CurveGroup};
+use ark_ec::short_weierstrass::Affine;
+use ark_ff::{Field, PrimeField, Zero};
+use ark_r1cs_std::fields::FieldOpsBounds;
+use ark_r1cs_std::R1CSVar;
+use ark_relations::r1cs::ConstraintSystem;
+use ark_std::rand::Rng;
+use ark_std::UniformRand;
+use derivative::Derivative;
+
+use crate::aggregation;
+use crate::apk_circuits::allocate_inputs;
+use crate::bitmask::Bitmask;
+use crate::chain::{Chain, KeyBaseField};
+use crate::config::{AdditionStrategy, Config};
+use crate::error::Result;
+use crate::fragments;
+
+/// The value of the accumulator at the end of the circuit, and the number of keys added to it.
+///
+/// Replicates the circuit step by step:
+/// - the accumulator starts from `seed`, so the result is offset by it,
+/// - only the first `MODULUS_BIT_SIZE` keys are considered, as many as the packed bitmask decomposes into,
+/// - missing bits count as unset, and bits past the last key are ignored (they make the circuit unsatisfiable),
+/// - additions are incomplete: adding points with the same `x` uses `lambda = 0`, the circuit witness
+///   for `0^{-1}`, so the result may be off the curve. Adding a point to its inverse makes the circuit
+///   unsatisfiable, but adding it to itself doesn't constrain `lambda` at all: the circuit is satisfied
+///   by the wrong result, e.g. when the seed is among the selected keys,
+/// - with `AdditionStrategy::Accumulated`, the keys of unset bits take part too, by the slope through the opposite
+///   of the accumulator, with `lambda = 0` for the same `x` all the same,
+/// - with `AdditionStrategy::Complete`, the result is exact, as `fragments::aggregate` adds the complete sum to the seed.
+pub fn aggregate<C: Chain>(seed: Affine<C::Curve>, keys: &[Affine<C::Curve>], bitmask: &Bitmask, config: &Config) -> (Affine<C::Curve>, usize) {
+    let (acc, count, _) = replay::<C>(seed, keys, bitmask, config);
+    (acc, count)
+}
+
+/// Whether the circuit adds a point to itself on the way, the case its incomplete additions don't constrain,
+/// so that it's satisfied by a wrong aggregate key, see `aggregate`. Such a case is a known divergence.
+pub fn adds_to_itself<C: Chain>(seed: Affine<C::Curve>, keys: &[Affine<C::Curve>], bitmask: &Bitmask, config: &Config) -> bool {
+    replay::<C>(seed, keys, bitmask, config).2
+}
+
+// `aggregate`, and whether a point was added to itself.
+fn replay<C: Chain>(seed: Affine<C::Curve>, keys: &[Affine<C::Curve>], bitmask: &Bitmask, config: &Config) -> (Affine<C::Curve>, usize, bool) {
+    let capacity = C::ConstraintField::MODULUS_BIT_SIZE as usize;
+    let selected: Vec<(bool, &Affine<C::Curve>)> = keys.iter()
+        .take(capacity)
+        .enumerate()
+        .map(|(i, key)| (bitmask.bits().get(i).copied().unwrap_or(false), key))
+        .collect();
+    let mut acc = (seed.x, seed.y);
+    let mut doubled = false;
+    let mut add = |p1: (C::BaseField, C::BaseField), p2: (C::BaseField, C::BaseField)| {
+        doubled |= p1 == p2;
+        add_incomplete::<C>(p1, p2)
+    };
+    match config.addition {
+        AdditionStrategy::Incomplete => {
+            for (_, key) in selected.iter().filter(|(b, _)| *b) {
+                acc = add(acc, (key.x, key.y));
+            }
+        }
+        AdditionStrategy::Accumulated => {
+            // `SumAccumulator`, the sum being `(x3, lambda * (x1 - x3) - y1)`.
+            let (mut x1, mut y1, mut lambda, mut x3) = (seed.x, -seed.y, C::BaseField::zero(), seed.x);
+            for (b, key) in &selected {
+                let y3 = lambda * (x1 - x3) - y1;
+                let y3 = if *b { y3 } else { -y3 };
+                doubled |= (key.x, key.y) == (x3, y3);
+                lambda = (key.y - y3) * (key.x - x3).inverse().unwrap_or(C::BaseField::zero());
+                if *b {
+                    x3 = lambda.square() - x3 - key.x;
+                }
+                (x1, y1) = (key.x, key.y);
+            }
+            acc = (x3, lambda * (x1 - x3) - y1);
+        }
+        AdditionStrategy::Complete => {
+            let sum = selected.iter().filter(|(b, _)| *b).fold(seed.into_group(), |sum, (_, key)| sum + *key).into_affine();
+            acc = (sum.x, sum.y);
+        }
+    }
+    let count = selected.iter().filter(|(b, _)| *b).count();
+    (Affine::new_unchecked(acc.0, acc.1), count, doubled)
+}
+
+// `NonZeroAffineVarGeneric::add_unchecked`.
+fn add_incomplete<C: Chain>((x1, y1): (C::BaseField, C::BaseField), (x2, y2): (C::BaseField, C::BaseField)) -> (C::BaseField, C::BaseField) {
+    let lambda = (y2 - y1) * (x2 - x1).inverse().unwrap_or(C::BaseField::zero());
+    let x3 = lambda.square() - x1 - x2;
+    (x3, lambda * (x1 - x3) - y1)
+}
+
+/// A case on which the circuit disagrees with the host.
+#[derive(Derivative)]
+#[derivative(Clone, Debug)]
+pub struct Divergence<C: Chain> {
+    pub case: usize,
+    pub seed: Affine<C::Curve>,
+    pub keys: Vec<Affine<C::Curve>>,
+    pub bitmask: Bitmask,
+    /// The value of the accumulator in the circuit; may be off the curve.
+    pub circuit: Affine<C::Curve>,
+    /// `circuit_semantics::aggregate`, that the circuit value must match.
+    pub replica: Affine<C::Curve>,
+    /// `seed + aggregation::aggregate`, that the circuit value must match whenever the constraints are satisfied.
+    pub expected: Affine<C::Curve>,
+    pub satisfied: bool,
+}
+
+/// Synthesizes the circuit on `n_cases` random inputs of `num_keys` keys, and returns the first case on which
+/// it diverges from the host: either its value differs from the replica, or it's satisfied by a wrong aggregate key.
+///
+/// Besides random keysets, the cases cycle through adversarial ones: repeated keys, mutually inverse keys,
+/// the seed and its inverse among the keys, and full and empty bitmasks.
+pub fn consistency_check<C: Chain, R: Rng>(config: &Config, num_keys: usize, rng: &mut R, n_cases: usize) -> Result<Option<Divergence<C>>>
+    where for<'a> &'a C::FieldVar: FieldOpsBounds<'a, KeyBaseField<C>, C::FieldVar>
+{
+    for case in 0..n_cases {
+        let seed = Affine::<C::Curve>::rand(rng);
+        let mut keys: Vec<Affine<C::Curve>> = (0..num_keys).map(|_| Affine::rand(rng)).collect();
+        let mut bits: Vec<bool> = (0..num_keys).map(|_| bool::rand(rng)).collect();
+        if num_keys >= 2 {
+            match case % 7 {
+                1 => keys[1] = keys[0],
+                2 => keys[1] = -keys[0],
+                3 => keys[0] = seed,
+                4 => keys[0] = -seed,
+                5 => bits = vec![true; num_keys],
+                6 => bits = vec![false; num_keys],
+                _ => {}
+            }
+        }
+        let bitmask = Bitmask::from_bits(bits);
+        if let Some(divergence) = check_case::<C>(seed, keys, bitmask, config)? {
+            return Ok(Some(Divergence { case, ..divergence }));
+        }
+    }
+    Ok(None)
+}
+
+/// Synthesizes the circuit on the given inputs, and returns them as a `Divergence` numbered `0` if the circuit
+/// disagrees with the host on them, as `consistency_check` does.
+pub fn check_case<C: Chain>(seed: Affine<C::Curve>, keys: Vec<Affine<C::Curve>>, bitmask: Bitmask, config: &Config) -> Result<Option<Divergence<C>>>
+    where for<'a> &'a C::FieldVar: FieldOpsBounds<'a, KeyBaseField<C>, C::FieldVar>
+{
+    let (circuit, satisfied) = synthesize::<C>(seed, &keys, &bitmask, config)?;
+    let (replica, _) = aggregate::<C>(seed, &keys, &bitmask, config);
+    let expected = (aggregation::aggregate(&keys, &bitmask)? + seed).into_affine();
+    if circuit != replica || (satisfied && circuit != expected) {
+        return Ok(Some(Divergence { case: 0, seed, keys, bitmask, circuit, replica, expected, satisfied }));
+    }
+    Ok(None)
+}
+
+// The final accumulator of the circuit, and whether the constraints are satisfied.
+fn synthesize<C: Chain>(seed: Affine<C::Curve>, keys: &[Affine<C::Curve>], bitmask: &Bitmask, config: &Config) -> Result<(Affine<C::Curve>, bool)>
+    where for<'a> &'a C::FieldVar: FieldOpsBounds<'a, KeyBaseField<C>, C::FieldVar>
+{
+    let cs = ConstraintSystem::<C::ConstraintField>::new_ref();
+    config.apply(&cs);
+    let seed = fragments::constant_point::<C::Curve, _, C::FieldVar>(cs.clone(), seed)?;
+    let (key_vars, bitmask_var) = allocate_inputs::<C::Curve, _, C::FieldVar>(cs.clone(), keys.to_vec(), bitmask.pack()?, config)?;
+    bitmask_var.enforce_unused_zero()?;
+    let apk = fragments::aggregate(seed, &key_vars, bitmask_var.bits(), config)?;
+    Ok((Affine::new_unchecked(apk.x.value()?, apk.y.value()?), cs.is_satisfied()?))
+}
+
+#[cfg(all(test, feature = "bls12-377-bw6"))]
+mod tests {
+    use ark_std::test_rng;
+
+    use crate::chain::Bls377InBw6;
+    #[cfg(feature = "bls12-381-emulated")]
+    use crate::chain::Bls381Emulated;
+
+    use super::*;
+
+    fn check<C: Chain>()
+        where for<'a> &'a C::FieldVar: FieldOpsBounds<'a, KeyBaseField<C>, C::FieldVar>
+    {
+        let rng = &mut test_rng();
+        let n = 5;
+        let seed = Affine::<C::Curve>::rand(rng);
+        let mut keys: Vec<Affine<C::Curve>> = (0..n).map(|_| Affine::rand(rng)).collect();
+        for _ in 0..3 {
+            let bitmask = Bitmask::from_bits((0..n).map(|_| bool::rand(rng)).collect());
+            let (apk, count) = aggregate::<C>(seed, &keys, &bitmask, &Config::default());
+            assert_eq!(apk, synthesize::<C>(seed, &keys, &bitmask, &Config::default()).unwrap().0);
+            assert_eq!(count, bitmask.count_ones());
+            assert_eq!(apk, (aggregation::aggregate(&keys, &bitmask).unwrap() + seed).into_affine());
+            assert!(!adds_to_itself::<C>(seed, &keys, &bitmask, &Config::default()));
+        }
+
+        // The seed among the selected keys is added to itself: the circuit is satisfied by a wrong key.
+        let bitmask = Bitmask::from_bits(vec![true, false, false, false, false]);
+        assert!(adds_to_itself::<C>(keys[0], &keys, &bitmask, &Config::default()));
+        let (circuit, satisfied) = synthesize::<C>(keys[0], &keys, &bitmask, &Config::default()).unwrap();
+        assert!(satisfied);
+        assert_ne!(circuit, (keys[0] + keys[0]).into_affine());
+
+        // A repeated key makes the second addition hit the incomplete formula.
+        keys[1] = keys[0];
+        let bitmask = Bitmask::from_bits(vec![true, true, false, false, false]);
+        let (apk, _) = aggregate::<C>(-keys[0], &keys, &bitmask, &Config::default());
+        assert_eq!(apk, synthesize::<C>(-keys[0], &keys, &bitmask, &Config::default()).unwrap().0);
+        assert_ne!(apk, (aggregation::aggregate(&keys, &bitmask).unwrap() - keys[0]).into_affine());
+    }
+
+    #[test]
+    fn test_matches_circuit() {
+        check::<Bls377InBw6>();
+        #[cfg(feature = "bls12-381-emulated")]
+        check::<Bls381Emulated>();
+    }
+
+    #[test]
+    fn test_complete() {
+        let rng = &mut test_rng();
+        let config = Config { addition: AdditionStrategy::Complete, ..Default::default() };
+        assert!(consistency_check::<Bls377InBw6, _>(&config, 5, rng, 7).unwrap().is_none());
+        // The seed among the selected keys, that the incomplete additions get wrong.
+        let keys: Vec<_> = (0..3).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+        let bitmask = Bitmask::from_bits(vec![true, false, true]);
+        assert!(!adds_to_itself::<Bls377InBw6>(keys[0], &keys, &bitmask, &config));
+        assert!(check_case::<Bls377InBw6>(keys[0], keys, bitmask, &config).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_accumulated() {
+        let rng = &mut test_rng();
+        let config = Config { addition: AdditionStrategy::Accumulated, ..Default::default() };
+        let n = 5;
+        let seed = ark_bls12_377::G1Affine::rand(rng);
+        let mut keys: Vec<_> = (0..n).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+        for _ in 0..3 {
+            let bitmask = Bitmask::from_bits((0..n).map(|_| bool::rand(rng)).collect());
+            assert!(check_case::<Bls377InBw6>(seed, keys.clone(), bitmask, &config).unwrap().is_none());
+        }
+
+        // The seed as the key of an unset bit can't be added to the accumulator, but its opposite can.
+        let bitmask = Bitmask::from_bits(vec![false, true, true, false, true]);
+        keys[0] = seed;
+        let (apk, satisfied) = synthesize::<Bls377InBw6>(seed, &keys, &bitmask, &config).unwrap();
+        assert!(!satisfied);
+        assert_eq!(apk, aggregate::<Bls377InBw6>(seed, &keys, &bitmask, &config).0);
+        keys[0] = -seed;
+        let (apk, satisfied) = synthesize::<Bls377InBw6>(seed, &keys, &bitmask, &config).unwrap();
+        assert!(satisfied);
+        assert_eq!(apk, (aggregation::aggregate(&keys, &bitmask).unwrap() + seed).into_affine());
+
+        #[cfg(feature = "bls12-381-emulated")]
+        {
+            let keys: Vec<_> = (0..3).map(|_| ark_bls12_381::G1Affine::rand(rng)).collect();
+            let bitmask = Bitmask::from_bits(vec![true, false, true]);
+            assert!(check_case::<Bls381Emulated>(ark_bls12_381::G1Affine::rand(rng), keys, bitmask, &config).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_consistency_check() {
+        let rng = &mut test_rng();
+        assert!(consistency_check::<Bls377InBw6, _>(&Config::default(), 4, rng, 7).unwrap().is_none());
+        #[cfg(feature = "bls12-381-emulated")]
+        assert!(consistency_check::<Bls381Emulated, _>(&Config::default(), 4, rng, 7).unwrap().is_none());
+    }
+}
@@ -0,0 +1,187 @@
+//! Two-chain composition: a Groth16 proof over BLS12-377 of any circuit over its scalar field, verified in a Groth16
+//! proof over BW6-761, whose scalar field is the base field of BLS12-377, so that the pairings are native arithmetic.
+//!
+//! The outer proof is the one verifiers check, with the inputs of the inner proof as its instance,
+//! their bits packed into as few elements of the larger outer field as they fit into: `outer_inputs`.
+//! The inner verifying key is a constant of the outer circuit, so the outer keys are specific to the inner ones.
+
+use std::marker::PhantomData;
+
+use ark_bls12_377::{Bls12_377, Fq, Fr};
+use ark_bw6_761::BW6_761;
+use ark_crypto_primitives::snark::{BooleanInputVar, SNARKGadget};
+use ark_ff::{BigInteger, PrimeField};
+use ark_groth16::constraints::{Groth16VerifierGadget, ProofVar, VerifyingKeyVar};
+use ark_groth16::{Groth16, PreparedVerifyingKey, Proof, ProvingKey, VerifyingKey};
+use ark_r1cs_std::alloc::AllocVar;
+use ark_r1cs_std::boolean::Boolean;
+use ark_r1cs_std::eq::EqGadget;
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError};
+use ark_snark::SNARK;
+use rand::{CryptoRng, RngCore};
+
+use crate::error::{Result, SnowballError};
+use crate::labels::labeled;
+use crate::profiling::{host_phase, phase};
+use crate::prover::snark_error;
+use crate::witness::extract_assignment;
+
+type InnerVerifier = Groth16VerifierGadget<Bls12_377, ark_bls12_377::constraints::PairingVar>;
+
+// The bits of the inner inputs, each `Fr::MODULUS_BIT_SIZE` wide, little-endian.
+fn input_bits(inner_inputs: &[Fr]) -> Vec<bool> {
+    inner_inputs.iter()
+        .flat_map(|x| x.into_bigint().to_bits_le().into_iter().take(Fr::MODULUS_BIT_SIZE as usize))
+        .collect()
+}
+
+/// The instance of the outer proof: the bits of the inner inputs, packed little-endian
+/// into elements of `Fq::MODULUS_BIT_SIZE - 1` bits, as `Boolean::le_bits_to_fp_var` packs them.
+pub fn outer_inputs(inner_inputs: &[Fr]) -> Vec<Fq> {
+    input_bits(inner_inputs)
+        .chunks((Fq::MODULUS_BIT_SIZE - 1) as usize)
+        .map(|chunk| Fq::from_bigint(<Fq as PrimeField>::BigInt::from_bits_le(chunk)).expect("the chunk fits"))
+        .collect()
+}
+
+/// Verifies an inner proof against the inner verifying key, with the inner inputs packed into the instance.
+pub struct OuterCircuit {
+    vk: VerifyingKey<Bls12_377>,
+    inner_inputs: Vec<Fr>,
+    proof: Proof<Bls12_377>,
+}
+
+impl OuterCircuit {
+    pub fn new(vk: VerifyingKey<Bls12_377>, inner_inputs: Vec<Fr>, proof: Proof<Bls12_377>) -> Result<Self> {
+        let expected = vk.gamma_abc_g1.len() - 1;
+        if inner_inputs.len() != expected {
+            return Err(SnowballError::InstanceLength { expected, len: inner_inputs.len() });
+        }
+        Ok(Self { vk, inner_inputs, proof })
+    }
+
+    // An outer circuit of the right shape, for the setup, that doesn't look at the values.
+    fn blank(vk: VerifyingKey<Bls12_377>) -> Self {
+        let inner_inputs = vec![Fr::from(0u64); vk.gamma_abc_g1.len() - 1];
+        Self { vk, inner_inputs, proof: Proof::default() }
+    }
+}
+
+impl ConstraintSynthesizer<Fq> for OuterCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<Fq>) -> ark_relations::r1cs::Result<()> {
+        let (input_var, proof_var) = phase(&cs, "allocation", || {
+            // The bits are witnesses, packed into the inputs with a constraint per input: the packing can't wrap around,
+            // so the bits are those of the inputs, and a constraint per bit.
+            let bits = labeled(&cs, "inner_inputs", || {
+                let bits = Vec::<Boolean<Fq>>::new_witness(cs.clone(), || Ok(input_bits(&self.inner_inputs)))?;
+                for (chunk, input) in bits.chunks((Fq::MODULUS_BIT_SIZE - 1) as usize).zip(outer_inputs(&self.inner_inputs)) {
+                    FpVar::new_input(cs.clone(), || Ok(input))?.enforce_equal(&Boolean::le_bits_to_fp_var(chunk)?)?;
+                }
+                Ok::<_, SynthesisError>(bits)
+            })?;
+            let input_var = BooleanInputVar::<Fr, Fq>::new(bits.chunks(Fr::MODULUS_BIT_SIZE as usize).map(<[_]>::to_vec).collect());
+            let proof_var = labeled(&cs, "proof", || ProofVar::new_witness(cs.clone(), || Ok(self.proof)))?;
+            Ok::<_, SynthesisError>((input_var, proof_var))
+        })?;
+
+        phase(&cs, "verification", || {
+            let vk_var = VerifyingKeyVar::new_constant(cs.clone(), self.vk)?;
+            InnerVerifier::verify(&vk_var, &input_var, &proof_var)?.enforce_equal(&Boolean::TRUE)
+        })
+    }
+}
+
+/// The keys of both proofs, to prove inner circuits of the shape they've been set up for.
+#[derive(Clone)]
+pub struct ComposedProver<C> {
+    pub inner_pk: ProvingKey<Bls12_377>,
+    pub outer_pk: ProvingKey<BW6_761>,
+    _c: PhantomData<fn() -> C>,
+}
+
+#[derive(Clone)]
+pub struct ComposedVerifier {
+    pub pvk: PreparedVerifyingKey<BW6_761>,
+}
+
+/// Runs the setups of the inner circuit and of the outer circuit verifying it.
+pub fn setup_composed<C, R>(inner: C, rng: &mut R) -> Result<(ComposedProver<C>, ComposedVerifier)>
+    where C: ConstraintSynthesizer<Fr>,
+          R: RngCore + CryptoRng,
+{
+    host_phase("setup", || {
+        let (inner_pk, inner_vk) = Groth16::<Bls12_377>::circuit_specific_setup(inner, rng).map_err(snark_error)?;
+        let (outer_pk, outer_vk) = Groth16::<BW6_761>::circuit_specific_setup(OuterCircuit::blank(inner_vk), rng).map_err(snark_error)?;
+        let pvk = Groth16::<BW6_761>::process_vk(&outer_vk).map_err(snark_error)?;
+        Ok((ComposedProver { inner_pk, outer_pk, _c: PhantomData }, ComposedVerifier { pvk }))
+    })
+}
+
+impl<C: ConstraintSynthesizer<Fr> + Clone> ComposedProver<C> {
+    /// Proves the inner circuit, then the outer one, taking the inputs of the inner proof from the synthesis of the circuit.
+    pub fn prove<R: RngCore + CryptoRng>(&self, inner: C, rng: &mut R) -> Result<Proof<BW6_761>> {
+        let inner_inputs = extract_assignment(inner.clone())?.public_inputs().to_vec();
+        let inner_proof = host_phase("inner proving", || Groth16::<Bls12_377>::prove(&self.inner_pk, inner, rng).map_err(snark_error))?;
+        let outer = OuterCircuit::new(self.inner_pk.vk.clone(), inner_inputs, inner_proof)?;
+        host_phase("outer proving", || Groth16::<BW6_761>::prove(&self.outer_pk, outer, rng).map_err(snark_error))
+    }
+}
+
+impl ComposedVerifier {
+    /// Verifies the outer proof against the inputs of the inner one.
+    pub fn verify(&self, inner_inputs: &[Fr], proof: &Proof<BW6_761>) -> Result<bool> {
+        let public_inputs = outer_inputs(inner_inputs);
+        let expected = self.pvk.vk.gamma_abc_g1.len() - 1;
+        if public_inputs.len() != expected {
+            return Err(SnowballError::InstanceLength { expected, len: public_inputs.len() });
+        }
+        host_phase("verification", || Groth16::<BW6_761>::verify_with_processed_vk(&self.pvk, &public_inputs, proof).map_err(snark_error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_relations::r1cs::ConstraintSystem;
+    use ark_std::UniformRand;
+    use rand::rngs::OsRng;
+
+    use super::*;
+
+    // `a * b = c`, all three public.
+    #[derive(Clone)]
+    struct Product(Fr, Fr);
+
+    impl ConstraintSynthesizer<Fr> for Product {
+        fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> ark_relations::r1cs::Result<()> {
+            let a = FpVar::new_input(cs.clone(), || Ok(self.0))?;
+            let b = FpVar::new_input(cs.clone(), || Ok(self.1))?;
+            let c = FpVar::new_input(cs.clone(), || Ok(self.0 * self.1))?;
+            (a * b).enforce_equal(&c)
+        }
+    }
+
+    #[test]
+    fn test_composition() {
+        let rng = &mut OsRng;
+        let (a, b) = (Fr::rand(rng), Fr::rand(rng));
+        let inner_inputs = [a, b, a * b];
+        // 10 inputs of 253 bits fit into 7 elements of 376 bits.
+        assert_eq!(outer_inputs(&[a; 10]).len(), 7);
+
+        let (prover, verifier) = setup_composed(Product(a, b), rng).unwrap();
+        let inner_proof = Groth16::<Bls12_377>::prove(&prover.inner_pk, Product(a, b), rng).unwrap();
+        let outer = |inputs: &[Fr]| {
+            let cs = ConstraintSystem::new_ref();
+            OuterCircuit::new(prover.inner_pk.vk.clone(), inputs.to_vec(), inner_proof.clone()).unwrap().generate_constraints(cs.clone()).unwrap();
+            cs.is_satisfied().unwrap()
+        };
+        assert!(outer(&inner_inputs));
+        assert!(!outer(&[a, b, a * a]));
+        assert!(OuterCircuit::new(prover.inner_pk.vk.clone(), vec![a], inner_proof).is_err());
+
+        let proof = prover.prove(Product(a, b), rng).unwrap();
+        assert!(verifier.verify(&inner_inputs, &proof).unwrap());
+        assert!(!verifier.verify(&[a, b, a * a], &proof).unwrap());
+    }
+}
@@ -0,0 +1,152 @@
+use ark_ff::Field;
+use ark_r1cs_std::fields::nonnative::params::OptimizationType;
+use ark_relations::r1cs::{ConstraintSystemRef, OptimizationGoal};
+
+use crate::labels::enable_labels;
+
+/// What the limb decomposition of emulated fields minimizes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Optimization {
+    /// The number of constraints, what matters for Groth16.
+    #[default]
+    Constraints,
+    /// The number of non-zero entries of the constraint matrices, what matters for Marlin-like backends.
+    Weight,
+}
+
+impl Optimization {
+    pub fn goal(&self) -> OptimizationGoal {
+        match self {
+            Optimization::Constraints => OptimizationGoal::Constraints,
+            Optimization::Weight => OptimizationGoal::Weight,
+        }
+    }
+}
+
+impl From<Optimization> for OptimizationType {
+    fn from(opt: Optimization) -> Self {
+        match opt {
+            Optimization::Constraints => OptimizationType::Constraints,
+            Optimization::Weight => OptimizationType::Weight,
+        }
+    }
+}
+
+/// How the accumulator adds the keys in-circuit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum AdditionStrategy {
+    /// `NonZeroAffineVarGeneric::add_unchecked`, that is unsatisfiable on points with the same `x`.
+    #[default]
+    Incomplete,
+    /// `SumAccumulator::conditional_add` from the seed, that selects the abscissa of the sum alone,
+    /// leaving its ordinate to the end. A multiplication fewer per key for emulated fields, as many constraints natively.
+    /// Besides the points with the same `x`, as `Incomplete`, unsatisfiable on an unset bit of the key equal to the sum.
+    Accumulated,
+    /// `AffineVarGeneric::add`, complete: `ApkCircuit` sums the keys from the identity rather than from the seed,
+    /// see `fragments::aggregate_complete`, so that no bitmask nor keyset makes it unsatisfiable, an empty one included.
+    /// Two equality checks and the selections of the special cases more per key than `Incomplete`.
+    Complete,
+}
+
+/// How the limbs of emulated field elements are multiplied, see `limb_mul`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum Multiplication {
+    /// As `ark-r1cs-std` does for the optimization goal.
+    #[default]
+    Schoolbook,
+    /// Karatsuba's method, fewer constraints than the schoolbook products for the same weight.
+    /// Only affects the steps of `AdditionStrategy::Accumulated` with `Optimization::Weight`.
+    Karatsuba,
+}
+
+/// How the keys and the bitmask are made known to the verifier.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum CommitmentScheme {
+    /// As they are, in the instance vector.
+    #[default]
+    PublicInputs,
+    /// In the instance vector, with the limbs of the keys packed into as few field elements as the capacity
+    /// of the constraint field allows, and range-checked as the circuit unpacks them.
+    /// A smaller instance and verifying key for the emulated chains, for the constraints of the range checks:
+    /// for BLS12-381 emulated in itself, the 64 limbs of a key take about 3 inputs, for 835 more constraints on top of
+    /// the 3609 of its aggregation with the `Constraints`-optimized limbs.
+    /// The same as `PublicInputs` for the native chains, that have a single limb per coordinate.
+    PackedLimbs,
+    /// In the instance vector, compressed: the `x` of the keys, and the signs of their `y` packed into an element
+    /// as the bitmask is, `y` being decompressed in-circuit, see `fragments::allocate_compressed_input_keys`.
+    /// Half the inputs of `PublicInputs`, plus one, for a square and the canonical decomposition of `y` per key:
+    /// for BLS12-381 emulated in itself, 32 limbs per key instead of 64, for about 5500 more constraints per key,
+    /// and about 1700 for BLS12-377 in BW6-761.
+    CompressedInputs,
+    /// The keys as witnesses, and their SHA-256 `digest::keyset_digest` in the instance vector, in two halves,
+    /// for specs that commit to keysets with SHA-256. A constant instance, for about 27000 constraints
+    /// per block of 64 bytes of the uncompressed keys, on top of their canonical decompositions into bytes.
+    #[cfg(feature = "sha256")]
+    Sha256,
+}
+
+/// Choices shared by the circuits, the host-side encodings and the prover, that have to agree with each other.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Config {
+    pub optimization: Optimization,
+    pub addition: AdditionStrategy,
+    pub multiplication: Multiplication,
+    pub commitment: CommitmentScheme,
+    /// Size of the dedicated thread pool the prover runs in, `None` to use the global rayon pool.
+    /// Has no effect without the `parallel` feature.
+    pub num_threads: Option<usize>,
+    /// Whether to record the labels of the variables and constraints, see `labels`. On by default in debug builds.
+    pub labels: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            optimization: Optimization::default(),
+            addition: AdditionStrategy::default(),
+            multiplication: Multiplication::default(),
+            commitment: CommitmentScheme::default(),
+            num_threads: None,
+            labels: cfg!(debug_assertions),
+        }
+    }
+}
+
+impl Config {
+    /// Sets the optimization goal of the constraint system, that the non-native gadgets pick their limbs by,
+    /// if nothing has been allocated in it yet. Otherwise the goal of the constraint system stays in effect.
+    /// Enables labeling if requested.
+    pub(crate) fn apply<F: Field>(&self, cs: &ConstraintSystemRef<F>) {
+        if self.labels {
+            enable_labels(cs);
+        }
+        let goal = self.optimization.goal();
+        let is_empty = cs.num_instance_variables() == 1 && cs.num_witness_variables() == 0 && cs.num_constraints() == 0;
+        if cs.optimization_goal() != goal && is_empty {
+            cs.set_optimization_goal(goal);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_relations::r1cs::ConstraintSystem;
+
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let config = Config { optimization: Optimization::Weight, ..Default::default() };
+        let cs = ConstraintSystem::<ark_bls12_381::Fr>::new_ref();
+        config.apply(&cs);
+        assert_eq!(cs.optimization_goal(), OptimizationGoal::Weight);
+    }
+}
@@ -0,0 +1,159 @@
+//! SHA-256 keyset digests and seeds, for specs that fix the hash, as eth2 does.
+//!
+//! The digest of a keyset is the SHA-256 of the coordinates of the keys, `x` then `y` of one key after the other,
+//! each as the little-endian bytes of its canonical representative, as many as the limbs of the field take.
+//! That's not a serialization of the points: those carry flags, and the BLS12-381 one is big-endian.
+//! With the `sha256` feature, `keyset_digest_var` computes it in-circuit for `CommitmentScheme::Sha256`. The seed is a constant of the circuits, so it's only derived on the host.
+
+use ark_ec::AffineRepr;
+use ark_ec::short_weierstrass::{Affine, SWCurveConfig};
+use ark_ff::PrimeField;
+use ark_serialize::CanonicalSerialize;
+use sha2::{Digest, Sha256};
+
+/// SHA-256 of the coordinates of the keys.
+pub fn keyset_digest<P: SWCurveConfig>(keys: &[Affine<P>]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for key in keys {
+        let mut bytes = Vec::with_capacity(key.uncompressed_size());
+        key.x.serialize_uncompressed(&mut bytes).expect("serializing into a vector can't fail");
+        key.y.serialize_uncompressed(&mut bytes).expect("serializing into a vector can't fail");
+        hasher.update(bytes);
+    }
+    hasher.finalize().into()
+}
+
+/// The digest as public inputs, its two halves of 16 bytes, each little-endian.
+pub fn digest_to_inputs<F: PrimeField>(digest: &[u8; 32]) -> Vec<F> {
+    digest.chunks(16).map(F::from_le_bytes_mod_order).collect()
+}
+
+/// A point of the prime-order subgroup of unknown discrete logarithm, by try-and-increment:
+/// the first `x = SHA-256(domain, i, 0) || SHA-256(domain, i, 1)` reduced modulo `p`, `i` a little-endian `u32`,
+/// that's the abscissa of a point, with the smaller `y` of the two, the cofactor cleared.
+pub fn derive_seed<P: SWCurveConfig>(domain: &[u8]) -> Affine<P>
+    where P::BaseField: PrimeField,
+{
+    (0u32..)
+        .find_map(|i| {
+            let bytes: Vec<u8> = (0u8..2).flat_map(|j| Sha256::new().chain_update(domain).chain_update(i.to_le_bytes()).chain_update([j]).finalize()).collect();
+            let x = P::BaseField::from_le_bytes_mod_order(&bytes);
+            let p = Affine::<P>::get_point_from_x_unchecked(x, false)?.clear_cofactor();
+            (!p.is_zero()).then_some(p)
+        })
+        .expect("half of the abscissae are on the curve")
+}
+
+#[cfg(feature = "sha256")]
+pub use self::gadget::*;
+
+#[cfg(feature = "sha256")]
+mod gadget {
+    use ark_crypto_primitives::crh::sha256::constraints::Sha256Gadget;
+    use ark_ec::short_weierstrass::{Affine, SWCurveConfig};
+    use ark_ff::PrimeField;
+    use ark_r1cs_std::alloc::{AllocationMode, AllocVar};
+    use ark_r1cs_std::boolean::Boolean;
+    use ark_r1cs_std::eq::EqGadget;
+    use ark_r1cs_std::fields::FieldVar;
+    use ark_r1cs_std::fields::fp::FpVar;
+    use ark_r1cs_std::uint8::UInt8;
+    use ark_r1cs_std::ToBitsGadget;
+    use ark_relations::r1cs::{ConstraintSystemRef, SynthesisError};
+
+    use crate::affine_gen::NonZeroAffineVarGeneric;
+    use crate::fragments::allocate_keys;
+    use crate::labels::labeled;
+
+    use super::{digest_to_inputs, keyset_digest};
+
+    /// `keyset_digest` of the keys. The coordinates are decomposed into their canonical bytes,
+    /// so the keys are the only ones with the digest, short of a collision.
+    pub fn keyset_digest_var<P, F, CF>(keys: &[NonZeroAffineVarGeneric<P, F, CF>]) -> Result<Vec<UInt8<CF>>, SynthesisError>
+        where P: SWCurveConfig,
+              CF: PrimeField,
+              F: FieldVar<P::BaseField, CF>,
+    {
+        let mut sha256 = Sha256Gadget::default();
+        for key in keys {
+            sha256.update(&key.x.to_bytes()?)?;
+            sha256.update(&key.y.to_bytes()?)?;
+        }
+        Ok(sha256.finalize()?.0)
+    }
+
+    /// Allocates the keys as witnesses, labeled `keys/i`, and their digest as two public inputs, labeled `keys_digest`,
+    /// enforcing the inputs to be the halves of `keyset_digest_var` of the keys.
+    pub fn allocate_digest_keys<P, CF, F>(cs: ConstraintSystemRef<CF>, keys: Vec<Affine<P>>) -> Result<Vec<NonZeroAffineVarGeneric<P, F, CF>>, SynthesisError>
+        where P: SWCurveConfig,
+              CF: PrimeField,
+              F: FieldVar<P::BaseField, CF>,
+    {
+        let digest = keyset_digest(&keys);
+        let key_vars = allocate_keys(cs.clone(), keys, AllocationMode::Witness)?;
+        labeled(&cs, "keys_digest", || {
+            let digest_var = keyset_digest_var(&key_vars)?;
+            for (half, input) in digest_var.chunks(16).zip(digest_to_inputs::<CF>(&digest)) {
+                let bits = half.to_bits_le()?;
+                FpVar::new_input(cs.clone(), || Ok(input))?.enforce_equal(&Boolean::le_bits_to_fp_var(&bits)?)?;
+            }
+            Ok(key_vars)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_ec::CurveGroup;
+    use ark_std::{test_rng, UniformRand};
+
+    use super::*;
+
+    #[test]
+    fn test_derive_seed() {
+        let seed = derive_seed::<ark_bls12_377::g1::Config>(b"snowball");
+        assert!(seed.is_on_curve() && seed.is_in_correct_subgroup_assuming_on_curve() && !seed.is_zero());
+        assert_eq!(seed, derive_seed(b"snowball"));
+        assert_ne!(seed, derive_seed(b"snowball2"));
+
+        let rng = &mut test_rng();
+        let keys: Vec<ark_bls12_381::G1Affine> = (0..3).map(|_| ark_bls12_381::G1Projective::rand(rng).into_affine()).collect();
+        assert_ne!(keyset_digest(&keys), keyset_digest(&keys[..2]));
+        assert_eq!(digest_to_inputs::<ark_bls12_381::Fr>(&[1; 32]).len(), 2);
+    }
+
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn test_keyset_digest_var() {
+        use ark_r1cs_std::fields::fp::FpVar;
+        use ark_r1cs_std::R1CSVar;
+        use ark_relations::r1cs::ConstraintSystem;
+
+        use crate::apk_circuits::public_inputs;
+        use crate::config::{CommitmentScheme, Config};
+        use crate::bitmask::Bitmask;
+
+        fn check<P, F, CF>(keys: Vec<Affine<P>>)
+            where P: SWCurveConfig,
+                  P::BaseField: PrimeField,
+                  CF: PrimeField,
+                  F: crate::fragments::LimbVar<P::BaseField, CF>,
+        {
+            let cs = ConstraintSystem::<CF>::new_ref();
+            let digest = keyset_digest(&keys);
+            let key_vars = allocate_digest_keys::<P, CF, F>(cs.clone(), keys.clone()).unwrap();
+            assert_eq!(keyset_digest_var(&key_vars).unwrap().value().unwrap(), digest);
+            assert!(cs.is_satisfied().unwrap());
+            assert_eq!(cs.borrow().unwrap().instance_assignment[1..], digest_to_inputs::<CF>(&digest));
+
+            let config = Config { commitment: CommitmentScheme::Sha256, ..Default::default() };
+            let bitmask = Bitmask::from_bits(vec![true; keys.len()]);
+            let pi = public_inputs::<P, CF, F>(&keys, &bitmask, &config).unwrap();
+            assert_eq!(pi, [digest_to_inputs::<CF>(&digest), vec![bitmask.pack().unwrap()]].concat());
+        }
+
+        let rng = &mut test_rng();
+        check::<_, FpVar<ark_bw6_761::Fr>, _>((0..2).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect());
+        check::<_, crate::tests::BlsInBls, _>(vec![ark_bls12_381::G1Affine::rand(rng)]);
+    }
+}
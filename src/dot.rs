@@ -0,0 +1,143 @@
+//! Graphviz rendering of the labels of a synthesized circuit, for auditing what it enforces.
+//!
+//! The labels that differ only by their indices are collapsed into a node, `keys/*/x` for all the `keys/i/x`,
+//! with the constraints and the variables they own, those not owned by a nested label.
+//! Solid edges go from a label to the labels nested in it, and dashed ones from the label owning a variable
+//! to the labels owning the constraints it's used in, weighted by the number of such constraints.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::ops::Range;
+
+use ark_ff::Field;
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystem, ConstraintSystemRef, SynthesisError, SynthesisMode};
+
+use crate::error::Result;
+use crate::labels::{enable_labels, labels, LabelEntry, LabelTable};
+
+#[derive(Default)]
+struct Node {
+    paths: BTreeSet<String>,
+    constraints: usize,
+    witnesses: usize,
+    instances: usize,
+}
+
+/// `keys/*/x` for `keys/17/x`.
+fn collapse(path: &str) -> String {
+    path.split('/')
+        .map(|s| if s.chars().all(|c| c.is_ascii_digit()) { "*" } else { s })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// The collapsed path of the innermost label of each item, assigning the outer labels first for the inner ones to override them.
+fn owners(table: &LabelTable, len: usize, range: impl Fn(&LabelEntry) -> &Range<usize>) -> Vec<Option<String>> {
+    let mut owners = vec![None; len];
+    for entry in table.entries().iter().rev() {
+        let path = collapse(&entry.path);
+        let r = range(entry);
+        for owner in &mut owners[r.start.min(len)..r.end.min(len)] {
+            *owner = Some(path.clone());
+        }
+    }
+    owners
+}
+
+/// Renders the labels of the constraint system, finalizing it. `None` if labeling isn't enabled,
+/// or if the constraint system doesn't construct its matrices.
+pub fn to_dot<F: Field>(cs: &ConstraintSystemRef<F>) -> Option<String> {
+    let table = labels(cs)?;
+    cs.finalize();
+    let matrices = cs.to_matrices()?;
+    let num_instances = matrices.num_instance_variables;
+
+    let mut nodes: BTreeMap<String, Node> = BTreeMap::new();
+    for entry in table.entries() {
+        let path = collapse(&entry.path);
+        // The enclosing labels, that may own nothing themselves.
+        for (i, _) in path.match_indices('/') {
+            nodes.entry(path[..i].to_string()).or_default();
+        }
+        nodes.entry(path).or_default().paths.insert(entry.path.clone());
+    }
+    let constraints = owners(&table, matrices.num_constraints, |e| &e.constraints);
+    let instances = owners(&table, num_instances, |e| &e.instances);
+    let witnesses = owners(&table, matrices.num_witness_variables, |e| &e.witnesses);
+    for owner in constraints.iter().flatten() {
+        nodes.get_mut(owner)?.constraints += 1;
+    }
+    for owner in instances.iter().flatten() {
+        nodes.get_mut(owner)?.instances += 1;
+    }
+    for owner in witnesses.iter().flatten() {
+        nodes.get_mut(owner)?.witnesses += 1;
+    }
+
+    let mut flows: BTreeMap<(&str, &str), usize> = BTreeMap::new();
+    for (i, owner) in constraints.iter().enumerate() {
+        let Some(owner) = owner else { continue };
+        let sources: BTreeSet<&str> = [&matrices.a[i], &matrices.b[i], &matrices.c[i]].into_iter()
+            .flatten()
+            .filter_map(|(_, v)| if *v < num_instances { instances[*v].as_deref() } else { witnesses[*v - num_instances].as_deref() })
+            .filter(|source| source != owner)
+            .collect();
+        for source in sources {
+            *flows.entry((source, owner)).or_default() += 1;
+        }
+    }
+
+    let ids: BTreeMap<&str, usize> = nodes.keys().enumerate().map(|(id, path)| (path.as_str(), id)).collect();
+    let mut dot = "digraph circuit {\n    node [shape=box, fontname=monospace];\n".to_string();
+    for (path, node) in &nodes {
+        let copies = if node.paths.len() > 1 { format!(" ×{}", node.paths.len()) } else { String::new() };
+        writeln!(dot, "    n{} [label=\"{}{}\\n{} constraints, {} witnesses, {} inputs\"];",
+            ids[path.as_str()], path.replace('"', "\\\""), copies, node.constraints, node.witnesses, node.instances).unwrap();
+    }
+    for path in nodes.keys() {
+        if let Some((parent, _)) = path.rsplit_once('/') {
+            writeln!(dot, "    n{} -> n{};", ids[parent], ids[path.as_str()]).unwrap();
+        }
+    }
+    for ((source, target), count) in flows {
+        writeln!(dot, "    n{} -> n{} [style=dashed, label=\"{}\"];", ids[source], ids[target], count).unwrap();
+    }
+    dot.push_str("}\n");
+    Some(dot)
+}
+
+/// Synthesizes the circuit in setup mode with labeling enabled, and renders it with `to_dot`.
+pub fn circuit_to_dot<F: Field, C: ConstraintSynthesizer<F>>(circuit: C) -> Result<String> {
+    let cs = ConstraintSystem::<F>::new_ref();
+    cs.set_mode(SynthesisMode::Setup);
+    enable_labels(&cs);
+    circuit.generate_constraints(cs.clone())?;
+    Ok(to_dot(&cs).ok_or(SynthesisError::MissingCS)?)
+}
+
+#[cfg(all(test, feature = "bls12-381-emulated"))]
+mod tests {
+    use ark_std::{test_rng, UniformRand};
+
+    use crate::bitmask::Bitmask;
+    use crate::chain::{Bls381Emulated, ChainCircuit};
+
+    use super::*;
+
+    #[test]
+    fn test_circuit_to_dot() {
+        assert_eq!(collapse("keys/17/x/3"), "keys/*/x/*");
+
+        let rng = &mut test_rng();
+        let keys = (0..3).map(|_| ark_bls12_381::G1Affine::rand(rng)).collect();
+        let circuit = ChainCircuit::<Bls381Emulated>::new(keys, ark_bls12_381::G1Affine::rand(rng), &Bitmask::from_bits(vec![true; 3])).unwrap();
+        let dot = circuit_to_dot(circuit).unwrap();
+        assert!(dot.starts_with("digraph circuit {"));
+        let keys = dot.lines().find(|l| l.contains("label=\"keys/*/x ×3\\n")).unwrap();
+        assert!(keys.ends_with(", 0 witnesses, 96 inputs\"];"));
+        // The keys flow into the additions of the accumulator.
+        let id = |line: &str| line.trim().split(' ').next().unwrap().to_string();
+        let acc = dot.lines().filter(|l| l.contains("label=\"acc/")).map(id).collect::<Vec<_>>();
+        assert!(dot.lines().any(|l| l.contains("style=dashed") && l.trim().starts_with(&format!("{} ->", id(keys))) && acc.iter().any(|a| l.contains(&format!("-> {} ", a)))));
+    }
+}
@@ -0,0 +1,198 @@
+//! Text encodings of the values users handle: keys, apks, bitmasks, keyset digests, proofs and verifying keys,
+//! so that the CLI, RPC layers and logs all print and parse them the same way.
+//!
+//! A value is encoded as its compressed canonical serialization, as `serialization::to_bytes` gives it,
+//! then as text: `0x` and lowercase hex, or standard padded base64. Decoding is strict: base64 has to be canonical,
+//! hex may only differ in the case of the digits and the missing `0x`, the points are checked to be
+//! in the prime-order subgroup, and trailing bytes are rejected.
+
+use std::fmt;
+use std::str::FromStr;
+
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+
+use crate::error::{Result, SnowballError};
+use crate::serialization::{from_bytes, SerializationMode, to_bytes};
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// The text encoding of the bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TextEncoding {
+    #[default]
+    Hex,
+    Base64,
+}
+
+impl TextEncoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TextEncoding::Hex => "hex",
+            TextEncoding::Base64 => "base64",
+        }
+    }
+
+    pub fn encode_bytes(&self, bytes: &[u8]) -> String {
+        match self {
+            TextEncoding::Hex => encode_hex(bytes),
+            TextEncoding::Base64 => encode_base64(bytes),
+        }
+    }
+
+    pub fn decode_bytes(&self, text: &str) -> Result<Vec<u8>> {
+        match self {
+            TextEncoding::Hex => decode_hex(text),
+            TextEncoding::Base64 => decode_base64(text),
+        }
+    }
+
+    /// The compressed serialization of the value, as text.
+    pub fn encode<T: CanonicalSerialize>(&self, t: &T) -> Result<String> {
+        Ok(self.encode_bytes(&to_bytes(t, SerializationMode::Compressed)?))
+    }
+
+    /// The value of the text, with full validation.
+    pub fn decode<T: CanonicalDeserialize>(&self, text: &str) -> Result<T> {
+        from_bytes(&self.decode_bytes(text)?, SerializationMode::Compressed)
+    }
+}
+
+impl fmt::Display for TextEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for TextEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        [TextEncoding::Hex, TextEncoding::Base64].into_iter()
+            .find(|e| e.name() == s)
+            .ok_or_else(|| format!("unknown encoding '{}'", s))
+    }
+}
+
+fn malformed(reason: impl Into<String>) -> SnowballError {
+    SnowballError::MalformedEncoding(reason.into())
+}
+
+/// `0x` and two lowercase hex digits per byte.
+pub fn encode_hex(bytes: &[u8]) -> String {
+    let digits: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("0x{}", digits)
+}
+
+/// Two hex digits per byte, of either case, optionally prefixed with `0x`.
+pub fn decode_hex(text: &str) -> Result<Vec<u8>> {
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+    if !digits.len().is_multiple_of(2) {
+        return Err(malformed("odd number of hex digits"));
+    }
+    digits.as_bytes().chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair).ok()
+                .filter(|pair| pair.bytes().all(|c| c.is_ascii_hexdigit()))
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| malformed("invalid hex digit"))
+        })
+        .collect()
+}
+
+/// Standard base64, padded with `=` to a multiple of 4 characters.
+pub fn encode_base64(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+/// Standard padded base64, rejecting the texts that `encode_base64` doesn't produce: no missing padding,
+/// no whitespace, and no set bits past the last byte.
+pub fn decode_base64(text: &str) -> Result<Vec<u8>> {
+    if !text.len().is_multiple_of(4) {
+        return Err(malformed("base64 length not a multiple of 4"));
+    }
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    let num_chunks = text.len() / 4;
+    for (j, chunk) in text.as_bytes().chunks(4).enumerate() {
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && j + 1 != num_chunks) {
+            return Err(malformed("misplaced base64 padding"));
+        }
+        let mut n = 0u32;
+        for (i, c) in chunk[..4 - padding].iter().enumerate() {
+            let digit = BASE64_ALPHABET.iter().position(|a| a == c).ok_or_else(|| malformed("invalid base64 character"))?;
+            n |= (digit as u32) << (18 - 6 * i);
+        }
+        let len = 3 - padding;
+        if n & ((1 << (8 * (3 - len))) - 1) != 0 {
+            return Err(malformed("non-canonical base64 padding bits"));
+        }
+        bytes.extend_from_slice(&n.to_be_bytes()[1..1 + len]);
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_groth16::Proof;
+    use ark_std::{test_rng, UniformRand};
+
+    use crate::bitmask::Bitmask;
+
+    use super::*;
+
+    #[test]
+    fn test_text_encodings() {
+        for (bytes, base64) in [(&b""[..], ""), (b"f", "Zg=="), (b"fo", "Zm8="), (b"foo", "Zm9v"), (b"foob", "Zm9vYg==")] {
+            assert_eq!(encode_base64(bytes), base64);
+            assert_eq!(decode_base64(base64).unwrap(), bytes);
+        }
+        for bad in ["Zg", "Zh==", "Zg=a", "Z===", "Zg==Zg==", "Zm9v\n", "Zm9*"] {
+            assert!(matches!(decode_base64(bad), Err(SnowballError::MalformedEncoding(_))), "{}", bad);
+        }
+        assert_eq!(encode_hex(&[0x0a, 0xff]), "0x0aff");
+        assert_eq!(decode_hex("0x0AfF").unwrap(), [0x0a, 0xff]);
+        assert_eq!(decode_hex("0aff").unwrap(), [0x0a, 0xff]);
+        for bad in ["0x0af", "0x+a", "0xgg", "0x0aé"] {
+            assert!(decode_hex(bad).is_err(), "{}", bad);
+        }
+        assert_eq!("base64".parse::<TextEncoding>().unwrap(), TextEncoding::Base64);
+        assert!("hex2".parse::<TextEncoding>().is_err());
+    }
+
+    #[test]
+    fn test_value_encodings() {
+        let rng = &mut test_rng();
+        let key = ark_bls12_381::G1Affine::rand(rng);
+        let bitmask = Bitmask::from_bits(vec![true, false, true]);
+        let proof = Proof::<ark_bw6_761::BW6_761> {
+            a: ark_bw6_761::G1Affine::rand(rng),
+            b: ark_bw6_761::G2Affine::rand(rng),
+            c: ark_bw6_761::G1Affine::rand(rng),
+        };
+        for encoding in [TextEncoding::Hex, TextEncoding::Base64] {
+            let text = encoding.encode(&key).unwrap();
+            assert_eq!(encoding.decode::<ark_bls12_381::G1Affine>(&text).unwrap(), key);
+            assert_eq!(encoding.decode::<Bitmask>(&encoding.encode(&bitmask).unwrap()).unwrap(), bitmask);
+            assert_eq!(encoding.decode::<[u8; 32]>(&encoding.encode(&[7u8; 32]).unwrap()).unwrap(), [7; 32]);
+            assert_eq!(encoding.decode::<Proof<ark_bw6_761::BW6_761>>(&encoding.encode(&proof).unwrap()).unwrap(), proof);
+            // Trailing bytes.
+            let mut bytes = encoding.decode_bytes(&text).unwrap();
+            bytes.push(0);
+            assert!(encoding.decode::<ark_bls12_381::G1Affine>(&encoding.encode_bytes(&bytes)).is_err());
+        }
+        assert_eq!(TextEncoding::Hex.encode(&key).unwrap().len(), 2 + 2 * 48);
+        assert_eq!(TextEncoding::Base64.encode(&key).unwrap().len(), 64);
+    }
+}
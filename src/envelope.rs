@@ -0,0 +1,110 @@
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_snark::SNARK;
+
+use crate::bitmask::Bitmask;
+use crate::chain::{Chain, CircuitId};
+use crate::error::SnowballError;
+use crate::serialization::{from_bytes, SerializationMode, to_bytes};
+
+/// Bumped whenever a change to the circuits or to the envelope invalidates existing proofs.
+pub const ENVELOPE_VERSION: u16 = 3;
+
+/// Public data the proof attests to, besides the keyset the verifier already knows.
+#[derive(Clone, Debug, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PublicData {
+    pub bitmask: Bitmask,
+}
+
+/// A proof with enough metadata to reject it before verification if it comes from
+/// an incompatible crate version, another chain, or another circuit shape.
+#[derive(Clone, Debug, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProofEnvelope {
+    pub version: u16,
+    pub chain_id: String,
+    pub circuit_id: CircuitId,
+    /// The compressed serialization of the proof.
+    pub proof: Vec<u8>,
+    pub public_data: PublicData,
+}
+
+impl ProofEnvelope {
+    /// Wraps a proof of the circuit `circuit_id`, see `CircuitId::of_circuit`.
+    pub fn new<C: Chain, S: SNARK<C::ConstraintField>>(circuit_id: CircuitId, proof: &S::Proof, public_data: PublicData) -> Result<Self, SnowballError> {
+        Ok(Self {
+            version: ENVELOPE_VERSION,
+            chain_id: C::NAME.to_string(),
+            circuit_id,
+            proof: to_bytes(proof, SerializationMode::Compressed)?,
+            public_data,
+        })
+    }
+
+    /// Checks the envelope is meant for the chain `C`, the backend `S` and the number of keys of the bitmask,
+    /// and for the circuit `circuit_id` if the caller knows it, then deserializes the proof.
+    pub fn open<C: Chain, S: SNARK<C::ConstraintField>>(&self, circuit_id: Option<&CircuitId>) -> Result<S::Proof, SnowballError> {
+        if self.version != ENVELOPE_VERSION {
+            return Err(SnowballError::IncompatibleEnvelope(format!("version {}, expected {}", self.version, ENVELOPE_VERSION)));
+        }
+        if self.chain_id != C::NAME || self.circuit_id.chain != C::NAME {
+            return Err(SnowballError::IncompatibleEnvelope(format!("chain {}, expected {}", self.chain_id, C::NAME)));
+        }
+        if self.circuit_id.num_keys != self.public_data.bitmask.len() {
+            return Err(SnowballError::IncompatibleEnvelope(format!("circuit {} for {} keys", self.circuit_id, self.public_data.bitmask.len())));
+        }
+        if self.circuit_id.backend != std::any::type_name::<S>() {
+            return Err(SnowballError::IncompatibleEnvelope(format!("backend {}, expected {}", self.circuit_id.backend, std::any::type_name::<S>())));
+        }
+        if let Some(circuit_id) = circuit_id.filter(|&id| *id != self.circuit_id) {
+            return Err(SnowballError::IncompatibleEnvelope(format!("circuit {}, expected {}", self.circuit_id, circuit_id)));
+        }
+        from_bytes(&self.proof, SerializationMode::Compressed)
+    }
+}
+
+#[cfg(all(test, feature = "bls12-377-bw6"))]
+mod tests {
+    use ark_ec::AffineRepr;
+    use ark_groth16::Groth16;
+    use ark_std::{test_rng, UniformRand};
+
+    use crate::chain::Bls377InBw6;
+    use crate::config::Config;
+    #[cfg(feature = "bls12-381-emulated")]
+    use crate::chain::Bls381Emulated;
+
+    use super::*;
+
+    type Bw6Groth16 = Groth16<ark_bw6_761::BW6_761>;
+
+    #[test]
+    fn test_envelope() {
+        let rng = &mut test_rng();
+        // Not a valid proof, but the envelope doesn't care.
+        let proof = ark_groth16::Proof::<ark_bw6_761::BW6_761> {
+            a: ark_bw6_761::G1Affine::rand(rng),
+            b: ark_bw6_761::G2Affine::rand(rng),
+            c: ark_bw6_761::G1Affine::rand(rng),
+        };
+        let public_data = PublicData { bitmask: Bitmask::from_bits(vec![true, false]) };
+        let id = |seed| CircuitId::new::<Bls377InBw6, Bw6Groth16>(2, &seed, &Config::default());
+        let circuit_id = id(ark_bls12_377::G1Affine::generator());
+        let envelope = ProofEnvelope::new::<Bls377InBw6, Bw6Groth16>(circuit_id.clone(), &proof, public_data).unwrap();
+
+        let bytes = to_bytes(&envelope, SerializationMode::Compressed).unwrap();
+        let envelope = from_bytes::<ProofEnvelope>(&bytes, SerializationMode::Compressed).unwrap();
+        assert_eq!(envelope.open::<Bls377InBw6, Bw6Groth16>(None).unwrap(), proof);
+        assert_eq!(envelope.open::<Bls377InBw6, Bw6Groth16>(Some(&circuit_id)).unwrap(), proof);
+        let other_seed = id(ark_bls12_377::G1Affine::rand(rng));
+        assert!(matches!(envelope.open::<Bls377InBw6, Bw6Groth16>(Some(&other_seed)), Err(SnowballError::IncompatibleEnvelope(_))));
+
+        #[cfg(feature = "bls12-381-emulated")]
+        {
+            let res = envelope.open::<Bls381Emulated, Groth16<ark_bls12_381::Bls12_381>>(None);
+            assert!(matches!(res, Err(SnowballError::IncompatibleEnvelope(_))));
+        }
+        let future = ProofEnvelope { version: ENVELOPE_VERSION + 1, ..envelope };
+        assert!(matches!(future.open::<Bls377InBw6, Bw6Groth16>(None), Err(SnowballError::IncompatibleEnvelope(_))));
+    }
+}
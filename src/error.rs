@@ -0,0 +1,100 @@
+use std::fmt;
+
+use ark_relations::r1cs::SynthesisError;
+use ark_serialize::SerializationError;
+
+use crate::profiling::Report;
+
+/// Error returned by the host-side APIs of the crate.
+///
+/// Gadgets keep returning bare `SynthesisError`, as they are meant to be called
+/// from `ConstraintSynthesizer::generate_constraints` and compose with arkworks' own gadgets.
+#[derive(Debug)]
+pub enum SnowballError {
+    Synthesis(SynthesisError),
+    Serialization(SerializationError),
+    Io(std::io::Error),
+    /// The public key is the identity.
+    InvalidKey,
+    /// The signature isn't a point of the prime-order subgroup.
+    InvalidSignature,
+    /// Stored data doesn't match its integrity hash.
+    Integrity,
+    /// The proof envelope was produced by an incompatible crate version, for another chain, or another circuit.
+    IncompatibleEnvelope(String),
+    /// Error reported by the SNARK backend.
+    Snark(Box<dyn ark_std::error::Error>),
+    /// The universal parameters are too small to index the circuit; carries the required bound.
+    BoundTooSmall(String),
+    /// The bitmask doesn't fit into a single element of the constraint field.
+    BitmaskTooLong { len: usize, capacity: usize },
+    /// The participation bitfield isn't a valid SSZ encoding.
+    MalformedBitfield(String),
+    /// The text isn't a valid hex or base64 encoding.
+    MalformedEncoding(String),
+    /// The number of keys differs from the number of bits in the bitmask.
+    LengthMismatch { keys: usize, bits: usize },
+    /// The keys can't be split into chunks of this size.
+    InvalidChunkSize(usize),
+    /// The instance vector doesn't have as many elements as the verifying key takes.
+    InstanceLength { expected: usize, len: usize },
+    /// The proof chain holds no proof for the epoch.
+    UnknownEpoch(u64),
+    /// The circuit takes more constraints than the budget; carries the phases up to the one that exceeded it.
+    BudgetExceeded { budget: usize, report: Box<Report> },
+}
+
+impl fmt::Display for SnowballError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnowballError::Synthesis(e) => write!(f, "synthesis error: {}", e),
+            SnowballError::Serialization(e) => write!(f, "serialization error: {}", e),
+            SnowballError::Io(e) => write!(f, "io error: {}", e),
+            SnowballError::InvalidKey => write!(f, "invalid public key"),
+            SnowballError::InvalidSignature => write!(f, "invalid signature"),
+            SnowballError::Integrity => write!(f, "integrity check failed"),
+            SnowballError::IncompatibleEnvelope(reason) => write!(f, "incompatible proof envelope: {}", reason),
+            SnowballError::Snark(e) => write!(f, "snark error: {}", e),
+            SnowballError::BoundTooSmall(bound) => write!(f, "universal parameters too small, required bound: {}", bound),
+            SnowballError::BitmaskTooLong { len, capacity } =>
+                write!(f, "bitmask of {} bits is longer than the field capacity of {} bits", len, capacity),
+            SnowballError::MalformedBitfield(reason) => write!(f, "malformed bitfield: {}", reason),
+            SnowballError::MalformedEncoding(reason) => write!(f, "malformed encoding: {}", reason),
+            SnowballError::LengthMismatch { keys, bits } =>
+                write!(f, "{} keys don't match {} bitmask bits", keys, bits),
+            SnowballError::InvalidChunkSize(size) => write!(f, "invalid chunk size: {}", size),
+            SnowballError::InstanceLength { expected, len } =>
+                write!(f, "the verifying key takes {} public inputs, got {}", expected, len),
+            SnowballError::UnknownEpoch(epoch) => write!(f, "no proof for epoch {}", epoch),
+            SnowballError::BudgetExceeded { budget, report } => {
+                write!(f, "{} constraints exceed the budget of {}", report.total.num_constraints, budget)?;
+                for (i, phase) in report.phases.iter().enumerate() {
+                    write!(f, "{} {}: {}", if i == 0 { "," } else { ";" }, phase.name, phase.counts.num_constraints)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnowballError {}
+
+impl From<SynthesisError> for SnowballError {
+    fn from(e: SynthesisError) -> Self {
+        SnowballError::Synthesis(e)
+    }
+}
+
+impl From<std::io::Error> for SnowballError {
+    fn from(e: std::io::Error) -> Self {
+        SnowballError::Io(e)
+    }
+}
+
+impl From<SerializationError> for SnowballError {
+    fn from(e: SerializationError) -> Self {
+        SnowballError::Serialization(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, SnowballError>;
@@ -0,0 +1,162 @@
+//! Ethereum consensus conventions, mapped onto the crate's types.
+//!
+//! Committees are keysets in committee order, duplicates included, as a validator may sit in a committee
+//! more than once. Participation comes as SSZ bitfields: a `Bitvector` for sync aggregates, and a `Bitlist`,
+//! that marks its length with an extra set bit, for attestations.
+
+use ark_bls12_381::G1Affine;
+use sha2::{Digest, Sha256};
+
+use crate::bitmask::Bitmask;
+use crate::bls_keys::{public_key_from_bytes, PUBLIC_KEY_SIZE};
+use crate::chain::{Bls381Emulated, ChainCircuit};
+use crate::error::{Result, SnowballError};
+
+pub type DomainType = [u8; 4];
+pub type Version = [u8; 4];
+pub type Root = [u8; 32];
+pub type Domain = [u8; 32];
+
+pub const DOMAIN_BEACON_ATTESTER: DomainType = [1, 0, 0, 0];
+pub const DOMAIN_SYNC_COMMITTEE: DomainType = [7, 0, 0, 0];
+
+/// Parses the committee pubkeys, keeping their order.
+pub fn committee_keys(pubkeys: &[[u8; PUBLIC_KEY_SIZE]]) -> Result<Vec<G1Affine>> {
+    pubkeys.iter().map(public_key_from_bytes).collect()
+}
+
+/// Decodes a `Bitvector[len]`, rejecting set padding bits.
+pub fn bitvector_to_bitmask(bytes: &[u8], len: usize) -> Result<Bitmask> {
+    if bytes.len() != len.div_ceil(8) {
+        return Err(SnowballError::MalformedBitfield(format!("{} bytes for a bitvector of {} bits", bytes.len(), len)));
+    }
+    let bits = bytes_to_bits(bytes);
+    if bits[len..].iter().any(|b| *b) {
+        return Err(SnowballError::MalformedBitfield("padding bits set".to_string()));
+    }
+    Ok(Bitmask::from_bits(bits[..len].to_vec()))
+}
+
+pub fn bitmask_to_bitvector(bitmask: &Bitmask) -> Vec<u8> {
+    bits_to_bytes(bitmask.bits())
+}
+
+/// Decodes a `Bitlist`, whose length is given by its highest set bit.
+pub fn bitlist_to_bitmask(bytes: &[u8]) -> Result<Bitmask> {
+    let last = match bytes.last() {
+        Some(last) if *last != 0 => *last,
+        _ => return Err(SnowballError::MalformedBitfield("missing length bit".to_string())),
+    };
+    let len = 8 * (bytes.len() - 1) + (7 - last.leading_zeros() as usize);
+    let mut bits = bytes_to_bits(bytes);
+    bits.truncate(len);
+    Ok(Bitmask::from_bits(bits))
+}
+
+pub fn bitmask_to_bitlist(bitmask: &Bitmask) -> Vec<u8> {
+    let mut bits = bitmask.bits().to_vec();
+    bits.push(true);
+    bits_to_bytes(&bits)
+}
+
+fn bytes_to_bits(bytes: &[u8]) -> Vec<bool> {
+    bytes.iter().flat_map(|byte| (0..8).map(move |i| (byte >> i) & 1 == 1)).collect()
+}
+
+fn bits_to_bytes(bits: &[bool]) -> Vec<u8> {
+    bits.chunks(8)
+        .map(|c| c.iter().enumerate().fold(0u8, |byte, (i, &bit)| byte | ((bit as u8) << i)))
+        .collect()
+}
+
+/// `hash_tree_root` of a container of two 32-byte chunks.
+fn merkleize_pair(left: &[u8; 32], right: &[u8; 32]) -> Root {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+pub fn compute_fork_data_root(current_version: Version, genesis_validators_root: Root) -> Root {
+    let mut version_chunk = [0u8; 32];
+    version_chunk[..4].copy_from_slice(&current_version);
+    merkleize_pair(&version_chunk, &genesis_validators_root)
+}
+
+pub fn compute_domain(domain_type: DomainType, fork_version: Version, genesis_validators_root: Root) -> Domain {
+    let fork_data_root = compute_fork_data_root(fork_version, genesis_validators_root);
+    let mut domain = [0u8; 32];
+    domain[..4].copy_from_slice(&domain_type);
+    domain[4..].copy_from_slice(&fork_data_root[..28]);
+    domain
+}
+
+/// The message the committee signs for an object with the given `hash_tree_root`.
+pub fn compute_signing_root(object_root: Root, domain: Domain) -> Root {
+    merkleize_pair(&object_root, &domain)
+}
+
+/// The apk circuit for a sync aggregate over the committee.
+///
+/// The bitmask has to fit into a single field element, so that's for committees of up to 254 members,
+/// as in the minimal preset; larger ones fail with `BitmaskTooLong`.
+pub fn sync_committee_circuit(
+    pubkeys: &[[u8; PUBLIC_KEY_SIZE]],
+    sync_committee_bits: &[u8],
+    seed: G1Affine,
+) -> Result<ChainCircuit<Bls381Emulated>> {
+    let keys = committee_keys(pubkeys)?;
+    let bitmask = bitvector_to_bitmask(sync_committee_bits, keys.len())?;
+    ChainCircuit::<Bls381Emulated>::new(keys, seed, &bitmask)
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_ec::AffineRepr;
+    use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystem};
+    use ark_std::{test_rng, UniformRand};
+
+    use crate::bls_keys::public_key_to_bytes;
+
+    use super::*;
+
+    #[test]
+    fn test_bitfields() {
+        let bitmask = Bitmask::from_bits(vec![true, false, true, true, false, false, false, false, true]);
+        let bitlist = bitmask_to_bitlist(&bitmask);
+        assert_eq!(bitlist, vec![0b0000_1101, 0b0000_0011]);
+        assert_eq!(bitlist_to_bitmask(&bitlist).unwrap(), bitmask);
+        assert_eq!(bitlist_to_bitmask(&[0b0000_0001]).unwrap(), Bitmask::default());
+        assert!(bitlist_to_bitmask(&[0b0000_1101, 0]).is_err());
+
+        let bitvector = bitmask_to_bitvector(&bitmask);
+        assert_eq!(bitvector, vec![0b0000_1101, 0b0000_0001]);
+        assert_eq!(bitvector_to_bitmask(&bitvector, 9).unwrap(), bitmask);
+        assert!(bitvector_to_bitmask(&bitvector, 8).is_err());
+        assert!(bitvector_to_bitmask(&[0b0000_0011], 1).is_err());
+    }
+
+    #[test]
+    fn test_domain() {
+        // Mainnet genesis validators root and Altair fork version.
+        let genesis_validators_root = [
+            0x4b, 0x36, 0x3d, 0xb9, 0x4e, 0x28, 0x61, 0x20, 0xd7, 0x6e, 0xb9, 0x05, 0x34, 0x0f, 0xdd, 0x4e,
+            0x54, 0xbf, 0xe9, 0xf0, 0x6b, 0xf3, 0x3f, 0xf6, 0xcf, 0x5a, 0xd2, 0x7f, 0x51, 0x1b, 0xfe, 0x95,
+        ];
+        let domain = compute_domain(DOMAIN_SYNC_COMMITTEE, [1, 0, 0, 0], genesis_validators_root);
+        assert_eq!(domain[..4], DOMAIN_SYNC_COMMITTEE);
+        assert_eq!(domain[4..], compute_fork_data_root([1, 0, 0, 0], genesis_validators_root)[..28]);
+        assert_ne!(compute_signing_root([0; 32], domain), compute_signing_root([1; 32], domain));
+    }
+
+    #[test]
+    fn test_sync_committee_circuit() {
+        let rng = &mut test_rng();
+        let pubkeys: Vec<_> = (0..4).map(|_| public_key_to_bytes(&G1Affine::rand(rng))).collect();
+        let circuit = sync_committee_circuit(&pubkeys, &[0b0000_0101], G1Affine::generator()).unwrap();
+        assert_eq!(circuit.num_keys(), 4);
+        let cs = ConstraintSystem::<ark_bls12_381::Fr>::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+        assert!(cs.is_satisfied().unwrap());
+    }
+}
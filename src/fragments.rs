@@ -0,0 +1,701 @@
+//! Pieces of the apk circuit, to compose other statements from, and an adapter turning a closure into a circuit.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::marker::PhantomData;
+
+use ark_ec::short_weierstrass::{Affine, SWCurveConfig};
+use ark_ff::{BigInteger, Field, PrimeField};
+use ark_r1cs_std::alloc::{AllocationMode, AllocVar};
+use ark_r1cs_std::boolean::Boolean;
+use ark_r1cs_std::eq::EqGadget;
+use ark_r1cs_std::fields::{FieldOpsBounds, FieldVar};
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_r1cs_std::fields::nonnative::{AllocatedNonNativeFieldVar, NonNativeFieldMulResultVar, NonNativeFieldVar};
+use ark_r1cs_std::fields::nonnative::params::{get_params, OptimizationType};
+use ark_r1cs_std::{R1CSVar, ToBitsGadget};
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef, OptimizationGoal, SynthesisError, Variable};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::affine_gen::{AffineVarGeneric, NonZeroAffineVarGeneric};
+use crate::config::{AdditionStrategy, Config};
+use crate::labels::labeled;
+use crate::schema::{limbs_per_input, pack_limbs};
+use crate::sum_acc::{AccumulatorField, PointSummation, SumAccumulator};
+
+/// A circuit defined by a closure, for one-off statements.
+pub struct CircuitFn<CF, F> {
+    f: F,
+    _cf: PhantomData<fn() -> CF>,
+}
+
+impl<CF: Field, F: FnOnce(ConstraintSystemRef<CF>) -> Result<(), SynthesisError>> CircuitFn<CF, F> {
+    pub fn new(f: F) -> Self {
+        Self { f, _cf: PhantomData }
+    }
+}
+
+impl<CF: Field, F: FnOnce(ConstraintSystemRef<CF>) -> Result<(), SynthesisError>> ConstraintSynthesizer<CF> for CircuitFn<CF, F> {
+    fn generate_constraints(self, cs: ConstraintSystemRef<CF>) -> ark_relations::r1cs::Result<()> {
+        (self.f)(cs)
+    }
+}
+
+/// Allocates the keys one after the other, labeled `keys/i`.
+pub fn allocate_keys<P, CF, F>(cs: ConstraintSystemRef<CF>, keys: Vec<Affine<P>>, mode: AllocationMode) -> Result<Vec<NonZeroAffineVarGeneric<P, F, CF>>, SynthesisError>
+    where P: SWCurveConfig,
+          CF: PrimeField,
+          F: FieldVar<P::BaseField, CF>,
+{
+    labeled(&cs, "keys", || {
+        keys.into_iter()
+            .enumerate()
+            .map(|(i, key)| labeled(&cs, i, || NonZeroAffineVarGeneric::<P, F, CF>::new_variable(ark_relations::ns!(cs, "keys"), || Ok(key), mode)))
+            .collect()
+    })
+}
+
+/// Field variables with their limbs exposed to the host: the element itself for native fields,
+/// and its limb decomposition for emulated ones.
+pub trait LimbVar<TF: Field, CF: PrimeField>: FieldVar<TF, CF> {
+    /// The encoding of `x` in the instance vector, for the limb decomposition picked by the optimization goal.
+    fn input_limbs(x: &TF, goal: OptimizationGoal) -> Result<Vec<CF>, SynthesisError>;
+
+    /// Allocates the limbs as public inputs. Nothing is range-checked: the verifier provides the limbs.
+    fn new_input_from_limbs(cs: ConstraintSystemRef<CF>, limbs: &[CF]) -> Result<Self, SynthesisError>;
+
+    /// The constant `x`, decomposed into limbs once, rather than by every operation with a variable it takes part in.
+    fn new_constant_limbs(cs: ConstraintSystemRef<CF>, x: TF) -> Result<Self, SynthesisError>;
+
+    /// Allocates a witness as `new_witness` does, without range-checking the limbs,
+    /// for a variable whose limbs are identified with ones range-checked elsewhere.
+    fn new_witness_unchecked(cs: ConstraintSystemRef<CF>, f: impl FnOnce() -> Result<TF, SynthesisError>) -> Result<Self, SynthesisError>;
+
+    /// The variables of the limbs, `None` if any of them is a constant or a linear combination.
+    fn limb_variables(&self) -> Option<Vec<Variable>>;
+
+    /// The width of the limbs, `None` if the element is a single limb of the full width of the constraint field.
+    fn bits_per_limb(goal: OptimizationGoal) -> Option<usize>;
+
+    /// The variable with the limbs, allocated and range-checked to `bits_per_limb` by the caller.
+    fn from_limbs(cs: ConstraintSystemRef<CF>, limbs: Vec<FpVar<CF>>) -> Self;
+
+    /// The limbs, as they are: those of an allocated variable are the `input_limbs` of its value,
+    /// those of a constant are decomposed for `OptimizationGoal::Constraints`.
+    fn limbs(&self) -> Result<Vec<FpVar<CF>>, SynthesisError>;
+
+    /// Enforces `y^2 = x^3 + a * x + b`, the curve equation `NonZeroAffineVarGeneric::enforce_on_curve` checks.
+    /// The emulated fields sum `y^2 - x^2 * x - a * x - b` unreduced, reducing `x^2` and the sum only, two reductions of the three of the products.
+    fn enforce_curve_equation(x: &Self, y: &Self, a: TF, b: TF) -> Result<(), SynthesisError>;
+
+    /// Whether the variable is a constant, a public input or a witness, judging by its limbs.
+    fn kind(&self) -> VarKind {
+        if self.is_constant() {
+            return VarKind::Constant;
+        }
+        match self.limb_variables() {
+            Some(vars) if vars.iter().all(Variable::is_instance) => VarKind::Input,
+            Some(vars) if vars.iter().all(Variable::is_witness) => VarKind::Witness,
+            _ => VarKind::Derived,
+        }
+    }
+}
+
+/// What a field variable is made of, see `LimbVar::kind`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VarKind {
+    Constant,
+    Input,
+    Witness,
+    /// Linear combinations of variables, or limbs of different kinds, as the results of operations are.
+    Derived,
+}
+
+impl fmt::Display for VarKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            VarKind::Constant => "constant",
+            VarKind::Input => "input",
+            VarKind::Witness => "witness",
+            VarKind::Derived => "derived",
+        })
+    }
+}
+
+fn fp_variable<F: PrimeField>(limb: &FpVar<F>) -> Option<Variable> {
+    match limb {
+        FpVar::Var(v) if !v.variable.is_lc() => Some(v.variable),
+        _ => None,
+    }
+}
+
+impl<F: PrimeField> LimbVar<F, F> for FpVar<F> {
+    fn input_limbs(x: &F, _goal: OptimizationGoal) -> Result<Vec<F>, SynthesisError> {
+        Ok(vec![*x])
+    }
+
+    fn new_input_from_limbs(cs: ConstraintSystemRef<F>, limbs: &[F]) -> Result<Self, SynthesisError> {
+        FpVar::new_input(cs, || Ok(limbs[0]))
+    }
+
+    fn new_constant_limbs(_cs: ConstraintSystemRef<F>, x: F) -> Result<Self, SynthesisError> {
+        Ok(FpVar::Constant(x))
+    }
+
+    fn new_witness_unchecked(cs: ConstraintSystemRef<F>, f: impl FnOnce() -> Result<F, SynthesisError>) -> Result<Self, SynthesisError> {
+        FpVar::new_witness(cs, f)
+    }
+
+    fn limb_variables(&self) -> Option<Vec<Variable>> {
+        fp_variable(self).map(|v| vec![v])
+    }
+
+    fn bits_per_limb(_goal: OptimizationGoal) -> Option<usize> {
+        None
+    }
+
+    fn from_limbs(_cs: ConstraintSystemRef<F>, mut limbs: Vec<FpVar<F>>) -> Self {
+        limbs.remove(0)
+    }
+
+    fn limbs(&self) -> Result<Vec<FpVar<F>>, SynthesisError> {
+        Ok(vec![self.clone()])
+    }
+
+    fn enforce_curve_equation(x: &Self, y: &Self, a: F, b: F) -> Result<(), SynthesisError> {
+        y.square_equals(&(x.square()? * x + x * a + b))
+    }
+}
+
+impl<TF: PrimeField, CF: PrimeField> LimbVar<TF, CF> for NonNativeFieldVar<TF, CF> {
+    fn input_limbs(x: &TF, goal: OptimizationGoal) -> Result<Vec<CF>, SynthesisError> {
+        AllocatedNonNativeFieldVar::<TF, CF>::get_limbs_representations(x, optimization_type(goal))
+    }
+
+    // As `AllocationMode::Input` does, but from the limbs.
+    fn new_input_from_limbs(cs: ConstraintSystemRef<CF>, limbs: &[CF]) -> Result<Self, SynthesisError> {
+        let limbs = limbs.iter()
+            .map(|limb| FpVar::new_input(ark_relations::ns!(cs, "alloc"), || Ok(*limb)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self::from_limbs(cs, limbs))
+    }
+
+    fn new_constant_limbs(cs: ConstraintSystemRef<CF>, x: TF) -> Result<Self, SynthesisError> {
+        Ok(NonNativeFieldVar::Var(AllocatedNonNativeFieldVar::constant(cs, x)?))
+    }
+
+    // As `AllocationMode::Witness` does, short of `enforce_in_range`.
+    fn new_witness_unchecked(cs: ConstraintSystemRef<CF>, f: impl FnOnce() -> Result<TF, SynthesisError>) -> Result<Self, SynthesisError> {
+        let limbs = Self::input_limbs(&f().unwrap_or_default(), cs.optimization_goal())?;
+        let limbs = limbs.into_iter()
+            .map(|limb| FpVar::new_witness(ark_relations::ns!(cs, "alloc"), || Ok(limb)))
+            .collect::<Result<_, _>>()?;
+        Ok(NonNativeFieldVar::Var(AllocatedNonNativeFieldVar {
+            cs,
+            limbs,
+            num_of_additions_over_normal_form: CF::one(),
+            is_in_the_normal_form: false,
+            target_phantom: PhantomData,
+        }))
+    }
+
+    fn limb_variables(&self) -> Option<Vec<Variable>> {
+        match self {
+            NonNativeFieldVar::Var(v) => v.limbs.iter().map(fp_variable).collect(),
+            NonNativeFieldVar::Constant(_) => None,
+        }
+    }
+
+    fn bits_per_limb(goal: OptimizationGoal) -> Option<usize> {
+        Some(get_params(TF::MODULUS_BIT_SIZE as usize, CF::MODULUS_BIT_SIZE as usize, optimization_type(goal)).bits_per_limb)
+    }
+
+    fn from_limbs(cs: ConstraintSystemRef<CF>, limbs: Vec<FpVar<CF>>) -> Self {
+        NonNativeFieldVar::Var(AllocatedNonNativeFieldVar {
+            cs,
+            limbs,
+            num_of_additions_over_normal_form: CF::zero(),
+            is_in_the_normal_form: true,
+            target_phantom: PhantomData,
+        })
+    }
+
+    fn limbs(&self) -> Result<Vec<FpVar<CF>>, SynthesisError> {
+        match self {
+            NonNativeFieldVar::Var(v) => Ok(v.limbs.clone()),
+            NonNativeFieldVar::Constant(x) => Ok(Self::input_limbs(x, OptimizationGoal::Constraints)?.into_iter().map(FpVar::constant).collect()),
+        }
+    }
+
+    // The remainder of the sum is enforced to be `0`, that the reduction bounds to its canonical representative.
+    fn enforce_curve_equation(x: &Self, y: &Self, a: TF, b: TF) -> Result<(), SynthesisError> {
+        let x2 = x.square()?;
+        let mut sum = y.mul_without_reduce(y)? + x2.mul_without_reduce(&x.negate()?)?;
+        if !a.is_zero() {
+            sum += NonNativeFieldMulResultVar::from(&(x * -a));
+        }
+        (sum + -b).reduce()?.enforce_equal(&Self::zero())
+    }
+}
+
+fn optimization_type(goal: OptimizationGoal) -> OptimizationType {
+    match goal {
+        OptimizationGoal::Weight => OptimizationType::Weight,
+        OptimizationGoal::None | OptimizationGoal::Constraints => OptimizationType::Constraints,
+    }
+}
+
+/// A constant point, with the coordinates decomposed into limbs once, see `LimbVar::new_constant_limbs`.
+///
+/// Only the seed is worth it: it's the left operand of the subtractions of the first step, and `constant - variable`
+/// costs a negation on top of what the limbs would. The other constants of the steps, the zero the accumulator
+/// compares to, the identity, `1` and the coefficient `a` of the complete additions, and `b` of the curve equation,
+/// are added, selected or compared, and a constant operand is made of constant limbs either way,
+/// so caching them saves no constraints. What it would save is the decomposition, 23µs per constant for BLS12-381
+/// emulated in itself, of the 29ms (`Accumulated`) to 100ms (`Complete`) a key takes to synthesize: not worth
+/// threading a cache through the generic gadgets, that would need `LimbVar` rather than `FieldVar` for it.
+pub fn constant_point<P, CF, F>(cs: ConstraintSystemRef<CF>, p: Affine<P>) -> Result<NonZeroAffineVarGeneric<P, F, CF>, SynthesisError>
+    where P: SWCurveConfig,
+          CF: PrimeField,
+          F: LimbVar<P::BaseField, CF>,
+{
+    let x = F::new_constant_limbs(cs.clone(), p.x)?;
+    let y = F::new_constant_limbs(cs, p.y)?;
+    Ok(NonZeroAffineVarGeneric::new(x, y))
+}
+
+/// Allocates the keys as public inputs, as `allocate_keys` does, but decomposing the coordinates into limbs up front,
+/// in parallel with the `parallel` feature, instead of one coordinate at a time during the allocation.
+pub fn allocate_input_keys<P, CF, F>(cs: ConstraintSystemRef<CF>, keys: &[Affine<P>]) -> Result<Vec<NonZeroAffineVarGeneric<P, F, CF>>, SynthesisError>
+    where P: SWCurveConfig,
+          CF: PrimeField,
+          F: LimbVar<P::BaseField, CF>,
+{
+    let goal = cs.optimization_goal();
+    let limbs = ark_std::cfg_iter!(keys)
+        .map(|p| Ok((F::input_limbs(&p.x, goal)?, F::input_limbs(&p.y, goal)?)))
+        .collect::<Result<Vec<_>, SynthesisError>>()?;
+    labeled(&cs, "keys", || {
+        limbs.iter()
+            .enumerate()
+            .map(|(i, (x, y))| labeled(&cs, i, || {
+                let x = labeled(&cs, "x", || F::new_input_from_limbs(cs.clone(), x))?;
+                let y = labeled(&cs, "y", || F::new_input_from_limbs(cs.clone(), y))?;
+                Ok(NonZeroAffineVarGeneric::new(x, y))
+            }))
+            .collect()
+    })
+}
+
+/// Allocates the keys as `allocate_input_keys` does, but with their limbs packed into the public inputs as
+/// `schema::pack_limbs` does, labeled `keys/packed/j`. The limbs are witnesses range-checked to `bits_per_limb` bits,
+/// so that the packing is unique. Falls back to `allocate_input_keys` for the native fields.
+pub fn allocate_packed_input_keys<P, CF, F>(cs: ConstraintSystemRef<CF>, keys: &[Affine<P>]) -> Result<Vec<NonZeroAffineVarGeneric<P, F, CF>>, SynthesisError>
+    where P: SWCurveConfig,
+          CF: PrimeField,
+          F: LimbVar<P::BaseField, CF>,
+{
+    let goal = cs.optimization_goal();
+    let Some(bits_per_limb) = F::bits_per_limb(goal) else {
+        return allocate_input_keys(cs, keys);
+    };
+    if keys.is_empty() {
+        return Ok(vec![]);
+    }
+    let limbs = ark_std::cfg_iter!(keys)
+        .map(|p| Ok([F::input_limbs(&p.x, goal)?, F::input_limbs(&p.y, goal)?].concat()))
+        .collect::<Result<Vec<_>, SynthesisError>>()?;
+    let limbs_per_key = limbs[0].len();
+    let limbs = limbs.concat();
+    let limbs_per_input = limbs_per_input::<CF>(bits_per_limb);
+    let packed = pack_limbs(&limbs, bits_per_limb);
+    let shift = CF::from(2u64).pow([bits_per_limb as u64]);
+
+    let limb_vars = labeled(&cs, "keys", || {
+        let mut limb_vars = Vec::with_capacity(limbs.len());
+        for (j, (chunk, packed)) in limbs.chunks(limbs_per_input).zip(packed).enumerate() {
+            labeled(&cs, format_args!("packed/{}", j), || {
+                let packed = FpVar::new_input(ark_relations::ns!(cs, "packed"), || Ok(packed))?;
+                let mut sum = FpVar::zero();
+                let mut coeff = CF::one();
+                for limb in chunk {
+                    let var = FpVar::new_witness(ark_relations::ns!(cs, "limb"), || Ok(*limb))?;
+                    let bits = limb.into_bigint().to_bits_le()[..bits_per_limb].to_vec();
+                    let bits = Vec::<Boolean<CF>>::new_witness(ark_relations::ns!(cs, "bits"), || Ok(bits))?;
+                    var.enforce_equal(&Boolean::le_bits_to_fp_var(&bits)?)?;
+                    sum += &var * coeff;
+                    coeff *= shift;
+                    limb_vars.push(var);
+                }
+                packed.enforce_equal(&sum)
+            })?;
+        }
+        Ok::<_, SynthesisError>(limb_vars)
+    })?;
+
+    Ok(limb_vars.chunks(limbs_per_key)
+        .map(|limbs| {
+            let (x, y) = limbs.split_at(limbs_per_key / 2);
+            NonZeroAffineVarGeneric::new(F::from_limbs(cs.clone(), x.to_vec()), F::from_limbs(cs.clone(), y.to_vec()))
+        })
+        .collect())
+}
+
+/// Allocates the keys compressed, as `NonZeroAffineVarGeneric::compress` has them: the limbs of `x` as public inputs,
+/// labeled `keys/i/x`, then the signs of `y` packed into a single one as the bitmask is, labeled `keys/signs`,
+/// and `y` as a witness, see `NonZeroAffineVarGeneric::new_decompressed`. Half the limbs of `allocate_input_keys`,
+/// for a square and the canonical decomposition of `y` per key.
+pub fn allocate_compressed_input_keys<P, CF, F>(cs: ConstraintSystemRef<CF>, keys: &[Affine<P>]) -> Result<Vec<NonZeroAffineVarGeneric<P, F, CF>>, SynthesisError>
+    where P: SWCurveConfig,
+          P::BaseField: PrimeField,
+          CF: PrimeField,
+          F: LimbVar<P::BaseField, CF>,
+{
+    let goal = cs.optimization_goal();
+    let limbs = ark_std::cfg_iter!(keys)
+        .map(|p| F::input_limbs(&p.x, goal))
+        .collect::<Result<Vec<_>, SynthesisError>>()?;
+    let packed_signs = keys.iter().rev().fold(CF::zero(), |acc, p| acc.double() + CF::from(p.y > -p.y));
+    labeled(&cs, "keys", || {
+        let xs = limbs.iter()
+            .enumerate()
+            .map(|(i, x)| labeled(&cs, format_args!("{}/x", i), || F::new_input_from_limbs(cs.clone(), x)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let signs = labeled(&cs, "signs", || {
+            let signs = BitmaskVar::new(FpVar::new_input(ark_relations::ns!(cs, "signs"), || Ok(packed_signs))?, keys.len())?;
+            signs.enforce_unused_zero()?;
+            Ok::<_, SynthesisError>(signs)
+        })?;
+        xs.into_iter()
+            .zip(signs.bits())
+            .enumerate()
+            .map(|(i, (x, sign))| labeled(&cs, i, || NonZeroAffineVarGeneric::new_decompressed(x, sign)))
+            .collect()
+    })
+}
+
+/// The packed bitmask along with its decomposition, done once here and shared by everything that needs the bits.
+#[derive(Clone, Debug)]
+pub struct BitmaskVar<CF: PrimeField> {
+    packed: FpVar<CF>,
+    bits: Vec<Boolean<CF>>,
+    num_keys: usize,
+}
+
+impl<CF: PrimeField> BitmaskVar<CF> {
+    /// Decomposes the packed bitmask of a keyset of `num_keys` keys.
+    pub fn new(packed: FpVar<CF>, num_keys: usize) -> Result<Self, SynthesisError> {
+        let bits = labeled(&packed.cs(), "bits", || packed.to_bits_le())?;
+        Ok(Self { packed, bits, num_keys })
+    }
+
+    pub fn packed(&self) -> &FpVar<CF> {
+        &self.packed
+    }
+
+    /// The bits of the keys, at most as many as the packed bitmask decomposes into.
+    pub fn bits(&self) -> &[Boolean<CF>] {
+        &self.bits[..self.num_keys.min(self.bits.len())]
+    }
+
+    /// The number of set bits, a linear combination of them.
+    pub fn popcount(&self) -> FpVar<CF> {
+        self.bits().iter().fold(FpVar::zero(), |acc, b| acc + FpVar::from(b.clone()))
+    }
+
+    /// Enforces at least `threshold` bits to be set.
+    pub fn enforce_threshold(&self, threshold: usize) -> Result<(), SynthesisError> {
+        let threshold = FpVar::constant(CF::from(threshold as u64));
+        self.popcount().enforce_cmp(&threshold, Ordering::Greater, true)
+    }
+
+    /// Enforces the bits past the keys to be unset, with a single constraint, as a sum of bits can't wrap around.
+    pub fn enforce_unused_zero(&self) -> Result<(), SynthesisError> {
+        let unused = self.bits.iter().skip(self.num_keys).fold(FpVar::zero(), |acc, b| acc + FpVar::from(b.clone()));
+        unused.enforce_equal(&FpVar::zero())
+    }
+}
+
+/// Adds to the seed the keys for which the bit is set, using the addition strategy of the config.
+/// With `AdditionStrategy::Complete`, the sum of `aggregate_complete` is added to the seed.
+/// Keys past the end of `bit_vars`, and bits past the end of `key_vars`, are ignored.
+pub fn aggregate<P, CF, F>(
+    seed: NonZeroAffineVarGeneric<P, F, CF>,
+    key_vars: &[NonZeroAffineVarGeneric<P, F, CF>],
+    bit_vars: &[Boolean<CF>],
+    config: &Config,
+) -> Result<NonZeroAffineVarGeneric<P, F, CF>, SynthesisError>
+    where P: SWCurveConfig,
+          CF: PrimeField,
+          F: AccumulatorField<P, CF>,
+          for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+{
+    let cs = key_vars.cs().or(bit_vars.cs());
+    let mut curr_sum = seed;
+    labeled(&cs, "acc", || {
+        match config.addition {
+            AdditionStrategy::Incomplete => curr_sum = sum_selected(curr_sum, key_vars, bit_vars)?,
+            AdditionStrategy::Accumulated => {
+                let acc = SumAccumulator::from_point(curr_sum)?.with_multiplication(config.multiplication);
+                curr_sum = sum_selected(acc, key_vars, bit_vars)?;
+            }
+            AdditionStrategy::Complete => {
+                // The sum is added to the seed last, the result being non-zero short of knowing the logarithm of the seed.
+                let sum = aggregate_complete(key_vars, bit_vars)?.add(&curr_sum.clone().into())?;
+                sum.infinity.enforce_equal(&Boolean::FALSE)?;
+                curr_sum = NonZeroAffineVarGeneric::new(sum.x, sum.y);
+            }
+        }
+        Ok(curr_sum)
+    })
+}
+
+/// Adds to the seed the keys for which the bit is set, using the summation `S` rather than the strategy of a config.
+/// Keys past the end of `bit_vars`, and bits past the end of `key_vars`, are ignored.
+pub fn aggregate_with<S, P, CF, F>(
+    seed: NonZeroAffineVarGeneric<P, F, CF>,
+    key_vars: &[NonZeroAffineVarGeneric<P, F, CF>],
+    bit_vars: &[Boolean<CF>],
+) -> Result<NonZeroAffineVarGeneric<P, F, CF>, SynthesisError>
+    where S: PointSummation<P, F, CF>,
+          P: SWCurveConfig,
+          CF: PrimeField,
+          F: FieldVar<P::BaseField, CF>,
+{
+    let cs = key_vars.cs().or(bit_vars.cs());
+    labeled(&cs, "acc", || sum_selected(S::init(seed)?, key_vars, bit_vars))
+}
+
+fn sum_selected<S, P, CF, F>(
+    mut acc: S,
+    key_vars: &[NonZeroAffineVarGeneric<P, F, CF>],
+    bit_vars: &[Boolean<CF>],
+) -> Result<NonZeroAffineVarGeneric<P, F, CF>, SynthesisError>
+    where S: PointSummation<P, F, CF>,
+          P: SWCurveConfig,
+          CF: PrimeField,
+          F: FieldVar<P::BaseField, CF>,
+{
+    let cs = key_vars.cs().or(bit_vars.cs());
+    for (i, (b, key)) in bit_vars.iter().zip(key_vars).enumerate() {
+        acc = labeled(&cs, format_args!("step/{}", i), || acc.conditional_add(key, b))?;
+    }
+    labeled(&cs, "finalize", || acc.finalize())
+}
+
+/// The sum of the keys for which the bit is set, with complete additions from the identity:
+/// no seed, and the identity for no bit set. The keys of unset bits are added as the identity.
+/// Keys past the end of `bit_vars`, and bits past the end of `key_vars`, are ignored.
+/// What `ApkCircuit` computes with `AdditionStrategy::Complete`.
+pub fn aggregate_complete<P, CF, F>(key_vars: &[NonZeroAffineVarGeneric<P, F, CF>], bit_vars: &[Boolean<CF>]) -> Result<AffineVarGeneric<P, F, CF>, SynthesisError>
+    where P: SWCurveConfig,
+          CF: PrimeField,
+          F: FieldVar<P::BaseField, CF>,
+          for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+{
+    let cs = key_vars.cs().or(bit_vars.cs());
+    labeled(&cs, "acc", || {
+        bit_vars.iter().zip(key_vars).enumerate().try_fold(AffineVarGeneric::zero(), |acc, (i, (b, key))| {
+            labeled(&cs, format_args!("step/{}", i), || acc.add(&AffineVarGeneric::new(key.x.clone(), key.y.clone(), b.not())))
+        })
+    })
+}
+
+/// Exposes the point as a public input, enforced to be equal to it.
+pub fn commit_point<P, CF, F>(point: &NonZeroAffineVarGeneric<P, F, CF>) -> Result<NonZeroAffineVarGeneric<P, F, CF>, SynthesisError>
+    where P: SWCurveConfig,
+          CF: PrimeField,
+          F: FieldVar<P::BaseField, CF>,
+{
+    let cs = point.cs();
+    labeled(&cs, "commit", || {
+        let input = NonZeroAffineVarGeneric::<P, F, CF>::new_input(ark_relations::ns!(cs, "commit"), || point.value())?;
+        input.x.enforce_equal(&point.x)?;
+        input.y.enforce_equal(&point.y)?;
+        Ok(input)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_ec::{AffineRepr, CurveGroup};
+    use ark_r1cs_std::fields::fp::FpVar;
+    use ark_relations::r1cs::ConstraintSystem;
+    use ark_std::{test_rng, UniformRand};
+
+    use crate::aggregation;
+    use crate::bitmask::Bitmask;
+
+    use super::*;
+
+    type Key = NonZeroAffineVarGeneric<ark_bls12_377::g1::Config, FpVar<ark_bw6_761::Fr>, ark_bw6_761::Fr>;
+
+    #[test]
+    fn test_aggregate_complete() {
+        let rng = &mut test_rng();
+        let keys: Vec<ark_bls12_377::G1Affine> = (0..4).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+        // The last key is the opposite of the first, so the sum of all of them hits the identity on the way.
+        let keys = [&keys[..], &[-keys[0]]].concat();
+        for bits in [vec![false; 5], vec![true, false, false, false, true], vec![true, true, false, true, true], vec![true; 5]] {
+            let bitmask = Bitmask::from_bits(bits);
+            let cs = ConstraintSystem::<ark_bw6_761::Fr>::new_ref();
+            let key_vars: Vec<NonZeroAffineVarGeneric<_, FpVar<_>, _>> = allocate_input_keys(cs.clone(), &keys).unwrap();
+            let bit_vars = Vec::<Boolean<_>>::new_witness(cs.clone(), || Ok(bitmask.bits().to_vec())).unwrap();
+            let apk = aggregate_complete(&key_vars, &bit_vars).unwrap();
+            assert_eq!(apk.value().unwrap(), aggregation::aggregate(&keys, &bitmask).unwrap());
+            assert_eq!(apk.is_zero().value().unwrap(), bitmask.count_ones() == 0 || bitmask.bits() == [true, false, false, false, true]);
+            assert!(cs.is_satisfied().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_circuit_fn() {
+        let rng = &mut test_rng();
+        let keys: Vec<ark_bls12_377::G1Affine> = (0..4).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+        let bits = vec![true, true, false, true];
+        let seed = ark_bls12_377::G1Affine::generator();
+        let apk = (aggregation::aggregate(&keys, &Bitmask::from_bits(bits.clone())).unwrap() + seed).into_affine();
+
+        // Private keys and bitmask, public aggregate key.
+        let circuit = CircuitFn::new(|cs: ConstraintSystemRef<ark_bw6_761::Fr>| {
+            let key_vars: Vec<Key> = allocate_keys(cs.clone(), keys, AllocationMode::Witness)?;
+            let bit_vars = Vec::<Boolean<_>>::new_witness(cs.clone(), || Ok(bits))?;
+            let seed_var = Key::new_constant(cs.clone(), seed)?;
+            let apk_var = aggregate(seed_var, &key_vars, &bit_vars, &Config::default())?;
+            let _apk_input = commit_point(&apk_var)?;
+            Ok(())
+        });
+        let cs = ConstraintSystem::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+        assert!(cs.is_satisfied().unwrap());
+        let instance = cs.borrow().unwrap().instance_assignment.clone();
+        assert_eq!(instance[1..], [apk.x, apk.y]);
+    }
+
+    fn check_input_keys<P, CF, F>(keys: &[Affine<P>], goal: OptimizationGoal)
+        where P: SWCurveConfig,
+              CF: PrimeField,
+              F: LimbVar<P::BaseField, CF>,
+    {
+        let by_limbs = ConstraintSystem::<CF>::new_ref();
+        by_limbs.set_optimization_goal(goal);
+        let vars = allocate_input_keys::<P, CF, F>(by_limbs.clone(), keys).unwrap();
+        assert_eq!(vars.value().unwrap(), keys);
+        assert_eq!(by_limbs.num_constraints(), 0);
+
+        let by_coordinates = ConstraintSystem::<CF>::new_ref();
+        by_coordinates.set_optimization_goal(goal);
+        allocate_keys::<P, CF, F>(by_coordinates.clone(), keys.to_vec(), AllocationMode::Input).unwrap();
+        assert_eq!(by_limbs.borrow().unwrap().instance_assignment, by_coordinates.borrow().unwrap().instance_assignment);
+    }
+
+    #[test]
+    fn test_allocate_input_keys() {
+        let rng = &mut test_rng();
+        let keys: Vec<ark_bls12_377::G1Affine> = (0..3).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+        check_input_keys::<_, _, FpVar<ark_bw6_761::Fr>>(&keys, OptimizationGoal::Constraints);
+        let keys: Vec<ark_bls12_381::G1Affine> = (0..3).map(|_| ark_bls12_381::G1Affine::rand(rng)).collect();
+        check_input_keys::<_, _, crate::tests::BlsInBls>(&keys, OptimizationGoal::Constraints);
+        check_input_keys::<_, _, crate::tests::BlsInBls>(&keys, OptimizationGoal::Weight);
+    }
+
+    fn check_compressed_input_keys<P, CF, F>(keys: &[Affine<P>])
+        where P: SWCurveConfig,
+              P::BaseField: PrimeField,
+              CF: PrimeField,
+              F: LimbVar<P::BaseField, CF>,
+    {
+        let cs = ConstraintSystem::<CF>::new_ref();
+        let vars = allocate_compressed_input_keys::<P, CF, F>(cs.clone(), keys).unwrap();
+        assert_eq!(vars.value().unwrap(), keys);
+        assert!(cs.is_satisfied().unwrap());
+        let num_limbs = F::input_limbs(&keys[0].x, cs.optimization_goal()).unwrap().len();
+        assert_eq!(cs.num_instance_variables(), 1 + keys.len() * num_limbs + 1);
+    }
+
+    #[test]
+    fn test_allocate_compressed_input_keys() {
+        let rng = &mut test_rng();
+        let keys: Vec<ark_bls12_377::G1Affine> = (0..3).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+        check_compressed_input_keys::<_, _, FpVar<ark_bw6_761::Fr>>(&keys);
+        let keys: Vec<ark_bls12_381::G1Affine> = (0..3).map(|_| ark_bls12_381::G1Affine::rand(rng)).collect();
+        check_compressed_input_keys::<_, _, crate::tests::BlsInBls>(&keys);
+    }
+
+    fn check_packed_input_keys<P, CF, F>(keys: &[Affine<P>], goal: OptimizationGoal) -> (usize, usize)
+        where P: SWCurveConfig,
+              CF: PrimeField,
+              F: LimbVar<P::BaseField, CF>,
+    {
+        let cs = ConstraintSystem::<CF>::new_ref();
+        cs.set_optimization_goal(goal);
+        let vars = allocate_packed_input_keys::<P, CF, F>(cs.clone(), keys).unwrap();
+        assert_eq!(vars.value().unwrap(), keys);
+        assert!(cs.is_satisfied().unwrap());
+        for v in &vars {
+            assert!(v.x.limb_variables().is_some() && v.y.limb_variables().is_some());
+        }
+        (cs.num_instance_variables() - 1, cs.num_constraints())
+    }
+
+    #[test]
+    fn test_allocate_packed_input_keys() {
+        let rng = &mut test_rng();
+        let keys: Vec<ark_bls12_377::G1Affine> = (0..3).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+        assert_eq!(check_packed_input_keys::<_, _, FpVar<ark_bw6_761::Fr>>(&keys, OptimizationGoal::Constraints), (6, 0));
+        let keys: Vec<ark_bls12_381::G1Affine> = (0..3).map(|_| ark_bls12_381::G1Affine::rand(rng)).collect();
+        for goal in [OptimizationGoal::Constraints, OptimizationGoal::Weight] {
+            let (inputs, constraints) = check_packed_input_keys::<_, _, crate::tests::BlsInBls>(&keys, goal);
+            let bits_per_limb = crate::tests::BlsInBls::bits_per_limb(goal).unwrap();
+            let num_limbs = 2 * keys.len() * crate::tests::BlsInBls::input_limbs(&keys[0].x, goal).unwrap().len();
+            println!("{:?}: {} limbs packed into {} inputs, for {} constraints per key", goal, num_limbs, inputs, constraints / keys.len());
+            assert_eq!(inputs, num_limbs.div_ceil(limbs_per_input::<ark_bls12_381::Fr>(bits_per_limb)));
+            // A boolean per bit, an unpacking per limb, and a packing per input.
+            assert_eq!(constraints, num_limbs * (bits_per_limb + 1) + inputs);
+        }
+    }
+
+    #[test]
+    fn test_constant_point() {
+        let rng = &mut test_rng();
+        let keys: Vec<ark_bls12_381::G1Affine> = (0..3).map(|_| ark_bls12_381::G1Affine::rand(rng)).collect();
+        let seed = ark_bls12_381::G1Affine::rand(rng);
+        let bits = vec![Boolean::TRUE, Boolean::FALSE, Boolean::TRUE];
+        let sum = |seed_var: fn(ConstraintSystemRef<_>, _) -> NonZeroAffineVarGeneric<_, crate::tests::BlsInBls, _>| {
+            let cs = ConstraintSystem::<ark_bls12_381::Fr>::new_ref();
+            let key_vars = allocate_input_keys(cs.clone(), &keys).unwrap();
+            let sum = aggregate(seed_var(cs.clone(), seed), &key_vars, &bits, &Config::default()).unwrap();
+            assert!(cs.is_satisfied().unwrap());
+            (sum.value().unwrap(), cs.num_constraints(), cs.num_witness_variables())
+        };
+        let constant = sum(|cs, seed| NonZeroAffineVarGeneric::new_constant(cs, seed).unwrap());
+        let limbs = sum(|cs, seed| constant_point(cs, seed).unwrap());
+        assert_eq!(constant.0, limbs.0);
+        // `constant - variable` costs a negation on top.
+        assert!(limbs.1 <= constant.1);
+    }
+
+    #[test]
+    fn test_bitmask_var() {
+        let cs = ConstraintSystem::<ark_bw6_761::Fr>::new_ref();
+        let bitmask = Bitmask::from_bits(vec![true, false, true, true]);
+        let packed = FpVar::new_input(cs.clone(), || Ok(bitmask.pack::<ark_bw6_761::Fr>().unwrap())).unwrap();
+        let bitmask_var = BitmaskVar::new(packed, 4).unwrap();
+        let decomposition = cs.num_constraints();
+        assert_eq!(bitmask_var.bits().value().unwrap(), bitmask.bits());
+        assert_eq!(bitmask_var.popcount().value().unwrap(), ark_bw6_761::Fr::from(3u8));
+        bitmask_var.enforce_unused_zero().unwrap();
+        assert_eq!(cs.num_constraints(), decomposition + 1);
+        bitmask_var.enforce_threshold(3).unwrap();
+        assert!(cs.is_satisfied().unwrap());
+        bitmask_var.enforce_threshold(4).unwrap();
+        assert!(!cs.is_satisfied().unwrap());
+
+        // A bit past the keys.
+        let cs = ConstraintSystem::<ark_bw6_761::Fr>::new_ref();
+        let packed = FpVar::new_input(cs.clone(), || Ok(ark_bw6_761::Fr::from(0b10001u8))).unwrap();
+        BitmaskVar::new(packed, 4).unwrap().enforce_unused_zero().unwrap();
+        assert!(!cs.is_satisfied().unwrap());
+    }
+}
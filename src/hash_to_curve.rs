@@ -0,0 +1,249 @@
+//! Hashing to short Weierstrass curves in-circuit, by the simplified SWU map of RFC 9380, as `ark_ec::hashing` does it natively.
+//!
+//! A message is hashed to two elements of the base field on the host, `hash_to_field`, that are then mapped to the curve
+//! in-circuit, `map_to_curve`. The map of a constant is folded into a constant, at no cost, so the gadget is for variable
+//! messages: the seed of `ApkCircuit::new_with_domain`, hashed from a constant domain tag, is hashed on the host instead,
+//! by `hash_to_curve`, into the same constant point.
+//! The curves with `a * b = 0`, as BLS12 ones, are reached through an isogenous curve with `a * b != 0`, the map of `WBConfig`.
+//!
+//! The map of a variable `u` follows the one of `SWUMap`, avoiding the square roots: of the two candidate abscissae `x1` and `x2`,
+//! that with a square `g(x) = x^3 + a * x + b` is picked by a witness bit, enforced by a witness square root of either `g(x1)`
+//! or of `Z * g(x1)`, for the non-square `Z`, that is then one of `g(x2) = (Z * u^2)^3 * g(x1)` up to `Z * u^3`.
+//! The sign of `y` is that of `u`, its parity, which decomposes both into their canonical bits.
+
+use ark_ec::hashing::curve_maps::swu::{SWUConfig, SWUMap};
+use ark_ec::hashing::curve_maps::wb::{WBConfig, WBMap};
+use ark_ec::hashing::HashToCurve;
+use ark_ec::hashing::map_to_curve_hasher::{MapToCurve, MapToCurveBasedHasher};
+use ark_ec::short_weierstrass::{Affine, Projective};
+use ark_ff::field_hashers::{DefaultFieldHasher, HashToField};
+use ark_ff::{Field, One, PrimeField, Zero};
+use ark_r1cs_std::alloc::AllocVar;
+use ark_r1cs_std::boolean::Boolean;
+use ark_r1cs_std::eq::EqGadget;
+use ark_r1cs_std::fields::{FieldOpsBounds, FieldVar};
+use ark_r1cs_std::R1CSVar;
+use ark_relations::r1cs::SynthesisError;
+use sha2::Sha256;
+
+use crate::affine_gen::{nonzero_inverse, NonZeroAffineVarGeneric};
+use crate::labels::labeled;
+use crate::scalar_mul::CofactorConfig;
+
+/// The two elements of the base field `hash_to_curve` maps, by `expand_message_xmd` with SHA-256, as `DefaultFieldHasher` has it.
+pub fn hash_to_field<F: PrimeField>(domain: &[u8], msg: &[u8]) -> [F; 2] {
+    let u = <DefaultFieldHasher<Sha256> as HashToField<F>>::new(domain).hash_to_field(msg, 2);
+    [u[0], u[1]]
+}
+
+/// The hash of `msg` to the prime-order subgroup, the `hash_to_curve` of RFC 9380 with SHA-256, as `MapToCurveBasedHasher` computes it.
+/// A point of unknown discrete logarithm, as `digest::derive_seed`, but the one `hash_to_curve_var` gives in-circuit.
+pub fn hash_to_curve<P: WBConfig>(domain: &[u8], msg: &[u8]) -> Affine<P> {
+    MapToCurveBasedHasher::<Projective<P>, DefaultFieldHasher<Sha256>, WBMap<P>>::new(domain)
+        .and_then(|hasher| hasher.hash(msg))
+        .expect("the map is defined for the curves with a `WBConfig`")
+}
+
+/// `clear_cofactor(map_to_curve(u[0]) + map_to_curve(u[1]))`, `hash_to_curve` of the elements `hash_to_field` gives.
+/// The sum is enforced to be of distinct points, so it's unsatisfiable for `u[0] = ±u[1]`, as unlikely for hashes as a collision,
+/// and so is the clearing of the cofactor of a point of the torsion, see `NonZeroAffineVarGeneric::clear_cofactor`.
+pub fn hash_to_curve_var<P, F, CF>(u: &[F; 2]) -> Result<NonZeroAffineVarGeneric<P, F, CF>, SynthesisError>
+    where P: WBConfig + CofactorConfig,
+          P::BaseField: PrimeField,
+          CF: PrimeField,
+          F: FieldVar<P::BaseField, CF>,
+          for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+{
+    labeled(&u.cs(), "hash_to_curve", || {
+        let q0 = labeled(&u.cs(), "q0", || map_to_curve(&u[0]))?;
+        let q1 = labeled(&u.cs(), "q1", || map_to_curve(&u[1]))?;
+        q0.add_checked(&q1)?.clear_cofactor()
+    })
+}
+
+/// The map of `WBMap`: `map_to_curve_swu` to the isogenous curve, then the isogeny, the rational functions of which
+/// are evaluated over the powers of `x`, with an inverse of each denominator. They don't vanish but at the kernel of the isogeny,
+/// that the map reaches with negligible probability, and the system is then unsatisfiable.
+pub fn map_to_curve<P, F, CF>(u: &F) -> Result<NonZeroAffineVarGeneric<P, F, CF>, SynthesisError>
+    where P: WBConfig,
+          P::BaseField: PrimeField,
+          CF: PrimeField,
+          F: FieldVar<P::BaseField, CF>,
+          for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+{
+    if u.is_constant() {
+        let u = u.value()?;
+        let p = WBMap::<P>::new().and_then(|map| map.map_to_curve(u)).map_err(|_| SynthesisError::Unsatisfiable)?;
+        return Ok(NonZeroAffineVarGeneric::new(F::constant(p.x), F::constant(p.y)));
+    }
+    let p = map_to_curve_swu::<P::IsogenousCurve, F, CF>(u)?;
+    labeled(&u.cs(), "isogeny", || {
+        let map = P::ISOGENY_MAP;
+        let degree = [map.x_map_numerator, map.x_map_denominator, map.y_map_numerator, map.y_map_denominator].iter().map(|c| c.len()).max().unwrap_or(1);
+        let mut powers = vec![F::one(), p.x.clone()];
+        while powers.len() < degree {
+            powers.push(powers.last().unwrap() * &p.x);
+        }
+        let eval = |coeffs: &[P::BaseField]| coeffs.iter().zip(&powers).fold(F::zero(), |acc, (c, x_i)| acc + x_i.clone() * *c);
+        let x = eval(map.x_map_numerator) * nonzero_inverse(&eval(map.x_map_denominator))?;
+        let y = p.y * eval(map.y_map_numerator) * nonzero_inverse(&eval(map.y_map_denominator))?;
+        Ok(NonZeroAffineVarGeneric::new(x, y))
+    })
+}
+
+/// The simplified SWU map of `SWUMap`, for curves with `a * b != 0`, see the module.
+pub fn map_to_curve_swu<P, F, CF>(u: &F) -> Result<NonZeroAffineVarGeneric<P, F, CF>, SynthesisError>
+    where P: SWUConfig,
+          P::BaseField: PrimeField,
+          CF: PrimeField,
+          F: FieldVar<P::BaseField, CF>,
+          for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+{
+    if u.is_constant() {
+        let p = swu_native::<P>(u.value()?)?;
+        return Ok(NonZeroAffineVarGeneric::new(F::constant(p.x), F::constant(p.y)));
+    }
+    let cs = u.cs();
+    labeled(&cs, "swu", || {
+        let (a, b, z) = (P::COEFF_A, P::COEFF_B, P::ZETA);
+        // `x1 = b * (ta + 1) / (-a * ta)` for `ta = (Z * u^2)^2 + Z * u^2`, or `b / (Z * a)` for `ta = 0`.
+        let zu2 = u.square()? * z;
+        let ta = &zu2.square()? + &zu2;
+        let div = ta.is_zero()?.select(&F::constant(a * z), &(ta.clone() * (-a)))?;
+        let x1 = (ta * b + b).mul_by_inverse_unchecked(&div)?;
+        let gx1 = (x1.square()? + a) * &x1 + b;
+
+        let is_square = Boolean::new_witness(ark_relations::ns!(cs, "is_square"), || Ok(swu_hint::<P>(u.value()?)?.0))?;
+        let y1 = F::new_witness(ark_relations::ns!(cs, "y1"), || Ok(swu_hint::<P>(u.value()?)?.1))?;
+        y1.square_equals(&is_square.select(&gx1, &(gx1.clone() * z))?)?;
+
+        let x = is_square.select(&x1, &(&zu2 * &x1))?;
+        let y = is_square.select(&y1, &(zu2 * u * &y1))?;
+        y.to_bits_le()?[0].enforce_equal(&u.to_bits_le()?[0])?;
+        Ok(NonZeroAffineVarGeneric::new(x, y))
+    })
+}
+
+fn swu_native<P: SWUConfig>(u: P::BaseField) -> Result<Affine<P>, SynthesisError> {
+    SWUMap::<P>::new().and_then(|map| map.map_to_curve(u)).map_err(|_| SynthesisError::Unsatisfiable)
+}
+
+// Whether `x1` is the abscissa of the point `SWUMap` maps `u` to, and the square root `y1` the gadget witnesses:
+// `y` itself then, or `y / (Z * u^3)`, a square root of `Z * g(x1)`, otherwise.
+fn swu_hint<P: SWUConfig>(u: P::BaseField) -> Result<(bool, P::BaseField), SynthesisError> {
+    let p = swu_native::<P>(u)?;
+    let zu2 = P::ZETA * u.square();
+    let ta = zu2.square() + zu2;
+    let x1 = if ta.is_zero() { P::COEFF_B / (P::ZETA * P::COEFF_A) } else { P::COEFF_B * (ta + P::BaseField::one()) / (-P::COEFF_A * ta) };
+    if p.x == x1 {
+        Ok((true, p.y))
+    } else {
+        Ok((false, p.y * (zu2 * u).inverse().ok_or(SynthesisError::Unsatisfiable)?))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ark_ec::short_weierstrass::SWCurveConfig;
+    use ark_r1cs_std::fields::fp::FpVar;
+    use ark_relations::ns;
+    use ark_relations::r1cs::ConstraintSystem;
+    use ark_std::{test_rng, UniformRand};
+
+    use crate::tests::BlsInBls;
+
+    use super::*;
+
+    // The value of a point maybe out of the prime-order subgroup, that `value` rejects.
+    fn value_unchecked<P, F, CF>(p: &NonZeroAffineVarGeneric<P, F, CF>) -> Affine<P>
+        where P: SWCurveConfig,
+              CF: PrimeField,
+              F: FieldVar<P::BaseField, CF>,
+    {
+        Affine::new_unchecked(p.x.value().unwrap(), p.y.value().unwrap())
+    }
+
+    fn check_map<P, F, CF>(us: &[P::BaseField])
+        where P: WBConfig,
+              P::BaseField: PrimeField,
+              CF: PrimeField,
+              F: FieldVar<P::BaseField, CF>,
+              for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+    {
+        let swu = SWUMap::<P::IsogenousCurve>::new().unwrap();
+        let wb = WBMap::<P>::new().unwrap();
+        for &u in us {
+            let cs = ConstraintSystem::<CF>::new_ref();
+            let u_var = F::new_witness(ns!(cs, "u"), || Ok(u)).unwrap();
+            let p = map_to_curve_swu::<P::IsogenousCurve, F, CF>(&u_var).unwrap();
+            assert_eq!(value_unchecked(&p), swu.map_to_curve(u).unwrap());
+            let q = map_to_curve::<P, F, CF>(&u_var).unwrap();
+            assert_eq!(value_unchecked(&q), wb.map_to_curve(u).unwrap());
+            assert!(cs.is_satisfied().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_map_to_curve() {
+        let rng = &mut test_rng();
+        let us: Vec<_> = [0u8, 1].into_iter().map(ark_bls12_377::Fq::from).chain((0..4).map(|_| ark_bls12_377::Fq::rand(rng))).collect();
+        check_map::<ark_bls12_377::g1::Config, FpVar<ark_bls12_377::Fq>, _>(&us);
+        check_map::<ark_bls12_381::g1::Config, BlsInBls, _>(&[ark_bls12_381::Fq::rand(rng)]);
+    }
+
+    #[test]
+    fn test_map_to_curve_sign() {
+        // The other square root, of the other parity, fails the constraints.
+        let rng = &mut test_rng();
+        let u = ark_bls12_377::Fq::rand(rng);
+        let (_, y1) = swu_hint::<<ark_bls12_377::g1::Config as WBConfig>::IsogenousCurve>(u).unwrap();
+        let cs = ConstraintSystem::<ark_bls12_377::Fq>::new_ref();
+        let u_var = FpVar::new_witness(ns!(cs, "u"), || Ok(u)).unwrap();
+        let _ = map_to_curve::<ark_bls12_377::g1::Config, _, _>(&u_var).unwrap();
+        assert!(cs.is_satisfied().unwrap());
+        let mut witness = cs.borrow_mut().unwrap();
+        let i = witness.witness_assignment.iter().position(|w| *w == y1).unwrap();
+        witness.witness_assignment[i] = -y1;
+        drop(witness);
+        assert!(!cs.is_satisfied().unwrap());
+    }
+
+    #[cfg(feature = "bls12-377-bw6")]
+    #[test]
+    fn test_hash_to_curve() {
+        use ark_ec::AffineRepr;
+
+        use crate::apk_circuits::ApkCircuit;
+        use crate::bitmask::Bitmask;
+        use crate::tests::Tracker;
+
+        type P = ark_bls12_377::g1::Config;
+        let domain = b"snowball";
+        let u = hash_to_field::<ark_bls12_377::Fq>(domain, b"msg");
+        let expected = hash_to_curve::<P>(domain, b"msg");
+
+        // Of the constants, as the seed, a constant, and of witnesses.
+        let cs = ConstraintSystem::<ark_bls12_377::Fq>::new_ref();
+        let p = hash_to_curve_var::<P, _, _>(&u.map(FpVar::constant)).unwrap();
+        assert!(p.x.is_constant() && p.y.is_constant());
+        assert_eq!(p.value().unwrap(), expected);
+        assert_eq!(cs.num_constraints(), 0);
+
+        let mut tracker = Tracker::new(&cs);
+        let u_var = u.map(|u| FpVar::new_witness(ns!(cs, "u"), || Ok(u)).unwrap());
+        let p = hash_to_curve_var::<P, _, _>(&u_var).unwrap();
+        println!("hash_to_curve natively: {:?}", tracker.update(&cs));
+        assert_eq!(p.value().unwrap(), expected);
+        assert!(cs.is_satisfied().unwrap());
+
+        let seed = hash_to_curve::<ark_bls12_381::g1::Config>(domain, &[]);
+        assert!(seed.is_in_correct_subgroup_assuming_on_curve() && !seed.is_zero());
+
+        // The seed of the circuit, that the gadget would fold into the same constant.
+        let keys = vec![ark_bls12_377::G1Affine::rand(&mut test_rng())];
+        let circuit = ApkCircuit::<P, ark_bls12_377::Fq, FpVar<_>>::new_with_domain(keys, domain, &Bitmask::from_bits(vec![true])).unwrap();
+        let u = hash_to_field::<ark_bls12_377::Fq>(domain, &[]);
+        assert_eq!(hash_to_curve_var::<P, _, _>(&u.map(FpVar::constant)).unwrap().value().unwrap(), circuit.seed);
+    }
+}
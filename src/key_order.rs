@@ -0,0 +1,125 @@
+//! The canonical order of the keysets, and a gadget enforcing it, so that a keyset committed to elsewhere
+//! can't be permuted by the prover relative to the bits of the bitmask.
+//!
+//! Keys are ordered by `x`, then by `y`, as integers, the order of their big-endian uncompressed encodings.
+//! Equal keys may follow each other, as a validator may sit in a committee more than once.
+
+use std::cmp::Ordering;
+
+use ark_ec::short_weierstrass::{Affine, SWCurveConfig};
+use ark_ff::PrimeField;
+use ark_r1cs_std::boolean::Boolean;
+use ark_r1cs_std::eq::EqGadget;
+use ark_r1cs_std::fields::FieldVar;
+use ark_r1cs_std::select::CondSelectGadget;
+use ark_relations::r1cs::SynthesisError;
+
+use crate::affine_gen::NonZeroAffineVarGeneric;
+use crate::labels::labeled;
+
+/// The canonical order of the keys.
+pub fn canonical_cmp<P: SWCurveConfig>(a: &Affine<P>, b: &Affine<P>) -> Ordering
+    where P::BaseField: PrimeField,
+{
+    (a.x.into_bigint(), a.y.into_bigint()).cmp(&(b.x.into_bigint(), b.y.into_bigint()))
+}
+
+/// Sorts the keys in the canonical order, as the circuit expects them if it enforces it.
+pub fn sort_keys<P: SWCurveConfig>(keys: &mut [Affine<P>])
+    where P::BaseField: PrimeField,
+{
+    keys.sort_by(canonical_cmp)
+}
+
+pub fn is_sorted<P: SWCurveConfig>(keys: &[Affine<P>]) -> bool
+    where P::BaseField: PrimeField,
+{
+    keys.windows(2).all(|w| canonical_cmp(&w[0], &w[1]) != Ordering::Greater)
+}
+
+// The canonical bit decomposition of `x`, then of `y`, most significant bits first.
+fn sort_key<P, F, CF>(key: &NonZeroAffineVarGeneric<P, F, CF>) -> Result<Vec<Boolean<CF>>, SynthesisError>
+    where P: SWCurveConfig,
+          CF: PrimeField,
+          F: FieldVar<P::BaseField, CF>,
+{
+    let mut bits = key.y.to_bits_le()?;
+    bits.extend(key.x.to_bits_le()?);
+    bits.reverse();
+    Ok(bits)
+}
+
+// Whether `a <= b`, for bit strings of the same length, most significant bits first.
+// Scanning from the least significant bit, the comparison is decided by the last bit that differs,
+// a constraint for the difference and one for the selection per bit.
+fn is_le<CF: PrimeField>(a: &[Boolean<CF>], b: &[Boolean<CF>]) -> Result<Boolean<CF>, SynthesisError> {
+    assert_eq!(a.len(), b.len());
+    a.iter().zip(b).rev().try_fold(Boolean::TRUE, |le, (a, b)| {
+        Boolean::conditionally_select(&a.xor(b)?, b, &le)
+    })
+}
+
+/// Enforces the keys to be in the canonical order, decomposing each coordinate into its canonical bits once.
+/// About `4 * log(|F|)` constraints per key on top of the decompositions, labeled `sorted_keys`.
+pub fn enforce_sorted_keys<P, F, CF>(keys: &[NonZeroAffineVarGeneric<P, F, CF>]) -> Result<(), SynthesisError>
+    where P: SWCurveConfig,
+          CF: PrimeField,
+          F: FieldVar<P::BaseField, CF>,
+{
+    let Some(cs) = keys.first().map(|k| k.x.cs()) else { return Ok(()) };
+    labeled(&cs, "sorted_keys", || {
+        let sort_keys = keys.iter().map(sort_key).collect::<Result<Vec<_>, _>>()?;
+        for (i, w) in sort_keys.windows(2).enumerate() {
+            labeled(&cs, i, || is_le(&w[0], &w[1])?.enforce_equal(&Boolean::TRUE))?;
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_r1cs_std::alloc::AllocationMode;
+    use ark_r1cs_std::fields::fp::FpVar;
+    use ark_relations::r1cs::ConstraintSystem;
+    use ark_std::{test_rng, UniformRand};
+
+    use crate::fragments::allocate_keys;
+
+    use super::*;
+
+    fn check<P, F, CF>(keys: Vec<Affine<P>>) -> bool
+        where P: SWCurveConfig,
+              P::BaseField: PrimeField,
+              CF: PrimeField,
+              F: FieldVar<P::BaseField, CF>,
+    {
+        let cs = ConstraintSystem::<CF>::new_ref();
+        let key_vars = allocate_keys::<P, CF, F>(cs.clone(), keys.clone(), AllocationMode::Witness).unwrap();
+        enforce_sorted_keys(&key_vars).unwrap();
+        let satisfied = cs.is_satisfied().unwrap();
+        assert_eq!(satisfied, is_sorted(&keys));
+        satisfied
+    }
+
+    #[test]
+    fn test_enforce_sorted_keys() {
+        let rng = &mut test_rng();
+        let mut keys: Vec<ark_bls12_377::G1Affine> = (0..4).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+        sort_keys(&mut keys);
+        assert!(check::<_, FpVar<ark_bw6_761::Fr>, _>(keys.clone()));
+        // A duplicate, and the negation of a key, that has the same `x`.
+        let (lo, hi) = if canonical_cmp(&keys[0], &-keys[0]) == Ordering::Less { (keys[0], -keys[0]) } else { (-keys[0], keys[0]) };
+        assert!(check::<_, FpVar<ark_bw6_761::Fr>, _>(vec![lo, lo, hi, keys[3]]));
+        assert!(!check::<_, FpVar<ark_bw6_761::Fr>, _>(vec![lo, hi, lo, keys[3]]));
+        keys.swap(1, 2);
+        assert!(!check::<_, FpVar<ark_bw6_761::Fr>, _>(keys));
+
+        let mut keys: Vec<ark_bls12_381::G1Affine> = (0..3).map(|_| ark_bls12_381::G1Affine::rand(rng)).collect();
+        sort_keys(&mut keys);
+        assert!(check::<_, crate::tests::BlsInBls, _>(keys.clone()));
+        keys.reverse();
+        assert!(!check::<_, crate::tests::BlsInBls, _>(keys));
+
+        assert!(enforce_sorted_keys::<ark_bls12_377::g1::Config, FpVar<ark_bw6_761::Fr>, _>(&[]).is_ok());
+    }
+}
@@ -0,0 +1,215 @@
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use ark_r1cs_std::fields::FieldOpsBounds;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Validate};
+use ark_snark::SNARK;
+use rand::{CryptoRng, RngCore};
+use sha2::{Digest, Sha256};
+
+use crate::chain::{Chain, ChainCircuit, CircuitId, KeyBaseField};
+use crate::error::{Result, SnowballError};
+use crate::prover::{Prover, Verifier};
+use crate::serialization::{SerializationMode, to_bytes};
+
+/// On-disk cache of proving and verifying keys, one pair of files per `CircuitId`.
+///
+/// Each file is the SHA-256 of the rest of the file, followed by the serialization mode byte and the key itself.
+/// The mode of the store only affects writing, files in either mode can be loaded.
+#[derive(Clone, Debug)]
+pub struct KeyStore {
+    dir: PathBuf,
+    mode: SerializationMode,
+}
+
+impl KeyStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into(), mode: SerializationMode::default() }
+    }
+
+    pub fn with_mode(self, mode: SerializationMode) -> Self {
+        Self { mode, ..self }
+    }
+
+    fn path(&self, id: &CircuitId, ext: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", id, ext))
+    }
+
+    pub fn save<C: Chain, S: SNARK<C::ConstraintField>>(&self, id: &CircuitId, pk: &S::ProvingKey, vk: &S::VerifyingKey) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        write_hashed(&self.path(id, "pk"), pk, self.mode)?;
+        write_hashed(&self.path(id, "vk"), vk, self.mode)
+    }
+
+    /// Returns `None` if the keys for the circuit haven't been stored.
+    pub fn load<C: Chain, S: SNARK<C::ConstraintField>>(&self, id: &CircuitId) -> Result<Option<(S::ProvingKey, S::VerifyingKey)>> {
+        let (pk_path, vk_path) = (self.path(id, "pk"), self.path(id, "vk"));
+        if !pk_path.exists() || !vk_path.exists() {
+            return Ok(None);
+        }
+        Ok(Some((read_hashed(&pk_path)?, read_hashed(&vk_path)?)))
+    }
+
+    /// Loads the keys of the circuit, `CircuitId::of_circuit`, running and storing the setup if they're missing.
+    pub fn load_or_setup<C, S, R>(&self, circuit: ChainCircuit<C>, rng: &mut R) -> Result<(Prover<C, S>, Verifier<C, S>)>
+        where C: Chain,
+              S: SNARK<C::ConstraintField>,
+              R: RngCore + CryptoRng,
+              for<'a> &'a C::FieldVar: FieldOpsBounds<'a, KeyBaseField<C>, C::FieldVar>,
+    {
+        let config = circuit.config().clone();
+        let id = CircuitId::of_circuit::<C, S>(&circuit);
+        let (pk, vk) = match self.load::<C, S>(&id)? {
+            Some(keys) => keys,
+            None => {
+                let (pk, vk) = S::circuit_specific_setup(circuit, rng).map_err(|e| SnowballError::Snark(Box::new(e)))?;
+                self.save::<C, S>(&id, &pk, &vk)?;
+                (pk, vk)
+            }
+        };
+        Ok((Prover::new(pk).with_config(config.clone()), Verifier::new(&vk)?.with_config(config).with_circuit_id(id)))
+    }
+}
+
+fn write_hashed<T: CanonicalSerialize>(path: &Path, t: &T, mode: SerializationMode) -> Result<()> {
+    let mut bytes = vec![mode.to_byte()];
+    bytes.extend(to_bytes(t, mode)?);
+    let mut file = Sha256::digest(&bytes).to_vec();
+    file.extend(bytes);
+    fs::write(path, file)?;
+    Ok(())
+}
+
+// Deserializes straight from the file, hashing the bytes as they go by, so that the serialized key
+// is never held in memory alongside the deserialized one. For BW6-761 proving keys that halves the peak.
+fn read_hashed<T: CanonicalDeserialize>(path: &Path) -> Result<T> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hash = [0u8; 32];
+    reader.read_exact(&mut hash).map_err(|_| SnowballError::Integrity)?;
+    let mut reader = HashingReader { inner: reader, hasher: Sha256::new() };
+    let mut mode = [0u8];
+    reader.read_exact(&mut mode).map_err(|_| SnowballError::Integrity)?;
+    let mode = SerializationMode::from_byte(mode[0]).map_err(|_| SnowballError::Integrity)?;
+    // The files are written by `save` and the hash guards against corruption, so the costly curve checks are skipped.
+    let t = T::deserialize_with_mode(&mut reader, mode.compress(), Validate::No);
+    let trailing = std::io::copy(&mut reader, &mut std::io::sink())?;
+    if trailing > 0 || reader.hasher.finalize().as_slice() != hash {
+        return Err(SnowballError::Integrity);
+    }
+    Ok(t?)
+}
+
+struct HashingReader<R: Read> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+#[cfg(all(test, feature = "bls12-377-bw6"))]
+mod tests {
+    use ark_groth16::Groth16;
+    use ark_std::UniformRand;
+    use rand::rngs::OsRng;
+
+    use crate::bitmask::Bitmask;
+    use crate::chain::Bls377InBw6;
+    use crate::config::{CommitmentScheme, Config};
+
+    use super::*;
+
+    type Bw6Groth16 = Groth16<ark_bw6_761::BW6_761>;
+
+    #[test]
+    fn test_load_or_setup() {
+        let rng = &mut OsRng;
+        let dir = std::env::temp_dir().join(format!("snowball-keystore-{}", u64::rand(rng)));
+        let store = KeyStore::new(&dir);
+        let keys = vec![ark_bls12_377::G1Affine::rand(rng)];
+        let seed = ark_bls12_377::G1Affine::rand(rng);
+        let bitmask = Bitmask::from_bits(vec![true]);
+        let circuit = ChainCircuit::<Bls377InBw6>::new(keys.clone(), seed, &bitmask).unwrap();
+        let id = CircuitId::of_circuit::<Bls377InBw6, Bw6Groth16>(&circuit);
+
+        assert!(store.load::<Bls377InBw6, Bw6Groth16>(&id).unwrap().is_none());
+        let (prover, _) = store.load_or_setup::<Bls377InBw6, Bw6Groth16, _>(circuit.clone(), rng).unwrap();
+        let (_, verifier) = store.load_or_setup::<Bls377InBw6, Bw6Groth16, _>(circuit.clone(), rng).unwrap();
+        let proof = prover.prove(circuit, rng).unwrap();
+        assert!(verifier.verify(&keys, &bitmask, &proof).unwrap());
+
+        let vk_path = dir.join(format!("{}.vk", id));
+        let mut file = fs::read(&vk_path).unwrap();
+        *file.last_mut().unwrap() ^= 1;
+        fs::write(&vk_path, file).unwrap();
+        assert!(matches!(store.load::<Bls377InBw6, Bw6Groth16>(&id), Err(SnowballError::Integrity)));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_config() {
+        let rng = &mut OsRng;
+        let dir = std::env::temp_dir().join(format!("snowball-keystore-{}", u64::rand(rng)));
+        let store = KeyStore::new(&dir);
+        let keys = vec![ark_bls12_377::G1Affine::rand(rng)];
+        let seed = ark_bls12_377::G1Affine::rand(rng);
+        let bitmask = Bitmask::from_bits(vec![true]);
+        let config = Config { commitment: CommitmentScheme::CompressedInputs, ..Default::default() };
+        let circuit = ChainCircuit::<Bls377InBw6>::new(keys.clone(), seed, &bitmask).unwrap().with_config(config.clone());
+        let id = CircuitId::of_circuit::<Bls377InBw6, Bw6Groth16>(&circuit);
+        let default_id = CircuitId::new::<Bls377InBw6, Bw6Groth16>(1, &seed, &Config::default());
+        assert_ne!(id, default_id);
+
+        let (prover, verifier) = store.load_or_setup::<Bls377InBw6, Bw6Groth16, _>(circuit.clone(), rng).unwrap();
+        assert!(store.load::<Bls377InBw6, Bw6Groth16>(&id).unwrap().is_some());
+        assert!(store.load::<Bls377InBw6, Bw6Groth16>(&default_id).unwrap().is_none());
+        let proof = prover.prove(circuit, rng).unwrap();
+        assert!(verifier.verify(&keys, &bitmask, &proof).unwrap());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_seed() {
+        let rng = &mut OsRng;
+        let dir = std::env::temp_dir().join(format!("snowball-keystore-{}", u64::rand(rng)));
+        let store = KeyStore::new(&dir);
+        let keys = vec![ark_bls12_377::G1Affine::rand(rng)];
+        let bitmask = Bitmask::from_bits(vec![true]);
+        let circuit = |seed| ChainCircuit::<Bls377InBw6>::new(keys.clone(), seed, &bitmask).unwrap();
+        let (c1, c2) = (circuit(ark_bls12_377::G1Affine::rand(rng)), circuit(ark_bls12_377::G1Affine::rand(rng)));
+        let (id1, id2) = (CircuitId::of_circuit::<Bls377InBw6, Bw6Groth16>(&c1), CircuitId::of_circuit::<Bls377InBw6, Bw6Groth16>(&c2));
+        assert_ne!(id1, id2);
+        assert_ne!(id1.to_string(), id2.to_string());
+
+        // The keys of the first seed aren't those of the second.
+        store.load_or_setup::<Bls377InBw6, Bw6Groth16, _>(c1, rng).unwrap();
+        assert!(store.load::<Bls377InBw6, Bw6Groth16>(&id2).unwrap().is_none());
+        let (prover, verifier) = store.load_or_setup::<Bls377InBw6, Bw6Groth16, _>(c2.clone(), rng).unwrap();
+        let proof = prover.prove(c2, rng).unwrap();
+        assert!(verifier.verify(&keys, &bitmask, &proof).unwrap());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_modes() {
+        let rng = &mut OsRng;
+        let dir = std::env::temp_dir().join(format!("snowball-keystore-{}", u64::rand(rng)));
+        fs::create_dir_all(&dir).unwrap();
+        let points: Vec<ark_bls12_377::G1Affine> = (0..3).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+        for mode in [SerializationMode::Compressed, SerializationMode::Uncompressed] {
+            let path = dir.join("points");
+            write_hashed(&path, &points, mode).unwrap();
+            assert_eq!(read_hashed::<Vec<ark_bls12_377::G1Affine>>(&path).unwrap(), points);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+//! Structured labels of the variables and constraints, like `keys/17/x/3` or `acc/step/42/lambda`.
+//!
+//! `ark_relations::ns!` only takes static names, so the labels are kept in a table of their own,
+//! stored in the cache map of the constraint system. Labeling is off unless enabled on the constraint system,
+//! which `Config::apply` does when `Config::labels` is set, as it is by default in debug builds.
+
+use std::any::{Any, TypeId};
+use std::fmt;
+use std::ops::Range;
+
+use ark_ff::Field;
+use ark_relations::r1cs::ConstraintSystemRef;
+
+/// A labeled range of constraints and variables, allocated between the entry and the exit of the label.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LabelEntry {
+    pub path: String,
+    pub constraints: Range<usize>,
+    pub witnesses: Range<usize>,
+    pub instances: Range<usize>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LabelTable {
+    entries: Vec<LabelEntry>,
+}
+
+impl LabelTable {
+    /// Entries in the order their labels were exited, so inner labels come before the outer ones.
+    pub fn entries(&self) -> &[LabelEntry] {
+        &self.entries
+    }
+
+    /// Label of the `i`-th constraint.
+    pub fn constraint(&self, i: usize) -> Option<String> {
+        self.lookup(i, |e| &e.constraints)
+    }
+
+    /// Label of the `i`-th witness variable.
+    pub fn witness(&self, i: usize) -> Option<String> {
+        self.lookup(i, |e| &e.witnesses)
+    }
+
+    /// Label of the `i`-th instance variable, counting the constant `1` as the `0`-th.
+    pub fn instance(&self, i: usize) -> Option<String> {
+        self.lookup(i, |e| &e.instances)
+    }
+
+    // The innermost label containing the index, with the offset within its range appended
+    // when it spans several items, as the limbs of a non-native coordinate do.
+    fn lookup(&self, i: usize, range: impl Fn(&LabelEntry) -> &Range<usize>) -> Option<String> {
+        let entry = self.entries.iter().find(|e| range(e).contains(&i))?;
+        let r = range(entry);
+        if r.len() > 1 {
+            Some(format!("{}/{}", entry.path, i - r.start))
+        } else {
+            Some(entry.path.clone())
+        }
+    }
+}
+
+#[derive(Default)]
+struct LabelState {
+    stack: Vec<String>,
+    table: LabelTable,
+}
+
+fn with_state<F: Field, T>(cs: &ConstraintSystemRef<F>, f: impl FnOnce(&mut LabelState) -> T) -> Option<T> {
+    let cs = cs.borrow()?;
+    let mut cache = cs.cache_map.borrow_mut();
+    let state = cache.get_mut(&TypeId::of::<LabelState>())?.downcast_mut::<LabelState>()?;
+    Some(f(state))
+}
+
+pub fn enable_labels<F: Field>(cs: &ConstraintSystemRef<F>) {
+    if let Some(cs) = cs.borrow() {
+        cs.cache_map.borrow_mut()
+            .entry(TypeId::of::<LabelState>())
+            .or_insert_with(|| Box::new(LabelState::default()) as Box<dyn Any>);
+    }
+}
+
+/// The labels recorded so far, `None` if labeling isn't enabled.
+pub fn labels<F: Field>(cs: &ConstraintSystemRef<F>) -> Option<LabelTable> {
+    with_state(cs, |state| state.table.clone())
+}
+
+/// Runs `f` under the label `segment`, appended to the path of the enclosing labels.
+/// The segment is only formatted when labeling is enabled.
+pub fn labeled<F: Field, T, E>(cs: &ConstraintSystemRef<F>, segment: impl fmt::Display, f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+    let start = with_state(cs, |state| state.stack.push(segment.to_string()))
+        .map(|()| (cs.num_constraints(), cs.num_witness_variables(), cs.num_instance_variables()));
+    let res = f();
+    if let Some((constraints, witnesses, instances)) = start {
+        let end = (cs.num_constraints(), cs.num_witness_variables(), cs.num_instance_variables());
+        with_state(cs, |state| {
+            let path = state.stack.join("/");
+            state.stack.pop();
+            state.table.entries.push(LabelEntry {
+                path,
+                constraints: constraints..end.0,
+                witnesses: witnesses..end.1,
+                instances: instances..end.2,
+            });
+        });
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_r1cs_std::alloc::AllocVar;
+    use ark_r1cs_std::eq::EqGadget;
+    use ark_r1cs_std::fields::fp::FpVar;
+    use ark_relations::r1cs::{ConstraintSystem, SynthesisError};
+
+    use super::*;
+
+    type F = ark_bls12_381::Fr;
+
+    #[test]
+    fn test_labels() {
+        let cs = ConstraintSystem::<F>::new_ref();
+        assert!(labels(&cs).is_none());
+        enable_labels(&cs);
+        labeled(&cs, "keys", || {
+            for i in 0..2 {
+                labeled(&cs, i, || {
+                    let a = FpVar::new_input(cs.clone(), || Ok(F::from(1u8)))?;
+                    let b = labeled(&cs, "b", || Vec::<FpVar<F>>::new_witness(cs.clone(), || Ok(vec![F::from(1u8); 3])))?;
+                    a.enforce_equal(&b[2])
+                })?;
+            }
+            Ok::<_, SynthesisError>(())
+        }).unwrap();
+
+        let table = labels(&cs).unwrap();
+        assert_eq!(table.instance(2).unwrap(), "keys/1");
+        assert_eq!(table.witness(4).unwrap(), "keys/1/b/1");
+        assert_eq!(table.constraint(0).unwrap(), "keys/0");
+        assert_eq!(table.entries().last().unwrap().path, "keys");
+    }
+}
@@ -1,6 +1,54 @@
-mod affine_gen;
-mod apk_circuits;
-mod sum_acc;
+pub mod affine_gen;
+pub mod aggregation;
+pub mod apk_circuits;
+pub mod batch_inv;
+pub mod bitmask;
+#[cfg(feature = "bls12-381-emulated")]
+pub mod bls_keys;
+#[cfg(feature = "blst")]
+pub mod blst_interop;
+pub mod chain;
+pub mod churn;
+pub mod circuit_semantics;
+#[cfg(feature = "composition")]
+pub mod composition;
+pub mod config;
+pub mod digest;
+pub mod dot;
+pub mod encoding;
+pub mod envelope;
+pub mod error;
+#[cfg(feature = "bls12-381-emulated")]
+pub mod eth2;
+pub mod fragments;
+pub mod hash_to_curve;
+pub mod key_order;
+pub mod keystore;
+pub mod labels;
+pub mod limb_mul;
+pub mod montgomery_gen;
+pub mod prelude;
+pub mod profiling;
+pub mod projective_gen;
+pub mod proof_chain;
+pub mod r1cs_std_interop;
+pub mod rle;
+pub mod scalar_mul;
+pub mod prover;
+pub mod schema;
+pub mod serialization;
+pub mod shards;
+pub mod signers;
+#[cfg(feature = "sponge")]
+pub mod sponge;
+pub mod stake;
+pub mod sum_acc;
+pub mod te_affine_gen;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+pub mod tuning;
+pub mod vectors;
+pub mod witness;
 
 #[cfg(test)]
 mod tests {
@@ -14,7 +62,7 @@ mod tests {
     #[derive(Derivative)]
     #[derivative(Debug, Clone)]
     pub struct Tracker {
-        num_constraints: usize,
+        pub num_constraints: usize,
         num_witness_variables: usize,
         num_instance_variables: usize,
     }
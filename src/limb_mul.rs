@@ -0,0 +1,208 @@
+//! Products of emulated field elements, with the limb products computed by Karatsuba's method.
+//!
+//! `ark-r1cs-std` multiplies the limbs either by evaluating the product polynomial at `2n - 1` points
+//! (`Optimization::Constraints`), that is already the least number of constraints, but dense,
+//! or with the `n^2` schoolbook products (`Optimization::Weight`), that are sparse.
+//! Karatsuba's method takes `3^log2(n)` products instead of `n^2`, each of sums of limbs, that are free.
+//! The `Constraints`-optimized limbs are many more, so the evaluation stays cheaper for them.
+//!
+//! Measured by `test_cost`, per multiplication with the product limbs materialized, as the reduction does
+//! (constraints / weight after inlining):
+//!
+//! | fields                       | limbs | schoolbook | Karatsuba |
+//! |------------------------------|-------|------------|-----------|
+//! | BLS12-381 Fq in BLS12-381 Fr | 8     | 86 / 307   | 61 / 307  |
+//! | BLS12-377 Fq in BLS12-381 Fr | 8     | 86 / 307   | 61 / 307  |
+//! | BLS12-381 Fq in BW6-761 Fr   | 8     | 86 / 307   | 61 / 307  |
+//!
+//! The weight-optimized parameters settle on 8 limbs for all the fields at hand, so the saving is the same
+//! for all of them, and as every key costs the same number of multiplications, it's the same share for any number of keys.
+//! The native chains have nothing to gain.
+//!
+//! `Config::multiplication` selects the method for the two products of each step of `SumAccumulator`,
+//! the squarings and the reductions being left to `ark-r1cs-std`. For the BLS12-381 chain with `Optimization::Weight`
+//! and `AdditionStrategy::Accumulated`, `benches/multiplication.rs` measures:
+//!
+//! | keys | schoolbook             | Karatsuba              |
+//! |------|------------------------|------------------------|
+//! | 4    | 16719 / 64239, 1.06 s  | 16547 / 64260, 1.18 s  |
+//! | 16   | 62085 / 238611, 2.33 s | 61301 / 238608, 2.40 s |
+//!
+//! (constraints / weight, proving time). About 50 constraints fewer per key out of 3900, with the proving time
+//! within the noise at any number of keys, so the schoolbook products stay the default.
+
+use ark_ff::PrimeField;
+use ark_r1cs_std::alloc::AllocVar;
+use ark_r1cs_std::fields::FieldVar;
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_r1cs_std::fields::nonnative::{AllocatedNonNativeFieldMulResultVar, AllocatedNonNativeFieldVar, NonNativeFieldMulResultVar, NonNativeFieldVar};
+use ark_r1cs_std::fields::nonnative::params::{get_params, OptimizationType};
+use ark_relations::r1cs::{OptimizationGoal, SynthesisError};
+
+use crate::config::Multiplication;
+
+// Karatsuba's 3 products of 2 limbs each beat the 4 schoolbook ones, so it recurses down to single limbs.
+const KARATSUBA_THRESHOLD: usize = 2;
+
+/// `a * b` without the reduction, the limb products computed according to `strategy`.
+///
+/// Karatsuba applies to the `Weight`-optimized limbs only, as the `Constraints`-optimized multiplication
+/// is cheaper in constraints already. It also falls back to `ark-r1cs-std` for the operands that need reducing
+/// before the multiplication, as the reduction isn't exposed by it.
+/// A constant operand takes the limbs of the constraint system of the other one, where `ark-r1cs-std` gives it
+/// the `Constraints`-optimized limbs whatever the goal, and fails on the mismatch for a constant left operand.
+pub fn mul_without_reduce<TF, CF>(a: &NonNativeFieldVar<TF, CF>, b: &NonNativeFieldVar<TF, CF>, strategy: Multiplication) -> Result<NonNativeFieldMulResultVar<TF, CF>, SynthesisError>
+    where
+        TF: PrimeField,
+        CF: PrimeField,
+{
+    let (a, b) = match (a, b) {
+        (NonNativeFieldVar::Constant(a), NonNativeFieldVar::Constant(b)) => return Ok(NonNativeFieldMulResultVar::Constant(*a * b)),
+        (NonNativeFieldVar::Constant(a), NonNativeFieldVar::Var(b)) => (AllocatedNonNativeFieldVar::new_constant(b.cs.clone(), a)?, b.clone()),
+        (NonNativeFieldVar::Var(a), NonNativeFieldVar::Constant(b)) => (a.clone(), AllocatedNonNativeFieldVar::new_constant(a.cs.clone(), b)?),
+        (NonNativeFieldVar::Var(a), NonNativeFieldVar::Var(b)) => (a.clone(), b.clone()),
+    };
+    if strategy == Multiplication::Karatsuba && a.cs.optimization_goal() == OptimizationGoal::Weight && fits_without_reduction(&a, &b) {
+        let limbs = karatsuba(&a.limbs, &b.limbs)?;
+        let prod_of_num_of_additions = (a.num_of_additions_over_normal_form + CF::one()) * (b.num_of_additions_over_normal_form + CF::one());
+        return Ok(NonNativeFieldMulResultVar::Var(AllocatedNonNativeFieldMulResultVar {
+            cs: a.cs.clone(),
+            limbs,
+            prod_of_num_of_additions,
+            target_phantom: Default::default(),
+        }));
+    }
+    Ok(NonNativeFieldMulResultVar::Var(a.mul_without_reduce(&b)?))
+}
+
+// Mirrors the check of `Reducer::pre_mul_reduce` for the `Weight`-optimized limbs, erring on the side of the reduction.
+fn fits_without_reduction<TF: PrimeField, CF: PrimeField>(a: &AllocatedNonNativeFieldVar<TF, CF>, b: &AllocatedNonNativeFieldVar<TF, CF>) -> bool {
+    let params = get_params(TF::MODULUS_BIT_SIZE as usize, CF::MODULUS_BIT_SIZE as usize, OptimizationType::Weight);
+    let prod_of_num_of_additions = (a.num_of_additions_over_normal_form + CF::one()) * (b.num_of_additions_over_normal_form + CF::one());
+    let overhead = (prod_of_num_of_additions * CF::from(params.num_limbs as u64)).into_bigint();
+    let overhead_bits = ark_ff::BigInteger::num_bits(&overhead) as usize;
+    2 * (params.bits_per_limb + 1) + overhead_bits < CF::MODULUS_BIT_SIZE as usize
+}
+
+/// Coefficients of the product of the polynomials with the coefficients `a` and `b`, of the same length.
+/// The coefficients agree with the schoolbook ones as field elements, so whatever bounds the reduction
+/// assumes about them hold regardless of how large the intermediate sums get.
+fn karatsuba<F: PrimeField>(a: &[FpVar<F>], b: &[FpVar<F>]) -> Result<Vec<FpVar<F>>, SynthesisError> {
+    debug_assert_eq!(a.len(), b.len());
+    let n = a.len();
+    if n < KARATSUBA_THRESHOLD {
+        let mut res = vec![FpVar::zero(); 2 * n - 1];
+        for (i, ai) in a.iter().enumerate() {
+            for (j, bj) in b.iter().enumerate() {
+                res[i + j] += ai * bj;
+            }
+        }
+        return Ok(res);
+    }
+
+    // The high halves are at least as long as the low ones.
+    let m = n / 2;
+    let (a0, a1) = a.split_at(m);
+    let (b0, b1) = b.split_at(m);
+    let sum = |lo: &[FpVar<F>], hi: &[FpVar<F>]| -> Vec<FpVar<F>> {
+        hi.iter().enumerate().map(|(i, h)| lo.get(i).map_or(h.clone(), |l| l + h)).collect()
+    };
+
+    let z0 = karatsuba(a0, b0)?;
+    let z2 = karatsuba(a1, b1)?;
+    let mut z1 = karatsuba(&sum(a0, a1), &sum(b0, b1))?;
+    for (i, z) in z0.iter().enumerate() {
+        z1[i] -= z;
+    }
+    for (i, z) in z2.iter().enumerate() {
+        z1[i] -= z;
+    }
+
+    let mut res = vec![FpVar::zero(); 2 * n - 1];
+    for (i, z) in z0.into_iter().enumerate() {
+        res[i] += z;
+    }
+    for (i, z) in z1.into_iter().enumerate() {
+        res[i + m] += z;
+    }
+    for (i, z) in z2.into_iter().enumerate() {
+        res[i + 2 * m] += z;
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_r1cs_std::eq::EqGadget;
+    use ark_r1cs_std::R1CSVar;
+    use ark_relations::r1cs::{ConstraintSystem, SynthesisMode};
+    use ark_std::{test_rng, UniformRand};
+
+    use super::*;
+
+    // Constraints and weight of a multiplication of two fresh witnesses, including an equality constraint
+    // per limb of the product, as the reduction takes one.
+    fn cost<TF: PrimeField, CF: PrimeField>(goal: OptimizationGoal, strategy: Option<Multiplication>) -> (usize, usize) {
+        let rng = &mut test_rng();
+        let cs = ConstraintSystem::<CF>::new_ref();
+        cs.set_optimization_goal(goal);
+        cs.set_mode(SynthesisMode::Setup);
+        let a = NonNativeFieldVar::<TF, CF>::new_witness(cs.clone(), || Ok(TF::rand(rng))).unwrap();
+        let b = NonNativeFieldVar::<TF, CF>::new_witness(cs.clone(), || Ok(TF::rand(rng))).unwrap();
+        if let Some(strategy) = strategy {
+            if let NonNativeFieldMulResultVar::Var(prod) = mul_without_reduce(&a, &b, strategy).unwrap() {
+                for limb in prod.limbs {
+                    FpVar::new_witness(cs.clone(), || Ok(CF::zero())).unwrap().enforce_equal(&limb).unwrap();
+                }
+            }
+        }
+        cs.finalize();
+        let m = cs.to_matrices().unwrap();
+        (m.num_constraints, m.a_num_non_zero + m.b_num_non_zero + m.c_num_non_zero)
+    }
+
+    fn mul_cost<TF: PrimeField, CF: PrimeField>(goal: OptimizationGoal, strategy: Multiplication) -> (usize, usize) {
+        let base = cost::<TF, CF>(goal, None);
+        let total = cost::<TF, CF>(goal, Some(strategy));
+        (total.0 - base.0, total.1 - base.1)
+    }
+
+    #[test]
+    fn test_karatsuba() {
+        type TF = ark_bls12_381::Fq;
+        type CF = ark_bls12_381::Fr;
+        let rng = &mut test_rng();
+        let cs = ConstraintSystem::<CF>::new_ref();
+        cs.set_optimization_goal(OptimizationGoal::Weight);
+        let (x, y) = (TF::rand(rng), TF::rand(rng));
+        let a = NonNativeFieldVar::<TF, CF>::new_witness(cs.clone(), || Ok(x)).unwrap();
+        let b = NonNativeFieldVar::<TF, CF>::new_witness(cs.clone(), || Ok(y)).unwrap();
+        let a = &a + &b;
+
+        let schoolbook = mul_without_reduce(&a, &b, Multiplication::Schoolbook).unwrap();
+        let karatsuba = mul_without_reduce(&a, &b, Multiplication::Karatsuba).unwrap();
+        if let (NonNativeFieldMulResultVar::Var(s), NonNativeFieldMulResultVar::Var(k)) = (&schoolbook, &karatsuba) {
+            assert_eq!(s.limbs.value().unwrap(), k.limbs.value().unwrap());
+        } else {
+            panic!("expected variables");
+        }
+        assert_eq!(karatsuba.reduce().unwrap().value().unwrap(), (x + y) * y);
+        assert!(cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn test_cost() {
+        fn report<TF: PrimeField, CF: PrimeField>(name: &str) {
+            let schoolbook = mul_cost::<TF, CF>(OptimizationGoal::Weight, Multiplication::Schoolbook);
+            let karatsuba = mul_cost::<TF, CF>(OptimizationGoal::Weight, Multiplication::Karatsuba);
+            let evaluation = mul_cost::<TF, CF>(OptimizationGoal::Constraints, Multiplication::Schoolbook);
+            let limbs = |opt| get_params(TF::MODULUS_BIT_SIZE as usize, CF::MODULUS_BIT_SIZE as usize, opt).num_limbs;
+            println!("{}: {} weight-optimized limbs: schoolbook {:?}, karatsuba {:?}; {} constraint-optimized limbs: {:?}",
+                     name, limbs(OptimizationType::Weight), schoolbook, karatsuba, limbs(OptimizationType::Constraints), evaluation);
+            assert!(karatsuba.0 < schoolbook.0);
+        }
+        report::<ark_bls12_381::Fq, ark_bls12_381::Fr>("bls12-381 fq in fr");
+        report::<ark_bls12_377::Fq, ark_bls12_381::Fr>("bls12-377 fq in bls12-381 fr");
+        report::<ark_bls12_381::Fq, ark_bw6_761::Fr>("bls12-381 fq in bw6-761 fr");
+    }
+}
@@ -0,0 +1,49 @@
+pub use ark_r1cs_std::alloc::AllocVar;
+pub use ark_r1cs_std::R1CSVar;
+pub use ark_relations::r1cs::ConstraintSynthesizer;
+
+pub use crate::affine_gen::{AffineVarGeneric, NonZeroAffineVarGeneric};
+pub use crate::aggregation::{aggregate, aggregate_ct};
+pub use crate::apk_circuits::{ApkCircuit, ApkCircuitFixed, keys_to_limbs, limb_params, LimbParams, public_inputs};
+pub use crate::bitmask::Bitmask;
+pub use crate::chain::{AnyChain, Chain, ChainCircuit, ChainVisitor, CircuitId};
+pub use crate::churn::{ChurnCircuit, count_changes, enforce_max_changes};
+pub use crate::circuit_semantics::{consistency_check, Divergence};
+pub use crate::config::{AdditionStrategy, CommitmentScheme, Config, Multiplication, Optimization};
+pub use crate::digest::{derive_seed, keyset_digest};
+pub use crate::dot::{circuit_to_dot, to_dot};
+pub use crate::encoding::TextEncoding;
+pub use crate::envelope::{ProofEnvelope, PublicData};
+pub use crate::error::SnowballError;
+pub use crate::fragments::{CircuitFn, LimbVar, VarKind};
+pub use crate::hash_to_curve::{hash_to_curve, hash_to_curve_var, hash_to_field, map_to_curve, map_to_curve_swu};
+pub use crate::key_order::{canonical_cmp, enforce_sorted_keys, is_sorted, sort_keys};
+pub use crate::keystore::KeyStore;
+pub use crate::montgomery_gen::MontgomeryXVarGeneric;
+pub use crate::proof_chain::{EpochProof, ProofChain};
+pub use crate::profiling::{check_budget, measure_synthesis, profile_synthesis, CsCounts, MemoryUsage, PhaseReport, Report};
+pub use crate::projective_gen::ProjectiveVarGeneric;
+pub use crate::prover::{index, PreparedKeys, Prover, setup, Verifier};
+pub use crate::rle::{decode_rle, RleBitmask};
+pub use crate::scalar_mul::{CofactorConfig, endomorphism, fixed_base_mul, FIXED_BASE_WINDOW, glv_mul, GlvConfig, msm};
+pub use crate::schema::{expected_shape, instance_layout, CircuitShape, InstanceLayout, point_to_instance};
+pub use crate::serialization::SerializationMode;
+pub use crate::shards::ShardedCircuit;
+pub use crate::signers::SignerIndicesVar;
+pub use crate::stake::{StakeTable, StakeTableVar};
+pub use crate::sum_acc::{sum_points, sum_points_checked, sum_points_tree, sum_points_tree_checked, AccumulatorField, PointSummation, SumAccumulator, SumPoints};
+pub use crate::te_affine_gen::NonZeroTEAffineVarGeneric;
+pub use crate::tuning::{probe, ProbeCost};
+pub use crate::vectors::{TestVector, VECTORS_VERSION};
+pub use crate::witness::{Assignment, constraint_matrices, extract_assignment};
+
+#[cfg(feature = "bls12-377-bw6")]
+pub use crate::chain::Bls377InBw6;
+#[cfg(feature = "bls12-381-emulated")]
+pub use crate::chain::Bls381Emulated;
+#[cfg(feature = "insecure-setup")]
+pub use crate::prover::setup_deterministic;
+#[cfg(feature = "blst")]
+pub use crate::blst_interop::pre_verify;
+#[cfg(feature = "composition")]
+pub use crate::composition::{ComposedProver, ComposedVerifier, outer_inputs, OuterCircuit, setup_composed};
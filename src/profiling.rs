@@ -0,0 +1,362 @@
+use std::any::{Any, TypeId};
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use ark_ff::Field;
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystem, ConstraintSystemRef, SynthesisError, SynthesisMode};
+
+use crate::error::SnowballError;
+
+/// Size of a constraint system at some point of synthesis, or the growth between two such points.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CsCounts {
+    pub num_constraints: usize,
+    pub num_witness_variables: usize,
+    pub num_instance_variables: usize,
+}
+
+impl CsCounts {
+    pub fn of<F: Field>(cs: &ConstraintSystemRef<F>) -> Self {
+        Self {
+            num_constraints: cs.num_constraints(),
+            num_witness_variables: cs.num_witness_variables(),
+            num_instance_variables: cs.num_instance_variables(),
+        }
+    }
+
+    /// Growth from `self` to `later`.
+    pub fn delta(&self, later: &Self) -> Self {
+        Self {
+            num_constraints: later.num_constraints - self.num_constraints,
+            num_witness_variables: later.num_witness_variables - self.num_witness_variables,
+            num_instance_variables: later.num_instance_variables - self.num_instance_variables,
+        }
+    }
+}
+
+/// Memory held by the variable vectors of a constraint system, along with the peak memory of the process.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub instance_bytes: usize,
+    pub witness_bytes: usize,
+    pub num_linear_combinations: usize,
+    /// `None` where the platform doesn't report it.
+    pub peak_rss_bytes: Option<u64>,
+}
+
+impl MemoryUsage {
+    pub fn of<F: Field>(cs: &ConstraintSystemRef<F>) -> Self {
+        let (instance_bytes, witness_bytes, num_linear_combinations) = cs.borrow()
+            .map(|cs| (
+                cs.instance_assignment.capacity() * std::mem::size_of::<F>(),
+                cs.witness_assignment.capacity() * std::mem::size_of::<F>(),
+                cs.num_linear_combinations,
+            ))
+            .unwrap_or_default();
+        Self { instance_bytes, witness_bytes, num_linear_combinations, peak_rss_bytes: peak_rss_bytes() }
+    }
+}
+
+/// Peak resident set size of the process so far, read from `/proc` on Linux.
+pub fn peak_rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kb: u64 = line.trim_start_matches("VmHWM:").trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// Synthesizes the circuit in proving mode, the way the prover does, and reports the size of the result,
+/// so that one can check a circuit fits in memory before running a setup or a prover on it.
+pub fn measure_synthesis<F: Field, C: ConstraintSynthesizer<F>>(circuit: C) -> Result<(CsCounts, MemoryUsage), SynthesisError> {
+    let cs = ConstraintSystem::<F>::new_ref();
+    circuit.generate_constraints(cs.clone())?;
+    cs.finalize();
+    Ok((CsCounts::of(&cs), MemoryUsage::of(&cs)))
+}
+
+/// The growth of the constraint system over a synthesis phase, and the time it took.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PhaseReport {
+    pub name: String,
+    pub counts: CsCounts,
+    pub duration: Duration,
+}
+
+/// The phases of a synthesis, in the order they ended, and the totals, for tracking the shape
+/// and the synthesis time of the circuits across versions.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Report {
+    pub phases: Vec<PhaseReport>,
+    pub total: CsCounts,
+    pub duration: Duration,
+}
+
+impl Report {
+    /// A row per phase and a last one for the totals, named `total`, the durations in seconds.
+    pub fn to_csv(&self) -> String {
+        let mut csv = "phase,constraints,witnesses,instances,seconds\n".to_string();
+        let rows = self.phases.iter().map(|p| (p.name.as_str(), &p.counts, p.duration))
+            .chain([("total", &self.total, self.duration)]);
+        for (name, counts, duration) in rows {
+            writeln!(csv, "{},{},{},{},{}", name, counts.num_constraints, counts.num_witness_variables, counts.num_instance_variables, duration.as_secs_f64()).unwrap();
+        }
+        csv
+    }
+
+    /// The same fields as the CSV, as `{"phases": [...], "total": {...}}`.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> String {
+        let phases = self.phases.iter().map(|p| JsonRow::new(&p.name, &p.counts, p.duration)).collect();
+        let json = JsonReport { phases, total: JsonRow::new("total", &self.total, self.duration) };
+        serde_json::to_string(&json).expect("a report serializes")
+    }
+}
+
+// A row of `Report::to_csv`, as `Report::to_json` serializes it.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct JsonRow<'a> {
+    phase: &'a str,
+    constraints: usize,
+    witnesses: usize,
+    instances: usize,
+    seconds: f64,
+}
+
+#[cfg(feature = "serde")]
+impl<'a> JsonRow<'a> {
+    fn new(phase: &'a str, counts: &CsCounts, duration: Duration) -> Self {
+        Self {
+            phase,
+            constraints: counts.num_constraints,
+            witnesses: counts.num_witness_variables,
+            instances: counts.num_instance_variables,
+            seconds: duration.as_secs_f64(),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct JsonReport<'a> {
+    phases: Vec<JsonRow<'a>>,
+    total: JsonRow<'a>,
+}
+
+#[derive(Default)]
+struct ReportState {
+    phases: Vec<PhaseReport>,
+    budget: Option<usize>,
+}
+
+// Synthesizes the circuit recording its phases, stopping at the end of the first phase past the budget, if any.
+fn synthesize_profiled<F: Field, C: ConstraintSynthesizer<F>>(circuit: C, cs: ConstraintSystemRef<F>, budget: Option<usize>) -> (Result<(), SynthesisError>, Report) {
+    if let Some(cs) = cs.borrow() {
+        cs.cache_map.borrow_mut().insert(TypeId::of::<ReportState>(), Box::new(ReportState { phases: vec![], budget }) as Box<dyn Any>);
+    }
+    let start = Instant::now();
+    let res = circuit.generate_constraints(cs.clone());
+    if res.is_ok() {
+        cs.finalize();
+    }
+    let duration = start.elapsed();
+    let phases = cs.borrow()
+        .and_then(|cs| cs.cache_map.borrow_mut().remove(&TypeId::of::<ReportState>()))
+        .and_then(|state| state.downcast::<ReportState>().ok())
+        .map(|state| state.phases)
+        .unwrap_or_default();
+    (res, Report { phases, total: CsCounts::of(&cs), duration })
+}
+
+/// Synthesizes the circuit as `measure_synthesis` does, recording the phases of the synthesis.
+pub fn profile_synthesis<F: Field, C: ConstraintSynthesizer<F>>(circuit: C) -> Result<Report, SynthesisError> {
+    let (res, report) = synthesize_profiled(circuit, ConstraintSystem::<F>::new_ref(), None);
+    res.map(|_| report)
+}
+
+/// Synthesizes the circuit in setup mode, as the setup does, failing with `BudgetExceeded` at the end of the first phase
+/// that takes the circuit past `max_constraints`, with the phases so far. That's for checking the key count and
+/// the limb parameters of a service before running a setup, without computing the witnesses.
+pub fn check_budget<F: Field, C: ConstraintSynthesizer<F>>(circuit: C, max_constraints: usize) -> Result<Report, SnowballError> {
+    let cs = ConstraintSystem::<F>::new_ref();
+    cs.set_mode(SynthesisMode::Setup);
+    let (res, report) = synthesize_profiled(circuit, cs, Some(max_constraints));
+    if report.total.num_constraints > max_constraints {
+        return Err(SnowballError::BudgetExceeded { budget: max_constraints, report: Box::new(report) });
+    }
+    res?;
+    Ok(report)
+}
+
+/// Runs a synthesis phase, recording it if the synthesis is profiled, see `profile_synthesis`,
+/// and within a `tracing` span reporting the constraint system growth when the `tracing` feature is on.
+/// Past the budget of a `check_budget` synthesis, the phase fails, to stop the synthesis there.
+pub(crate) fn phase<F: Field, T, E>(cs: &ConstraintSystemRef<F>, name: &'static str, f: impl FnOnce() -> Result<T, E>) -> Result<T, E>
+    where E: From<SynthesisError>,
+{
+    let profiled = cs.borrow().is_some_and(|cs| cs.cache_map.borrow().contains_key(&TypeId::of::<ReportState>()));
+    if !profiled {
+        return traced_phase(cs, name, f);
+    }
+    let (before, start) = (CsCounts::of(cs), Instant::now());
+    let res = traced_phase(cs, name, f);
+    let report = PhaseReport { name: name.to_string(), counts: before.delta(&CsCounts::of(cs)), duration: start.elapsed() };
+    let mut over_budget = false;
+    if let Some(cs_ref) = cs.borrow() {
+        if let Some(state) = cs_ref.cache_map.borrow_mut().get_mut(&TypeId::of::<ReportState>()).and_then(|s| s.downcast_mut::<ReportState>()) {
+            state.phases.push(report);
+            over_budget = state.budget.is_some_and(|budget| cs.num_constraints() > budget);
+        }
+    }
+    if over_budget && res.is_ok() {
+        return Err(SynthesisError::Unsatisfiable.into());
+    }
+    res
+}
+
+fn traced_phase<F: Field, T, E>(cs: &ConstraintSystemRef<F>, name: &'static str, f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+    #[cfg(feature = "tracing")]
+    {
+        let _span = tracing::info_span!("snowball", phase = name).entered();
+        let before = CsCounts::of(cs);
+        let res = f();
+        let delta = before.delta(&CsCounts::of(cs));
+        let memory = MemoryUsage::of(cs);
+        tracing::debug!(
+            constraints = delta.num_constraints,
+            witnesses = delta.num_witness_variables,
+            instances = delta.num_instance_variables,
+            witness_bytes = memory.witness_bytes,
+            linear_combinations = memory.num_linear_combinations,
+            peak_rss_bytes = memory.peak_rss_bytes,
+            "{} done", name
+        );
+        res
+    }
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = (cs, name);
+        f()
+    }
+}
+
+/// Runs a host-side phase (setup, proving, verification) within a `tracing` span,
+/// reporting the peak memory of the process at its end, when the `tracing` feature is on.
+pub(crate) fn host_phase<T>(name: &'static str, f: impl FnOnce() -> T) -> T {
+    #[cfg(feature = "tracing")]
+    {
+        let _span = tracing::info_span!("snowball", phase = name).entered();
+        let res = f();
+        tracing::debug!(peak_rss_bytes = peak_rss_bytes(), "{} done", name);
+        res
+    }
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = name;
+        f()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_r1cs_std::alloc::AllocVar;
+    use ark_r1cs_std::fields::fp::FpVar;
+    use ark_relations::ns;
+    use ark_relations::r1cs::{ConstraintSystem, SynthesisError};
+
+    use super::*;
+
+    #[test]
+    fn test_phase_counts() {
+        let cs = ConstraintSystem::<ark_bls12_381::Fr>::new_ref();
+        let before = CsCounts::of(&cs);
+        phase(&cs, "alloc", || {
+            let _a = FpVar::new_input(ns!(cs, "a"), || Ok(ark_bls12_381::Fr::from(1u8)))?;
+            let _b = FpVar::new_witness(ns!(cs, "b"), || Ok(ark_bls12_381::Fr::from(2u8)))?;
+            Ok::<_, SynthesisError>(())
+        }).unwrap();
+        let delta = before.delta(&CsCounts::of(&cs));
+        assert_eq!(delta, CsCounts { num_constraints: 0, num_witness_variables: 1, num_instance_variables: 1 });
+    }
+
+    #[cfg(feature = "bls12-377-bw6")]
+    #[test]
+    fn test_measure_synthesis() {
+        use ark_std::{test_rng, UniformRand};
+
+        use crate::bitmask::Bitmask;
+        use crate::chain::{Bls377InBw6, ChainCircuit};
+
+        let rng = &mut test_rng();
+        let keys = (0..4).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+        let circuit = ChainCircuit::<Bls377InBw6>::new(keys, ark_bls12_377::G1Affine::rand(rng), &Bitmask::from_bits(vec![true; 4])).unwrap();
+        let (counts, memory) = measure_synthesis(circuit).unwrap();
+        assert!(memory.witness_bytes >= counts.num_witness_variables * std::mem::size_of::<ark_bw6_761::Fr>());
+        assert!(memory.instance_bytes >= counts.num_instance_variables * std::mem::size_of::<ark_bw6_761::Fr>());
+        #[cfg(target_os = "linux")]
+        assert!(memory.peak_rss_bytes.unwrap() > 0);
+    }
+
+    #[cfg(feature = "bls12-377-bw6")]
+    #[test]
+    fn test_profile_synthesis() {
+        use ark_std::{test_rng, UniformRand};
+
+        use crate::bitmask::Bitmask;
+        use crate::chain::{Bls377InBw6, ChainCircuit};
+
+        let rng = &mut test_rng();
+        let keys: Vec<_> = (0..4).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+        let circuit = || ChainCircuit::<Bls377InBw6>::new(keys.clone(), ark_bls12_377::G1Affine::rand(&mut test_rng()), &Bitmask::from_bits(vec![true; 4])).unwrap();
+        let report = profile_synthesis(circuit()).unwrap();
+        let names: Vec<_> = report.phases.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["allocation", "aggregation"]);
+        assert_eq!(report.total, measure_synthesis(circuit()).unwrap().0);
+        let constraints: usize = report.phases.iter().map(|p| p.counts.num_constraints).sum();
+        assert_eq!(constraints, report.total.num_constraints);
+
+        let csv = report.to_csv();
+        assert_eq!(csv.lines().count(), 4);
+        assert!(csv.lines().nth(2).unwrap().starts_with(&format!("aggregation,{},", report.phases[1].counts.num_constraints)));
+        #[cfg(feature = "serde")]
+        {
+            let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+            assert_eq!(json["phases"][0]["phase"], "allocation");
+            assert_eq!(json["phases"][1]["witnesses"], report.phases[1].counts.num_witness_variables);
+            assert_eq!(json["total"]["constraints"], report.total.num_constraints);
+        }
+    }
+
+    #[cfg(feature = "bls12-377-bw6")]
+    #[test]
+    fn test_check_budget() {
+        use ark_std::{test_rng, UniformRand};
+
+        use crate::bitmask::Bitmask;
+        use crate::chain::{Bls377InBw6, ChainCircuit};
+
+        let rng = &mut test_rng();
+        let keys: Vec<_> = (0..4).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+        let circuit = || ChainCircuit::<Bls377InBw6>::new(keys.clone(), ark_bls12_377::G1Affine::rand(&mut test_rng()), &Bitmask::from_bits(vec![true; 4])).unwrap();
+        let report = profile_synthesis(circuit()).unwrap();
+        let total = report.total.num_constraints;
+        assert_eq!(check_budget(circuit(), total).unwrap().total.num_constraints, total);
+
+        // The allocation fits, the aggregation doesn't.
+        let allocation = report.phases[0].counts.num_constraints;
+        match check_budget(circuit(), allocation) {
+            Err(SnowballError::BudgetExceeded { budget, report: partial }) => {
+                assert_eq!(budget, allocation);
+                let counts = |r: &Report| r.phases.iter().map(|p| (p.name.clone(), p.counts)).collect::<Vec<_>>();
+                assert_eq!(counts(&partial), counts(&report));
+                assert_eq!(partial.total.num_constraints, total);
+            }
+            res => panic!("{:?}", res),
+        }
+        // Stopped at the end of the allocation.
+        assert!(matches!(check_budget(circuit(), allocation - 1), Err(SnowballError::BudgetExceeded { report, .. }) if report.phases.len() == 1));
+    }
+}
@@ -0,0 +1,493 @@
+//! Points in Jacobian coordinates, `(X, Y, Z)` for the affine `(X / Z^2, Y / Z^3)` and the identity at `Z = 0`,
+//! as `short_weierstrass::Projective` has them. The formulas take no inverse: the points are normalized once,
+//! with `to_affine` or `batch_to_affine`, at the end of a sequence of operations.
+//!
+//! That doesn't save constraints: in a circuit an inverse is checked with a single multiplication, see `batch_inv`,
+//! and an addition here takes 16 multiplications and squarings, against the 3 of `NonZeroAffineVarGeneric::add_unchecked`.
+//! What it saves is the field inversions of the prover, one per normalized point instead of one per addition.
+
+use std::borrow::Borrow;
+use std::marker::PhantomData;
+
+use ark_ec::short_weierstrass::{Affine, Projective, SWCurveConfig};
+use ark_ff::{One, PrimeField, Zero};
+use ark_r1cs_std::alloc::{AllocationMode, AllocVar};
+use ark_r1cs_std::boolean::Boolean;
+use ark_r1cs_std::eq::EqGadget;
+use ark_r1cs_std::fields::{FieldOpsBounds, FieldVar};
+use ark_r1cs_std::R1CSVar;
+use ark_r1cs_std::select::CondSelectGadget;
+use ark_relations::r1cs::{ConstraintSystemRef, Field, Namespace, SynthesisError};
+use derivative::Derivative;
+
+use crate::affine_gen::{AffineVarGeneric, NonZeroAffineVarGeneric, nonzero_inverse};
+use crate::batch_inv::batch_inverse;
+use crate::labels::labeled;
+
+/// A point in Jacobian coordinates, the identity included, see the module.
+#[derive(Derivative)]
+#[derivative(Clone, Debug(bound = "F: std::fmt::Debug"))]
+#[must_use]
+pub struct ProjectiveVarGeneric<P, F, CF>
+    where
+        P: SWCurveConfig,
+        CF: Field,
+        F: FieldVar<P::BaseField, CF>,
+{
+    pub x: F,
+    pub y: F,
+    pub z: F,
+    #[derivative(Debug = "ignore")]
+    _p: PhantomData<P>,
+    #[derivative(Debug = "ignore")]
+    _cf: PhantomData<CF>,
+}
+
+impl<P, F, CF> ProjectiveVarGeneric<P, F, CF>
+    where
+        P: SWCurveConfig,
+        CF: PrimeField,
+        F: FieldVar<P::BaseField, CF>,
+        for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+{
+    pub fn new(x: F, y: F, z: F) -> Self {
+        Self { x, y, z, _p: PhantomData, _cf: PhantomData }
+    }
+
+    /// The identity, a constant, with the coordinates `(1, 1, 0)` as arkworks has them.
+    pub fn zero() -> Self {
+        Self::new(F::one(), F::one(), F::zero())
+    }
+
+    pub fn is_zero(&self) -> Result<Boolean<CF>, SynthesisError> {
+        self.z.is_eq(&F::zero())
+    }
+
+    /// `-self`, free: a linear combination.
+    pub fn negate(&self) -> Result<Self, SynthesisError> {
+        Ok(Self::new(self.x.clone(), self.y.negate()?, self.z.clone()))
+    }
+
+    /// `-self` if `cond` is set, `self` otherwise: a selection of `Y`.
+    pub fn conditionally_negate(&self, cond: &Boolean<CF>) -> Result<Self, SynthesisError> {
+        Ok(Self::new(self.x.clone(), cond.select(&self.y.negate()?, &self.y)?, self.z.clone()))
+    }
+
+    /// Enforces `Y^2 = X^3 + a * X * Z^4 + b * Z^6`, that the identity `(1, 1, 0)` satisfies as well.
+    pub fn enforce_on_curve(&self) -> Result<(), SynthesisError> {
+        labeled(&self.cs(), "on_curve", || {
+            let zz = self.z.square()?;
+            let z4 = zz.square()?;
+            let rhs = self.x.square()? * &self.x + &z4 * &zz * P::COEFF_B;
+            let rhs = if P::COEFF_A.is_zero() { rhs } else { rhs + &self.x * &z4 * P::COEFF_A };
+            self.y.square_equals(&rhs)
+        })
+    }
+
+    /// Allocates the coordinates as they are, unlike `new_variable`, that checks the point:
+    /// for the points that the circuit checks otherwise, or can take off the curve.
+    pub fn new_variable_unchecked<T: Borrow<Projective<P>>>(cs: impl Into<Namespace<CF>>, f: impl FnOnce() -> Result<T, SynthesisError>, mode: AllocationMode) -> Result<Self, SynthesisError> {
+        let ns = cs.into();
+        let cs = ns.cs();
+        let point = f().map(|b| *b.borrow());
+        let x = labeled(&cs, "x", || F::new_variable(ark_relations::ns!(cs, "x"), || point.map(|p| p.x), mode))?;
+        let y = labeled(&cs, "y", || F::new_variable(ark_relations::ns!(cs, "y"), || point.map(|p| p.y), mode))?;
+        let z = labeled(&cs, "z", || F::new_variable(ark_relations::ns!(cs, "z"), || point.map(|p| p.z), mode))?;
+        Ok(Self::new(x, y, z))
+    }
+
+    /// Enforces the point to be in the subgroup of order `r`, as `NonZeroAffineVarGeneric::enforce_in_prime_subgroup`
+    /// of its affine form, or of the generator for the identity, that is in the subgroup as well.
+    pub fn enforce_in_prime_subgroup(&self) -> Result<(), SynthesisError> {
+        let affine = self.to_affine()?;
+        let generator = NonZeroAffineVarGeneric::new_constant(ConstraintSystemRef::None, P::GENERATOR)?;
+        let p = NonZeroAffineVarGeneric::new(affine.x, affine.y);
+        NonZeroAffineVarGeneric::conditionally_select(&affine.infinity, &generator, &p)?.enforce_in_prime_subgroup()
+    }
+
+    /// The point, or the identity if flagged so.
+    pub fn from_affine(p: &AffineVarGeneric<P, F, CF>) -> Result<Self, SynthesisError> {
+        Ok(Self::new(p.x.clone(), p.y.clone(), p.infinity.select(&F::zero(), &F::one())?))
+    }
+
+    /// `2 * self`, for any point, the identity and the points of order 2 giving `Z = 0`.
+    /// A multiplication and 7 squarings, one more for the curves with `a != 0`.
+    pub fn double(&self) -> Result<Self, SynthesisError> {
+        labeled(&self.cs(), "double", || {
+            let xx = self.x.square()?;
+            let yy = self.y.square()?;
+            let yyyy = yy.square()?;
+            let zz = self.z.square()?;
+            let s = ((&self.x + &yy).square()? - &xx - &yyyy).double()?;
+            let m = if P::COEFF_A.is_zero() { &xx.double()? + &xx } else { &xx.double()? + &xx + zz.square()? * P::COEFF_A };
+            let t = m.square()? - s.double()?;
+            let y3 = m * &(s - &t) - yyyy.double()?.double()?.double()?;
+            let z3 = (&self.y + &self.z).square()? - &yy - &zz;
+            Ok(Self::new(t, y3, z3))
+        })
+    }
+
+    /// `self + other` by the generic formula, wrong if either point is the identity or they're equal:
+    /// it then gives the identity. The opposite points give the identity, rightly.
+    /// 11 multiplications and 5 squarings.
+    pub fn add_unchecked(&self, other: &Self) -> Result<Self, SynthesisError> {
+        labeled(&self.cs().or(other.cs()), "add", || Ok(self.add_generic(other)?.0))
+    }
+
+    /// `self + other`, for any points, the identity included: `add_unchecked`, or `double` for equal points,
+    /// selected by two zero checks, the doubling being computed regardless. For the emulated fields,
+    /// both are reduced before the selection, see `reduced`, for 6 more multiplications.
+    pub fn add(&self, other: &Self) -> Result<Self, SynthesisError> {
+        labeled(&self.cs().or(other.cs()), "complete_add", || {
+            let (sum, h, r) = self.add_generic(other)?;
+            let equal = h.is_eq(&F::zero())?.and(&r.is_eq(&F::zero())?)?;
+            let sum = Self::conditionally_select(&equal, &self.double()?.reduced()?, &sum.reduced()?)?;
+            let sum = Self::conditionally_select(&other.is_zero()?, self, &sum)?;
+            Self::conditionally_select(&self.is_zero()?, other, &sum)
+        })
+    }
+
+    // The coordinates multiplied by `1`, a linear combination for the native fields, a multiplication for the emulated ones,
+    // that reduces their limbs. An emulated selection takes the bound of the limbs of the larger of its values,
+    // which `ark-r1cs-std` then fails to reduce from if the smaller one was selected: the sum and the doubling,
+    // of very different bounds, are reduced before they're selected.
+    fn reduced(&self) -> Result<Self, SynthesisError> {
+        let one = P::BaseField::one();
+        Ok(Self::new(self.x.clone() * one, self.y.clone() * one, self.z.clone() * one))
+    }
+
+    // The sum by `add-2007-bl`, with `H = U2 - U1` and `r = 2 (S2 - S1)`, both zero for equal points.
+    fn add_generic(&self, other: &Self) -> Result<(Self, F, F), SynthesisError> {
+        let z1z1 = self.z.square()?;
+        let z2z2 = other.z.square()?;
+        let u1 = &self.x * &z2z2;
+        let u2 = &other.x * &z1z1;
+        let s1 = &self.y * &other.z * &z2z2;
+        let s2 = &other.y * &self.z * &z1z1;
+        let h = u2 - &u1;
+        let i = h.double()?.square()?;
+        let j = &h * &i;
+        let r = (s2 - &s1).double()?;
+        let v = u1 * &i;
+        let x3 = r.square()? - &j - v.double()?;
+        let y3 = &r * &(v - &x3) - (s1 * &j).double()?;
+        let z3 = ((&self.z + &other.z).square()? - &z1z1 - &z2z2) * &h;
+        Ok((Self::new(x3, y3, z3), h, r))
+    }
+
+    /// `self + other` for an affine `other`, with `Z2 = 1`, by the generic formula: wrong if `self` is the identity
+    /// or equals `other`, as `add_unchecked`. 7 multiplications and 4 squarings.
+    pub fn add_mixed_unchecked(&self, other: &NonZeroAffineVarGeneric<P, F, CF>) -> Result<Self, SynthesisError> {
+        labeled(&self.cs().or(other.cs()), "add_mixed", || Ok(self.add_mixed_generic(other)?.0))
+    }
+
+    /// `self + other` for an affine `other`, for any `self`, the identity included, the special cases selected as in `add`.
+    pub fn add_mixed(&self, other: &NonZeroAffineVarGeneric<P, F, CF>) -> Result<Self, SynthesisError> {
+        labeled(&self.cs().or(other.cs()), "complete_add_mixed", || {
+            let (sum, h, r) = self.add_mixed_generic(other)?;
+            let equal = h.is_eq(&F::zero())?.and(&r.is_eq(&F::zero())?)?;
+            let sum = Self::conditionally_select(&equal, &self.double()?.reduced()?, &sum.reduced()?)?;
+            Self::conditionally_select(&self.is_zero()?, &other.clone().into(), &sum)
+        })
+    }
+
+    /// The sum of the points, as `add_mixed` of each of them to the identity, normalized once.
+    /// Affine witnesses folded without any of the restrictions of `NonZeroAffineVarGeneric::add_unchecked`.
+    pub fn sum_mixed(points: &[NonZeroAffineVarGeneric<P, F, CF>]) -> Result<AffineVarGeneric<P, F, CF>, SynthesisError> {
+        let cs = points.iter().fold(ConstraintSystemRef::None, |cs, p| cs.or(p.cs()));
+        labeled(&cs, "sum_mixed", || points.iter().try_fold(Self::zero(), |acc, p| acc.add_mixed(p))?.to_affine())
+    }
+
+    // The sum by `madd-2007-bl`, with `H` and `r` as in `add_generic`.
+    fn add_mixed_generic(&self, other: &NonZeroAffineVarGeneric<P, F, CF>) -> Result<(Self, F, F), SynthesisError> {
+        let z1z1 = self.z.square()?;
+        let u2 = &other.x * &z1z1;
+        let s2 = &other.y * &self.z * &z1z1;
+        let h = u2 - &self.x;
+        let hh = h.square()?;
+        let i = hh.double()?.double()?;
+        let j = &h * &i;
+        let r = (s2 - &self.y).double()?;
+        let v = &self.x * &i;
+        let x3 = r.square()? - &j - v.double()?;
+        let y3 = &r * &(v - &x3) - (&self.y * &j).double()?;
+        let z3 = (&self.z + &h).square()? - &z1z1 - &hh;
+        Ok((Self::new(x3, y3, z3), h, r))
+    }
+
+    // `(X / Z^2, Y / Z^3)` given `1 / Z`.
+    fn scale(&self, z_inv: &F) -> Result<(F, F), SynthesisError> {
+        let z_inv2 = z_inv.square()?;
+        let x = &self.x * &z_inv2;
+        let y = &self.y * &(z_inv2 * z_inv);
+        Ok((x, y))
+    }
+
+    /// The affine point, the identity included, with an inverse of `Z`, or of `1` for the identity.
+    pub fn to_affine(&self) -> Result<AffineVarGeneric<P, F, CF>, SynthesisError> {
+        labeled(&self.cs(), "to_affine", || {
+            let infinity = self.is_zero()?;
+            let z_inv = infinity.select(&F::one(), &self.z)?.inverse()?;
+            let (x, y) = self.scale(&z_inv)?;
+            Ok(AffineVarGeneric::new(x, y, infinity))
+        })
+    }
+
+    /// The affine point, enforcing it not to be the identity, with an inverse of `Z` and no zero check.
+    pub fn to_non_zero_affine(&self) -> Result<NonZeroAffineVarGeneric<P, F, CF>, SynthesisError> {
+        labeled(&self.cs(), "to_affine", || {
+            let (x, y) = self.scale(&nonzero_inverse(&self.z)?)?;
+            Ok(NonZeroAffineVarGeneric::new(x, y))
+        })
+    }
+
+    /// `to_affine` of each point, the inverses computed by the prover with a single field inversion, see `batch_inv`.
+    pub fn batch_to_affine(points: &[Self]) -> Result<Vec<AffineVarGeneric<P, F, CF>>, SynthesisError> {
+        let cs = points.iter().fold(ConstraintSystemRef::None, |cs, p| cs.or(p.cs()));
+        labeled(&cs, "to_affine", || {
+            let infinities = points.iter().map(|p| p.is_zero()).collect::<Result<Vec<_>, _>>()?;
+            let zs = points.iter().zip(&infinities).map(|(p, infinity)| infinity.select(&F::one(), &p.z)).collect::<Result<Vec<_>, _>>()?;
+            let z_invs = batch_inverse(&zs)?;
+            points.iter().zip(infinities).zip(z_invs).map(|((p, infinity), z_inv)| {
+                let (x, y) = p.scale(&z_inv)?;
+                Ok(AffineVarGeneric::new(x, y, infinity))
+            }).collect()
+        })
+    }
+}
+
+impl<P, F, CF> From<NonZeroAffineVarGeneric<P, F, CF>> for ProjectiveVarGeneric<P, F, CF>
+    where
+        P: SWCurveConfig,
+        CF: PrimeField,
+        F: FieldVar<P::BaseField, CF>,
+        for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+{
+    fn from(p: NonZeroAffineVarGeneric<P, F, CF>) -> Self {
+        Self::new(p.x, p.y, F::one())
+    }
+}
+
+/// Witnesses and inputs are enforced to be on the curve and in the prime-order subgroup, as the stock `ProjectiveVar` does:
+/// a costly check for emulated fields, that `ProjectiveVarGeneric::new_variable_unchecked` skips.
+impl<P, F, CF> AllocVar<Projective<P>, CF> for ProjectiveVarGeneric<P, F, CF>
+    where
+        P: SWCurveConfig,
+        CF: PrimeField,
+        F: FieldVar<P::BaseField, CF>,
+        for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+{
+    fn new_variable<T: Borrow<Projective<P>>>(cs: impl Into<Namespace<CF>>, f: impl FnOnce() -> Result<T, SynthesisError>, mode: AllocationMode) -> Result<Self, SynthesisError> {
+        let p = Self::new_variable_unchecked(cs, f, mode)?;
+        if mode != AllocationMode::Constant {
+            p.enforce_on_curve()?;
+            p.enforce_in_prime_subgroup()?;
+        }
+        Ok(p)
+    }
+}
+
+/// The point with `Z = 1`, or the identity `(1, 1, 0)`.
+impl<P, F, CF> AllocVar<Affine<P>, CF> for ProjectiveVarGeneric<P, F, CF>
+    where
+        P: SWCurveConfig,
+        CF: PrimeField,
+        F: FieldVar<P::BaseField, CF>,
+        for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+{
+    fn new_variable<T: Borrow<Affine<P>>>(cs: impl Into<Namespace<CF>>, f: impl FnOnce() -> Result<T, SynthesisError>, mode: AllocationMode) -> Result<Self, SynthesisError> {
+        Self::new_variable(cs, || f().map(|p| Projective::from(*p.borrow())), mode)
+    }
+}
+
+impl<P, F, CF> CondSelectGadget<CF> for ProjectiveVarGeneric<P, F, CF>
+    where
+        P: SWCurveConfig,
+        CF: PrimeField,
+        F: FieldVar<P::BaseField, CF>,
+        for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+{
+    fn conditionally_select(cond: &Boolean<CF>, true_value: &Self, false_value: &Self) -> Result<Self, SynthesisError> {
+        let x = cond.select(&true_value.x, &false_value.x)?;
+        let y = cond.select(&true_value.y, &false_value.y)?;
+        let z = cond.select(&true_value.z, &false_value.z)?;
+        Ok(Self::new(x, y, z))
+    }
+}
+
+/// The same points, `X1 * Z2^2 = X2 * Z1^2` and `Y1 * Z2^3 = Y2 * Z1^3`, or both the identity:
+/// the coordinates of the identity can be anything with `Z = 0`.
+impl<P, F, CF> EqGadget<CF> for ProjectiveVarGeneric<P, F, CF>
+    where
+        P: SWCurveConfig,
+        CF: PrimeField,
+        F: FieldVar<P::BaseField, CF>,
+        for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+{
+    fn is_eq(&self, other: &Self) -> Result<Boolean<CF>, SynthesisError> {
+        labeled(&self.cs().or(other.cs()), "is_eq", || {
+            let (zz1, zz2) = (self.z.square()?, other.z.square()?);
+            let x_equal = (&self.x * &zz2).is_eq(&(&other.x * &zz1))?;
+            let y_equal = (&self.y * &zz2 * &other.z).is_eq(&(&other.y * &zz1 * &self.z))?;
+            let (zero1, zero2) = (self.is_zero()?, other.is_zero()?);
+            zero1.is_eq(&zero2)?.and(&zero1.or(&x_equal.and(&y_equal)?)?)
+        })
+    }
+}
+
+impl<P, F, CF> R1CSVar<CF> for ProjectiveVarGeneric<P, F, CF>
+    where
+        P: SWCurveConfig,
+        CF: PrimeField,
+        F: FieldVar<P::BaseField, CF>,
+{
+    type Value = Projective<P>;
+
+    fn cs(&self) -> ConstraintSystemRef<CF> {
+        self.x.cs().or(self.y.cs()).or(self.z.cs())
+    }
+
+    fn value(&self) -> Result<Self::Value, SynthesisError> {
+        Ok(Projective::<P>::new_unchecked(self.x.value()?, self.y.value()?, self.z.value()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_ec::CurveGroup;
+    use ark_r1cs_std::fields::fp::FpVar;
+    use ark_relations::ns;
+    use ark_relations::r1cs::ConstraintSystem;
+    use ark_std::{test_rng, UniformRand};
+
+    use crate::tests::{BlsInBls, Tracker};
+
+    use super::*;
+
+    // The point with a random `Z`.
+    fn scaled<P: SWCurveConfig>(p: Affine<P>, rng: &mut impl ark_std::rand::Rng) -> Projective<P> {
+        use ark_ff::Field;
+
+        if p.infinity {
+            return Projective::default();
+        }
+        let z = P::BaseField::rand(rng);
+        let z2 = z.square();
+        Projective::new_unchecked(p.x * z2, p.y * z2 * z, z)
+    }
+
+    fn check_projective<P, F, CF>(rng: &mut impl ark_std::rand::Rng)
+        where P: SWCurveConfig,
+              CF: PrimeField,
+              F: FieldVar<P::BaseField, CF>,
+              for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+    {
+        let (p, q) = (Affine::<P>::rand(rng), Affine::<P>::rand(rng));
+        let zero = Affine::<P>::identity();
+        for (a, b) in [(p, q), (p, p), (p, -p), (zero, p), (p, zero), (zero, zero)] {
+            let cs = ConstraintSystem::<CF>::new_ref();
+            let a_var = ProjectiveVarGeneric::<P, F, CF>::new_variable_unchecked(ns!(cs, "a"), || Ok(scaled(a, rng)), AllocationMode::Witness).unwrap();
+            let b_var = ProjectiveVarGeneric::<P, F, CF>::new_variable_unchecked(ns!(cs, "b"), || Ok(scaled(b, rng)), AllocationMode::Witness).unwrap();
+            let sum = a_var.add(&b_var).unwrap();
+            let double = a_var.double().unwrap();
+            assert_eq!(sum.value().unwrap(), a + b);
+            assert_eq!(double.value().unwrap(), a + a);
+            let affine = ProjectiveVarGeneric::batch_to_affine(&[sum.clone(), double]).unwrap();
+            assert_eq!(affine[0].value().unwrap(), (a + b).into_affine());
+            assert_eq!(affine[1].value().unwrap(), (a + a).into_affine());
+            assert_eq!(sum.to_affine().unwrap().value().unwrap(), (a + b).into_affine());
+            let rescaled = ProjectiveVarGeneric::<P, F, CF>::new_variable_unchecked(ns!(cs, "c"), || Ok(scaled((a + b).into_affine(), rng)), AllocationMode::Witness).unwrap();
+            sum.enforce_equal(&rescaled).unwrap();
+            assert_eq!(sum.is_eq(&ProjectiveVarGeneric::zero()).unwrap().value().unwrap(), (a + b).is_zero());
+            assert_eq!(a_var.negate().unwrap().value().unwrap(), -a);
+            let negated = a_var.conditionally_negate(&Boolean::new_witness(ns!(cs, "c"), || Ok(true)).unwrap()).unwrap();
+            assert_eq!(negated.value().unwrap(), -a);
+            a_var.enforce_on_curve().unwrap();
+            assert!(cs.is_satisfied().unwrap());
+        }
+
+        let cs = ConstraintSystem::<CF>::new_ref();
+        let p_var = NonZeroAffineVarGeneric::<P, F, CF>::new_witness(ns!(cs, "p"), || Ok(p)).unwrap();
+        let q_var = ProjectiveVarGeneric::<P, F, CF>::new_variable_unchecked(ns!(cs, "q"), || Ok(scaled(q, rng)), AllocationMode::Witness).unwrap();
+        let sum = ProjectiveVarGeneric::from(p_var).add_unchecked(&q_var).unwrap();
+        assert_eq!(sum.to_non_zero_affine().unwrap().value().unwrap(), (p + q).into_affine());
+        assert!(cs.is_satisfied().unwrap());
+        assert!(matches!(ProjectiveVarGeneric::<P, F, CF>::zero().to_non_zero_affine(), Err(SynthesisError::Unsatisfiable)));
+    }
+
+    fn check_add_mixed<P, F, CF>(rng: &mut impl ark_std::rand::Rng)
+        where P: SWCurveConfig,
+              CF: PrimeField,
+              F: FieldVar<P::BaseField, CF>,
+              for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+    {
+        let (p, q) = (Affine::<P>::rand(rng), Affine::<P>::rand(rng));
+        for (a, b) in [(p, q), (p, p), (-p, p), (Affine::identity(), p)] {
+            let cs = ConstraintSystem::<CF>::new_ref();
+            let a_var = ProjectiveVarGeneric::<P, F, CF>::new_variable_unchecked(ns!(cs, "a"), || Ok(scaled(a, rng)), AllocationMode::Witness).unwrap();
+            let b_var = NonZeroAffineVarGeneric::<P, F, CF>::new_witness(ns!(cs, "b"), || Ok(b)).unwrap();
+            assert_eq!(a_var.add_mixed(&b_var).unwrap().value().unwrap(), a + b);
+            if (a, b) == (p, q) {
+                assert_eq!(a_var.add_mixed_unchecked(&b_var).unwrap().value().unwrap(), a + b);
+            }
+            assert!(cs.is_satisfied().unwrap());
+        }
+
+        // Repeated keys, and a key and its negation, that `NonZeroAffineVarGeneric::add_unchecked` can't sum.
+        let points = [p, q, p, -q];
+        let cs = ConstraintSystem::<CF>::new_ref();
+        let point_vars = Vec::<NonZeroAffineVarGeneric<P, F, CF>>::new_witness(ns!(cs, "points"), || Ok(points.to_vec())).unwrap();
+        let sum = ProjectiveVarGeneric::sum_mixed(&point_vars).unwrap();
+        assert_eq!(sum.value().unwrap(), (p + p).into_affine());
+        let sum = point_vars.iter().try_fold(ProjectiveVarGeneric::zero(), |acc, p| acc.add(&p.clone().into())).unwrap();
+        assert_eq!(sum.value().unwrap(), p + p);
+        let tail = ProjectiveVarGeneric::sum_mixed(&point_vars[1..]).unwrap();
+        assert_eq!(tail.value().unwrap(), p);
+        assert!(cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn test_add_mixed() {
+        let rng = &mut test_rng();
+        check_add_mixed::<ark_bls12_377::g1::Config, FpVar<ark_bw6_761::Fr>, _>(rng);
+        check_add_mixed::<ark_bls12_381::g1::Config, BlsInBls, _>(rng);
+    }
+
+    #[test]
+    fn test_projective() {
+        let rng = &mut test_rng();
+        check_projective::<ark_bls12_377::g1::Config, FpVar<ark_bw6_761::Fr>, _>(rng);
+        check_projective::<ark_bls12_381::g1::Config, BlsInBls, _>(rng);
+    }
+
+    // The inverses of the affine additions cost a multiplication each, and the projective ones are about 5 times as many.
+    #[test]
+    fn test_projective_cost() {
+        let rng = &mut test_rng();
+        let n = 4;
+        let points: Vec<ark_bls12_381::G1Affine> = (0..n).map(|_| ark_bls12_381::G1Affine::rand(rng)).collect();
+        let cs = ConstraintSystem::<ark_bls12_381::Fr>::new_ref();
+        let point_vars = Vec::<NonZeroAffineVarGeneric<_, BlsInBls, _>>::new_witness(ns!(cs, "points"), || Ok(points.clone())).unwrap();
+        let mut tracker = Tracker::new(&cs);
+        let affine = point_vars[1..].iter().try_fold(point_vars[0].clone(), |acc, p| acc.add_unchecked(p)).unwrap();
+        let affine_cost = tracker.update(&cs);
+        let projective = point_vars[1..].iter()
+            .try_fold(ProjectiveVarGeneric::from(point_vars[0].clone()), |acc, p| acc.add_unchecked(&p.clone().into()))
+            .unwrap()
+            .to_non_zero_affine()
+            .unwrap();
+        let projective_cost = tracker.update(&cs);
+        let mixed = point_vars[1..].iter()
+            .try_fold(ProjectiveVarGeneric::from(point_vars[0].clone()), |acc, p| acc.add_mixed_unchecked(p))
+            .unwrap()
+            .to_non_zero_affine()
+            .unwrap();
+        let mixed_cost = tracker.update(&cs);
+        println!("summing {} emulated points, affine: {:?}, projective: {:?}, mixed: {:?}", n, affine_cost, projective_cost, mixed_cost);
+        assert_eq!(affine.value().unwrap(), projective.value().unwrap());
+        assert_eq!(affine.value().unwrap(), mixed.value().unwrap());
+        assert!(affine_cost.num_constraints < mixed_cost.num_constraints);
+        assert!(mixed_cost.num_constraints < projective_cost.num_constraints);
+        assert!(cs.is_satisfied().unwrap());
+    }
+}
@@ -0,0 +1,223 @@
+//! The sync history of a light client: a proof per epoch, each linked to the previous ones by a hash chain,
+//! so that a stored history can be checked to be the one that was appended, and verified over any range of epochs.
+//!
+//! The link of an epoch is the SHA-256 of the link of the previous epoch, the epoch number, the digest of the keyset
+//! the proof is for and the compressed envelope. The link before the first epoch is all zeros until the chain is pruned,
+//! and the link of the last pruned epoch afterwards, so the remaining links still check.
+
+use std::ops::Range;
+
+use ark_ec::short_weierstrass::{Affine, SWCurveConfig};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_snark::SNARK;
+use sha2::{Digest, Sha256};
+
+use crate::chain::Chain;
+use crate::envelope::ProofEnvelope;
+use crate::error::SnowballError;
+use crate::prover::Verifier;
+use crate::serialization::{SerializationMode, to_bytes};
+
+/// SHA-256 of the compressed keyset. Not `digest::keyset_digest`, that hashes the coordinates for the circuits:
+/// the links of the stored chains are computed over this one.
+pub fn keyset_digest<P: SWCurveConfig>(keys: &[Affine<P>]) -> Result<[u8; 32], SnowballError> {
+    Ok(Sha256::digest(to_bytes(&keys.to_vec(), SerializationMode::Compressed)?).into())
+}
+
+/// The proof of an epoch, with its link to the previous epochs.
+#[derive(Clone, Debug, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EpochProof {
+    pub epoch: u64,
+    /// `keyset_digest` of the keys the proof is for.
+    pub keyset: [u8; 32],
+    pub envelope: ProofEnvelope,
+    pub link: [u8; 32],
+}
+
+fn link(prev: &[u8; 32], epoch: u64, keyset: &[u8; 32], envelope: &ProofEnvelope) -> Result<[u8; 32], SnowballError> {
+    let mut hasher = Sha256::new();
+    hasher.update(prev);
+    hasher.update(epoch.to_le_bytes());
+    hasher.update(keyset);
+    hasher.update(to_bytes(envelope, SerializationMode::Compressed)?);
+    Ok(hasher.finalize().into())
+}
+
+/// Proofs of consecutive epochs.
+#[derive(Clone, Debug, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProofChain {
+    first_epoch: u64,
+    /// The link before the first epoch.
+    base: [u8; 32],
+    epochs: Vec<EpochProof>,
+}
+
+impl ProofChain {
+    /// An empty chain, whose first proof will be for `first_epoch`.
+    pub fn new(first_epoch: u64) -> Self {
+        Self { first_epoch, base: [0; 32], epochs: vec![] }
+    }
+
+    pub fn first_epoch(&self) -> u64 {
+        self.first_epoch
+    }
+
+    /// The epoch the next appended proof is for.
+    pub fn next_epoch(&self) -> u64 {
+        self.first_epoch + self.epochs.len() as u64
+    }
+
+    pub fn len(&self) -> usize {
+        self.epochs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.epochs.is_empty()
+    }
+
+    pub fn epochs(&self) -> &[EpochProof] {
+        &self.epochs
+    }
+
+    pub fn get(&self, epoch: u64) -> Option<&EpochProof> {
+        self.epochs.get(epoch.checked_sub(self.first_epoch)? as usize)
+    }
+
+    /// The link of the last epoch, that commits to the whole history.
+    pub fn head(&self) -> [u8; 32] {
+        self.epochs.last().map_or(self.base, |e| e.link)
+    }
+
+    /// Appends the proof for the next epoch, made for the keyset of digest `keyset`.
+    pub fn append(&mut self, keyset: [u8; 32], envelope: ProofEnvelope) -> Result<&EpochProof, SnowballError> {
+        let epoch = self.next_epoch();
+        let link = link(&self.head(), epoch, &keyset, &envelope)?;
+        self.epochs.push(EpochProof { epoch, keyset, envelope, link });
+        Ok(self.epochs.last().expect("just pushed"))
+    }
+
+    // The epochs of the range, with the link before them.
+    fn range(&self, epochs: Range<u64>) -> Result<(&[u8; 32], &[EpochProof]), SnowballError> {
+        if epochs.is_empty() {
+            return Ok((&self.base, &[]));
+        }
+        for epoch in [epochs.start, epochs.end - 1] {
+            self.get(epoch).ok_or(SnowballError::UnknownEpoch(epoch))?;
+        }
+        let start = (epochs.start - self.first_epoch) as usize;
+        let prev = if start == 0 { &self.base } else { &self.epochs[start - 1].link };
+        Ok((prev, &self.epochs[start..(epochs.end - self.first_epoch) as usize]))
+    }
+
+    /// Recomputes the links of the epochs of the range, `Integrity` if any differs from the stored one.
+    pub fn check_links(&self, epochs: Range<u64>) -> Result<(), SnowballError> {
+        let start = epochs.start;
+        let (mut prev, range) = self.range(epochs)?;
+        for (e, epoch) in range.iter().zip(start..) {
+            if e.epoch != epoch || link(prev, e.epoch, &e.keyset, &e.envelope)? != e.link {
+                return Err(SnowballError::Integrity);
+            }
+            prev = &e.link;
+        }
+        Ok(())
+    }
+
+    /// Checks the links of the epochs of the range, then verifies their proofs against `keysets`, the keys of each epoch.
+    /// `Integrity` if the links or the digests of the keysets don't match, `false` if any proof doesn't verify.
+    pub fn verify_range<C, S>(&self, epochs: Range<u64>, keysets: &[Vec<Affine<C::Curve>>], verifier: &Verifier<C, S>) -> Result<bool, SnowballError>
+        where C: Chain,
+              S: SNARK<C::ConstraintField>,
+              S::ProcessedVerifyingKey: 'static,
+              S::Proof: 'static,
+    {
+        self.check_links(epochs.clone())?;
+        let (_, range) = self.range(epochs)?;
+        if keysets.len() != range.len() {
+            return Err(SnowballError::Integrity);
+        }
+        for (e, keys) in range.iter().zip(keysets) {
+            if keyset_digest(keys)? != e.keyset {
+                return Err(SnowballError::Integrity);
+            }
+            if !verifier.verify_envelope(keys, &e.envelope)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Drops the proofs from `epoch` on, to roll back to an earlier history.
+    pub fn truncate(&mut self, epoch: u64) {
+        self.epochs.truncate(epoch.saturating_sub(self.first_epoch) as usize)
+    }
+
+    /// Drops the proofs before `epoch`, keeping the link of the last dropped one as the base of the remaining ones.
+    pub fn prune(&mut self, epoch: u64) {
+        let n = (epoch.saturating_sub(self.first_epoch) as usize).min(self.epochs.len());
+        if n > 0 {
+            self.base = self.epochs[n - 1].link;
+            self.epochs.drain(..n);
+            self.first_epoch += n as u64;
+        }
+    }
+}
+
+#[cfg(all(test, feature = "bls12-377-bw6"))]
+mod tests {
+    use ark_groth16::Groth16;
+    use ark_std::UniformRand;
+    use rand::rngs::OsRng;
+
+    use crate::bitmask::Bitmask;
+    use crate::chain::{Bls377InBw6, ChainCircuit, CircuitId};
+    use crate::envelope::PublicData;
+    use crate::prover::setup;
+    use crate::serialization::from_bytes;
+
+    use super::*;
+
+    type Backend = Groth16<ark_bw6_761::BW6_761>;
+
+    #[test]
+    fn test_proof_chain() {
+        let rng = &mut OsRng;
+        let keysets: Vec<Vec<ark_bls12_377::G1Affine>> = (0..4).map(|_| (0..2).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect()).collect();
+        let seed = ark_bls12_377::G1Affine::rand(rng);
+        let bitmask = Bitmask::from_bits(vec![true, false]);
+        let circuit = |keys: &Vec<_>| ChainCircuit::<Bls377InBw6>::new(keys.clone(), seed, &bitmask).unwrap();
+        let (prover, verifier) = setup::<Bls377InBw6, Backend, _>(circuit(&keysets[0]), rng).unwrap();
+
+        let mut chain = ProofChain::new(10);
+        for keys in &keysets {
+            let circuit_id = CircuitId::of_circuit::<Bls377InBw6, Backend>(&circuit(keys));
+            let proof = prover.prove(circuit(keys), rng).unwrap();
+            let envelope = ProofEnvelope::new::<Bls377InBw6, Backend>(circuit_id, &proof, PublicData { bitmask: bitmask.clone() }).unwrap();
+            chain.append(keyset_digest(keys).unwrap(), envelope).unwrap();
+        }
+        assert_eq!((chain.first_epoch(), chain.next_epoch()), (10, 14));
+        assert!(chain.verify_range(10..14, &keysets, &verifier).unwrap());
+        assert!(chain.verify_range(11..13, &keysets[1..3], &verifier).unwrap());
+        assert!(matches!(chain.verify_range(11..13, &keysets[..2], &verifier), Err(SnowballError::Integrity)));
+        assert!(matches!(chain.check_links(9..12), Err(SnowballError::UnknownEpoch(9))));
+
+        let bytes = to_bytes(&chain, SerializationMode::Compressed).unwrap();
+        assert_eq!(from_bytes::<ProofChain>(&bytes, SerializationMode::Compressed).unwrap(), chain);
+
+        // Another proof in the history breaks the links from there on.
+        let mut forged = chain.clone();
+        forged.epochs[1].envelope = forged.epochs[0].envelope.clone();
+        assert!(forged.check_links(10..11).is_ok());
+        assert!(matches!(forged.check_links(10..14), Err(SnowballError::Integrity)));
+
+        let head = chain.head();
+        chain.prune(12);
+        assert_eq!((chain.first_epoch(), chain.len(), chain.head()), (12, 2, head));
+        assert!(chain.verify_range(12..14, &keysets[2..], &verifier).unwrap());
+        assert!(chain.get(11).is_none());
+        chain.truncate(13);
+        assert_eq!(chain.next_epoch(), 13);
+        assert!(chain.check_links(12..13).is_ok());
+    }
+}
@@ -0,0 +1,383 @@
+use std::any::Any;
+use std::marker::PhantomData;
+
+use ark_ec::pairing::Pairing;
+use ark_ec::scalar_mul::variable_base::VariableBaseMSM;
+use ark_ec::short_weierstrass::Affine;
+use ark_groth16::{Groth16, PreparedVerifyingKey, Proof};
+use ark_r1cs_std::fields::FieldOpsBounds;
+use ark_snark::{SNARK, UniversalSetupIndexError, UniversalSetupSNARK};
+use derivative::Derivative;
+use rand::{CryptoRng, RngCore};
+
+use crate::apk_circuits::public_inputs;
+use crate::bitmask::Bitmask;
+use crate::chain::{Chain, ChainCircuit, CircuitId, KeyBaseField};
+use crate::config::Config;
+use crate::envelope::ProofEnvelope;
+use crate::error::{Result, SnowballError};
+use crate::profiling::host_phase;
+
+pub(crate) fn snark_error<E: ark_std::error::Error + 'static>(e: E) -> SnowballError {
+    SnowballError::Snark(Box::new(e))
+}
+
+#[derive(Derivative)]
+#[derivative(Clone(bound = ""))]
+pub struct Prover<C: Chain, S: SNARK<C::ConstraintField>> {
+    pub pk: S::ProvingKey,
+    pub config: Config,
+    _c: PhantomData<fn() -> C>,
+}
+
+#[derive(Derivative)]
+#[derivative(Clone(bound = ""))]
+pub struct Verifier<C: Chain, S: SNARK<C::ConstraintField>> {
+    pub pvk: S::ProcessedVerifyingKey,
+    pub config: Config,
+    /// The id of the circuit of the key, that the envelopes have to be for, if known.
+    pub circuit_id: Option<CircuitId>,
+    _c: PhantomData<fn() -> C>,
+}
+
+/// Runs the circuit-specific setup of the backend `S` for the shape of `circuit`.
+pub fn setup<C, S, R>(circuit: ChainCircuit<C>, rng: &mut R) -> Result<(Prover<C, S>, Verifier<C, S>)>
+    where C: Chain,
+          S: SNARK<C::ConstraintField>,
+          R: RngCore + CryptoRng,
+          for<'a> &'a C::FieldVar: FieldOpsBounds<'a, KeyBaseField<C>, C::FieldVar>,
+{
+    host_phase("setup", || {
+        let (config, id) = (circuit.config().clone(), CircuitId::of_circuit::<C, S>(&circuit));
+        let (pk, vk) = S::circuit_specific_setup(circuit, rng).map_err(snark_error)?;
+        Ok((Prover::new(pk).with_config(config.clone()), Verifier::new(&vk)?.with_config(config).with_circuit_id(id)))
+    })
+}
+
+/// Indexes the circuit shape against universal parameters of the backend `S` (e.g. Marlin),
+/// so that no circuit-specific trusted setup is needed.
+pub fn index<C, S, R>(pp: &S::PublicParameters, circuit: ChainCircuit<C>, rng: &mut R) -> Result<(Prover<C, S>, Verifier<C, S>)>
+    where C: Chain,
+          S: UniversalSetupSNARK<C::ConstraintField>,
+          R: RngCore + CryptoRng,
+          for<'a> &'a C::FieldVar: FieldOpsBounds<'a, KeyBaseField<C>, C::FieldVar>,
+{
+    host_phase("indexing", || {
+        let (config, id) = (circuit.config().clone(), CircuitId::of_circuit::<C, S>(&circuit));
+        let (pk, vk) = S::index(pp, circuit, rng).map_err(|e| match e {
+            UniversalSetupIndexError::NeedLargerBound(bound) => SnowballError::BoundTooSmall(format!("{:?}", bound)),
+            UniversalSetupIndexError::Other(e) => snark_error(e),
+        })?;
+        Ok((Prover::new(pk).with_config(config.clone()), Verifier::new(&vk)?.with_config(config).with_circuit_id(id)))
+    })
+}
+
+/// Circuit-specific setup with the randomness derived from `seed`, so that the keys are reproducible.
+///
+/// INSECURE: anyone knowing the seed knows the toxic waste and can forge proofs.
+#[cfg(feature = "insecure-setup")]
+pub fn setup_deterministic<C, S>(circuit: ChainCircuit<C>, seed: [u8; 32]) -> Result<(Prover<C, S>, Verifier<C, S>)>
+    where C: Chain,
+          S: SNARK<C::ConstraintField>,
+          for<'a> &'a C::FieldVar: FieldOpsBounds<'a, KeyBaseField<C>, C::FieldVar>,
+{
+    use rand::SeedableRng;
+    setup(circuit, &mut rand_chacha::ChaCha20Rng::from_seed(seed))
+}
+
+impl<C: Chain, S: SNARK<C::ConstraintField>> Prover<C, S> {
+    pub fn new(pk: S::ProvingKey) -> Self {
+        Self { pk, config: Config::default(), _c: PhantomData }
+    }
+
+    /// Sets the config, that has to be the one of the circuits to prove.
+    pub fn with_config(self, config: Config) -> Self {
+        Self { config, ..self }
+    }
+
+    pub fn prove<R: RngCore + CryptoRng + Send>(&self, circuit: ChainCircuit<C>, rng: &mut R) -> Result<S::Proof>
+        where for<'a> &'a C::FieldVar: FieldOpsBounds<'a, KeyBaseField<C>, C::FieldVar>,
+              S::ProvingKey: Sync,
+              S::Proof: Send,
+              S::Error: Send,
+    {
+        host_phase("proving", || {
+            let prove = || S::prove(&self.pk, circuit, rng);
+            #[cfg(feature = "parallel")]
+            if let Some(num_threads) = self.config.num_threads {
+                let pool = rayon::ThreadPoolBuilder::new().num_threads(num_threads).build().map_err(snark_error)?;
+                return pool.install(prove).map_err(snark_error);
+            }
+            prove().map_err(snark_error)
+        })
+    }
+}
+
+impl<C: Chain, S: SNARK<C::ConstraintField>> Verifier<C, S> {
+    pub fn new(vk: &S::VerifyingKey) -> Result<Self> {
+        let pvk = S::process_vk(vk).map_err(snark_error)?;
+        Ok(Self { pvk, config: Config::default(), circuit_id: None, _c: PhantomData })
+    }
+
+    /// Sets the config the public inputs are encoded with, that has to be the one of the proven circuits.
+    pub fn with_config(self, config: Config) -> Self {
+        Self { config, ..self }
+    }
+
+    /// Sets the id of the circuit of the key, so that `verify_envelope` rejects the envelopes of other circuits.
+    pub fn with_circuit_id(self, circuit_id: CircuitId) -> Self {
+        Self { circuit_id: Some(circuit_id), ..self }
+    }
+
+    /// Verifies the proof against the instance vector derived from the keys and the bitmask.
+    pub fn verify(&self, keys: &[Affine<C::Curve>], bitmask: &Bitmask, proof: &S::Proof) -> Result<bool>
+        where S::ProcessedVerifyingKey: 'static,
+              S::Proof: 'static,
+    {
+        let pi = public_inputs::<C::Curve, C::ConstraintField, C::FieldVar>(keys, bitmask, &self.config)?;
+        self.verify_with_inputs(&pi, proof)
+    }
+
+    /// Verifies the proof in the envelope, after checking it's been produced for this chain and circuit shape.
+    pub fn verify_envelope(&self, keys: &[Affine<C::Curve>], envelope: &ProofEnvelope) -> Result<bool>
+        where S::ProcessedVerifyingKey: 'static,
+              S::Proof: 'static,
+    {
+        let proof = envelope.open::<C, S>(self.circuit_id.as_ref())?;
+        self.verify(keys, &envelope.public_data.bitmask, &proof)
+    }
+
+    /// Verifies the proof against an instance vector prepared by the caller.
+    /// Groth16 proofs are verified with the inputs prepared by `prepare_inputs`, the other backends as they do.
+    pub fn verify_with_inputs(&self, public_inputs: &[C::ConstraintField], proof: &S::Proof) -> Result<bool>
+        where S::ProcessedVerifyingKey: 'static,
+              S::Proof: 'static,
+    {
+        let groth16 = (&self.pvk as &dyn Any).downcast_ref::<PreparedVerifyingKey<C::Pairing>>()
+            .zip((proof as &dyn Any).downcast_ref::<Proof<C::Pairing>>());
+        if let Some((pvk, proof)) = groth16 {
+            let prepared = prepare_inputs(pvk, public_inputs)?;
+            return host_phase("verification", || Groth16::<C::Pairing>::verify_proof_with_prepared_inputs(pvk, proof, &prepared).map_err(snark_error));
+        }
+        host_phase("verification", || S::verify_with_processed_vk(&self.pvk, public_inputs, proof).map_err(snark_error))
+    }
+}
+
+/// Prepares the Groth16 instance vector with a multi-scalar multiplication, where `Groth16::prepare_inputs`
+/// takes a scalar multiplication per input, that adds up for the thousands of limbs of the emulated chains.
+fn prepare_inputs<E: Pairing>(pvk: &PreparedVerifyingKey<E>, public_inputs: &[E::ScalarField]) -> Result<E::G1> {
+    let expected = pvk.vk.gamma_abc_g1.len() - 1;
+    if public_inputs.len() != expected {
+        return Err(SnowballError::InstanceLength { expected, len: public_inputs.len() });
+    }
+    Ok(host_phase("input preparation", || msm_inputs(pvk, public_inputs)))
+}
+
+// The prepared inputs of a prefix of the instance vector.
+fn msm_inputs<E: Pairing>(pvk: &PreparedVerifyingKey<E>, public_inputs: &[E::ScalarField]) -> E::G1 {
+    let bases = &pvk.vk.gamma_abc_g1;
+    bases[0] + E::G1::msm_unchecked(&bases[1..=public_inputs.len()], public_inputs)
+}
+
+/// The share of the keys in a prepared Groth16 instance, so that verifying proofs for many bitmasks
+/// over the same keys takes a single scalar multiplication of the prepared inputs each.
+#[derive(Derivative)]
+#[derivative(Clone(bound = ""), Debug(bound = ""))]
+pub struct PreparedKeys<C: Chain> {
+    prepared: <C::Pairing as Pairing>::G1,
+    num_keys: usize,
+}
+
+impl<C: Chain> PreparedKeys<C> {
+    pub fn num_keys(&self) -> usize {
+        self.num_keys
+    }
+}
+
+impl<C: Chain> Verifier<C, Groth16<C::Pairing>> {
+    /// Prepares the inputs of the keys, that `verify_prepared` completes with the bitmask.
+    pub fn prepare_keys(&self, keys: &[Affine<C::Curve>]) -> Result<PreparedKeys<C>> {
+        // The packed bitmask is the last input, so the limbs of the keys are a prefix of the instance.
+        let mut pi = public_inputs::<C::Curve, C::ConstraintField, C::FieldVar>(keys, &Bitmask::from_bits(vec![false; keys.len()]), &self.config)?;
+        let expected = self.pvk.vk.gamma_abc_g1.len() - 1;
+        if pi.len() != expected {
+            return Err(SnowballError::InstanceLength { expected, len: pi.len() });
+        }
+        pi.pop();
+        let prepared = host_phase("input preparation", || msm_inputs(&self.pvk, &pi));
+        Ok(PreparedKeys { prepared, num_keys: keys.len() })
+    }
+
+    /// The instance vector prepared as `verify` does, see `prepare_inputs`.
+    pub fn prepare_inputs(&self, public_inputs: &[C::ConstraintField]) -> Result<<C::Pairing as Pairing>::G1> {
+        prepare_inputs(&self.pvk, public_inputs)
+    }
+
+    /// Verifies the proof against the keys prepared by `prepare_keys` and the bitmask.
+    pub fn verify_prepared(&self, keys: &PreparedKeys<C>, bitmask: &Bitmask, proof: &Proof<C::Pairing>) -> Result<bool> {
+        if bitmask.len() != keys.num_keys {
+            return Err(SnowballError::LengthMismatch { keys: keys.num_keys, bits: bitmask.len() });
+        }
+        let packed_bits: C::ConstraintField = bitmask.pack()?;
+        let bits_base = self.pvk.vk.gamma_abc_g1.last().expect("the packed bitmask is an input");
+        let prepared = keys.prepared + *bits_base * packed_bits;
+        host_phase("verification", || Groth16::<C::Pairing>::verify_proof_with_prepared_inputs(&self.pvk, proof, &prepared).map_err(snark_error))
+    }
+}
+
+#[cfg(all(test, feature = "bls12-377-bw6"))]
+mod tests {
+    use ark_groth16::Groth16;
+    use ark_relations::r1cs::{ConstraintSynthesizer, SynthesisError};
+    use ark_std::UniformRand;
+    use rand::rngs::OsRng;
+
+    use crate::chain::Bls377InBw6;
+    #[cfg(feature = "bls12-381-emulated")]
+    use crate::chain::Bls381Emulated;
+    use crate::envelope::PublicData;
+
+    use super::*;
+
+    #[test]
+    fn test_groth16_native() {
+        let rng = &mut OsRng;
+        let keys: Vec<ark_bls12_377::G1Affine> = (0..2).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+        let seed = ark_bls12_377::G1Affine::rand(rng);
+        let bitmask = Bitmask::from_bits(vec![true, true]);
+        let circuit = ChainCircuit::<Bls377InBw6>::new(keys.clone(), seed, &bitmask).unwrap();
+
+        let (prover, verifier) = setup::<Bls377InBw6, Groth16<ark_bw6_761::BW6_761>, _>(circuit.clone(), rng).unwrap();
+        let prover = prover.with_config(Config { num_threads: Some(2), ..Default::default() });
+        let circuit_id = CircuitId::of_circuit::<Bls377InBw6, Groth16<ark_bw6_761::BW6_761>>(&circuit);
+        assert_eq!(verifier.circuit_id.as_ref(), Some(&circuit_id));
+        let proof = prover.prove(circuit, rng).unwrap();
+        assert!(verifier.verify(&keys, &bitmask, &proof).unwrap());
+        let envelope = ProofEnvelope::new::<Bls377InBw6, Groth16<ark_bw6_761::BW6_761>>(circuit_id, &proof, PublicData { bitmask: bitmask.clone() }).unwrap();
+        assert!(verifier.verify_envelope(&keys, &envelope).unwrap());
+        assert!(!verifier.verify(&keys, &Bitmask::from_bits(vec![true, false]), &proof).unwrap());
+    }
+
+    #[test]
+    fn test_prepared_keys() {
+        let rng = &mut OsRng;
+        let keys: Vec<ark_bls12_377::G1Affine> = (0..3).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+        let seed = ark_bls12_377::G1Affine::rand(rng);
+        let bitmasks = [Bitmask::from_bits(vec![true, false, true]), Bitmask::from_bits(vec![false, true, true])];
+        let circuit = |bitmask| ChainCircuit::<Bls377InBw6>::new(keys.clone(), seed, bitmask).unwrap();
+
+        let (prover, verifier) = setup::<Bls377InBw6, Groth16<ark_bw6_761::BW6_761>, _>(circuit(&bitmasks[0]), rng).unwrap();
+        let prepared = verifier.prepare_keys(&keys).unwrap();
+        for bitmask in &bitmasks {
+            let proof = prover.prove(circuit(bitmask), rng).unwrap();
+            assert!(verifier.verify_prepared(&prepared, bitmask, &proof).unwrap());
+            assert!(!verifier.verify_prepared(&prepared, &Bitmask::from_bits(vec![true; 3]), &proof).unwrap());
+        }
+        assert!(matches!(verifier.verify_prepared(&prepared, &Bitmask::from_bits(vec![true; 2]), &Proof::default()), Err(SnowballError::LengthMismatch { .. })));
+        assert!(matches!(verifier.prepare_keys(&keys[..2]), Err(SnowballError::InstanceLength { .. })));
+    }
+
+    #[cfg(feature = "bls12-381-emulated")]
+    #[test]
+    fn test_prepare_inputs() {
+        let rng = &mut OsRng;
+        let keys: Vec<ark_bls12_381::G1Affine> = (0..2).map(|_| ark_bls12_381::G1Affine::rand(rng)).collect();
+        let seed = ark_bls12_381::G1Affine::rand(rng);
+        let bitmask = Bitmask::from_bits(vec![true, true]);
+        let circuit = ChainCircuit::<Bls381Emulated>::new(keys.clone(), seed, &bitmask).unwrap();
+
+        let (prover, verifier) = setup::<Bls381Emulated, Groth16<ark_bls12_381::Bls12_381>, _>(circuit.clone(), rng).unwrap();
+        let pi = public_inputs::<_, _, <Bls381Emulated as Chain>::FieldVar>(&keys, &bitmask, &verifier.config).unwrap();
+        assert_eq!(verifier.prepare_inputs(&pi).unwrap(), Groth16::<ark_bls12_381::Bls12_381>::prepare_inputs(&verifier.pvk, &pi).unwrap());
+        assert!(matches!(verifier.prepare_inputs(&pi[1..]), Err(SnowballError::InstanceLength { .. })));
+
+        let proof = prover.prove(circuit, rng).unwrap();
+        assert!(verifier.verify(&keys, &bitmask, &proof).unwrap());
+        assert!(!verifier.verify(&keys, &Bitmask::from_bits(vec![true, false]), &proof).unwrap());
+        assert!(matches!(verifier.verify_with_inputs(&pi[1..], &proof), Err(SnowballError::InstanceLength { .. })));
+    }
+
+    #[cfg(feature = "insecure-setup")]
+    #[test]
+    fn test_setup_deterministic() {
+        use ark_serialize::CanonicalSerialize;
+
+        let rng = &mut OsRng;
+        let keys = vec![ark_bls12_377::G1Affine::rand(rng)];
+        let seed = ark_bls12_377::G1Affine::rand(rng);
+        let circuit = ChainCircuit::<Bls377InBw6>::new(keys, seed, &Bitmask::from_bits(vec![true])).unwrap();
+
+        let vk_bytes = |circuit| {
+            let (prover, _) = setup_deterministic::<Bls377InBw6, Groth16<ark_bw6_761::BW6_761>>(circuit, [7; 32]).unwrap();
+            let mut bytes = vec![];
+            prover.pk.vk.serialize_compressed(&mut bytes).unwrap();
+            bytes
+        };
+        assert_eq!(vk_bytes(circuit.clone()), vk_bytes(circuit));
+    }
+
+    // Delegates to Groth16, pretending the circuit-specific setup is an indexer.
+    struct MockUniversal;
+
+    type Bw6Groth16 = Groth16<ark_bw6_761::BW6_761>;
+
+    impl SNARK<ark_bw6_761::Fr> for MockUniversal {
+        type ProvingKey = <Bw6Groth16 as SNARK<ark_bw6_761::Fr>>::ProvingKey;
+        type VerifyingKey = <Bw6Groth16 as SNARK<ark_bw6_761::Fr>>::VerifyingKey;
+        type Proof = <Bw6Groth16 as SNARK<ark_bw6_761::Fr>>::Proof;
+        type ProcessedVerifyingKey = <Bw6Groth16 as SNARK<ark_bw6_761::Fr>>::ProcessedVerifyingKey;
+        type Error = SynthesisError;
+
+        fn circuit_specific_setup<CS: ConstraintSynthesizer<ark_bw6_761::Fr>, R: RngCore + CryptoRng>(circuit: CS, rng: &mut R) -> std::result::Result<(Self::ProvingKey, Self::VerifyingKey), Self::Error> {
+            Bw6Groth16::circuit_specific_setup(circuit, rng)
+        }
+
+        fn prove<CS: ConstraintSynthesizer<ark_bw6_761::Fr>, R: RngCore + CryptoRng>(pk: &Self::ProvingKey, circuit: CS, rng: &mut R) -> std::result::Result<Self::Proof, Self::Error> {
+            Bw6Groth16::prove(pk, circuit, rng)
+        }
+
+        fn process_vk(vk: &Self::VerifyingKey) -> std::result::Result<Self::ProcessedVerifyingKey, Self::Error> {
+            Bw6Groth16::process_vk(vk)
+        }
+
+        fn verify_with_processed_vk(pvk: &Self::ProcessedVerifyingKey, x: &[ark_bw6_761::Fr], proof: &Self::Proof) -> std::result::Result<bool, Self::Error> {
+            Bw6Groth16::verify_with_processed_vk(pvk, x, proof)
+        }
+    }
+
+    impl UniversalSetupSNARK<ark_bw6_761::Fr> for MockUniversal {
+        // Max number of variables.
+        type ComputationBound = usize;
+        type PublicParameters = usize;
+
+        fn universal_setup<R: RngCore + CryptoRng>(bound: &usize, _: &mut R) -> std::result::Result<usize, Self::Error> {
+            Ok(*bound)
+        }
+
+        fn index<CS: ConstraintSynthesizer<ark_bw6_761::Fr>, R: RngCore + CryptoRng>(pp: &usize, circuit: CS, rng: &mut R) -> std::result::Result<(Self::ProvingKey, Self::VerifyingKey), UniversalSetupIndexError<usize, Self::Error>> {
+            let (pk, vk) = Bw6Groth16::circuit_specific_setup(circuit, rng).map_err(UniversalSetupIndexError::Other)?;
+            if pk.a_query.len() > *pp {
+                return Err(UniversalSetupIndexError::NeedLargerBound(pk.a_query.len()));
+            }
+            Ok((pk, vk))
+        }
+    }
+
+    #[test]
+    fn test_universal_setup() {
+        let rng = &mut OsRng;
+        let keys = vec![ark_bls12_377::G1Affine::rand(rng)];
+        let seed = ark_bls12_377::G1Affine::rand(rng);
+        let bitmask = Bitmask::from_bits(vec![true]);
+        let circuit = ChainCircuit::<Bls377InBw6>::new(keys.clone(), seed, &bitmask).unwrap();
+
+        let pp = MockUniversal::universal_setup(&1, rng).unwrap();
+        let res = index::<Bls377InBw6, MockUniversal, _>(&pp, circuit.clone(), rng);
+        assert!(matches!(res, Err(SnowballError::BoundTooSmall(_))));
+
+        let pp = MockUniversal::universal_setup(&1000, rng).unwrap();
+        let (prover, verifier) = index::<Bls377InBw6, MockUniversal, _>(&pp, circuit.clone(), rng).unwrap();
+        let proof = prover.prove(circuit, rng).unwrap();
+        assert!(verifier.verify(&keys, &bitmask, &proof).unwrap());
+    }
+}
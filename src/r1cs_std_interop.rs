@@ -0,0 +1,354 @@
+//! Conversions between the native instantiations of the point gadgets of this crate and the stock
+//! `ark-r1cs-std` ones, so the cheap incomplete additions can be mixed with their scalar multiplications.
+//!
+//! The stock gadgets are over the base field of the curve, so only the `FpVar` instantiations convert.
+//! Their `ProjectiveVar` is homogeneous, `(X / Z, Y / Z)`, unlike the Jacobian `ProjectiveVarGeneric`.
+//! The stock `AffineVar` can't be constructed outside of `ark-r1cs-std`: it's `ProjectiveVar::to_affine` of the conversion.
+//!
+//! The native `ProjectiveVarGeneric` is a `CurveVar` itself, for the gadgets generic over it.
+
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+
+use ark_ec::CurveConfig;
+use ark_ec::short_weierstrass::{Projective, SWCurveConfig};
+use ark_ff::PrimeField;
+use ark_r1cs_std::alloc::AllocationMode;
+use ark_r1cs_std::bits::{ToBitsGadget, ToBytesGadget};
+use ark_r1cs_std::bits::uint8::UInt8;
+use ark_r1cs_std::boolean::Boolean;
+use ark_r1cs_std::eq::EqGadget;
+use ark_r1cs_std::fields::FieldVar;
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_r1cs_std::groups::{CurveVar, GroupOpsBounds};
+use ark_r1cs_std::groups::curves::short_weierstrass::{AffineVar, ProjectiveVar};
+use ark_r1cs_std::groups::curves::short_weierstrass::non_zero_affine::NonZeroAffineVar;
+use ark_relations::r1cs::{Namespace, SynthesisError};
+
+use crate::affine_gen::{nonzero_inverse, AffineVarGeneric, NonZeroAffineVarGeneric};
+use crate::projective_gen::ProjectiveVarGeneric;
+
+impl<P> From<NonZeroAffineVarGeneric<P, FpVar<P::BaseField>, P::BaseField>> for ProjectiveVar<P, FpVar<P::BaseField>>
+    where P: SWCurveConfig, P::BaseField: PrimeField,
+{
+    fn from(p: NonZeroAffineVarGeneric<P, FpVar<P::BaseField>, P::BaseField>) -> Self {
+        Self::new(p.x, p.y, FpVar::one())
+    }
+}
+
+/// Enforces `Z` to be non-zero, for 3 constraints: the stock gadgets may well return the identity.
+impl<P> TryFrom<ProjectiveVar<P, FpVar<P::BaseField>>> for NonZeroAffineVarGeneric<P, FpVar<P::BaseField>, P::BaseField>
+    where P: SWCurveConfig, P::BaseField: PrimeField,
+{
+    type Error = SynthesisError;
+
+    fn try_from(p: ProjectiveVar<P, FpVar<P::BaseField>>) -> Result<Self, Self::Error> {
+        let z_inv = nonzero_inverse(&p.z)?;
+        Ok(Self::new(p.x * &z_inv, p.y * z_inv))
+    }
+}
+
+/// Enforces `infinity` to be `false`.
+impl<P> TryFrom<AffineVar<P, FpVar<P::BaseField>>> for NonZeroAffineVarGeneric<P, FpVar<P::BaseField>, P::BaseField>
+    where P: SWCurveConfig, P::BaseField: PrimeField,
+{
+    type Error = SynthesisError;
+
+    fn try_from(p: AffineVar<P, FpVar<P::BaseField>>) -> Result<Self, Self::Error> {
+        p.infinity.enforce_equal(&Boolean::FALSE)?;
+        Ok(Self::new(p.x, p.y))
+    }
+}
+
+impl<P> From<AffineVar<P, FpVar<P::BaseField>>> for AffineVarGeneric<P, FpVar<P::BaseField>, P::BaseField>
+    where P: SWCurveConfig, P::BaseField: PrimeField,
+{
+    fn from(p: AffineVar<P, FpVar<P::BaseField>>) -> Self {
+        Self::new(p.x, p.y, p.infinity)
+    }
+}
+
+impl<P> From<NonZeroAffineVarGeneric<P, FpVar<P::BaseField>, P::BaseField>> for NonZeroAffineVar<P, FpVar<P::BaseField>>
+    where P: SWCurveConfig, P::BaseField: PrimeField,
+{
+    fn from(p: NonZeroAffineVarGeneric<P, FpVar<P::BaseField>, P::BaseField>) -> Self {
+        Self::new(p.x, p.y)
+    }
+}
+
+impl<P> From<NonZeroAffineVar<P, FpVar<P::BaseField>>> for NonZeroAffineVarGeneric<P, FpVar<P::BaseField>, P::BaseField>
+    where P: SWCurveConfig, P::BaseField: PrimeField,
+{
+    fn from(p: NonZeroAffineVar<P, FpVar<P::BaseField>>) -> Self {
+        Self::new(p.x, p.y)
+    }
+}
+
+// The native instantiations, that of `ProjectiveVarGeneric` the `CurveVar` of this crate.
+type NativeProjectiveVar<P> = ProjectiveVarGeneric<P, FpVar<<P as CurveConfig>::BaseField>, <P as CurveConfig>::BaseField>;
+type NativeAffineVar<P> = AffineVarGeneric<P, FpVar<<P as CurveConfig>::BaseField>, <P as CurveConfig>::BaseField>;
+
+fn add<P>(a: &NativeProjectiveVar<P>, b: &NativeProjectiveVar<P>) -> NativeProjectiveVar<P>
+    where P: SWCurveConfig, P::BaseField: PrimeField,
+{
+    a.add(b).unwrap()
+}
+
+fn sub<P>(a: &NativeProjectiveVar<P>, b: &NativeProjectiveVar<P>) -> NativeProjectiveVar<P>
+    where P: SWCurveConfig, P::BaseField: PrimeField,
+{
+    a.add(&b.negate().unwrap()).unwrap()
+}
+
+// The operators `CurveVar` wants, on the values and the references, of the variables and the constants, by `$f`.
+// They can't fail but by a missing assignment, that the stock gadgets unwrap as well.
+macro_rules! impl_group_op {
+    ($trait:ident, $fn:ident, $assign_trait:ident, $assign_fn:ident, $f:path) => {
+        impl<'a, P: SWCurveConfig> $trait<&'a NativeProjectiveVar<P>> for &'a NativeProjectiveVar<P> where P::BaseField: PrimeField {
+            type Output = NativeProjectiveVar<P>;
+
+            fn $fn(self, other: Self) -> Self::Output {
+                $f(self, other)
+            }
+        }
+
+        impl<'a, P: SWCurveConfig> $trait<NativeProjectiveVar<P>> for &'a NativeProjectiveVar<P> where P::BaseField: PrimeField {
+            type Output = NativeProjectiveVar<P>;
+
+            fn $fn(self, other: NativeProjectiveVar<P>) -> Self::Output {
+                $f(self, &other)
+            }
+        }
+
+        impl<'a, P: SWCurveConfig> $trait<&'a NativeProjectiveVar<P>> for NativeProjectiveVar<P> where P::BaseField: PrimeField {
+            type Output = NativeProjectiveVar<P>;
+
+            fn $fn(self, other: &'a Self) -> Self::Output {
+                $f(&self, other)
+            }
+        }
+
+        impl<P: SWCurveConfig> $trait<NativeProjectiveVar<P>> for NativeProjectiveVar<P> where P::BaseField: PrimeField {
+            type Output = NativeProjectiveVar<P>;
+
+            fn $fn(self, other: Self) -> Self::Output {
+                $f(&self, &other)
+            }
+        }
+
+        impl<'a, P: SWCurveConfig> $trait<Projective<P>> for &'a NativeProjectiveVar<P> where P::BaseField: PrimeField {
+            type Output = NativeProjectiveVar<P>;
+
+            fn $fn(self, other: Projective<P>) -> Self::Output {
+                $f(self, &NativeProjectiveVar::constant(other))
+            }
+        }
+
+        impl<P: SWCurveConfig> $trait<Projective<P>> for NativeProjectiveVar<P> where P::BaseField: PrimeField {
+            type Output = NativeProjectiveVar<P>;
+
+            fn $fn(self, other: Projective<P>) -> Self::Output {
+                $f(&self, &NativeProjectiveVar::constant(other))
+            }
+        }
+
+        impl<'a, P: SWCurveConfig> $assign_trait<&'a NativeProjectiveVar<P>> for NativeProjectiveVar<P> where P::BaseField: PrimeField {
+            fn $assign_fn(&mut self, other: &'a Self) {
+                *self = $f(self, other);
+            }
+        }
+
+        impl<P: SWCurveConfig> $assign_trait<NativeProjectiveVar<P>> for NativeProjectiveVar<P> where P::BaseField: PrimeField {
+            fn $assign_fn(&mut self, other: Self) {
+                *self = $f(self, &other);
+            }
+        }
+
+        impl<P: SWCurveConfig> $assign_trait<Projective<P>> for NativeProjectiveVar<P> where P::BaseField: PrimeField {
+            fn $assign_fn(&mut self, other: Projective<P>) {
+                *self = $f(self, &NativeProjectiveVar::constant(other));
+            }
+        }
+    };
+}
+
+impl_group_op!(Add, add, AddAssign, add_assign, add);
+impl_group_op!(Sub, sub, SubAssign, sub_assign, sub);
+
+impl<'a, P: SWCurveConfig> GroupOpsBounds<'a, Projective<P>, NativeProjectiveVar<P>> for NativeProjectiveVar<P> where P::BaseField: PrimeField {}
+
+impl<'a, P: SWCurveConfig> GroupOpsBounds<'a, Projective<P>, NativeProjectiveVar<P>> for &'a NativeProjectiveVar<P> where P::BaseField: PrimeField {}
+
+// `to_affine`, with the coordinates `(0, 0)` for the identity: `to_affine` leaves them as they are.
+fn canonical_affine<P>(p: &NativeProjectiveVar<P>) -> Result<NativeAffineVar<P>, SynthesisError>
+    where P: SWCurveConfig, P::BaseField: PrimeField,
+{
+    let affine = p.to_affine()?;
+    let x = affine.infinity.select(&FpVar::zero(), &affine.x)?;
+    let y = affine.infinity.select(&FpVar::zero(), &affine.y)?;
+    Ok(AffineVarGeneric::new(x, y, affine.infinity))
+}
+
+/// The affine coordinates and the flag, as the stock gadget has them.
+impl<P> ToBitsGadget<P::BaseField> for NativeProjectiveVar<P>
+    where P: SWCurveConfig, P::BaseField: PrimeField,
+{
+    fn to_bits_le(&self) -> Result<Vec<Boolean<P::BaseField>>, SynthesisError> {
+        let p = canonical_affine(self)?;
+        Ok([p.x.to_bits_le()?, p.y.to_bits_le()?, vec![p.infinity]].concat())
+    }
+}
+
+impl<P> ToBytesGadget<P::BaseField> for NativeProjectiveVar<P>
+    where P: SWCurveConfig, P::BaseField: PrimeField,
+{
+    fn to_bytes(&self) -> Result<Vec<UInt8<P::BaseField>>, SynthesisError> {
+        let p = canonical_affine(self)?;
+        Ok([p.x.to_bytes()?, p.y.to_bytes()?, p.infinity.to_bytes()?].concat())
+    }
+}
+
+/// The complete `ProjectiveVarGeneric`, for the gadgets generic over `CurveVar`. The additions are those of
+/// `ProjectiveVarGeneric::add`, and the allocations check the points, `new_variable_omit_prime_order_check`
+/// only that they're on the curve.
+impl<P> CurveVar<Projective<P>, P::BaseField> for NativeProjectiveVar<P>
+    where P: SWCurveConfig, P::BaseField: PrimeField,
+{
+    fn zero() -> Self {
+        ProjectiveVarGeneric::zero()
+    }
+
+    fn is_zero(&self) -> Result<Boolean<P::BaseField>, SynthesisError> {
+        ProjectiveVarGeneric::is_zero(self)
+    }
+
+    fn constant(other: Projective<P>) -> Self {
+        Self::new(FpVar::constant(other.x), FpVar::constant(other.y), FpVar::constant(other.z))
+    }
+
+    fn new_variable_omit_prime_order_check(cs: impl Into<Namespace<P::BaseField>>, f: impl FnOnce() -> Result<Projective<P>, SynthesisError>, mode: AllocationMode) -> Result<Self, SynthesisError> {
+        let p = Self::new_variable_unchecked(cs, f, mode)?;
+        if mode != AllocationMode::Constant {
+            p.enforce_on_curve()?;
+        }
+        Ok(p)
+    }
+
+    fn enforce_prime_order(&self) -> Result<(), SynthesisError> {
+        self.enforce_in_prime_subgroup()
+    }
+
+    fn double_in_place(&mut self) -> Result<(), SynthesisError> {
+        *self = ProjectiveVarGeneric::double(self)?;
+        Ok(())
+    }
+
+    fn negate(&self) -> Result<Self, SynthesisError> {
+        ProjectiveVarGeneric::negate(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_bls12_377::{Fq, G1Affine};
+    use ark_bls12_377::g1::Config;
+    use ark_ec::{AffineRepr, CurveGroup};
+    use ark_ff::{BigInteger, One};
+    use ark_r1cs_std::alloc::AllocVar;
+    use ark_r1cs_std::groups::CurveVar;
+    use ark_r1cs_std::R1CSVar;
+    use ark_relations::ns;
+    use ark_relations::r1cs::ConstraintSystem;
+    use ark_std::{test_rng, UniformRand};
+
+    use super::*;
+
+    type PointVar = NonZeroAffineVarGeneric<Config, FpVar<Fq>, Fq>;
+    type ProjectivePointVar = ProjectiveVarGeneric<Config, FpVar<Fq>, Fq>;
+
+    // `[k] p + q - p`, with nothing but the trait.
+    fn curve_var_ops<C: CurveVar<Projective<Config>, Fq>>(p: &C, q: &C, k: &[Boolean<Fq>]) -> C {
+        let mut sum = p.scalar_mul_le(k.iter()).unwrap() + q;
+        sum -= p;
+        sum
+    }
+
+    #[test]
+    fn test_conversions() {
+        let rng = &mut test_rng();
+        let (p, q) = (G1Affine::rand(rng), G1Affine::rand(rng));
+        let cs = ConstraintSystem::<Fq>::new_ref();
+        let p_var = PointVar::new_witness(ns!(cs, "p"), || Ok(p)).unwrap();
+        let q_var = PointVar::new_witness(ns!(cs, "q"), || Ok(q)).unwrap();
+
+        // The stock doubling of the sum of the crate, back.
+        let sum = ProjectiveVar::from(p_var.add_unchecked(&q_var).unwrap());
+        let doubled = PointVar::try_from(sum.double().unwrap()).unwrap();
+        assert_eq!(doubled.value().unwrap(), ((p + q) + (p + q)).into_affine());
+
+        let affine = PointVar::try_from(ProjectiveVar::from(p_var.clone()).to_affine().unwrap()).unwrap();
+        assert_eq!(affine.value().unwrap(), p);
+        let stock = NonZeroAffineVar::from(q_var.clone());
+        assert_eq!(PointVar::from(stock).value().unwrap(), q);
+        let identity = AffineVarGeneric::from(ProjectiveVar::<Config, FpVar<Fq>>::zero().to_affine().unwrap());
+        assert!(identity.value().unwrap().is_zero());
+        assert!(cs.is_satisfied().unwrap());
+
+        // The identity doesn't convert.
+        let zero = ProjectiveVar::from(p_var.clone()) - ProjectiveVar::from(p_var);
+        let _ = PointVar::try_from(zero).unwrap();
+        assert!(!cs.is_satisfied().unwrap());
+        assert!(matches!(PointVar::try_from(ProjectiveVar::<Config, FpVar<Fq>>::zero()), Err(SynthesisError::Unsatisfiable)));
+    }
+
+    #[test]
+    fn test_curve_var() {
+        let rng = &mut test_rng();
+        let (p, q) = (G1Affine::rand(rng), G1Affine::rand(rng));
+        let k = ark_bls12_377::Fr::from(1000003u64);
+        let cs = ConstraintSystem::<Fq>::new_ref();
+        let k_bits = Vec::<Boolean<Fq>>::new_witness(ns!(cs, "k"), || Ok(k.into_bigint().to_bits_le())).unwrap();
+        let p_var = ProjectivePointVar::new_variable_omit_prime_order_check(ns!(cs, "p"), || Ok(p.into()), AllocationMode::Witness).unwrap();
+        let q_var = ProjectivePointVar::new_witness(ns!(cs, "q"), || Ok(q)).unwrap();
+        let stock_p = ProjectiveVar::<Config, FpVar<Fq>>::new_witness(ns!(cs, "stock_p"), || Ok(p)).unwrap();
+        let stock_q = ProjectiveVar::<Config, FpVar<Fq>>::new_witness(ns!(cs, "stock_q"), || Ok(q)).unwrap();
+
+        let expected = p * k + q - p;
+        let sum = curve_var_ops(&p_var, &q_var, &k_bits);
+        assert_eq!(sum.value().unwrap(), expected);
+        let stock = curve_var_ops(&stock_p, &stock_q, &k_bits);
+        assert_eq!(sum.to_bits_le().unwrap().value().unwrap(), stock.to_bits_le().unwrap().value().unwrap());
+        assert_eq!(sum.to_bytes().unwrap().value().unwrap(), stock.to_bytes().unwrap().value().unwrap());
+        sum.enforce_equal(&(ProjectivePointVar::constant(p * k) + &q_var - p.into_group())).unwrap();
+        sum.enforce_not_equal(&q_var).unwrap();
+
+        let zero = &p_var - &p_var;
+        assert!(CurveVar::is_zero(&zero).unwrap().value().unwrap());
+        zero.enforce_equal(&ProjectivePointVar::zero()).unwrap();
+        zero.enforce_prime_order().unwrap();
+        p_var.enforce_prime_order().unwrap();
+        assert!(cs.is_satisfied().unwrap());
+
+        // Off the curve, and not in the subgroup.
+        let cs = ConstraintSystem::<Fq>::new_ref();
+        let off = Projective::<Config>::new_unchecked(p.x, p.x, Fq::one());
+        let _ = ProjectivePointVar::new_witness(ns!(cs, "off"), || Ok(off)).unwrap();
+        assert!(!cs.is_satisfied().unwrap());
+        let cs = ConstraintSystem::<Fq>::new_ref();
+        let off = Projective::<Config>::new_unchecked(p.x, p.x, Fq::one());
+        let _ = ProjectivePointVar::new_variable_omit_prime_order_check(ns!(cs, "off"), || Ok(off), AllocationMode::Witness).unwrap();
+        assert!(!cs.is_satisfied().unwrap());
+        let cs = ConstraintSystem::<Fq>::new_ref();
+        let point = loop {
+            let x = Fq::rand(rng);
+            if let Some(point) = G1Affine::get_point_from_x_unchecked(x, false) {
+                break point;
+            }
+        };
+        let var = ProjectivePointVar::new_variable_omit_prime_order_check(ns!(cs, "point"), || Ok(point.into()), AllocationMode::Witness).unwrap();
+        var.enforce_prime_order().unwrap();
+        assert!(!cs.is_satisfied().unwrap());
+        let cs = ConstraintSystem::<Fq>::new_ref();
+        let _ = ProjectivePointVar::new_witness(ns!(cs, "point"), || Ok(point)).unwrap();
+        assert!(!cs.is_satisfied().unwrap());
+    }
+}
@@ -0,0 +1,154 @@
+//! Run-length encoding of participation bitmasks, for when participation is near-total or near-empty.
+//!
+//! A bitmask is encoded as its first bit followed by the lengths of the maximal runs of equal bits,
+//! so `1110011` is `(true, [3, 2, 2])`.
+
+use ark_ff::PrimeField;
+use ark_r1cs_std::alloc::AllocVar;
+use ark_r1cs_std::boolean::Boolean;
+use ark_r1cs_std::eq::EqGadget;
+use ark_r1cs_std::fields::FieldVar;
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_r1cs_std::R1CSVar;
+use ark_relations::r1cs::SynthesisError;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+
+use crate::bitmask::Bitmask;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, CanonicalSerialize, CanonicalDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RleBitmask {
+    first: bool,
+    runs: Vec<u32>,
+}
+
+impl RleBitmask {
+    pub fn first(&self) -> bool {
+        self.first
+    }
+
+    pub fn runs(&self) -> &[u32] {
+        &self.runs
+    }
+
+    pub fn len(&self) -> usize {
+        self.runs.iter().map(|r| *r as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    pub fn to_bitmask(&self) -> Bitmask {
+        let bits = self.runs.iter()
+            .enumerate()
+            .flat_map(|(i, r)| std::iter::repeat_n(self.first ^ (i % 2 == 1), *r as usize))
+            .collect();
+        Bitmask::from_bits(bits)
+    }
+}
+
+impl From<&Bitmask> for RleBitmask {
+    fn from(bitmask: &Bitmask) -> Self {
+        let bits = bitmask.bits();
+        let mut runs: Vec<u32> = vec![];
+        for (i, bit) in bits.iter().enumerate() {
+            match runs.last_mut() {
+                Some(run) if *bit == bits[i - 1] => *run += 1,
+                _ => runs.push(1),
+            }
+        }
+        Self { first: bits.first().copied().unwrap_or_default(), runs }
+    }
+}
+
+/// Decodes `len` bits from the first bit and the run lengths.
+///
+/// Each boundary between runs is compared to every position, so that's about `2 * len * runs` constraints,
+/// which beats allocating the bits one by one only when there are a few runs.
+/// Enforces the runs to be non-empty and to sum up to `len`.
+pub fn decode_rle<CF: PrimeField>(first: &Boolean<CF>, run_lengths: &[FpVar<CF>], len: usize) -> Result<Vec<Boolean<CF>>, SynthesisError> {
+    if len == 0 {
+        for l in run_lengths {
+            l.enforce_equal(&FpVar::zero())?;
+        }
+        return Ok(vec![]);
+    }
+    let cs = first.cs().or(run_lengths.cs());
+    let total = run_lengths.iter().fold(FpVar::zero(), |acc, l| acc + l);
+    total.enforce_equal(&FpVar::constant(CF::from(len as u64)))?;
+
+    // Positions of the first bits of the runs but the first one, each required to be within `1..len`.
+    let mut boundaries = Vec::with_capacity(run_lengths.len().saturating_sub(1));
+    let mut acc = FpVar::zero();
+    for l in run_lengths.iter().take(run_lengths.len().saturating_sub(1)) {
+        acc += l;
+        boundaries.push(acc.clone());
+    }
+    let mut hits: Vec<Vec<Boolean<CF>>> = vec![vec![]; len];
+    for p in &boundaries {
+        let mut p_hits = vec![];
+        for (i, hits_i) in hits.iter_mut().enumerate().skip(1) {
+            let hit = p.is_eq(&FpVar::constant(CF::from(i as u64)))?;
+            hits_i.push(hit.clone());
+            p_hits.push(hit);
+        }
+        Boolean::enforce_equal(&Boolean::kary_or(&p_hits)?, &Boolean::TRUE)?;
+        // With the above, exactly one hit, as at most one position can be equal to `p`.
+    }
+
+    let mut bits = Vec::with_capacity(len);
+    bits.push(first.clone());
+    for hits_i in hits.iter().skip(1) {
+        let sum = hits_i.iter().fold(FpVar::zero(), |acc, h| acc + FpVar::from(h.clone()));
+        // Two boundaries at the same position would make the sum 2, which isn't a bit.
+        let flip = Boolean::new_witness(cs.clone(), || Ok(sum.value()? == CF::one()))?;
+        FpVar::from(flip.clone()).enforce_equal(&sum)?;
+        let prev = bits.last().expect("starts with the first bit");
+        bits.push(prev.xor(&flip)?);
+    }
+    Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_relations::r1cs::ConstraintSystem;
+    use ark_std::test_rng;
+    use rand::Rng;
+
+    use super::*;
+
+    type F = ark_bls12_381::Fr;
+
+    fn decode(rle: &RleBitmask, len: usize) -> (Vec<bool>, bool) {
+        let cs = ConstraintSystem::<F>::new_ref();
+        let first = Boolean::new_witness(cs.clone(), || Ok(rle.first())).unwrap();
+        let runs = Vec::<FpVar<F>>::new_witness(cs.clone(), || Ok(rle.runs().iter().map(|r| F::from(*r)).collect::<Vec<_>>())).unwrap();
+        let bits = decode_rle(&first, &runs, len).unwrap();
+        (bits.iter().map(|b| b.value().unwrap()).collect(), cs.is_satisfied().unwrap())
+    }
+
+    #[test]
+    fn test_rle() {
+        let bitmask = Bitmask::from_bits(vec![true, true, true, false, false, true, true]);
+        let rle = RleBitmask::from(&bitmask);
+        assert_eq!((rle.first(), rle.runs()), (true, &[3, 2, 2][..]));
+        assert_eq!(rle.to_bitmask(), bitmask);
+        assert_eq!(RleBitmask::from(&Bitmask::default()).to_bitmask(), Bitmask::default());
+
+        let rng = &mut test_rng();
+        let n = 50;
+        let bits: Vec<bool> = (0..n).map(|_| rng.gen_bool(0.95)).collect();
+        let rle = RleBitmask::from(&Bitmask::from_bits(bits.clone()));
+        assert_eq!(decode(&rle, n), (bits, true));
+    }
+
+    #[test]
+    fn test_malformed_runs() {
+        let n = 6;
+        for (first, runs) in [(true, vec![3, 2]), (true, vec![3, 0, 3]), (false, vec![0, 6]), (false, vec![7, u32::MAX])] {
+            let (_, satisfied) = decode(&RleBitmask { first, runs }, n);
+            assert!(!satisfied);
+        }
+    }
+}
@@ -0,0 +1,356 @@
+//! Scalar multiplications of points in-circuit, the scalars given as their bits, little-endian.
+
+use std::num::NonZeroUsize;
+
+use ark_ec::{CurveGroup, Group};
+use ark_ec::short_weierstrass::{Affine, Projective, SWCurveConfig};
+use ark_ff::{BigInteger, PrimeField};
+use ark_r1cs_std::alloc::AllocVar;
+use ark_r1cs_std::boolean::Boolean;
+use ark_r1cs_std::eq::EqGadget;
+use ark_r1cs_std::fields::{FieldOpsBounds, FieldVar};
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_r1cs_std::R1CSVar;
+use ark_r1cs_std::select::CondSelectGadget;
+use ark_relations::r1cs::{Field, SynthesisError};
+
+use crate::affine_gen::{AffineVarGeneric, NonZeroAffineVarGeneric};
+use crate::labels::labeled;
+use crate::sum_acc::{AccumulatorField, sum_points_checked};
+
+/// The default number of bits per window of `fixed_base_mul`: tables of 16 constant points.
+pub const FIXED_BASE_WINDOW: NonZeroUsize = NonZeroUsize::new(4).unwrap();
+
+/// The point as a constant, the identity included.
+fn constant<P, F, CF>(p: &Affine<P>) -> AffineVarGeneric<P, F, CF>
+    where
+        P: SWCurveConfig,
+        CF: Field,
+        F: FieldVar<P::BaseField, CF>,
+{
+    AffineVarGeneric::new(F::constant(p.x), F::constant(p.y), Boolean::constant(p.infinity))
+}
+
+/// `[k] base` for a constant base, `k` given by its bits, little-endian.
+/// The multiples `[j 2^(w i)] base` of each window of `window` bits are computed natively,
+/// the one of the bits of the window is selected, and the selections are summed with complete additions:
+/// no doubling, and a single addition per window, instead of a doubling and an addition per bit.
+pub fn fixed_base_mul<P, F, CF>(base: &Affine<P>, bits: &[Boolean<CF>], window: NonZeroUsize) -> Result<AffineVarGeneric<P, F, CF>, SynthesisError>
+    where
+        P: SWCurveConfig,
+        CF: Field,
+        F: FieldVar<P::BaseField, CF>,
+        for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+{
+    let cs = bits.cs();
+    labeled(&cs, "fixed_base_mul", || {
+        let mut window_base = Projective::<P>::from(*base);
+        let mut acc = AffineVarGeneric::zero();
+        for chunk in bits.chunks(window.get()) {
+            let table = (0..1usize << chunk.len())
+                .scan(Projective::<P>::default(), |multiple, _| {
+                    let current = *multiple;
+                    *multiple += window_base;
+                    Some(current)
+                })
+                .collect::<Vec<_>>();
+            let table = Projective::normalize_batch(&table).iter().map(constant).collect::<Vec<_>>();
+            let position = chunk.iter().rev().cloned().collect::<Vec<_>>();
+            let selected = AffineVarGeneric::conditionally_select_power_of_two_vector(&position, &table)?;
+            acc = acc.add(&selected)?;
+            for _ in 0..chunk.len() {
+                window_base.double_in_place();
+            }
+        }
+        Ok(acc)
+    })
+}
+
+/// `sum_i [k_i] P_i`, each `k_i` given by its bits, little-endian, as a single sum of the accumulator.
+/// With the signed digits `d_j = 2 b_j - 1`, `k = sum_{j >= 1} d_j 2^(j - 1) + (2^(n - 1) - 1 + b_0)`,
+/// so each point adds `n - 1` terms `[±2^(j - 1)] P` and a last one `[2^(n - 1)] P` or `[2^(n - 1) - 1] P`,
+/// none of them the identity, the multiples from `n - 1` doublings of the point.
+/// The additions are checked: for a zero result, or a linear relation between the points the additions happen to hit,
+/// as for a single point and `k = 2^n - 2`, the system is unsatisfiable rather than the result wrong.
+/// Points past the end of `scalars`, and scalars past the end of `points`, are ignored.
+pub fn msm<P, F, CF>(points: &[NonZeroAffineVarGeneric<P, F, CF>], scalars: &[Vec<Boolean<CF>>]) -> Result<NonZeroAffineVarGeneric<P, F, CF>, SynthesisError>
+    where
+        P: SWCurveConfig,
+        CF: Field,
+        F: AccumulatorField<P, CF>,
+        for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+{
+    let cs = scalars.iter().fold(points.cs(), |cs, bits| cs.or(bits.cs()));
+    labeled(&cs, "msm", || {
+        let mut terms = vec![];
+        let mut lasts = vec![];
+        for (i, (p, bits)) in points.iter().zip(scalars).enumerate() {
+            let (last, point_terms) = labeled(&cs, format_args!("terms/{}", i), || {
+                // At least 2 bits, so the last term isn't the identity.
+                let bits = bits.iter().cloned().chain(std::iter::repeat(Boolean::FALSE)).take(bits.len().max(2)).collect::<Vec<_>>();
+                let mut multiple = p.clone();
+                let mut point_terms = vec![];
+                for b in &bits[1..] {
+                    point_terms.push(NonZeroAffineVarGeneric::conditionally_select(b, &multiple, &multiple.negate()?)?);
+                    multiple = multiple.double_checked()?;
+                }
+                let last = NonZeroAffineVarGeneric::conditionally_select(&bits[0], &multiple, &multiple.add_checked(&p.negate()?)?)?;
+                Ok::<_, SynthesisError>((last, point_terms))
+            })?;
+            terms.extend(point_terms);
+            lasts.push(last);
+        }
+        // The last terms at the end: the partial sums then depend on all of the points.
+        sum_points_checked(terms.into_iter().chain(lasts))
+    })
+}
+
+/// Curves with an endomorphism `φ(x, y) = (β x, y)` acting on the prime-order subgroup as the multiplication by `λ`,
+/// a scalar of at most 128 bits, as for BLS12 curves, where `λ = x^2 - 1` for the parameter `x` of the curve.
+pub trait GlvConfig: SWCurveConfig {
+    const BETA: Self::BaseField;
+    const LAMBDA: u128;
+}
+
+#[cfg(feature = "bls12-377-bw6")]
+impl GlvConfig for ark_bls12_377::g1::Config {
+    const BETA: ark_bls12_377::Fq = ark_ff::MontFp!("80949648264912719408558363140637477264845294720710499478137287262712535938301461879813459410945");
+    const LAMBDA: u128 = 91893752504881257701523279626832445440;
+}
+
+#[cfg(feature = "bls12-381-emulated")]
+impl GlvConfig for ark_bls12_381::g1::Config {
+    const BETA: ark_bls12_381::Fq = ark_ff::MontFp!("4002409555221667392624310435006688643935503118305586438271171395842971157480381377015405980053539358417135540939436");
+    const LAMBDA: u128 = 228988810152649578064853576960394133503;
+}
+
+/// The scalar `SWCurveConfig::clear_cofactor` multiplies by, for `NonZeroAffineVarGeneric::clear_cofactor`: the cofactor by default,
+/// or a shorter one that maps the curve onto the prime-order subgroup as well, the effective cofactor of Section 5 of
+/// Wahby and Boneh, eprint 2019/403: `1 - x` for BLS12-381 and `x - 1` for BLS12-377, of 64 bits, where `(x - 1)^2 / 3` has 126.
+pub trait CofactorConfig: SWCurveConfig {
+    /// The limbs of the scalar, little-endian.
+    fn effective_cofactor() -> &'static [u64] {
+        Self::COFACTOR
+    }
+}
+
+#[cfg(feature = "bls12-377-bw6")]
+impl CofactorConfig for ark_bls12_377::g1::Config {
+    fn effective_cofactor() -> &'static [u64] {
+        &[0x8508c00000000000]
+    }
+}
+
+#[cfg(feature = "bls12-381-emulated")]
+impl CofactorConfig for ark_bls12_381::g1::Config {
+    fn effective_cofactor() -> &'static [u64] {
+        &[0xd201000000010001]
+    }
+}
+
+/// `φ(p) = (β x, y)`, `[λ] p` for the points of the prime-order subgroup.
+pub fn endomorphism<P, F, CF>(p: &NonZeroAffineVarGeneric<P, F, CF>) -> Result<NonZeroAffineVarGeneric<P, F, CF>, SynthesisError>
+    where
+        P: GlvConfig,
+        CF: Field,
+        F: FieldVar<P::BaseField, CF>,
+        for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+{
+    Ok(NonZeroAffineVarGeneric::new(&p.x * P::BETA, p.y.clone()))
+}
+
+// The quotient and the remainder of the division of the integer by `lambda`, both as bits, little-endian.
+fn divide(bits: &[bool], lambda: u128) -> (Vec<bool>, Vec<bool>) {
+    let mut quotient = vec![];
+    let mut rem = 0u128;
+    for &b in bits.iter().rev() {
+        // `2 rem + b >= lambda` without overflowing, as `rem < lambda`.
+        let gap = lambda - rem - b as u128;
+        let carry = rem >= gap;
+        rem = if carry { rem - gap } else { 2 * rem + b as u128 };
+        quotient.push(carry);
+    }
+    quotient.reverse();
+    (quotient, (0..128).map(|i| rem >> i & 1 == 1).collect())
+}
+
+/// `[k] p` for a point of the prime-order subgroup, `k` given by its bits, little-endian, with the GLV method:
+/// `k = k1 + λ k2` for `k1 < λ`, witnessed and enforced on the packed bits, and `[k1] p + [k2] φ(p)` is computed
+/// with a single doubling per bit of the longer of `k1` and `k2`, half of those of `k`, adding one of `O`, `p`, `φ(p)`
+/// and `p + φ(p)` per bit. The additions are complete, so any `k` works, `0` included.
+///
+/// The relation holds as integers when they fit in `CF`, or modulo `r` when `CF` is the scalar field itself,
+/// `k` then reduced modulo `r` before the split: otherwise, for more bits than `CF` holds the relation in,
+/// fails with `SynthesisError::Unsatisfiable`. For a point outside of the subgroup the result is meaningless,
+/// see `NonZeroAffineVarGeneric::enforce_in_prime_subgroup`.
+pub fn glv_mul<P, F, CF>(p: &NonZeroAffineVarGeneric<P, F, CF>, bits: &[Boolean<CF>]) -> Result<AffineVarGeneric<P, F, CF>, SynthesisError>
+    where
+        P: GlvConfig,
+        CF: PrimeField,
+        F: FieldVar<P::BaseField, CF>,
+        for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+{
+    let lambda_bits = 128 - P::LAMBDA.leading_zeros() as usize;
+    let modular = CF::MODULUS.as_ref() == P::ScalarField::MODULUS.as_ref();
+    let k_bits = if modular { bits.len().min(P::ScalarField::MODULUS_BIT_SIZE as usize) } else { bits.len() };
+    let num_bits_1 = lambda_bits.min(k_bits);
+    let num_bits_2 = (k_bits + 1).saturating_sub(lambda_bits);
+    if !modular && num_bits_2 + lambda_bits + 1 >= CF::MODULUS_BIT_SIZE as usize {
+        return Err(SynthesisError::Unsatisfiable);
+    }
+
+    let cs = p.cs().or(bits.cs());
+    labeled(&cs, "glv_mul", || {
+        let split = bits.value().map(|k| {
+            if modular {
+                let bytes = k.chunks(8).map(|byte| byte.iter().rev().fold(0u8, |acc, b| acc << 1 | *b as u8)).collect::<Vec<_>>();
+                divide(&P::ScalarField::from_le_bytes_mod_order(&bytes).into_bigint().to_bits_le(), P::LAMBDA)
+            } else {
+                divide(&k, P::LAMBDA)
+            }
+        });
+        let witness_bits = |label, num_bits, values: Result<&Vec<bool>, &SynthesisError>| {
+            labeled(&cs, label, || {
+                (0..num_bits)
+                    .map(|i| Boolean::new_witness(cs.clone(), || values.map(|v| v[i]).map_err(|_| SynthesisError::AssignmentMissing)))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+        };
+        let k1 = witness_bits("k1", num_bits_1, split.as_ref().map(|s| &s.1))?;
+        let k2 = witness_bits("k2", num_bits_2, split.as_ref().map(|s| &s.0))?;
+        let pack = |bits: &[Boolean<CF>]| bits.iter().rev().try_fold(FpVar::zero(), |acc, b| Ok::<_, SynthesisError>(acc.double()? + FpVar::from(b.clone())));
+        pack(bits)?.enforce_equal(&(pack(&k1)? + pack(&k2)? * CF::from(P::LAMBDA)))?;
+
+        let phi = AffineVarGeneric::from(endomorphism(p)?);
+        let p = AffineVarGeneric::from(p.clone());
+        let table = [AffineVarGeneric::zero(), p.clone(), phi.clone(), p.add(&phi)?];
+        let mut acc = AffineVarGeneric::zero();
+        for i in (0..num_bits_1.max(num_bits_2)).rev() {
+            let position = [k2.get(i).cloned().unwrap_or(Boolean::FALSE), k1.get(i).cloned().unwrap_or(Boolean::FALSE)];
+            acc = acc.add(&acc)?.add(&AffineVarGeneric::conditionally_select_power_of_two_vector(&position, &table)?)?;
+        }
+        Ok(acc)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_ec::AffineRepr;
+    use ark_ff::{BigInteger, PrimeField};
+    use ark_r1cs_std::alloc::AllocVar;
+    use ark_r1cs_std::fields::fp::FpVar;
+    use ark_relations::ns;
+    use ark_relations::r1cs::ConstraintSystem;
+    use ark_std::{test_rng, UniformRand};
+
+    use crate::tests::BlsInBls;
+
+    use super::*;
+
+    fn check_fixed_base_mul<P, F, CF>(num_bits: usize, window: NonZeroUsize)
+        where
+            P: SWCurveConfig,
+            CF: ark_ff::PrimeField,
+            F: FieldVar<P::BaseField, CF>,
+            for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+    {
+        let rng = &mut test_rng();
+        let base = Affine::<P>::generator();
+        let k = P::ScalarField::rand(rng);
+        let bits = k.into_bigint().to_bits_le()[..num_bits].to_vec();
+        let k = P::ScalarField::from_bigint(<P::ScalarField as PrimeField>::BigInt::from_bits_le(&bits)).unwrap();
+        let cs = ConstraintSystem::<CF>::new_ref();
+        let bit_vars = Vec::<Boolean<CF>>::new_witness(ns!(cs, "bits"), || Ok(bits)).unwrap();
+        let product = fixed_base_mul::<P, F, CF>(&base, &bit_vars, window).unwrap();
+        assert_eq!(product.value().unwrap(), (base * k).into_affine());
+        assert!(cs.is_satisfied().unwrap());
+
+        let zero_bits = vec![Boolean::FALSE; num_bits];
+        assert!(fixed_base_mul::<P, F, CF>(&base, &zero_bits, window).unwrap().value().unwrap().is_zero());
+    }
+
+    fn check_msm<P, F, CF>(num_bits: &[usize])
+        where
+            P: SWCurveConfig,
+            CF: ark_ff::PrimeField,
+            F: AccumulatorField<P, CF>,
+            for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+    {
+        let rng = &mut test_rng();
+        let cs = ConstraintSystem::<CF>::new_ref();
+        let mut expected = Projective::<P>::default();
+        let mut point_vars = vec![];
+        let mut scalar_vars = vec![];
+        for &n in num_bits {
+            let p = Affine::<P>::rand(rng);
+            let bits = P::ScalarField::rand(rng).into_bigint().to_bits_le()[..n].to_vec();
+            expected += p * P::ScalarField::from_bigint(<P::ScalarField as PrimeField>::BigInt::from_bits_le(&bits)).unwrap();
+            point_vars.push(NonZeroAffineVarGeneric::new_witness(ns!(cs, "point"), || Ok(p)).unwrap());
+            scalar_vars.push(Vec::<Boolean<CF>>::new_witness(ns!(cs, "scalar"), || Ok(bits)).unwrap());
+        }
+        let sum = msm::<P, F, CF>(&point_vars, &scalar_vars).unwrap();
+        assert!(cs.is_satisfied().unwrap());
+        assert_eq!(sum.value().unwrap(), expected.into_affine());
+    }
+
+    #[test]
+    fn test_msm() {
+        check_msm::<ark_bls12_377::g1::Config, FpVar<ark_bw6_761::Fr>, _>(&[253, 253, 253]);
+        check_msm::<ark_bls12_377::g1::Config, FpVar<ark_bw6_761::Fr>, _>(&[1, 3, 64]);
+        check_msm::<ark_bls12_381::g1::Config, BlsInBls, _>(&[8, 8]);
+
+        // A single point with `k = 2^n - 2` hits a doubling.
+        let rng = &mut test_rng();
+        let cs = ConstraintSystem::<ark_bw6_761::Fr>::new_ref();
+        let p = NonZeroAffineVarGeneric::<_, FpVar<_>, _>::new_witness(ns!(cs, "p"), || Ok(ark_bls12_377::G1Affine::rand(rng))).unwrap();
+        let bits = vec![Boolean::FALSE, Boolean::TRUE, Boolean::TRUE];
+        let _ = msm(&[p], &[bits]).unwrap();
+        assert!(!cs.is_satisfied().unwrap());
+    }
+
+    #[cfg(feature = "bls12-377-bw6")]
+    fn check_glv_mul<P, F, CF>(k: P::ScalarField, num_bits: usize)
+        where
+            P: GlvConfig,
+            CF: ark_ff::PrimeField,
+            F: FieldVar<P::BaseField, CF>,
+            for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+    {
+        let rng = &mut test_rng();
+        let p = Affine::<P>::rand(rng);
+        let cs = ConstraintSystem::<CF>::new_ref();
+        let p_var = NonZeroAffineVarGeneric::<P, F, CF>::new_witness(ns!(cs, "p"), || Ok(p)).unwrap();
+        let bits = k.into_bigint().to_bits_le()[..num_bits].to_vec();
+        let bit_vars = Vec::<Boolean<CF>>::new_witness(ns!(cs, "bits"), || Ok(bits)).unwrap();
+        let product = glv_mul(&p_var, &bit_vars).unwrap();
+        assert!(cs.is_satisfied().unwrap());
+        assert_eq!(product.value().unwrap(), (p * k).into_affine());
+    }
+
+    #[cfg(feature = "bls12-377-bw6")]
+    #[test]
+    fn test_glv_mul() {
+        use ark_ff::{One, Zero};
+
+        let rng = &mut test_rng();
+        type Fr377 = ark_bls12_377::Fr;
+        for k in [Fr377::rand(rng), Fr377::zero(), Fr377::one(), -Fr377::one(), Fr377::from(ark_bls12_377::g1::Config::LAMBDA)] {
+            check_glv_mul::<ark_bls12_377::g1::Config, FpVar<ark_bw6_761::Fr>, _>(k, 253);
+        }
+        check_glv_mul::<ark_bls12_377::g1::Config, FpVar<ark_bw6_761::Fr>, _>(Fr377::from(1000u64), 10);
+        // Too long for the relation to hold in BW6-761 Fr as integers.
+        let cs = ConstraintSystem::<ark_bw6_761::Fr>::new_ref();
+        let p_var = NonZeroAffineVarGeneric::<_, FpVar<_>, _>::new_witness(ns!(cs, "p"), || Ok(ark_bls12_377::G1Affine::rand(rng))).unwrap();
+        assert!(matches!(glv_mul(&p_var, &vec![Boolean::FALSE; 600]), Err(SynthesisError::Unsatisfiable)));
+        // Modulo `r`.
+        #[cfg(feature = "bls12-381-emulated")]
+        check_glv_mul::<ark_bls12_381::g1::Config, BlsInBls, _>(ark_bls12_381::Fr::from(200u64), 8);
+    }
+
+    #[test]
+    fn test_fixed_base_mul() {
+        check_fixed_base_mul::<ark_bls12_377::g1::Config, FpVar<ark_bw6_761::Fr>, _>(253, FIXED_BASE_WINDOW);
+        check_fixed_base_mul::<ark_bls12_377::g1::Config, FpVar<ark_bw6_761::Fr>, _>(10, NonZeroUsize::new(3).unwrap());
+        check_fixed_base_mul::<ark_bls12_381::g1::Config, BlsInBls, _>(16, FIXED_BASE_WINDOW);
+    }
+}
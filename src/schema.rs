@@ -0,0 +1,241 @@
+use ark_ec::{AffineRepr, CurveConfig};
+use ark_ec::short_weierstrass::Affine;
+use ark_ff::{BigInteger, PrimeField};
+use ark_r1cs_std::fields::FieldOpsBounds;
+use ark_r1cs_std::fields::nonnative::AllocatedNonNativeFieldVar;
+
+use crate::apk_circuits::limb_params;
+use crate::bitmask::Bitmask;
+use crate::chain::{Chain, ChainCircuit, KeyBaseField};
+use crate::config::{CommitmentScheme, Config};
+use crate::error::Result;
+use crate::witness::constraint_matrices;
+
+/// How a segment of the instance vector encodes its values.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Encoding {
+    /// Affine points as `x, y` pairs, each coordinate being a single constraint field element.
+    NativePoints { count: usize },
+    /// Affine points as `x, y` pairs, each coordinate split into `num_limbs` limbs of `bits_per_limb` bits,
+    /// the most significant limb first.
+    LimbPoints { count: usize, num_limbs: usize, bits_per_limb: usize },
+    /// The limbs of `LimbPoints`, all the points one after the other, packed `limbs_per_input` to an element,
+    /// the first limb of a chunk least significant, see `pack_limbs`.
+    PackedLimbs { count: usize, num_limbs: usize, bits_per_limb: usize, limbs_per_input: usize },
+    /// The `x` coordinates of points, each a single element for native chains, with `num_limbs` of `1`,
+    /// or split into limbs as in `LimbPoints`, the signs of the `y` being in the `key_signs` segment.
+    CompressedPoints { count: usize, num_limbs: usize, bits_per_limb: usize },
+    /// Bits packed little-endian into a single field element.
+    PackedBits { num_bits: usize },
+    /// The SHA-256 of the points, `digest::keyset_digest`, as two halves of 16 bytes.
+    #[cfg(feature = "sha256")]
+    Sha256Digest { count: usize },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Segment {
+    pub name: String,
+    pub offset: usize,
+    pub len: usize,
+    pub encoding: Encoding,
+}
+
+/// Layout of the instance vector of an apk circuit, excluding the leading `1`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InstanceLayout {
+    pub chain: String,
+    pub segments: Vec<Segment>,
+}
+
+impl InstanceLayout {
+    pub fn len(&self) -> usize {
+        self.segments.iter().map(|s| s.len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn segment(&self, name: &str) -> Option<&Segment> {
+        self.segments.iter().find(|s| s.name == name)
+    }
+}
+
+pub fn instance_layout<C: Chain>(num_keys: usize, config: &Config) -> InstanceLayout {
+    let params = limb_params::<KeyBaseField<C>, C::ConstraintField>(config.optimization.into());
+    let (num_limbs, bits_per_limb) = (params.num_limbs, params.bits_per_limb);
+    let (len, encoding) = match (C::EMULATED, config.commitment) {
+        #[cfg(feature = "sha256")]
+        (_, CommitmentScheme::Sha256) => (2, Encoding::Sha256Digest { count: num_keys }),
+        (false, CommitmentScheme::CompressedInputs) => {
+            (num_keys, Encoding::CompressedPoints { count: num_keys, num_limbs: 1, bits_per_limb: KeyBaseField::<C>::MODULUS_BIT_SIZE as usize })
+        }
+        (true, CommitmentScheme::CompressedInputs) => (num_keys * num_limbs, Encoding::CompressedPoints { count: num_keys, num_limbs, bits_per_limb }),
+        (false, _) => (2 * num_keys, Encoding::NativePoints { count: num_keys }),
+        (true, CommitmentScheme::PublicInputs) => (2 * num_keys * num_limbs, Encoding::LimbPoints { count: num_keys, num_limbs, bits_per_limb }),
+        (true, CommitmentScheme::PackedLimbs) => {
+            let limbs_per_input = limbs_per_input::<C::ConstraintField>(bits_per_limb);
+            ((2 * num_keys * num_limbs).div_ceil(limbs_per_input), Encoding::PackedLimbs { count: num_keys, num_limbs, bits_per_limb, limbs_per_input })
+        }
+    };
+    let mut segments = vec![Segment { name: "keys".to_string(), offset: 0, len, encoding }];
+    if config.commitment == CommitmentScheme::CompressedInputs {
+        segments.push(Segment { name: "key_signs".to_string(), offset: len, len: 1, encoding: Encoding::PackedBits { num_bits: num_keys } });
+    }
+    let offset = segments.iter().map(|s| s.len).sum();
+    segments.push(Segment { name: "bitmask_packed".to_string(), offset, len: 1, encoding: Encoding::PackedBits { num_bits: num_keys } });
+    InstanceLayout { chain: C::NAME.to_string(), segments }
+}
+
+/// Size of the constraint system of an apk circuit, that its proving and verifying keys are specific to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CircuitShape {
+    pub constraints: usize,
+    pub witnesses: usize,
+    /// Public inputs, excluding the leading `1`, as in `InstanceLayout::len`.
+    pub instance: usize,
+}
+
+/// The shape of the apk circuit with `num_keys` keys under `config`, synthesized in setup mode.
+/// A change of the gadgets that changes it invalidates the existing keys: the tests pin it for a few circuits.
+pub fn expected_shape<C: Chain>(num_keys: usize, config: &Config) -> Result<CircuitShape>
+    where for<'a> &'a C::FieldVar: FieldOpsBounds<'a, KeyBaseField<C>, C::FieldVar>
+{
+    // The shape doesn't depend on the values, any keys will do.
+    let g = Affine::<C::Curve>::generator();
+    let keys = (1..=num_keys as u64).map(|i| (g * <C::Curve as CurveConfig>::ScalarField::from(i)).into()).collect();
+    let circuit = ChainCircuit::<C>::new(keys, g, &Bitmask::from_bits(vec![true; num_keys]))?.with_config(config.clone());
+    let m = constraint_matrices(circuit)?;
+    Ok(CircuitShape { constraints: m.num_constraints, witnesses: m.num_witness_variables, instance: m.num_instance_variables - 1 })
+}
+
+/// How many limbs of `bits_per_limb` bits `CommitmentScheme::PackedLimbs` packs into an element of `CF`,
+/// short of its capacity, so that the packing doesn't wrap around.
+pub fn limbs_per_input<CF: PrimeField>(bits_per_limb: usize) -> usize {
+    (CF::MODULUS_BIT_SIZE as usize - 1) / bits_per_limb
+}
+
+/// Packs the limbs, in chunks of `limbs_per_input`, each into `sum(limb_j * 2^(j * bits_per_limb))`.
+pub fn pack_limbs<CF: PrimeField>(limbs: &[CF], bits_per_limb: usize) -> Vec<CF> {
+    let shift = CF::from(2u64).pow([bits_per_limb as u64]);
+    limbs.chunks(limbs_per_input::<CF>(bits_per_limb))
+        .map(|chunk| chunk.iter().rev().fold(CF::zero(), |acc, limb| acc * shift + limb))
+        .collect()
+}
+
+/// Host-side encoding of a point as the apk circuit allocates it as a public input:
+/// coordinates as they are for native chains, and their limbs for emulated ones.
+pub fn point_to_instance<C: Chain>(p: &Affine<C::Curve>, config: &Config) -> Result<Vec<C::ConstraintField>> {
+    let mut res = Vec::new();
+    for c in [p.x, p.y] {
+        if C::EMULATED {
+            res.extend(AllocatedNonNativeFieldVar::<KeyBaseField<C>, C::ConstraintField>::get_limbs_representations(&c, config.optimization.into())?);
+        } else {
+            res.push(C::ConstraintField::from_le_bytes_mod_order(&c.into_bigint().to_bytes_le()));
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(all(test, feature = "bls12-377-bw6"))]
+mod tests {
+    use ark_std::{test_rng, UniformRand};
+
+    use crate::apk_circuits::public_inputs;
+    use crate::bitmask::Bitmask;
+    use crate::chain::Bls377InBw6;
+    #[cfg(feature = "bls12-381-emulated")]
+    use crate::chain::Bls381Emulated;
+    use crate::config::Optimization;
+
+    use super::*;
+
+    fn check_layout<C: Chain>(config: &Config) {
+        let rng = &mut test_rng();
+        let n = 3;
+        let keys: Vec<_> = (0..n).map(|_| ark_ec::short_weierstrass::Affine::<C::Curve>::rand(rng)).collect();
+        let bitmask = Bitmask::from_bits(vec![true, false, true]);
+        let pi = public_inputs::<C::Curve, C::ConstraintField, C::FieldVar>(&keys, &bitmask, config).unwrap();
+        let layout = instance_layout::<C>(n, config);
+        assert_eq!(layout.len(), pi.len());
+        let bits = layout.segment("bitmask_packed").unwrap();
+        assert_eq!(pi[bits.offset], bitmask.pack().unwrap());
+        let keys_segment = layout.segment("keys").unwrap();
+        let compressed = matches!(keys_segment.encoding, Encoding::CompressedPoints { .. });
+        let mut key_inputs: Vec<_> = keys.iter()
+            .flat_map(|p| {
+                let mut inputs = point_to_instance::<C>(p, config).unwrap();
+                // Only `x`, that comes first.
+                if compressed {
+                    inputs.truncate(inputs.len() / 2);
+                }
+                inputs
+            })
+            .collect();
+        if let Encoding::PackedLimbs { bits_per_limb, .. } = keys_segment.encoding {
+            key_inputs = pack_limbs(&key_inputs, bits_per_limb);
+        }
+        assert_eq!(key_inputs, pi[..keys_segment.len]);
+        if let Some(signs) = layout.segment("key_signs") {
+            assert!(compressed);
+            assert_eq!(pi[signs.offset], Bitmask::from_bits(keys.iter().map(|p| p.y > -p.y).collect()).pack().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_layout() {
+        check_layout::<Bls377InBw6>(&Config::default());
+        for optimization in [Optimization::Constraints, Optimization::Weight] {
+            check_layout::<Bls377InBw6>(&Config { optimization, commitment: CommitmentScheme::PackedLimbs, ..Default::default() });
+        }
+        check_layout::<Bls377InBw6>(&Config { commitment: CommitmentScheme::CompressedInputs, ..Default::default() });
+    }
+
+    #[cfg(feature = "bls12-381-emulated")]
+    #[test]
+    fn test_layout_emulated() {
+        check_layout::<Bls381Emulated>(&Config::default());
+        check_layout::<Bls381Emulated>(&Config { optimization: Optimization::Weight, ..Default::default() });
+        for optimization in [Optimization::Constraints, Optimization::Weight] {
+            let config = Config { optimization, commitment: CommitmentScheme::PackedLimbs, ..Default::default() };
+            check_layout::<Bls381Emulated>(&config);
+            let packed = instance_layout::<Bls381Emulated>(3, &config);
+            assert!(packed.len() < instance_layout::<Bls381Emulated>(3, &Config { optimization, ..Default::default() }).len());
+        }
+        let config = Config { commitment: CommitmentScheme::CompressedInputs, ..Default::default() };
+        check_layout::<Bls381Emulated>(&config);
+        let keys = |config: &Config| instance_layout::<Bls381Emulated>(3, config).segment("keys").unwrap().len;
+        assert_eq!(2 * keys(&config), keys(&Config::default()));
+    }
+
+    #[test]
+    fn test_expected_shape() {
+        use crate::config::CommitmentScheme::{CompressedInputs, PackedLimbs};
+        use crate::config::Optimization::Weight;
+
+        let shape = |constraints, witnesses, instance| CircuitShape { constraints, witnesses, instance };
+        let configs = [
+            Config::default(),
+            Config { optimization: Weight, ..Default::default() },
+            Config { commitment: PackedLimbs, ..Default::default() },
+            Config { commitment: CompressedInputs, ..Default::default() },
+        ];
+        // Changing any of these changes the keys of the circuit: update them deliberately.
+        let native = [shape(1013, 768, 7), shape(1109, 864, 7), shape(1013, 768, 7), shape(6142, 4917, 5)];
+        for (config, native) in configs.iter().zip(native) {
+            assert_eq!(expected_shape::<Bls377InBw6>(3, config).unwrap(), native, "{:?}", config);
+        }
+        #[cfg(feature = "bls12-381-emulated")]
+        {
+            let emulated = [shape(11435, 11269, 193), shape(15576, 15410, 49), shape(13941, 13765, 11), shape(27890, 26994, 98)];
+            for (config, emulated) in configs.iter().zip(emulated) {
+                assert_eq!(expected_shape::<Bls381Emulated>(3, config).unwrap(), emulated, "{:?}", config);
+                assert_eq!(emulated.instance, instance_layout::<Bls381Emulated>(3, config).len());
+            }
+        }
+    }
+}
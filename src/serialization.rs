@@ -0,0 +1,74 @@
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Compress, Validate};
+
+use crate::error::{Result, SnowballError};
+
+/// Encoding of curve points: compressed for network and storage, uncompressed for fast loading,
+/// as decompression (a square root per point) dominates deserialization of large keys.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SerializationMode {
+    #[default]
+    Compressed,
+    Uncompressed,
+}
+
+impl SerializationMode {
+    pub fn compress(&self) -> Compress {
+        match self {
+            SerializationMode::Compressed => Compress::Yes,
+            SerializationMode::Uncompressed => Compress::No,
+        }
+    }
+
+    pub(crate) fn to_byte(self) -> u8 {
+        match self {
+            SerializationMode::Compressed => 0,
+            SerializationMode::Uncompressed => 1,
+        }
+    }
+
+    pub(crate) fn from_byte(byte: u8) -> Result<Self> {
+        match byte {
+            0 => Ok(SerializationMode::Compressed),
+            1 => Ok(SerializationMode::Uncompressed),
+            _ => Err(SnowballError::Serialization(ark_serialize::SerializationError::InvalidData)),
+        }
+    }
+}
+
+pub fn to_bytes<T: CanonicalSerialize>(t: &T, mode: SerializationMode) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(t.serialized_size(mode.compress()));
+    t.serialize_with_mode(&mut bytes, mode.compress())?;
+    Ok(bytes)
+}
+
+/// Deserializes with full validation and rejects trailing bytes.
+pub fn from_bytes<T: CanonicalDeserialize>(bytes: &[u8], mode: SerializationMode) -> Result<T> {
+    let mut reader = bytes;
+    let t = T::deserialize_with_mode(&mut reader, mode.compress(), Validate::Yes)?;
+    if !reader.is_empty() {
+        return Err(SnowballError::Serialization(ark_serialize::SerializationError::InvalidData));
+    }
+    Ok(t)
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_std::{test_rng, UniformRand};
+
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let rng = &mut test_rng();
+        let points: Vec<ark_bls12_381::G1Affine> = (0..3).map(|_| ark_bls12_381::G1Affine::rand(rng)).collect();
+        for mode in [SerializationMode::Compressed, SerializationMode::Uncompressed] {
+            let bytes = to_bytes(&points, mode).unwrap();
+            assert_eq!(bytes.len(), 8 + 3 * if mode == SerializationMode::Compressed { 48 } else { 96 });
+            assert_eq!(from_bytes::<Vec<ark_bls12_381::G1Affine>>(&bytes, mode).unwrap(), points);
+            assert_eq!(SerializationMode::from_byte(mode.to_byte()).unwrap(), mode);
+        }
+        let bytes = to_bytes(&points, SerializationMode::Compressed).unwrap();
+        assert!(from_bytes::<Vec<ark_bls12_381::G1Affine>>(&bytes, SerializationMode::Uncompressed).is_err());
+    }
+}
@@ -0,0 +1,304 @@
+//! Synthesis of the apk circuit in shards, one per chunk of keys, in parallel with the `parallel` feature.
+//!
+//! The accumulator is the only dependency between the chunks, so each chunk starts from the value
+//! `circuit_semantics::aggregate` computes for it on the host, and the shards are stitched together
+//! by identifying the variables they share: the keys and the bits a chunk takes from the inputs,
+//! and the accumulator it takes from the previous chunk. Stitching replays the constraints of the shards
+//! into the target constraint system, without running the gadgets again.
+//!
+//! The result isn't `ApkCircuit`: every boundary between the chunks costs an allocation of the accumulator
+//! and an equality check, and the bits are checked to be boolean once more in their chunks.
+//! So it needs keys of its own, but it has the same public inputs, for any `CommitmentScheme`: only the first shard
+//! allocates the keys by the scheme, and the chunks take them from it.
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use ark_ff::{BigInteger, Field, PrimeField};
+use ark_r1cs_std::alloc::AllocVar;
+use ark_r1cs_std::boolean::Boolean;
+use ark_r1cs_std::eq::EqGadget;
+use ark_r1cs_std::fields::FieldOpsBounds;
+use ark_r1cs_std::R1CSVar;
+use ark_relations::r1cs::{ConstraintMatrices, ConstraintSynthesizer, ConstraintSystem, ConstraintSystemRef, LinearCombination, SynthesisError, SynthesisMode, Variable};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::affine_gen::NonZeroAffineVarGeneric;
+use crate::apk_circuits::allocate_inputs;
+use crate::bitmask::Bitmask;
+use crate::chain::{Chain, ChainCircuit, KeyBaseField};
+use crate::circuit_semantics;
+use crate::config::Config;
+use crate::error::{Result, SnowballError};
+use crate::fragments::{aggregate, allocate_input_keys, constant_point, LimbVar};
+
+// An export of an earlier shard: the index of the shard and the index of the variable in its exports.
+type Export = (usize, usize);
+
+// Variables of a shard that other shards refer to, and local variables that stand for exports of earlier shards.
+type Interface = (Vec<(Variable, Export)>, Vec<Variable>);
+
+struct Shard<F: Field> {
+    matrices: ConstraintMatrices<F>,
+    // Empty in setup mode.
+    instance: Vec<F>,
+    witness: Vec<F>,
+    imports: Vec<(Variable, Export)>,
+    exports: Vec<Variable>,
+}
+
+impl<F: Field> Shard<F> {
+    fn synthesize(setup: bool, config: &Config, f: impl FnOnce(ConstraintSystemRef<F>) -> std::result::Result<Interface, SynthesisError>) -> std::result::Result<Self, SynthesisError> {
+        let cs = ConstraintSystem::<F>::new_ref();
+        cs.set_mode(if setup { SynthesisMode::Setup } else { SynthesisMode::Prove { construct_matrices: true } });
+        config.apply(&cs);
+        let (imports, exports) = f(cs.clone())?;
+        cs.finalize();
+        let matrices = cs.to_matrices().ok_or(SynthesisError::MissingCS)?;
+        let cs = cs.into_inner().ok_or(SynthesisError::MissingCS)?;
+        Ok(Self { matrices, instance: cs.instance_assignment, witness: cs.witness_assignment, imports, exports })
+    }
+
+    // Index of the variable in the rows of the matrices.
+    fn column(&self, v: Variable) -> usize {
+        match v {
+            Variable::One => 0,
+            Variable::Instance(i) => i,
+            Variable::Witness(i) => self.matrices.num_instance_variables + i,
+            _ => unreachable!("linear combinations are inlined"),
+        }
+    }
+}
+
+// Allocates the variables of the shards in `cs`, in order, and enforces their constraints.
+fn replay<F: Field>(cs: &ConstraintSystemRef<F>, shards: &[Shard<F>]) -> std::result::Result<(), SynthesisError> {
+    let mut globals: Vec<Vec<Variable>> = Vec::with_capacity(shards.len());
+    for shard in shards {
+        let m = &shard.matrices;
+        let imported: BTreeMap<usize, Variable> = shard.imports.iter()
+            .map(|(local, (s, i))| (shard.column(*local), globals[*s][shards[*s].column(shards[*s].exports[*i])]))
+            .collect();
+        let mut map = vec![Variable::One];
+        for i in 1..m.num_instance_variables {
+            map.push(match imported.get(&i) {
+                Some(v) => *v,
+                None => cs.new_input_variable(|| shard.instance.get(i).copied().ok_or(SynthesisError::AssignmentMissing))?,
+            });
+        }
+        for i in 0..m.num_witness_variables {
+            map.push(match imported.get(&(m.num_instance_variables + i)) {
+                Some(v) => *v,
+                None => cs.new_witness_variable(|| shard.witness.get(i).copied().ok_or(SynthesisError::AssignmentMissing))?,
+            });
+        }
+        let lc = |row: &[(F, usize)]| LinearCombination(row.iter().map(|(coeff, i)| (*coeff, map[*i])).collect());
+        for i in 0..m.num_constraints {
+            cs.enforce_constraint(lc(&m.a[i]), lc(&m.b[i]), lc(&m.c[i]))?;
+        }
+        globals.push(map);
+    }
+    Ok(())
+}
+
+fn variables<P, F, CF>(p: &NonZeroAffineVarGeneric<P, F, CF>) -> std::result::Result<Vec<Variable>, SynthesisError>
+    where P: ark_ec::short_weierstrass::SWCurveConfig,
+          CF: PrimeField,
+          F: LimbVar<P::BaseField, CF>,
+{
+    let mut res = p.x.limb_variables().ok_or(SynthesisError::Unsatisfiable)?;
+    res.extend(p.y.limb_variables().ok_or(SynthesisError::Unsatisfiable)?);
+    Ok(res)
+}
+
+fn bit_variable<F: Field>(b: &Boolean<F>) -> std::result::Result<Variable, SynthesisError> {
+    match b {
+        Boolean::Is(b) => Ok(b.variable()),
+        _ => Err(SynthesisError::Unsatisfiable),
+    }
+}
+
+/// `ApkCircuit` synthesized in shards of `chunk_size` keys.
+pub struct ShardedCircuit<C: Chain> {
+    circuit: ChainCircuit<C>,
+    chunk_size: usize,
+}
+
+impl<C: Chain> ShardedCircuit<C> {
+    pub fn new(circuit: ChainCircuit<C>, chunk_size: usize) -> Result<Self> {
+        if chunk_size == 0 {
+            return Err(SnowballError::InvalidChunkSize(chunk_size));
+        }
+        Ok(Self { circuit, chunk_size })
+    }
+}
+
+impl<C: Chain> ConstraintSynthesizer<C::ConstraintField> for ShardedCircuit<C>
+    where for<'a> &'a C::FieldVar: FieldOpsBounds<'a, KeyBaseField<C>, C::FieldVar>
+{
+    fn generate_constraints(self, cs: ConstraintSystemRef<C::ConstraintField>) -> ark_relations::r1cs::Result<()> {
+        let setup = cs.is_in_setup_mode();
+        let ChainCircuit::<C> { keys, seed, packed_bits, config, .. } = self.circuit;
+        // As many keys as the packed bitmask has bits for take part in the aggregation.
+        let n = keys.len().min(C::ConstraintField::MODULUS_BIT_SIZE as usize);
+        let bits: Vec<bool> = packed_bits.into_bigint().to_bits_le().into_iter().take(n).collect();
+        let chunks: Vec<Range<usize>> = (0..n).step_by(self.chunk_size).map(|s| s..(s + self.chunk_size).min(n)).collect();
+        let acc_in = |start: usize| circuit_semantics::aggregate::<C>(seed, &keys[..start], &Bitmask::from_bits(bits[..start].to_vec()), &config).0;
+
+        let head = |cs: ConstraintSystemRef<C::ConstraintField>| -> std::result::Result<Interface, SynthesisError> {
+            let (key_vars, bitmask_var) = allocate_inputs::<C::Curve, _, C::FieldVar>(cs, keys.clone(), packed_bits, &config)?;
+            bitmask_var.enforce_unused_zero()?;
+            let mut exports = Vec::new();
+            for key in &key_vars {
+                exports.extend(variables(key)?);
+            }
+            for b in bitmask_var.bits() {
+                exports.push(bit_variable(b)?);
+            }
+            Ok((vec![], exports))
+        };
+
+        let chunk = |k: usize, cs: ConstraintSystemRef<C::ConstraintField>| -> std::result::Result<Interface, SynthesisError> {
+            let range = chunks[k].clone();
+            // Stand-ins for the keys of the head, whatever `config.commitment` is: their variables are imported from
+            // those the head allocates and checks by the scheme, so they're never inputs of the stitched circuit.
+            let key_vars = allocate_input_keys::<C::Curve, _, C::FieldVar>(cs.clone(), &keys[range.clone()])?;
+            let bit_vars = Vec::<Boolean<_>>::new_witness(cs.clone(), || Ok(bits[range.clone()].to_vec()))?;
+            let mut imports = Vec::new();
+            let key_limbs: Vec<Variable> = key_vars.iter().map(variables).collect::<std::result::Result<Vec<_>, _>>()?.concat();
+            let limbs_per_key = key_limbs.len() / range.len();
+            imports.extend(key_limbs.into_iter().enumerate().map(|(i, v)| (v, (0, range.start * limbs_per_key + i))));
+            for (i, b) in bit_vars.iter().enumerate() {
+                imports.push((bit_variable(b)?, (0, keys.len() * limbs_per_key + range.start + i)));
+            }
+
+            let start = if k == 0 {
+                constant_point::<C::Curve, _, C::FieldVar>(cs.clone(), seed)?
+            } else {
+                let value = if setup { Err(SynthesisError::AssignmentMissing) } else { Ok(acc_in(range.start)) };
+                let x = C::FieldVar::new_witness_unchecked(cs.clone(), || value.map(|p| p.x))?;
+                let y = C::FieldVar::new_witness_unchecked(cs.clone(), || value.map(|p| p.y))?;
+                let start = NonZeroAffineVarGeneric::new(x, y);
+                imports.extend(variables(&start)?.into_iter().enumerate().map(|(i, v)| (v, (k, i))));
+                start
+            };
+            let acc = aggregate(start, &key_vars, &bit_vars, &config)?;
+
+            let mut exports = Vec::new();
+            if k + 1 < chunks.len() {
+                let x = C::FieldVar::new_witness(cs.clone(), || acc.x.value())?;
+                let y = C::FieldVar::new_witness(cs.clone(), || acc.y.value())?;
+                x.enforce_equal(&acc.x)?;
+                y.enforce_equal(&acc.y)?;
+                exports = variables(&NonZeroAffineVarGeneric::<C::Curve, _, _>::new(x, y))?;
+            }
+            Ok((imports, exports))
+        };
+
+        let shards = ark_std::cfg_into_iter!(0..=chunks.len())
+            .map(|i| if i == 0 {
+                Shard::synthesize(setup, &config, head)
+            } else {
+                Shard::synthesize(setup, &config, |cs| chunk(i - 1, cs))
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        replay(&cs, &shards)
+    }
+}
+
+#[cfg(all(test, feature = "bls12-377-bw6"))]
+mod tests {
+    use ark_groth16::Groth16;
+    use ark_r1cs_std::fields::FieldVar;
+    use ark_r1cs_std::fields::fp::FpVar;
+    use ark_snark::SNARK;
+    use ark_std::{test_rng, UniformRand};
+    use rand::rngs::OsRng;
+
+    use crate::apk_circuits::public_inputs;
+    use crate::chain::Bls377InBw6;
+    #[cfg(feature = "bls12-381-emulated")]
+    use crate::chain::Bls381Emulated;
+    use crate::config::AdditionStrategy;
+    use crate::witness::{constraint_matrices, extract_assignment};
+
+    use super::*;
+
+    fn test_circuit<C: Chain>(n: usize, config: Config) -> (ChainCircuit<C>, Vec<ark_ec::short_weierstrass::Affine<C::Curve>>, Bitmask) {
+        let rng = &mut test_rng();
+        let keys: Vec<_> = (0..n).map(|_| ark_ec::short_weierstrass::Affine::<C::Curve>::rand(rng)).collect();
+        let seed = ark_ec::short_weierstrass::Affine::<C::Curve>::rand(rng);
+        let bitmask = Bitmask::from_bits((0..n).map(|i| i % 3 != 1).collect());
+        let circuit = ChainCircuit::<C>::new(keys.clone(), seed, &bitmask).unwrap().with_config(config);
+        (circuit, keys, bitmask)
+    }
+
+    fn check<C: Chain>(n: usize, chunk_size: usize, config: Config)
+        where for<'a> &'a C::FieldVar: FieldOpsBounds<'a, KeyBaseField<C>, C::FieldVar>
+    {
+        let (circuit, keys, bitmask) = test_circuit::<C>(n, config.clone());
+        let assignment = extract_assignment(ShardedCircuit::<C>::new(circuit, chunk_size).unwrap()).unwrap();
+        let matrices = constraint_matrices(ShardedCircuit::<C>::new(test_circuit::<C>(n, config.clone()).0, chunk_size).unwrap()).unwrap();
+        assert_eq!(assignment.first_unsatisfied(&matrices), None);
+        let expected = public_inputs::<C::Curve, C::ConstraintField, C::FieldVar>(&keys, &bitmask, &config).unwrap();
+        assert_eq!(assignment.public_inputs(), expected);
+    }
+
+    #[test]
+    fn test_replay() {
+        type F = ark_bls12_381::Fr;
+        let exporter = Shard::<F>::synthesize(false, &Config::default(), |cs| {
+            let w = FpVar::new_witness(cs, || Ok(F::from(1u8)))?;
+            w.enforce_equal(&FpVar::constant(F::from(1u8)))?;
+            Ok((vec![], vec![w.limb_variables().unwrap()[0]]))
+        }).unwrap();
+        // Satisfied on its own, with its own value of the imported variable.
+        let importer = |value: u8| Shard::<F>::synthesize(false, &Config::default(), |cs| {
+            let w = FpVar::new_witness(cs, || Ok(F::from(value)))?;
+            w.enforce_equal(&FpVar::constant(F::from(value)))?;
+            Ok((vec![(w.limb_variables().unwrap()[0], (0, 0))], vec![]))
+        }).unwrap();
+
+        let cs = ConstraintSystem::<F>::new_ref();
+        replay(&cs, &[exporter, importer(2)]).unwrap();
+        assert!(!cs.is_satisfied().unwrap());
+        assert_eq!(cs.num_witness_variables(), 1);
+    }
+
+    #[test]
+    fn test_sharded() {
+        check::<Bls377InBw6>(5, 2, Config::default());
+        check::<Bls377InBw6>(4, 4, Config { addition: AdditionStrategy::Accumulated, ..Default::default() });
+        #[cfg(feature = "bls12-381-emulated")]
+        {
+            check::<Bls381Emulated>(3, 2, Config::default());
+            check::<Bls381Emulated>(3, 2, Config { commitment: crate::config::CommitmentScheme::PackedLimbs, ..Default::default() });
+        }
+        assert!(ShardedCircuit::<Bls377InBw6>::new(test_circuit::<Bls377InBw6>(4, Config::default()).0, 0).is_err());
+    }
+
+    #[test]
+    fn test_commitments() {
+        use crate::config::CommitmentScheme;
+
+        for commitment in [
+            CommitmentScheme::PublicInputs,
+            CommitmentScheme::PackedLimbs,
+            CommitmentScheme::CompressedInputs,
+            #[cfg(feature = "sha256")]
+            CommitmentScheme::Sha256,
+        ] {
+            check::<Bls377InBw6>(3, 2, Config { commitment, ..Default::default() });
+        }
+    }
+
+    #[test]
+    fn test_groth16() {
+        let rng = &mut OsRng;
+        let (circuit, keys, bitmask) = test_circuit::<Bls377InBw6>(6, Config::default());
+        let (pk, vk) = Groth16::<ark_bw6_761::BW6_761>::circuit_specific_setup(ShardedCircuit::<Bls377InBw6>::new(circuit.clone(), 4).unwrap(), rng).unwrap();
+        let proof = Groth16::<ark_bw6_761::BW6_761>::prove(&pk, ShardedCircuit::<Bls377InBw6>::new(circuit, 4).unwrap(), rng).unwrap();
+        let pi = public_inputs::<ark_bls12_377::g1::Config, _, ark_r1cs_std::fields::fp::FpVar<_>>(&keys, &bitmask, &Config::default()).unwrap();
+        assert!(Groth16::<ark_bw6_761::BW6_761>::verify(&vk, &pi, &proof).unwrap());
+    }
+}
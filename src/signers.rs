@@ -0,0 +1,134 @@
+//! Signers given by their indices in the keyset rather than by a bitmask, for committees where few keys sign.
+//!
+//! The indices are witnesses, so the prover could repeat one to count a key twice toward the aggregate key
+//! and the threshold. `SignerIndicesVar` enforces them to be strictly increasing and below the number of keys,
+//! and turns them into the bits `aggregate` takes, so each key is added at most once and counted at most once.
+
+use std::cmp::Ordering;
+
+use ark_ff::{BigInteger, PrimeField};
+use ark_r1cs_std::alloc::AllocVar;
+use ark_r1cs_std::boolean::Boolean;
+use ark_r1cs_std::eq::EqGadget;
+use ark_r1cs_std::fields::FieldVar;
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_r1cs_std::R1CSVar;
+use ark_relations::r1cs::{ConstraintSystemRef, SynthesisError};
+
+use crate::labels::labeled;
+
+/// The indices of the signers, strictly increasing and below `num_keys`.
+pub struct SignerIndicesVar<CF: PrimeField> {
+    indices: Vec<FpVar<CF>>,
+    num_keys: usize,
+}
+
+// Enforces `0 <= x < 2^num_bits`, with a boolean per bit and a constraint for the packing.
+fn enforce_range<CF: PrimeField>(x: &FpVar<CF>, num_bits: usize) -> Result<(), SynthesisError> {
+    let cs = x.cs();
+    let bits = (0..num_bits)
+        .map(|i| Boolean::new_witness(cs.clone(), || Ok(x.value()?.into_bigint().get_bit(i))))
+        .collect::<Result<Vec<_>, _>>()?;
+    Boolean::le_bits_to_fp_var(&bits)?.enforce_equal(x)
+}
+
+impl<CF: PrimeField> SignerIndicesVar<CF> {
+    /// Allocates the indices as witnesses, enforcing them to be strictly increasing and below `num_keys`:
+    /// the first index, the gaps between the consecutive ones minus `1`, and `num_keys - 1` minus the last one
+    /// are range-checked to the bits of `num_keys`. As there are fewer indices than elements of the field,
+    /// none of these sums wraps around, so the range checks bound the indices as integers.
+    /// The setup has to be run with as many indices as the proofs have.
+    pub fn new_witness(cs: ConstraintSystemRef<CF>, indices: &[usize], num_keys: usize) -> Result<Self, SynthesisError> {
+        let num_bits = (usize::BITS - num_keys.leading_zeros()) as usize;
+        labeled(&cs, "signers", || {
+            let indices = indices.iter()
+                .map(|i| FpVar::new_witness(cs.clone(), || Ok(CF::from(*i as u64))))
+                .collect::<Result<Vec<_>, _>>()?;
+            if let (Some(first), Some(last)) = (indices.first(), indices.last()) {
+                enforce_range(first, num_bits)?;
+                for w in indices.windows(2) {
+                    enforce_range(&(&w[1] - &w[0] - CF::one()), num_bits)?;
+                }
+                enforce_range(&(FpVar::constant(CF::from(num_keys as u64)) - CF::one() - last), num_bits)?;
+            }
+            Ok(Self { indices, num_keys })
+        })
+    }
+
+    pub fn indices(&self) -> &[FpVar<CF>] {
+        &self.indices
+    }
+
+    /// The bit of each key, set iff its index is among the signers, for `aggregate`.
+    pub fn bits(&self) -> Result<Vec<Boolean<CF>>, SynthesisError> {
+        if self.indices.is_empty() {
+            return Ok(vec![Boolean::FALSE; self.num_keys]);
+        }
+        (0..self.num_keys)
+            .map(|j| {
+                let j = FpVar::constant(CF::from(j as u64));
+                let hits = self.indices.iter().map(|i| i.is_eq(&j)).collect::<Result<Vec<_>, _>>()?;
+                Boolean::kary_or(&hits)
+            })
+            .collect()
+    }
+
+    /// Enforces at least `threshold` keys to sign, counting the set bits, as `BitmaskVar::enforce_threshold` does.
+    /// With no indices, the count is a constant, and a threshold above `0` is `Unsatisfiable`.
+    pub fn enforce_threshold(&self, threshold: usize) -> Result<(), SynthesisError> {
+        if self.indices.is_empty() {
+            return if threshold == 0 { Ok(()) } else { Err(SynthesisError::Unsatisfiable) };
+        }
+        let count = self.bits()?.iter().fold(FpVar::zero(), |acc, b| acc + FpVar::from(b.clone()));
+        count.enforce_cmp(&FpVar::constant(CF::from(threshold as u64)), Ordering::Greater, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_ec::CurveGroup;
+    use ark_ec::short_weierstrass::Affine;
+    use ark_relations::r1cs::ConstraintSystem;
+    use ark_std::{test_rng, UniformRand};
+
+    use crate::affine_gen::NonZeroAffineVarGeneric;
+    use crate::bitmask::Bitmask;
+    use crate::config::Config;
+    use crate::fragments::{aggregate, allocate_input_keys};
+
+    use super::*;
+
+    type Key = NonZeroAffineVarGeneric<ark_bls12_377::g1::Config, FpVar<ark_bw6_761::Fr>, ark_bw6_761::Fr>;
+
+    fn check(indices: &[usize], num_keys: usize, threshold: usize) -> bool {
+        let cs = ConstraintSystem::<ark_bw6_761::Fr>::new_ref();
+        let signers = SignerIndicesVar::new_witness(cs.clone(), indices, num_keys).unwrap();
+        signers.enforce_threshold(threshold).unwrap();
+        cs.is_satisfied().unwrap()
+    }
+
+    #[test]
+    fn test_signer_indices() {
+        let rng = &mut test_rng();
+        let keys: Vec<ark_bls12_377::G1Affine> = (0..5).map(|_| Affine::rand(rng)).collect();
+        let bitmask = Bitmask::from_bits(vec![false, true, false, true, true]);
+        let seed = Affine::rand(rng);
+        let cs = ConstraintSystem::<ark_bw6_761::Fr>::new_ref();
+        let key_vars: Vec<Key> = allocate_input_keys(cs.clone(), &keys).unwrap();
+        let signers = SignerIndicesVar::new_witness(cs.clone(), &bitmask.signers(), keys.len()).unwrap();
+        let bits = signers.bits().unwrap();
+        assert_eq!(bits.value().unwrap(), bitmask.bits());
+        let apk = aggregate(Key::new_constant(cs.clone(), seed).unwrap(), &key_vars, &bits, &Config::default()).unwrap();
+        assert_eq!(apk.value().unwrap(), (crate::aggregation::aggregate(&keys, &bitmask).unwrap() + seed).into_affine());
+        assert!(cs.is_satisfied().unwrap());
+
+        assert!(check(&[1, 3, 4], 5, 3));
+        assert!(!check(&[1, 3, 4], 5, 4));
+        assert!(check(&[], 5, 0));
+        assert!(check(&[0, 1, 2, 3, 4], 5, 5));
+        // A repeated index, that would count a key twice.
+        assert!(!check(&[1, 3, 3], 5, 3));
+        assert!(!check(&[3, 1, 4], 5, 3));
+        assert!(!check(&[1, 3, 5], 5, 3));
+    }
+}
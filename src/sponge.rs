@@ -0,0 +1,147 @@
+//! Sponge absorption of the inputs of the apk circuit, the same on the host and in the circuit,
+//! so that a Fiat-Shamir transcript can be replayed in-circuit.
+//!
+//! What's absorbed is the instance encoding of `schema`: the coordinates of the keys for native chains,
+//! and their limbs for emulated ones, and the packed bitmask. The elements are those of the constraint field
+//! of the chain, and the bytes their compressed serializations, as `Absorb` does for field elements.
+//! Sponges over another field can't absorb them, and panic as `ark-crypto-primitives` does on points.
+//!
+//! There is no `KeysetCommitment` in the crate to absorb: the keys are absorbed one by one.
+
+use ark_crypto_primitives::sponge::Absorb;
+use ark_crypto_primitives::sponge::constraints::AbsorbGadget;
+use ark_ec::short_weierstrass::{Affine, SWCurveConfig};
+use ark_ff::{BigInteger, Field, PrimeField};
+use ark_r1cs_std::boolean::Boolean;
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_r1cs_std::uint8::UInt8;
+use ark_relations::r1cs::SynthesisError;
+use ark_serialize::CanonicalSerialize;
+use derivative::Derivative;
+
+use crate::affine_gen::NonZeroAffineVarGeneric;
+use crate::bitmask::Bitmask;
+use crate::chain::Chain;
+use crate::config::{Config, Optimization};
+use crate::fragments::{BitmaskVar, LimbVar};
+use crate::schema::point_to_instance;
+
+/// A point absorbed as `schema::point_to_instance` encodes it, with the limbs of `optimization`,
+/// as `NonZeroAffineVarGeneric` absorbs it in a constraint system optimized for it.
+#[derive(Derivative)]
+#[derivative(Clone(bound = ""), Copy(bound = ""), Debug(bound = ""), PartialEq(bound = ""), Eq(bound = ""))]
+pub struct InstancePoint<C: Chain> {
+    pub point: Affine<C::Curve>,
+    pub optimization: Optimization,
+}
+
+impl<C: Chain> InstancePoint<C> {
+    pub fn new(point: Affine<C::Curve>, config: &Config) -> Self {
+        Self { point, optimization: config.optimization }
+    }
+
+    fn elements(&self) -> Vec<C::ConstraintField> {
+        let config = Config { optimization: self.optimization, ..Default::default() };
+        point_to_instance::<C>(&self.point, &config).expect("the limbs of a field element always fit")
+    }
+}
+
+impl<C: Chain> Absorb for InstancePoint<C> {
+    fn to_sponge_bytes(&self, dest: &mut Vec<u8>) {
+        for x in self.elements() {
+            x.serialize_compressed(&mut *dest).expect("serializing into a vector can't fail");
+        }
+    }
+
+    fn to_sponge_field_elements<F: PrimeField>(&self, dest: &mut Vec<F>) {
+        assert_eq!(F::characteristic(), C::ConstraintField::characteristic(), "absorbing into a sponge over another field than the constraint field");
+        dest.extend(self.elements().iter().map(|x| F::from_le_bytes_mod_order(&x.into_bigint().to_bytes_le())))
+    }
+}
+
+/// Chunks of at most `MODULUS_BIT_SIZE - 1` bits, each packed as `Bitmask::pack` does:
+/// a single element, the packed input of the circuit, for the bitmasks the circuit takes.
+fn packed_chunks<F: PrimeField>(bitmask: &Bitmask) -> Vec<F> {
+    bitmask.bits()
+        .chunks((F::MODULUS_BIT_SIZE - 1) as usize)
+        .map(|chunk| Bitmask::from_bits(chunk.to_vec()).pack().expect("chunks fit"))
+        .collect()
+}
+
+/// The bytes are the bits, little-endian, the last byte padded with unset bits, as `BitmaskVar` has them,
+/// and the elements are `Bitmask::pack`, the packed input of the circuit.
+impl Absorb for Bitmask {
+    fn to_sponge_bytes(&self, dest: &mut Vec<u8>) {
+        dest.extend(self.bits().chunks(8).map(|c| c.iter().enumerate().fold(0u8, |byte, (i, &bit)| byte | ((bit as u8) << i))))
+    }
+
+    fn to_sponge_field_elements<F: PrimeField>(&self, dest: &mut Vec<F>) {
+        dest.extend(packed_chunks::<F>(self))
+    }
+}
+
+impl<P, F, CF> AbsorbGadget<CF> for NonZeroAffineVarGeneric<P, F, CF>
+    where P: SWCurveConfig,
+          CF: PrimeField,
+          F: LimbVar<P::BaseField, CF>,
+{
+    fn to_sponge_bytes(&self) -> Result<Vec<UInt8<CF>>, SynthesisError> {
+        self.to_sponge_field_elements()?.to_sponge_bytes()
+    }
+
+    fn to_sponge_field_elements(&self) -> Result<Vec<FpVar<CF>>, SynthesisError> {
+        Ok([self.x.limbs()?, self.y.limbs()?].concat())
+    }
+}
+
+impl<CF: PrimeField> AbsorbGadget<CF> for BitmaskVar<CF> {
+    fn to_sponge_bytes(&self) -> Result<Vec<UInt8<CF>>, SynthesisError> {
+        Ok(self.bits().chunks(8).map(|c| {
+            let mut byte = c.to_vec();
+            byte.resize(8, Boolean::FALSE);
+            UInt8::from_bits_le(&byte)
+        }).collect())
+    }
+
+    fn to_sponge_field_elements(&self) -> Result<Vec<FpVar<CF>>, SynthesisError> {
+        Ok(vec![self.packed().clone()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_r1cs_std::fields::FieldOpsBounds;
+    use ark_r1cs_std::R1CSVar;
+    use ark_relations::r1cs::ConstraintSystem;
+    use ark_std::{test_rng, UniformRand};
+
+    use crate::apk_circuits::allocate_inputs;
+    use crate::chain::{Bls377InBw6, Bls381Emulated, KeyBaseField};
+
+    use super::*;
+
+    fn check<C: Chain>(config: &Config)
+        where for<'a> &'a C::FieldVar: FieldOpsBounds<'a, KeyBaseField<C>, C::FieldVar>
+    {
+        let rng = &mut test_rng();
+        let keys: Vec<Affine<C::Curve>> = (0..3).map(|_| Affine::rand(rng)).collect();
+        let bitmask = Bitmask::from_bits(vec![true, false, true]);
+        let cs = ConstraintSystem::<C::ConstraintField>::new_ref();
+        config.apply(&cs);
+        let (key_vars, bitmask_var) = allocate_inputs::<C::Curve, _, C::FieldVar>(cs, keys.clone(), bitmask.pack().unwrap(), config).unwrap();
+
+        let host: Vec<_> = keys.iter().map(|k| InstancePoint::<C>::new(*k, config)).collect();
+        let elements: Vec<C::ConstraintField> = host.to_sponge_field_elements_as_vec();
+        assert_eq!(key_vars.to_sponge_field_elements().unwrap().value().unwrap(), elements);
+        assert_eq!(key_vars.to_sponge_bytes().unwrap().value().unwrap(), host.to_sponge_bytes_as_vec());
+        assert_eq!(bitmask_var.to_sponge_field_elements().unwrap().value().unwrap(), bitmask.to_sponge_field_elements_as_vec::<C::ConstraintField>());
+        assert_eq!(bitmask_var.to_sponge_bytes().unwrap().value().unwrap(), bitmask.to_sponge_bytes_as_vec());
+    }
+
+    #[test]
+    fn test_absorb() {
+        check::<Bls377InBw6>(&Config::default());
+        check::<Bls381Emulated>(&Config::default());
+        check::<Bls381Emulated>(&Config { optimization: Optimization::Weight, ..Default::default() });
+    }
+}
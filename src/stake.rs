@@ -0,0 +1,143 @@
+//! Stake tables, the weight of each key of a keyset, and their in-circuit counterpart for weighted thresholds.
+//!
+//! The table is committed to as the keys are: the weights are public inputs, one per key, in the order of the keys,
+//! so the weight of the `i`-th key is the `i`-th weight of the instance, and a proof with other weights than
+//! those of the table the verifier holds doesn't verify. As for the keys, nothing is range-checked:
+//! the verifier provides the weights, each below `2^64`, so weighted sums of fewer than `2^64` keys don't wrap around.
+
+use std::cmp::Ordering;
+
+use ark_ec::short_weierstrass::{Affine, SWCurveConfig};
+use ark_ff::PrimeField;
+use ark_r1cs_std::alloc::AllocVar;
+use ark_r1cs_std::boolean::Boolean;
+use ark_r1cs_std::fields::FieldVar;
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_relations::r1cs::{ConstraintSystemRef, SynthesisError};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use derivative::Derivative;
+
+use crate::bitmask::Bitmask;
+use crate::error::SnowballError;
+use crate::labels::labeled;
+
+/// The keys of a keyset with their weights, in keyset order.
+#[derive(Derivative, CanonicalSerialize, CanonicalDeserialize)]
+#[derivative(Clone(bound = ""), Debug(bound = ""), PartialEq(bound = ""), Eq(bound = ""))]
+pub struct StakeTable<P: SWCurveConfig> {
+    entries: Vec<(Affine<P>, u64)>,
+}
+
+impl<P: SWCurveConfig> StakeTable<P> {
+    pub fn new(entries: Vec<(Affine<P>, u64)>) -> Self {
+        Self { entries }
+    }
+
+    pub fn entries(&self) -> &[(Affine<P>, u64)] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The keys, as the apk circuit takes them.
+    pub fn keys(&self) -> Vec<Affine<P>> {
+        self.entries.iter().map(|(key, _)| *key).collect()
+    }
+
+    pub fn weights(&self) -> Vec<u64> {
+        self.entries.iter().map(|(_, weight)| *weight).collect()
+    }
+
+    /// The weight of the first entry of the key.
+    pub fn weight_of(&self, key: &Affine<P>) -> Option<u64> {
+        self.entries.iter().find(|(k, _)| k == key).map(|(_, weight)| *weight)
+    }
+
+    /// The total weight of the keys for which the bit is set, as `StakeTableVar::signed_weight` computes it.
+    pub fn signed_weight(&self, bitmask: &Bitmask) -> Result<u128, SnowballError> {
+        if bitmask.len() != self.len() {
+            return Err(SnowballError::LengthMismatch { keys: self.len(), bits: bitmask.len() });
+        }
+        Ok(self.entries.iter().zip(bitmask.bits()).filter(|(_, b)| **b).map(|((_, weight), _)| *weight as u128).sum())
+    }
+
+    /// The weights in the instance vector, as `StakeTableVar::new_input` allocates them.
+    pub fn weight_inputs<F: PrimeField>(&self) -> Vec<F> {
+        self.entries.iter().map(|(_, weight)| F::from(*weight)).collect()
+    }
+}
+
+/// The weights of a stake table, allocated as public inputs.
+pub struct StakeTableVar<CF: PrimeField> {
+    weights: Vec<FpVar<CF>>,
+}
+
+impl<CF: PrimeField> StakeTableVar<CF> {
+    /// Allocates the weights as public inputs, labeled `stake/i`.
+    pub fn new_input<P: SWCurveConfig>(cs: ConstraintSystemRef<CF>, table: &StakeTable<P>) -> Result<Self, SynthesisError> {
+        let weights = labeled(&cs, "stake", || {
+            table.weight_inputs::<CF>().into_iter()
+                .enumerate()
+                .map(|(i, weight)| labeled(&cs, i, || FpVar::new_input(cs.clone(), || Ok(weight))))
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+        Ok(Self { weights })
+    }
+
+    pub fn weights(&self) -> &[FpVar<CF>] {
+        &self.weights
+    }
+
+    /// The total weight of the keys for which the bit is set, a constraint per key.
+    /// Keys past the end of `bits`, and bits past the end of the table, are ignored.
+    pub fn signed_weight(&self, bits: &[Boolean<CF>]) -> Result<FpVar<CF>, SynthesisError> {
+        self.weights.iter().zip(bits).try_fold(FpVar::zero(), |acc, (weight, bit)| {
+            Ok(acc + FpVar::from(bit.clone()) * weight)
+        })
+    }
+
+    /// Enforces the keys for which the bit is set to weigh at least `threshold`.
+    pub fn enforce_weight_threshold(&self, bits: &[Boolean<CF>], threshold: u64) -> Result<(), SynthesisError> {
+        self.signed_weight(bits)?.enforce_cmp(&FpVar::constant(CF::from(threshold)), Ordering::Greater, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_r1cs_std::R1CSVar;
+    use ark_relations::r1cs::ConstraintSystem;
+    use ark_std::{test_rng, UniformRand};
+
+    use crate::fragments::BitmaskVar;
+
+    use super::*;
+
+    #[test]
+    fn test_stake_table() {
+        let rng = &mut test_rng();
+        let table = StakeTable::<ark_bls12_377::g1::Config>::new((0..4).map(|i| (Affine::rand(rng), 10 * (i + 1))).collect());
+        assert_eq!(table.weight_of(&table.keys()[2]), Some(30));
+        let bitmask = Bitmask::from_bits(vec![true, false, true, true]);
+        assert_eq!(table.signed_weight(&bitmask).unwrap(), 80);
+        assert!(table.signed_weight(&Bitmask::from_bits(vec![true])).is_err());
+
+        let check = |threshold| {
+            let cs = ConstraintSystem::<ark_bw6_761::Fr>::new_ref();
+            let packed = FpVar::new_input(cs.clone(), || Ok(bitmask.pack::<ark_bw6_761::Fr>().unwrap())).unwrap();
+            let bitmask_var = BitmaskVar::new(packed, table.len()).unwrap();
+            let table_var = StakeTableVar::new_input(cs.clone(), &table).unwrap();
+            assert_eq!(table_var.signed_weight(bitmask_var.bits()).unwrap().value().unwrap(), 80u64.into());
+            table_var.enforce_weight_threshold(bitmask_var.bits(), threshold).unwrap();
+            assert_eq!(cs.borrow().unwrap().instance_assignment[2..], table.weight_inputs::<ark_bw6_761::Fr>());
+            cs.is_satisfied().unwrap()
+        };
+        assert!(check(80));
+        assert!(!check(81));
+    }
+}
@@ -1,21 +1,34 @@
+use std::borrow::Borrow;
+use std::fmt;
 use std::marker::PhantomData;
 
 use ark_ec::short_weierstrass::SWCurveConfig;
 use ark_ff::{Field, PrimeField, Zero};
 use ark_r1cs_std::alloc::AllocVar;
+use ark_r1cs_std::boolean::Boolean;
 use ark_r1cs_std::eq::EqGadget;
 use ark_r1cs_std::fields::{FieldOpsBounds, FieldVar};
 use ark_r1cs_std::fields::fp::FpVar;
 use ark_r1cs_std::fields::nonnative::NonNativeFieldVar;
+use ark_r1cs_std::select::CondSelectGadget;
 use ark_r1cs_std::R1CSVar;
 use ark_relations::ns;
 use ark_relations::r1cs::SynthesisError;
 use derivative::Derivative;
 
-use crate::affine_gen::NonZeroAffineVarGeneric;
+use crate::affine_gen::{nonzero_inverse, short_hex, NonZeroAffineVarGeneric};
+use crate::batch_inv::{batch_div, batch_inverse};
+use crate::config::{AdditionStrategy, Multiplication};
+use crate::limb_mul;
 
+/// The partial sum of a sequence of points, kept as the slope of the line through the last point added and `x3`,
+/// its ordinate `y3` being left to the next step, that takes it unreduced.
+///
+/// The denominator of a step, `x - x3`, depends on the slope of the previous one, so the inversions of the witnesses
+/// are sequential, and can't be batched with Montgomery's trick, as `sum_points_tree` does.
+/// In the circuit a slope costs a multiplication to check whatever way its witness is computed, see `batch_inv`.
 #[derive(Derivative)]
-#[derivative(Debug, Clone)]
+#[derivative(Clone)]
 #[must_use]
 pub struct SumAccumulator<P, F, CF>
     where
@@ -27,75 +40,175 @@ pub struct SumAccumulator<P, F, CF>
     pub y1_prev: F,
     pub lambda_prev: F,
     pub x3_prev: F,
+    /// How the products of the steps are computed, for emulated fields, see `with_multiplication`.
+    pub multiplication: Multiplication,
 
-    #[derivative(Debug = "ignore")]
     _p: PhantomData<P>,
-    #[derivative(Debug = "ignore")]
     _cf: PhantomData<CF>,
 }
 
+impl<P, F, CF> fmt::Debug for SumAccumulator<P, F, CF>
+    where
+        P: SWCurveConfig,
+        P::BaseField: PrimeField,
+        CF: Field,
+        F: FieldVar<P::BaseField, CF>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SumAccumulator")
+            .field("x1_prev", &format_args!("{}", short_hex(&self.x1_prev)))
+            .field("y1_prev", &format_args!("{}", short_hex(&self.y1_prev)))
+            .field("lambda_prev", &format_args!("{}", short_hex(&self.lambda_prev)))
+            .field("x3_prev", &format_args!("{}", short_hex(&self.x3_prev)))
+            .finish()
+    }
+}
+
+/// The partial sum `(x3, y3)`, with `y3` computed from the values, see `short_hex`.
+impl<P, F, CF> fmt::Display for SumAccumulator<P, F, CF>
+    where
+        P: SWCurveConfig,
+        P::BaseField: PrimeField,
+        CF: Field,
+        F: FieldVar<P::BaseField, CF>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let y3 = (|| Ok::<_, SynthesisError>(self.lambda_prev.value()? * (self.x1_prev.value()? - self.x3_prev.value()?) - self.y1_prev.value()?))();
+        let y3 = y3.map_or_else(|_| "?".to_string(), |y3| short_hex(&F::constant(y3)));
+        write!(f, "({}, {})", short_hex(&self.x3_prev), y3)
+    }
+}
+
 impl<P: SWCurveConfig, CF: Field, F: FieldVar<P::BaseField, CF>> SumAccumulator<P, F, CF>
     where for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F> {
-    fn init(p1: NonZeroAffineVarGeneric<P, F, CF>, p2: NonZeroAffineVarGeneric<P, F, CF>) -> Result<Self, SynthesisError> {
+    /// The sum of the first two points. Wrong for points with the same `x`, see `init_checked`.
+    pub fn init(p1: NonZeroAffineVarGeneric<P, F, CF>, p2: NonZeroAffineVarGeneric<P, F, CF>) -> Result<Self, SynthesisError> {
         let numerator = &p2.y - &p1.y;
         let denominator = &p2.x - &p1.x;
-        assert!(!denominator.value()?.is_zero());
         let lambda = numerator.mul_by_inverse_unchecked(&denominator)?;
         let x3 = lambda.square()? - &p1.x - &p2.x;
-        let acc = Self {
-            x1_prev: p1.x,
-            y1_prev: p1.y,
-            lambda_prev: lambda,
-            x3_prev: x3,
-            _p: PhantomData,
-            _cf: PhantomData,
-        };
-        Ok(acc)
-    }
-
-    // Generic implementation. Suboptimal for non-native as it can't enjoy `mul_without_reduce`
-
-    // fn add(&self, p: NonZeroAffineVarGeneric<P, F, CF>) -> Result<Self, SynthesisError> {
-    //     let numerator = &self.lambda_prev * (&self.x3_prev - &self.x1_prev) + &self.y1_prev + &p.y;
-    //     let denominator = &p.x - &self.x3_prev;
-    //     assert!(!denominator.value()?.is_zero());
-    //     let lambda = numerator.mul_by_inverse_unchecked(&denominator)?;
-    //     let x3 = lambda.square()? - &self.x3_prev - &p.x;
-    //     let acc = Self {
-    //         x1_prev: p.x,
-    //         y1_prev: p.y,
-    //         lambda_prev: lambda,
-    //         x3_prev: x3,
-    //         _p: PhantomData,
-    //         _cf: PhantomData,
-    //     };
-    //     Ok(acc)
-    // }
+        Ok(Self::next(p1, lambda, x3))
+    }
 
-    fn finalize(self) -> Result<NonZeroAffineVarGeneric<P, F, CF>, SynthesisError> {
+    /// The accumulator of the single point, with no constraints: the line through `(x, -y)` of slope `0`.
+    pub fn from_point(p: NonZeroAffineVarGeneric<P, F, CF>) -> Result<Self, SynthesisError> {
+        let y1 = p.y.negate()?;
+        Ok(Self::next(NonZeroAffineVarGeneric::new(p.x.clone(), y1), F::zero(), p.x))
+    }
+
+    /// `init` enforcing `x1 != x2`, see `NonZeroAffineVarGeneric::add_checked`.
+    pub fn init_checked(p1: NonZeroAffineVarGeneric<P, F, CF>, p2: NonZeroAffineVarGeneric<P, F, CF>) -> Result<Self, SynthesisError> {
+        nonzero_inverse(&(&p2.x - &p1.x))?;
+        Self::init(p1, p2)
+    }
+
+    /// Computes the products of the next steps with `multiplication`, for emulated fields.
+    /// The result of a step is the same either way, only the constraints differ, see `limb_mul`.
+    pub fn with_multiplication(self, multiplication: Multiplication) -> Self {
+        Self { multiplication, ..self }
+    }
+
+    /// Adds the point, with the addition of `AccumulatorField` for the field. Wrong for a point with the `x` of the partial sum,
+    /// see `add_checked` and `add_or_double`.
+    pub fn add(&self, p: NonZeroAffineVarGeneric<P, F, CF>) -> Result<Self, SynthesisError>
+        where F: AccumulatorField<P, CF>
+    {
+        F::accumulate(self, p)
+    }
+
+    /// Adds the point, enforcing its `x` to differ from that of the partial sum, so that the slope is determined,
+    /// at the cost of an inversion.
+    pub fn add_checked(&self, p: NonZeroAffineVarGeneric<P, F, CF>) -> Result<Self, SynthesisError>
+        where F: AccumulatorField<P, CF>
+    {
+        nonzero_inverse(&(&p.x - &self.x3_prev))?;
+        F::accumulate(self, p)
+    }
+
+    /// Adds the point if `b` is set, selecting `x3` and the sign of `y3` of the partial sum, rather than both coordinates
+    /// of the sum: for an unset bit, the slope is that of the line through the point and the opposite of the partial sum,
+    /// which the accumulator then keeps. As many constraints as `add` and the selection of the sum natively,
+    /// and a multiplication fewer for emulated fields, as `y3` isn't computed.
+    /// Wrong for a point with the `x` of the partial sum, and unsatisfiable for the point equal to it and an unset bit.
+    pub fn conditional_add(&self, p: NonZeroAffineVarGeneric<P, F, CF>, b: &Boolean<CF>) -> Result<Self, SynthesisError>
+        where F: AccumulatorField<P, CF>
+    {
+        F::conditional_accumulate(self, p, b)
+    }
+
+    /// Adds the point, doubling instead for the point equal to the partial sum, as duplicate keys make happen.
+    /// The choice is a hint bit, enforced with both coordinates being equal for the doubling,
+    /// and the `x` differing for the addition, so a point opposite to the partial sum makes it unsatisfiable,
+    /// as the sum isn't a non-zero point then. Costs `y3` of the partial sum, 2 equality checks and 2 selections
+    /// more than `add_checked`, and `add` is cheaper yet, for the points the prover has no hand in.
+    pub fn add_or_double(&self, p: NonZeroAffineVarGeneric<P, F, CF>) -> Result<Self, SynthesisError> {
+        let cs = p.cs().or(self.x3_prev.cs());
+        let y3_prev = &self.lambda_prev * (&self.x1_prev - &self.x3_prev) - &self.y1_prev;
+        let is_double = Boolean::new_witness(ns!(cs, "is_double"), || Ok(p.x.value()? == self.x3_prev.value()?))?;
+        p.x.conditional_enforce_equal(&self.x3_prev, &is_double)?;
+        p.y.conditional_enforce_equal(&y3_prev, &is_double)?;
+        p.x.conditional_enforce_not_equal(&self.x3_prev, &is_double.not())?;
+        // `2 y` is non-zero, as the points of order `2` aren't in the subgroup.
+        let numerator = is_double.select(&(p.x.square()? * P::BaseField::from(3u8) + P::COEFF_A), &(&p.y - &y3_prev))?;
+        let denominator = is_double.select(&p.y.double()?, &(&p.x - &self.x3_prev))?;
+        let lambda = numerator.mul_by_inverse_unchecked(&denominator)?;
+        // `x3 = lambda^2 - 2 x` for the doubling, as `x3_prev = x` then.
+        let x3 = lambda.square()? - &self.x3_prev - &p.x;
+        Ok(self.step(p, lambda, x3))
+    }
+
+    /// The sum, computing `y3` of the last step.
+    pub fn finalize(self) -> Result<NonZeroAffineVarGeneric<P, F, CF>, SynthesisError> {
+        // `x3` (reduced from `lambda^2` in the last `add`) and `y3` are two independent witnesses, so each needs
+        // its own reduction check: enforcing a single reduction of a combination of both products
+        // would leave one degree of freedom to a malicious prover.
         let y3_prev = &self.lambda_prev * (&self.x1_prev - &self.x3_prev) - &self.y1_prev;
         let res = NonZeroAffineVarGeneric::new(self.x3_prev, y3_prev);
         Ok(res)
     }
-}
 
-// Native field impl.
-impl<F: PrimeField, P: SWCurveConfig<BaseField=F>> SumAccumulator<P, FpVar<F>, F> {
-    fn add(&self, p: NonZeroAffineVarGeneric<P, FpVar<F>, F>) -> Result<Self, SynthesisError> {
-        let numerator = &self.lambda_prev * (&self.x3_prev - &self.x1_prev) + &self.y1_prev + &p.y;
-        let denominator = &p.x - &self.x3_prev;
-        assert!(!denominator.value()?.is_zero());
-        let lambda = numerator.mul_by_inverse_unchecked(&denominator)?;
-        let x3 = lambda.square()? - &self.x3_prev - &p.x;
-        let acc = Self {
+    /// The sum of the points, by `init`, `add` and `finalize`: the point itself for a single one,
+    /// and `SynthesisError::Unsatisfiable` for none, as the sum isn't a non-zero point then. See `sum_points`.
+    pub fn sum(points: &[NonZeroAffineVarGeneric<P, F, CF>]) -> Result<NonZeroAffineVarGeneric<P, F, CF>, SynthesisError>
+        where F: AccumulatorField<P, CF>
+    {
+        sum_points(points)
+    }
+
+    // The partial sum at `x3` on the line through `p` of slope `lambda`, negated.
+    fn next(p: NonZeroAffineVarGeneric<P, F, CF>, lambda: F, x3: F) -> Self {
+        Self {
             x1_prev: p.x,
             y1_prev: p.y,
             lambda_prev: lambda,
             x3_prev: x3,
+            multiplication: Multiplication::default(),
             _p: PhantomData,
             _cf: PhantomData,
-        };
-        Ok(acc)
+        }
+    }
+
+    // `next`, keeping the multiplication of the accumulator.
+    fn step(&self, p: NonZeroAffineVarGeneric<P, F, CF>, lambda: F, x3: F) -> Self {
+        Self::next(p, lambda, x3).with_multiplication(self.multiplication)
+    }
+}
+
+// Native field impl.
+impl<F: PrimeField, P: SWCurveConfig<BaseField=F>> SumAccumulator<P, FpVar<F>, F> {
+    fn add_native(&self, p: NonZeroAffineVarGeneric<P, FpVar<F>, F>) -> Result<Self, SynthesisError> {
+        let y3_prev = &self.lambda_prev * (&self.x1_prev - &self.x3_prev) - &self.y1_prev;
+        let lambda = (&p.y - y3_prev).mul_by_inverse_unchecked(&(&p.x - &self.x3_prev))?;
+        let x3 = lambda.square()? - &self.x3_prev - &p.x;
+        Ok(self.step(p, lambda, x3))
+    }
+
+    fn conditional_add_native(&self, p: NonZeroAffineVarGeneric<P, FpVar<F>, F>, b: &Boolean<F>) -> Result<Self, SynthesisError> {
+        let y3_prev = &self.lambda_prev * (&self.x1_prev - &self.x3_prev) - &self.y1_prev;
+        let y3_prev = b.select(&y3_prev, &y3_prev.negate()?)?;
+        let lambda = (&p.y - y3_prev).mul_by_inverse_unchecked(&(&p.x - &self.x3_prev))?;
+        let x3 = b.select(&(lambda.square()? - &self.x3_prev - &p.x), &self.x3_prev)?;
+        Ok(self.step(p, lambda, x3))
     }
 }
 
@@ -103,46 +216,264 @@ impl<F: PrimeField, P: SWCurveConfig<BaseField=F>> SumAccumulator<P, FpVar<F>, F
 // `lambda  =  (lambda_prev * (x3_prev - x1_prev) + y1_prev + y)  /  (x - x3_prev)` that requires `2` reductions.
 // Instead we will prove  `lambda * (x - x3_prev) + (lambda_prev * (x1_prev - x3_prev)) - y1_prev - y  =  0`.
 impl<F: PrimeField, P: SWCurveConfig<BaseField=F>, CF: PrimeField> SumAccumulator<P, NonNativeFieldVar<F, CF>, CF> {
-    fn add(&self, p: NonZeroAffineVarGeneric<P, NonNativeFieldVar<F, CF>, CF>) -> Result<Self, SynthesisError> {
-        // let numerator = &self.lambda_prev * (&self.x3_prev - &self.x1_prev) + &self.y1_prev + &p.y;
-        // let denominator = &p.x - &self.x3_prev;
-        // assert!(!denominator.value()?.is_zero());
-        // let lambda = numerator.mul_by_inverse_unchecked(&denominator)?;
-        //
-        //
-        assert_ne!(p.x, self.x3_prev);
-        let lambda = (self.lambda_prev.value()? * (self.x3_prev.value()? - self.x1_prev.value()?) + self.y1_prev.value()? + p.y.value()?)
-            / (p.x.value()? - self.x3_prev.value()?);
-        let lambda = NonNativeFieldVar::<F, CF>::new_witness(ns!(p.cs(), "lambda"), || Ok(lambda))?;
-
-        let prod1 = lambda.mul_without_reduce(&(&p.x - &self.x3_prev))?;
-        let prod2 = self.lambda_prev.mul_without_reduce(&(&self.x1_prev - &self.x3_prev))?;
-        let zero = (prod1 + prod2).reduce()? - &self.y1_prev - &p.y;
+    fn add_emulated(&self, p: NonZeroAffineVarGeneric<P, NonNativeFieldVar<F, CF>, CF>) -> Result<Self, SynthesisError> {
+        let lambda = self.slope_emulated(&p, &(&self.x1_prev - &self.x3_prev), &self.y1_prev)?;
+        let x3 = lambda.square()? - &self.x3_prev - &p.x;
+        Ok(self.step(p, lambda, x3))
+    }
+
+    fn conditional_add_emulated(&self, p: NonZeroAffineVarGeneric<P, NonNativeFieldVar<F, CF>, CF>, b: &Boolean<CF>) -> Result<Self, SynthesisError> {
+        let dx = &self.x1_prev - &self.x3_prev;
+        let dx = b.select(&dx, &dx.negate()?)?;
+        let y1 = b.select(&self.y1_prev, &self.y1_prev.negate()?)?;
+        let lambda = self.slope_emulated(&p, &dx, &y1)?;
+        let x3 = b.select(&(lambda.square()? - &self.x3_prev - &p.x), &self.x3_prev)?;
+        Ok(self.step(p, lambda, x3))
+    }
+
+    // The slope of the line through `p` and `(x3_prev, lambda_prev * dx - y1)`, the partial sum for `dx = x1_prev - x3_prev`
+    // and `y1 = y1_prev`, and its opposite for both negated.
+    fn slope_emulated(&self, p: &NonZeroAffineVarGeneric<P, NonNativeFieldVar<F, CF>, CF>, dx: &NonNativeFieldVar<F, CF>, y1: &NonNativeFieldVar<F, CF>) -> Result<NonNativeFieldVar<F, CF>, SynthesisError> {
+        let cs = p.cs();
+        // Computed in the closure, as the values are missing during the setup.
+        // `0` for `x = x3_prev`, as `mul_by_inverse_unchecked` does, that `add_checked` makes unsatisfiable.
+        let lambda = NonNativeFieldVar::<F, CF>::new_witness(ns!(cs, "lambda"), || {
+            Ok((p.y.value()? - self.lambda_prev.value()? * dx.value()? + y1.value()?)
+                * (p.x.value()? - self.x3_prev.value()?).inverse().unwrap_or_default())
+        })?;
+
+        let prod1 = limb_mul::mul_without_reduce(&lambda, &(&p.x - &self.x3_prev), self.multiplication)?;
+        let prod2 = limb_mul::mul_without_reduce(&self.lambda_prev, dx, self.multiplication)?;
+        let zero = (prod1 + prod2).reduce()? - y1 - &p.y;
         zero.enforce_equal(&NonNativeFieldVar::zero())?;
+        Ok(lambda)
+    }
+}
 
-        let x3 = lambda.square()? - &self.x3_prev - &p.x;
+/// Field variables the accumulator has an `add` for, the best one for the field.
+pub trait AccumulatorField<P: SWCurveConfig, CF: Field>: FieldVar<P::BaseField, CF> + Sized {
+    fn accumulate(acc: &SumAccumulator<P, Self, CF>, p: NonZeroAffineVarGeneric<P, Self, CF>) -> Result<SumAccumulator<P, Self, CF>, SynthesisError>;
 
-        let acc = Self {
-            x1_prev: p.x,
-            y1_prev: p.y,
-            lambda_prev: lambda,
-            x3_prev: x3,
-            _p: PhantomData,
-            _cf: PhantomData,
+    fn conditional_accumulate(acc: &SumAccumulator<P, Self, CF>, p: NonZeroAffineVarGeneric<P, Self, CF>, b: &Boolean<CF>) -> Result<SumAccumulator<P, Self, CF>, SynthesisError>;
+}
+
+impl<F: PrimeField, P: SWCurveConfig<BaseField=F>> AccumulatorField<P, F> for FpVar<F> {
+    fn accumulate(acc: &SumAccumulator<P, Self, F>, p: NonZeroAffineVarGeneric<P, Self, F>) -> Result<SumAccumulator<P, Self, F>, SynthesisError> {
+        acc.add_native(p)
+    }
+
+    fn conditional_accumulate(acc: &SumAccumulator<P, Self, F>, p: NonZeroAffineVarGeneric<P, Self, F>, b: &Boolean<F>) -> Result<SumAccumulator<P, Self, F>, SynthesisError> {
+        acc.conditional_add_native(p, b)
+    }
+}
+
+impl<F: PrimeField, P: SWCurveConfig<BaseField=F>, CF: PrimeField> AccumulatorField<P, CF> for NonNativeFieldVar<F, CF> {
+    fn accumulate(acc: &SumAccumulator<P, Self, CF>, p: NonZeroAffineVarGeneric<P, Self, CF>) -> Result<SumAccumulator<P, Self, CF>, SynthesisError> {
+        acc.add_emulated(p)
+    }
+
+    fn conditional_accumulate(acc: &SumAccumulator<P, Self, CF>, p: NonZeroAffineVarGeneric<P, Self, CF>, b: &Boolean<CF>) -> Result<SumAccumulator<P, Self, CF>, SynthesisError> {
+        acc.conditional_add_emulated(p, b)
+    }
+}
+
+/// A way of summing points one by one, each added or not by a bit, as the apk circuit does with the keys,
+/// see `fragments::aggregate_with`: by `NonZeroAffineVarGeneric` itself, selecting the sum of `add_unchecked`
+/// as `AdditionStrategy::Incomplete` does, or by `SumAccumulator`, as `AdditionStrategy::Accumulated` does.
+pub trait PointSummation<P, F, CF>: Sized
+    where P: SWCurveConfig,
+          CF: Field,
+          F: FieldVar<P::BaseField, CF>,
+{
+    /// The strategy that sums the keys of the apk circuit this way, see `ApkCircuit::with_summation`.
+    const ADDITION: AdditionStrategy;
+
+    /// The sum of the single point.
+    fn init(p: NonZeroAffineVarGeneric<P, F, CF>) -> Result<Self, SynthesisError>;
+
+    /// Adds the point if `b` is set.
+    fn conditional_add(&self, p: &NonZeroAffineVarGeneric<P, F, CF>, b: &Boolean<CF>) -> Result<Self, SynthesisError>;
+
+    fn finalize(self) -> Result<NonZeroAffineVarGeneric<P, F, CF>, SynthesisError>;
+}
+
+impl<P, F, CF> PointSummation<P, F, CF> for NonZeroAffineVarGeneric<P, F, CF>
+    where P: SWCurveConfig,
+          CF: Field,
+          F: FieldVar<P::BaseField, CF>,
+          for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+{
+    const ADDITION: AdditionStrategy = AdditionStrategy::Incomplete;
+
+    fn init(p: NonZeroAffineVarGeneric<P, F, CF>) -> Result<Self, SynthesisError> {
+        Ok(p)
+    }
+
+    fn conditional_add(&self, p: &NonZeroAffineVarGeneric<P, F, CF>, b: &Boolean<CF>) -> Result<Self, SynthesisError> {
+        Self::conditionally_select(b, &self.add_unchecked(p)?, self)
+    }
+
+    fn finalize(self) -> Result<NonZeroAffineVarGeneric<P, F, CF>, SynthesisError> {
+        Ok(self)
+    }
+}
+
+impl<P, F, CF> PointSummation<P, F, CF> for SumAccumulator<P, F, CF>
+    where P: SWCurveConfig,
+          CF: Field,
+          F: AccumulatorField<P, CF>,
+          for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+{
+    const ADDITION: AdditionStrategy = AdditionStrategy::Accumulated;
+
+    fn init(p: NonZeroAffineVarGeneric<P, F, CF>) -> Result<Self, SynthesisError> {
+        Self::from_point(p)
+    }
+
+    fn conditional_add(&self, p: &NonZeroAffineVarGeneric<P, F, CF>, b: &Boolean<CF>) -> Result<Self, SynthesisError> {
+        F::conditional_accumulate(self, p.clone(), b)
+    }
+
+    fn finalize(self) -> Result<NonZeroAffineVarGeneric<P, F, CF>, SynthesisError> {
+        SumAccumulator::finalize(self)
+    }
+}
+
+/// The sum of the points, with `SumAccumulator` from two of them on.
+/// The additions are incomplete: a partial sum sharing `x` with the next point makes the result wrong.
+/// Fails with `SynthesisError::Unsatisfiable` on no points, as the sum isn't a non-zero point then.
+pub fn sum_points<P, F, CF, T>(points: impl IntoIterator<Item=T>) -> Result<NonZeroAffineVarGeneric<P, F, CF>, SynthesisError>
+    where P: SWCurveConfig,
+          CF: Field,
+          F: AccumulatorField<P, CF>,
+          for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+          T: Borrow<NonZeroAffineVarGeneric<P, F, CF>>,
+{
+    sum_points_with(points, false)
+}
+
+/// `sum_points` with each addition enforced to be of points with distinct `x`, see `SumAccumulator::add_checked`,
+/// so the proof fails rather than the result being wrong, for points the prover has a hand in.
+pub fn sum_points_checked<P, F, CF, T>(points: impl IntoIterator<Item=T>) -> Result<NonZeroAffineVarGeneric<P, F, CF>, SynthesisError>
+    where P: SWCurveConfig,
+          CF: Field,
+          F: AccumulatorField<P, CF>,
+          for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+          T: Borrow<NonZeroAffineVarGeneric<P, F, CF>>,
+{
+    sum_points_with(points, true)
+}
+
+fn sum_points_with<P, F, CF, T>(points: impl IntoIterator<Item=T>, checked: bool) -> Result<NonZeroAffineVarGeneric<P, F, CF>, SynthesisError>
+    where P: SWCurveConfig,
+          CF: Field,
+          F: AccumulatorField<P, CF>,
+          for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+          T: Borrow<NonZeroAffineVarGeneric<P, F, CF>>,
+{
+    let mut points = points.into_iter().map(|p| p.borrow().clone());
+    let first = points.next().ok_or(SynthesisError::Unsatisfiable)?;
+    let Some(second) = points.next() else {
+        return Ok(first);
+    };
+    let mut acc = if checked { SumAccumulator::init_checked(first, second)? } else { SumAccumulator::init(first, second)? };
+    for p in points {
+        acc = if checked { acc.add_checked(p)? } else { F::accumulate(&acc, p)? };
+    }
+    acc.finalize()
+}
+
+/// The sum of the points, added in pairs level by level, an odd point being carried over to the next level,
+/// rather than into a single accumulator as `sum_points` does. The additions of a level don't depend on each other,
+/// and the sums of the first levels are those of fixed ranges of the points, so they can be computed in parallel
+/// and shared by the sums of the sets having these ranges in common. The slopes of a level take a single inversion
+/// to compute, see `batch_div`, unlike the steps of `SumAccumulator`, checked or not. That only changes how the prover
+/// computes the witnesses: nothing changes in the circuit, the constraints being those of the separate additions.
+/// As many constraints natively as `sum_points`, but more for emulated fields, where `SumAccumulator` saves a reduction
+/// per point.
+/// Incomplete, and fails with `SynthesisError::Unsatisfiable` on no points, as `sum_points`.
+pub fn sum_points_tree<P, F, CF, T>(points: impl IntoIterator<Item=T>) -> Result<NonZeroAffineVarGeneric<P, F, CF>, SynthesisError>
+    where P: SWCurveConfig,
+          CF: PrimeField,
+          F: FieldVar<P::BaseField, CF>,
+          for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+          T: Borrow<NonZeroAffineVarGeneric<P, F, CF>>,
+{
+    sum_points_tree_with(points, false)
+}
+
+/// `sum_points_tree` with each addition enforced to be of points with distinct `x`, see `sum_points_checked`.
+pub fn sum_points_tree_checked<P, F, CF, T>(points: impl IntoIterator<Item=T>) -> Result<NonZeroAffineVarGeneric<P, F, CF>, SynthesisError>
+    where P: SWCurveConfig,
+          CF: PrimeField,
+          F: FieldVar<P::BaseField, CF>,
+          for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+          T: Borrow<NonZeroAffineVarGeneric<P, F, CF>>,
+{
+    sum_points_tree_with(points, true)
+}
+
+fn sum_points_tree_with<P, F, CF, T>(points: impl IntoIterator<Item=T>, checked: bool) -> Result<NonZeroAffineVarGeneric<P, F, CF>, SynthesisError>
+    where P: SWCurveConfig,
+          CF: PrimeField,
+          F: FieldVar<P::BaseField, CF>,
+          for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+          T: Borrow<NonZeroAffineVarGeneric<P, F, CF>>,
+{
+    let mut level: Vec<_> = points.into_iter().map(|p| p.borrow().clone()).collect();
+    while level.len() > 1 {
+        // The slopes of a level are independent, so their witnesses share an inversion.
+        let fractions: Vec<_> = level.chunks_exact(2).map(|pair| pair[0].slope_fraction(&pair[1])).collect();
+        let slopes = if checked {
+            // `n * (1 / d)`, with `d * (1 / d) = 1` enforcing `d` to be non-zero, as `add_checked` does.
+            let denominators: Vec<_> = fractions.iter().map(|(_, d)| d.clone()).collect();
+            if denominators.iter().any(|d| d.is_constant() && d.value().is_ok_and(|d| d.is_zero())) {
+                return Err(SynthesisError::Unsatisfiable);
+            }
+            batch_inverse(&denominators)?.iter().zip(&fractions).map(|(d_inv, (n, _))| n * d_inv).collect()
+        } else {
+            batch_div(&fractions)?
         };
-        Ok(acc)
+        level = level.chunks(2).enumerate().map(|(i, pair)| match pair {
+            [p1, p2] => p1.add_with_slope(p2, &slopes[i]),
+            [p] => Ok(p.clone()),
+            _ => unreachable!("chunks of 2"),
+        }).collect::<Result<_, _>>()?;
     }
+    level.pop().ok_or(SynthesisError::Unsatisfiable)
+}
+
+/// `keys.iter().sum_points()`, see `sum_points`.
+pub trait SumPoints<P, F, CF>: Iterator + Sized
+    where P: SWCurveConfig,
+          CF: Field,
+          F: AccumulatorField<P, CF>,
+          for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+{
+    fn sum_points(self) -> Result<NonZeroAffineVarGeneric<P, F, CF>, SynthesisError>;
 }
 
+impl<I, P, F, CF> SumPoints<P, F, CF> for I
+    where I: Iterator,
+          I::Item: Borrow<NonZeroAffineVarGeneric<P, F, CF>>,
+          P: SWCurveConfig,
+          CF: Field,
+          F: AccumulatorField<P, CF>,
+          for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+{
+    fn sum_points(self) -> Result<NonZeroAffineVarGeneric<P, F, CF>, SynthesisError> {
+        sum_points(self)
+    }
+}
 
 #[cfg(test)]
 mod tests {
-    use ark_bls12_381;
-    use ark_ec::CurveGroup;
+    use ark_ec::short_weierstrass::{Affine, Projective};
+    use ark_ec::{AffineRepr, CurveGroup};
     use ark_r1cs_std::alloc::AllocVar;
     use ark_r1cs_std::R1CSVar;
     use ark_relations::ns;
-    use ark_relations::r1cs::ConstraintSystem;
+    use ark_relations::r1cs::{ConstraintSystem, ConstraintSystemRef, SynthesisMode};
     use ark_std::{test_rng, UniformRand};
 
     use crate::tests::{BlsInBls, Tracker};
@@ -162,7 +493,9 @@ mod tests {
         for key in key_vars {
             acc = acc.add(key).unwrap();
         }
+        let display = acc.to_string();
         let sum = acc.finalize().unwrap();
+        assert_eq!(sum.to_string(), display + " derived");
         println!("summing {} native points: {:?}", n, tracker.update(&cs));
         assert_eq!(sum.value().unwrap(), keys.iter().sum::<ark_bls12_377::G1Projective>().into_affine());
         assert!(cs.is_satisfied().unwrap());
@@ -186,4 +519,262 @@ mod tests {
         assert_eq!(sum.value().unwrap(), keys.iter().sum::<ark_bls12_381::G1Projective>().into_affine());
         assert!(cs.is_satisfied().unwrap());
     }
+
+    #[test]
+    fn test_sum_points() {
+        let rng = &mut test_rng();
+        let cs = ConstraintSystem::<ark_bls12_381::Fr>::new_ref();
+        let keys: Vec<ark_bls12_381::G1Affine> = (0..4).map(|_| ark_bls12_381::G1Affine::rand(rng)).collect();
+        let key_vars = Vec::<NonZeroAffineVarGeneric<_, BlsInBls, _>>::new_witness(ns!(cs, "keys"), || Ok(keys.clone())).unwrap();
+        for n in 1..=4 {
+            let sum = key_vars[..n].iter().sum_points().unwrap();
+            assert_eq!(sum.value().unwrap(), keys[..n].iter().sum::<ark_bls12_381::G1Projective>().into_affine());
+            assert_eq!(SumAccumulator::sum(&key_vars[..n]).unwrap().value().unwrap(), sum.value().unwrap());
+        }
+        assert!(cs.is_satisfied().unwrap());
+        assert!(matches!(key_vars[..0].iter().sum_points(), Err(SynthesisError::Unsatisfiable)));
+        assert!(matches!(SumAccumulator::sum(&key_vars[..0]), Err(SynthesisError::Unsatisfiable)));
+
+        let cs = ConstraintSystem::<ark_bw6_761::Fr>::new_ref();
+        let keys: Vec<ark_bls12_377::G1Affine> = (0..3).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+        let key_vars = Vec::<NonZeroAffineVarGeneric<_, FpVar<ark_bw6_761::Fr>, _>>::new_witness(ns!(cs, "keys"), || Ok(keys.clone())).unwrap();
+        let sum = sum_points(key_vars).unwrap();
+        assert_eq!(sum.value().unwrap(), keys.iter().sum::<ark_bls12_377::G1Projective>().into_affine());
+        assert!(cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn test_sum_points_tree() {
+        let rng = &mut test_rng();
+        let cs = ConstraintSystem::<ark_bw6_761::Fr>::new_ref();
+        let keys: Vec<ark_bls12_377::G1Affine> = (0..7).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+        let key_vars = Vec::<NonZeroAffineVarGeneric<_, FpVar<ark_bw6_761::Fr>, _>>::new_witness(ns!(cs, "keys"), || Ok(keys.clone())).unwrap();
+        for n in 1..=7 {
+            let sum = sum_points_tree(&key_vars[..n]).unwrap();
+            assert_eq!(sum.value().unwrap(), keys[..n].iter().sum::<ark_bls12_377::G1Projective>().into_affine());
+            assert_eq!(sum_points_tree_checked(&key_vars[..n]).unwrap().value().unwrap(), sum.value().unwrap());
+        }
+        assert!(cs.is_satisfied().unwrap());
+        assert!(matches!(sum_points_tree(&key_vars[..0]), Err(SynthesisError::Unsatisfiable)));
+
+        let mut tracker = Tracker::new(&cs);
+        let _ = sum_points_tree(&key_vars).unwrap();
+        let tree = tracker.update(&cs);
+        let _ = sum_points(&key_vars).unwrap();
+        let sequential = tracker.update(&cs);
+        println!("summing {} native points in a tree: {:?}, sequentially: {:?}", keys.len(), tree, sequential);
+        assert_eq!(tree.num_constraints, 3 * (keys.len() - 1));
+        assert_eq!(sequential.num_constraints, tree.num_constraints);
+        let _ = sum_points_tree_checked(&key_vars).unwrap();
+        let tree_checked = tracker.update(&cs);
+        let _ = key_vars.chunks(2).map(|pair| match pair {
+            [p1, p2] => p1.add_checked(p2).map(|_| ()),
+            _ => Ok(()),
+        }).collect::<Result<Vec<_>, _>>().unwrap();
+        let pairs_checked = tracker.update(&cs);
+        assert_eq!(tree_checked.num_constraints, 4 * (keys.len() - 1));
+        assert_eq!(pairs_checked.num_constraints, 4 * (keys.len() / 2));
+
+        let cs = ConstraintSystem::<ark_bls12_381::Fr>::new_ref();
+        let keys: Vec<ark_bls12_381::G1Affine> = (0..3).map(|_| ark_bls12_381::G1Affine::rand(rng)).collect();
+        let key_vars = Vec::<NonZeroAffineVarGeneric<_, BlsInBls, _>>::new_witness(ns!(cs, "keys"), || Ok(keys.clone())).unwrap();
+        let mut tracker = Tracker::new(&cs);
+        let sum = sum_points_tree(&key_vars).unwrap();
+        let tree = tracker.update(&cs);
+        assert_eq!(sum.value().unwrap(), keys.iter().sum::<ark_bls12_381::G1Projective>().into_affine());
+        let _ = sum_points(&key_vars).unwrap();
+        assert!(tracker.update(&cs).num_constraints < tree.num_constraints);
+        assert!(cs.is_satisfied().unwrap());
+        // A point equal to the sum of a pair, that `sum_points_tree` would add wrong.
+        let cs = ConstraintSystem::<ark_bw6_761::Fr>::new_ref();
+        let keys: Vec<ark_bls12_377::G1Affine> = (0..2).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+        let keys = vec![keys[0], keys[1], (keys[0] + keys[1]).into_affine()];
+        let key_vars = Vec::<NonZeroAffineVarGeneric<_, FpVar<ark_bw6_761::Fr>, _>>::new_witness(ns!(cs, "keys"), || Ok(keys)).unwrap();
+        let _ = sum_points_tree_checked(key_vars).unwrap();
+        assert!(!cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn test_sum_points_checked() {
+        let rng = &mut test_rng();
+        let keys: Vec<ark_bls12_381::G1Affine> = (0..3).map(|_| ark_bls12_381::G1Affine::rand(rng)).collect();
+        let check = |keys: Vec<ark_bls12_381::G1Affine>| {
+            let cs = ConstraintSystem::<ark_bls12_381::Fr>::new_ref();
+            let key_vars = Vec::<NonZeroAffineVarGeneric<_, BlsInBls, _>>::new_witness(ns!(cs, "keys"), || Ok(keys.clone())).unwrap();
+            let sum = sum_points_checked(&key_vars).unwrap();
+            let satisfied = cs.is_satisfied().unwrap();
+            if satisfied {
+                assert_eq!(sum.value().unwrap(), keys.iter().sum::<ark_bls12_381::G1Projective>().into_affine());
+            }
+            satisfied
+        };
+        assert!(check(keys.clone()));
+        // Doubling, and a point equal to the partial sum before it.
+        assert!(!check(vec![keys[0], keys[0]]));
+        assert!(!check(vec![keys[0], keys[1], (keys[0] + keys[1]).into_affine()]));
+
+        let cs = ConstraintSystem::<ark_bw6_761::Fr>::new_ref();
+        let keys: Vec<ark_bls12_377::G1Affine> = (0..3).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+        let key_vars = Vec::<NonZeroAffineVarGeneric<_, FpVar<ark_bw6_761::Fr>, _>>::new_witness(ns!(cs, "keys"), || Ok(keys.clone())).unwrap();
+        let mut acc = SumAccumulator::init_checked(key_vars[0].clone(), key_vars[1].clone()).unwrap();
+        acc = acc.add_checked(key_vars[2].clone()).unwrap();
+        assert_eq!(acc.finalize().unwrap().value().unwrap(), keys.iter().sum::<ark_bls12_377::G1Projective>().into_affine());
+        assert!(cs.is_satisfied().unwrap());
+    }
+
+    fn check_add_or_double<P, F, CF>(keys: &[Affine<P>])
+        where P: SWCurveConfig,
+              P::BaseField: PrimeField,
+              CF: PrimeField,
+              F: AccumulatorField<P, CF>,
+              for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+    {
+        let check = |points: Vec<Affine<P>>| {
+            let cs = ConstraintSystem::<CF>::new_ref();
+            let point_vars = Vec::<NonZeroAffineVarGeneric<P, F, CF>>::new_witness(ns!(cs, "points"), || Ok(points.clone())).unwrap();
+            let mut acc = SumAccumulator::init(point_vars[0].clone(), point_vars[1].clone()).unwrap();
+            for p in &point_vars[2..] {
+                acc = acc.add_or_double(p.clone()).unwrap();
+            }
+            let sum = acc.finalize().unwrap();
+            let satisfied = cs.is_satisfied().unwrap();
+            if satisfied {
+                assert_eq!(sum.value().unwrap(), points.iter().sum::<Projective<P>>().into_affine());
+            }
+            satisfied
+        };
+        let (k0, k1, k2) = (keys[0], keys[1], keys[2]);
+        let s01 = (k0 + k1).into_affine();
+        assert!(check(vec![k0, k1, k2]));
+        // The point equal to the partial sum, then a repeated key.
+        assert!(check(vec![k0, k1, s01, k0]));
+        assert!(check(vec![k0, k1, k2, (k0 + k1 + k2).into_affine(), k2]));
+        // The point opposite to the partial sum.
+        assert!(!check(vec![k0, k1, -s01]));
+    }
+
+    #[test]
+    fn test_add_or_double() {
+        let rng = &mut test_rng();
+        let keys: Vec<ark_bls12_377::G1Affine> = (0..3).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+        check_add_or_double::<_, FpVar<ark_bw6_761::Fr>, _>(&keys);
+        let keys: Vec<ark_bls12_381::G1Affine> = (0..3).map(|_| ark_bls12_381::G1Affine::rand(rng)).collect();
+        check_add_or_double::<_, BlsInBls, _>(&keys);
+    }
+
+    fn check_conditional_add<P, F, CF>(seed: Affine<P>, keys: &[Affine<P>], bits: &[bool])
+        where P: SWCurveConfig,
+              P::BaseField: PrimeField,
+              CF: PrimeField,
+              F: AccumulatorField<P, CF>,
+              for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+    {
+        let cs = ConstraintSystem::<CF>::new_ref();
+        let seed_var = NonZeroAffineVarGeneric::<P, F, CF>::new_witness(ns!(cs, "seed"), || Ok(seed)).unwrap();
+        let key_vars = Vec::<NonZeroAffineVarGeneric<P, F, CF>>::new_witness(ns!(cs, "keys"), || Ok(keys.to_vec())).unwrap();
+        let bit_vars = Vec::<Boolean<CF>>::new_witness(ns!(cs, "bits"), || Ok(bits.to_vec())).unwrap();
+        let mut tracker = Tracker::new(&cs);
+        let mut acc = SumAccumulator::from_point(seed_var.clone()).unwrap();
+        for (key, b) in key_vars.iter().zip(&bit_vars) {
+            acc = acc.conditional_add(key.clone(), b).unwrap();
+        }
+        let sum = acc.finalize().unwrap();
+        let cost = tracker.update(&cs);
+        let expected = keys.iter().zip(bits).filter(|(_, b)| **b).fold(seed.into_group(), |acc, (key, _)| acc + key);
+        assert_eq!(sum.value().unwrap(), expected.into_affine());
+        assert!(cs.is_satisfied().unwrap());
+        let mut selected = seed_var;
+        for (key, b) in key_vars.iter().zip(&bit_vars) {
+            selected = NonZeroAffineVarGeneric::conditionally_select(b, &selected.add_unchecked(key).unwrap(), &selected).unwrap();
+        }
+        let cost_selected = tracker.update(&cs);
+        println!("conditionally adding {} points: {:?}, selecting the sums: {:?}", keys.len(), cost, cost_selected);
+        assert!(cost.num_constraints <= cost_selected.num_constraints);
+    }
+
+    #[test]
+    fn test_conditional_add() {
+        let rng = &mut test_rng();
+        let bits = [true, false, false, true, true, false];
+        let seed = ark_bls12_377::G1Affine::rand(rng);
+        let keys: Vec<ark_bls12_377::G1Affine> = (0..bits.len()).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+        check_conditional_add::<_, FpVar<ark_bw6_761::Fr>, _>(seed, &keys, &bits);
+        // The opposite of the partial sum, of an unset bit, that `add_unchecked` can't add.
+        check_conditional_add::<_, FpVar<ark_bw6_761::Fr>, _>(seed, &[-seed, keys[0]], &[false, true]);
+        let seed = ark_bls12_381::G1Affine::rand(rng);
+        let keys: Vec<ark_bls12_381::G1Affine> = (0..bits.len()).map(|_| ark_bls12_381::G1Affine::rand(rng)).collect();
+        check_conditional_add::<_, BlsInBls, _>(seed, &keys, &bits);
+    }
+
+    // Synthesizes the sum of `n` witness keys in the setup mode, in the proving mode, and in the proving mode without the matrices,
+    // as the prover generates the witness, and returns the numbers of constraints of the first two.
+    fn setup_and_prove<CF: PrimeField>(n: usize, sum: impl Fn(ConstraintSystemRef<CF>, usize) -> Result<(), SynthesisError>) -> (usize, usize) {
+        let setup = ConstraintSystem::<CF>::new_ref();
+        setup.set_mode(SynthesisMode::Setup);
+        sum(setup.clone(), n).unwrap();
+        let prove = ConstraintSystem::<CF>::new_ref();
+        sum(prove.clone(), n).unwrap();
+        assert!(prove.is_satisfied().unwrap());
+        let witness = ConstraintSystem::<CF>::new_ref();
+        witness.set_mode(SynthesisMode::Prove { construct_matrices: false });
+        sum(witness.clone(), n).unwrap();
+        assert_eq!(witness.borrow().unwrap().witness_assignment, prove.borrow().unwrap().witness_assignment);
+        (setup.num_constraints(), prove.num_constraints())
+    }
+
+    #[test]
+    fn test_setup_mode() {
+        let (setup, prove) = setup_and_prove(5, |cs: ConstraintSystemRef<ark_bw6_761::Fr>, n| {
+            let rng = &mut test_rng();
+            let keys: Vec<ark_bls12_377::G1Affine> = (0..n).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+            let mut key_vars = Vec::<NonZeroAffineVarGeneric<_, FpVar<ark_bw6_761::Fr>, _>>::new_witness(ns!(cs, "keys"), || Ok(keys))?.into_iter();
+            let mut acc = SumAccumulator::init(key_vars.next().unwrap(), key_vars.next().unwrap())?;
+            for key in key_vars {
+                acc = acc.add(key)?;
+            }
+            acc.finalize().map(|_| ())
+        });
+        assert_eq!(setup, prove);
+
+        let (setup, prove) = setup_and_prove(5, |cs: ConstraintSystemRef<ark_bls12_381::Fr>, n| {
+            let rng = &mut test_rng();
+            let keys: Vec<ark_bls12_381::G1Affine> = (0..n).map(|_| ark_bls12_381::G1Affine::rand(rng)).collect();
+            let mut key_vars = Vec::<NonZeroAffineVarGeneric<_, BlsInBls, _>>::new_witness(ns!(cs, "keys"), || Ok(keys))?.into_iter();
+            let mut acc = SumAccumulator::init(key_vars.next().unwrap(), key_vars.next().unwrap())?;
+            for key in key_vars {
+                acc = acc.add(key)?;
+            }
+            acc.finalize().map(|_| ())
+        });
+        assert_eq!(setup, prove);
+
+        let (setup, prove) = setup_and_prove(5, |cs: ConstraintSystemRef<ark_bw6_761::Fr>, n| {
+            let rng = &mut test_rng();
+            let keys: Vec<ark_bls12_377::G1Affine> = (0..n).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+            let key_vars = Vec::<NonZeroAffineVarGeneric<_, FpVar<ark_bw6_761::Fr>, _>>::new_witness(ns!(cs, "keys"), || Ok(keys))?;
+            sum_points_checked(key_vars).map(|_| ())
+        });
+        assert_eq!(setup, prove);
+
+        let (setup, prove) = setup_and_prove(5, |cs: ConstraintSystemRef<ark_bw6_761::Fr>, n| {
+            let rng = &mut test_rng();
+            let keys: Vec<ark_bls12_377::G1Affine> = (0..n).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+            let key_vars = Vec::<NonZeroAffineVarGeneric<_, FpVar<ark_bw6_761::Fr>, _>>::new_witness(ns!(cs, "keys"), || Ok(keys))?;
+            sum_points_tree(key_vars).map(|_| ())
+        });
+        assert_eq!(setup, prove);
+
+        let (setup, prove) = setup_and_prove(3, |cs: ConstraintSystemRef<ark_bls12_381::Fr>, n| {
+            let rng = &mut test_rng();
+            let keys: Vec<ark_bls12_381::G1Affine> = (0..n).map(|_| ark_bls12_381::G1Affine::rand(rng)).collect();
+            let key_vars = Vec::<NonZeroAffineVarGeneric<_, BlsInBls, _>>::new_witness(ns!(cs, "keys"), || Ok(keys))?;
+            let bit_vars = Vec::<Boolean<_>>::new_witness(ns!(cs, "bits"), || Ok(vec![true, false, true]))?;
+            let mut acc = SumAccumulator::from_point(key_vars[0].clone())?;
+            for (key, b) in key_vars[1..].iter().zip(&bit_vars) {
+                acc = acc.conditional_add(key.clone(), b)?;
+            }
+            acc.finalize().map(|_| ())
+        });
+        assert_eq!(setup, prove);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,254 @@
+//! Points of twisted Edwards curves, `a * x^2 + y^2 = 1 + d * x^2 * y^2`, as Jubjub and Bandersnatch have them,
+//! over the base field natively or emulated, as `NonZeroAffineVarGeneric`.
+//!
+//! The addition is complete for the curves with a square `a` and a non-square `d`, as Jubjub: its denominators can't be zero,
+//! and the identity `(0, 1)` is a point as any other. So the keys sum from the identity, with no seed
+//! and none of the special cases of the short Weierstrass additions, for 6 multiplications.
+//! The `a = -5` of Bandersnatch isn't a square, and its exceptional points are out of the prime-order subgroup:
+//! the addition is complete on the keys, as long as they're checked to be in the subgroup.
+
+use std::borrow::Borrow;
+use std::marker::PhantomData;
+
+use ark_ec::twisted_edwards::{Affine, TECurveConfig};
+use ark_r1cs_std::alloc::{AllocationMode, AllocVar};
+use ark_r1cs_std::boolean::Boolean;
+use ark_r1cs_std::eq::EqGadget;
+use ark_r1cs_std::fields::{FieldOpsBounds, FieldVar};
+use ark_r1cs_std::R1CSVar;
+use ark_r1cs_std::select::CondSelectGadget;
+use ark_relations::r1cs::{ConstraintSystemRef, Field, Namespace, SynthesisError};
+use derivative::Derivative;
+
+use crate::labels::labeled;
+
+/// A point of a twisted Edwards curve, the identity included, see the module.
+/// Named after `NonZeroAffineVarGeneric`, as no flag is needed for the identity.
+#[derive(Derivative)]
+#[derivative(Clone)]
+#[must_use]
+pub struct NonZeroTEAffineVarGeneric<P, F, CF>
+    where
+        P: TECurveConfig,
+        CF: Field,
+        F: FieldVar<P::BaseField, CF>,
+{
+    pub x: F,
+    pub y: F,
+    _p: PhantomData<P>,
+    _cf: PhantomData<CF>,
+}
+
+impl<P, F, CF> NonZeroTEAffineVarGeneric<P, F, CF>
+    where
+        P: TECurveConfig,
+        CF: Field,
+        F: FieldVar<P::BaseField, CF>,
+        for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+{
+    pub fn new(x: F, y: F) -> Self {
+        Self { x, y, _p: PhantomData, _cf: PhantomData }
+    }
+
+    /// The identity `(0, 1)`, a constant.
+    pub fn zero() -> Self {
+        Self::new(F::zero(), F::one())
+    }
+
+    /// `-self`, `(-x, y)`, free: a linear combination.
+    pub fn negate(&self) -> Result<Self, SynthesisError> {
+        Ok(Self::new(self.x.negate()?, self.y.clone()))
+    }
+
+    /// Enforces `a * x^2 + y^2 = 1 + d * x^2 * y^2`, that nothing else checks of a witness point.
+    pub fn enforce_on_curve(&self) -> Result<(), SynthesisError> {
+        labeled(&self.cs(), "on_curve", || {
+            let xx = self.x.square()?;
+            let yy = self.y.square()?;
+            let lhs = xx.clone() * P::COEFF_A + &yy;
+            (xx * P::COEFF_D).mul_equals(&yy, &(lhs - P::BaseField::from(1u8)))
+        })
+    }
+
+    /// `self + other`, for any points of a curve with a complete addition, see the module:
+    /// `x3 = (x1 * y2 + y1 * x2) / (1 + d * x1 * x2 * y1 * y2)` and `y3 = (y1 * y2 - a * x1 * x2) / (1 - d * x1 * x2 * y1 * y2)`,
+    /// with `y1 * y2 - a * x1 * x2` as `(y1 - a * x1) * (x2 + y2) + a * x1 * y2 - y1 * x2`.
+    /// For a curve with an incomplete addition, the sums of the exceptional points are unsatisfiable, or of the choosing of the prover.
+    pub fn add(&self, other: &Self) -> Result<Self, SynthesisError> {
+        labeled(&self.cs().or(other.cs()), "te_add", || {
+            let u = (&self.y - self.x.clone() * P::COEFF_A) * &(&other.x + &other.y);
+            let v0 = &self.x * &other.y;
+            let v1 = &self.y * &other.x;
+            let v2 = &v0 * &v1 * P::COEFF_D;
+            let one = P::BaseField::from(1u8);
+            let x3 = (&v0 + &v1).mul_by_inverse_unchecked(&(&v2 + one))?;
+            let y3 = (u + v0 * P::COEFF_A - v1).mul_by_inverse_unchecked(&(F::one() - v2))?;
+            Ok(Self::new(x3, y3))
+        })
+    }
+
+    /// `self + self`, as `add`.
+    pub fn double(&self) -> Result<Self, SynthesisError> {
+        self.add(self)
+    }
+
+    /// The sum of the points, from the identity, for the identity of no points.
+    pub fn sum(points: &[Self]) -> Result<Self, SynthesisError> {
+        let cs = points.iter().fold(ConstraintSystemRef::None, |cs, p| cs.or(p.cs()));
+        labeled(&cs, "te_sum", || points.iter().try_fold(Self::zero(), |acc, p| acc.add(p)))
+    }
+
+    /// The sum of the keys for which the bit is set, as `aggregate_complete`: the keys of unset bits
+    /// are selected as the identity, and added as any point. Keys past the end of `bit_vars`,
+    /// and bits past the end of `key_vars`, are ignored.
+    pub fn aggregate(key_vars: &[Self], bit_vars: &[Boolean<CF>]) -> Result<Self, SynthesisError> {
+        let cs = key_vars.iter().fold(bit_vars.cs(), |cs, p| cs.or(p.cs()));
+        labeled(&cs, "acc", || {
+            bit_vars.iter().zip(key_vars).enumerate().try_fold(Self::zero(), |acc, (i, (b, key))| {
+                labeled(&cs, format_args!("step/{}", i), || acc.add(&Self::conditionally_select(b, key, &Self::zero())?))
+            })
+        })
+    }
+}
+
+impl<P, F, CF> AllocVar<Affine<P>, CF> for NonZeroTEAffineVarGeneric<P, F, CF>
+    where
+        P: TECurveConfig,
+        CF: Field,
+        F: FieldVar<P::BaseField, CF>,
+        for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+{
+    fn new_variable<T: Borrow<Affine<P>>>(cs: impl Into<Namespace<CF>>, f: impl FnOnce() -> Result<T, SynthesisError>, mode: AllocationMode) -> Result<Self, SynthesisError> {
+        let ns = cs.into();
+        let cs = ns.cs();
+        let point = f().map(|b| *b.borrow());
+        let x = labeled(&cs, "x", || F::new_variable(ark_relations::ns!(cs, "x"), || point.map(|p| p.x), mode))?;
+        let y = labeled(&cs, "y", || F::new_variable(ark_relations::ns!(cs, "y"), || point.map(|p| p.y), mode))?;
+        Ok(Self::new(x, y))
+    }
+}
+
+impl<P, F, CF> CondSelectGadget<CF> for NonZeroTEAffineVarGeneric<P, F, CF>
+    where
+        P: TECurveConfig,
+        CF: Field,
+        F: FieldVar<P::BaseField, CF>,
+        for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+{
+    fn conditionally_select(cond: &Boolean<CF>, true_value: &Self, false_value: &Self) -> Result<Self, SynthesisError> {
+        let x = cond.select(&true_value.x, &false_value.x)?;
+        let y = cond.select(&true_value.y, &false_value.y)?;
+        Ok(Self::new(x, y))
+    }
+}
+
+/// The same coordinates: the affine representation is unique.
+impl<P, F, CF> EqGadget<CF> for NonZeroTEAffineVarGeneric<P, F, CF>
+    where
+        P: TECurveConfig,
+        CF: Field,
+        F: FieldVar<P::BaseField, CF>,
+        for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+{
+    fn is_eq(&self, other: &Self) -> Result<Boolean<CF>, SynthesisError> {
+        self.x.is_eq(&other.x)?.and(&self.y.is_eq(&other.y)?)
+    }
+}
+
+impl<P, F, CF> R1CSVar<CF> for NonZeroTEAffineVarGeneric<P, F, CF>
+    where
+        P: TECurveConfig,
+        CF: Field,
+        F: FieldVar<P::BaseField, CF>,
+{
+    type Value = Affine<P>;
+
+    fn cs(&self) -> ConstraintSystemRef<CF> {
+        self.x.cs().or(self.y.cs())
+    }
+
+    fn value(&self) -> Result<Self::Value, SynthesisError> {
+        Ok(Affine::<P>::new_unchecked(self.x.value()?, self.y.value()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_ec::{AffineRepr, CurveGroup};
+    use ark_ed_on_bls12_381_bandersnatch::{BandersnatchConfig, EdwardsAffine, Fq};
+    use ark_ff::{Field, LegendreSymbol, PrimeField};
+    use ark_r1cs_std::fields::fp::FpVar;
+    use ark_r1cs_std::fields::nonnative::NonNativeFieldVar;
+    use ark_relations::ns;
+    use ark_relations::r1cs::ConstraintSystem;
+    use ark_std::{test_rng, UniformRand};
+
+    use crate::tests::Tracker;
+
+    use super::*;
+
+    fn check_te<P, F, CF>(rng: &mut impl ark_std::rand::Rng)
+        where P: TECurveConfig,
+              CF: PrimeField,
+              F: FieldVar<P::BaseField, CF>,
+              for<'a> &'a F: FieldOpsBounds<'a, P::BaseField, F>,
+    {
+        let (p, q) = (Affine::<P>::rand(rng), Affine::<P>::rand(rng));
+        let zero = Affine::<P>::zero();
+        for (a, b) in [(p, q), (p, p), (p, -p), (zero, p), (zero, zero)] {
+            let cs = ConstraintSystem::<CF>::new_ref();
+            let a_var = NonZeroTEAffineVarGeneric::<P, F, CF>::new_witness(ns!(cs, "a"), || Ok(a)).unwrap();
+            let b_var = NonZeroTEAffineVarGeneric::<P, F, CF>::new_witness(ns!(cs, "b"), || Ok(b)).unwrap();
+            assert_eq!(a_var.add(&b_var).unwrap().value().unwrap(), (a + b).into_affine());
+            assert_eq!(a_var.double().unwrap().value().unwrap(), (a + a).into_affine());
+            assert_eq!(a_var.negate().unwrap().value().unwrap(), -a);
+            a_var.enforce_on_curve().unwrap();
+            assert!(cs.is_satisfied().unwrap());
+        }
+
+        // Repeated keys, and a key and its negation.
+        let keys = [p, q, p, -q, q];
+        let bits = [true, true, true, true, false];
+        let cs = ConstraintSystem::<CF>::new_ref();
+        let key_vars = Vec::<NonZeroTEAffineVarGeneric<P, F, CF>>::new_witness(ns!(cs, "keys"), || Ok(keys.to_vec())).unwrap();
+        let bit_vars = Vec::<Boolean<CF>>::new_witness(ns!(cs, "bits"), || Ok(bits.to_vec())).unwrap();
+        let sum = NonZeroTEAffineVarGeneric::sum(&key_vars).unwrap();
+        assert_eq!(sum.value().unwrap(), (p + p + q).into_affine());
+        let apk = NonZeroTEAffineVarGeneric::aggregate(&key_vars, &bit_vars).unwrap();
+        assert_eq!(apk.value().unwrap(), (p + p).into_affine());
+        apk.enforce_equal(&NonZeroTEAffineVarGeneric::new_witness(ns!(cs, "apk"), || Ok((p + p).into_affine())).unwrap()).unwrap();
+        assert!(cs.is_satisfied().unwrap());
+
+        let cs = ConstraintSystem::<CF>::new_ref();
+        let off = Affine::<P>::new_unchecked(p.x, p.x);
+        NonZeroTEAffineVarGeneric::<P, F, CF>::new_witness(ns!(cs, "off"), || Ok(off)).unwrap().enforce_on_curve().unwrap();
+        assert!(!cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn test_te() {
+        // Not the square `a` of a complete addition: the points are in the prime-order subgroup, where `rand` samples them.
+        assert_eq!(BandersnatchConfig::COEFF_A.legendre(), LegendreSymbol::QuadraticNonResidue);
+
+        let rng = &mut test_rng();
+        check_te::<BandersnatchConfig, FpVar<Fq>, _>(rng);
+        check_te::<BandersnatchConfig, NonNativeFieldVar<Fq, ark_bw6_761::Fr>, _>(rng);
+    }
+
+    #[test]
+    fn test_te_cost() {
+        let rng = &mut test_rng();
+        let n = 4;
+        let keys: Vec<EdwardsAffine> = (0..n).map(|_| EdwardsAffine::rand(rng)).collect();
+        let cs = ConstraintSystem::<Fq>::new_ref();
+        let key_vars = Vec::<NonZeroTEAffineVarGeneric<BandersnatchConfig, FpVar<Fq>, Fq>>::new_witness(ns!(cs, "keys"), || Ok(keys.clone())).unwrap();
+        let mut tracker = Tracker::new(&cs);
+        let sum = NonZeroTEAffineVarGeneric::sum(&key_vars).unwrap();
+        let cost = tracker.update(&cs);
+        println!("summing {} Bandersnatch points natively: {:?}", n, cost);
+        assert_eq!(sum.value().unwrap(), keys.iter().fold(EdwardsAffine::zero().into_group(), |acc, k| acc + k));
+        // The first addition, to the constant identity, takes 3.
+        assert_eq!(cost.num_constraints, 6 * n - 3);
+        assert!(cs.is_satisfied().unwrap());
+    }
+}
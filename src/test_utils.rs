@@ -0,0 +1,97 @@
+//! Keysets, bitmasks and circuits for testing integrations of the crate, behind the `test-utils` feature.
+
+use ark_ec::{AffineRepr, CurveGroup};
+use ark_ec::short_weierstrass::{Affine, SWCurveConfig};
+use ark_r1cs_std::fields::nonnative::NonNativeFieldVar;
+use ark_std::rand::Rng;
+use ark_std::UniformRand;
+use derivative::Derivative;
+
+use crate::bitmask::Bitmask;
+use crate::chain::{Chain, ChainCircuit};
+use crate::config::Config;
+use crate::error::Result;
+
+/// The base field of BLS12-381 emulated in its scalar field, the field variable of `Bls381Emulated`.
+#[cfg(feature = "bls12-381-emulated")]
+pub type BlsInBls = NonNativeFieldVar<ark_bls12_381::Fq, ark_bls12_381::Fr>;
+
+/// Public keys `sk * G` of random secret keys.
+pub fn keys<P: SWCurveConfig, R: Rng>(n: usize, rng: &mut R) -> Vec<Affine<P>> {
+    let g = Affine::<P>::generator();
+    let sks: Vec<_> = (0..n).map(|_| P::ScalarField::rand(rng)).collect();
+    let pks: Vec<_> = sks.iter().map(|sk| g * sk).collect();
+    CurveGroup::normalize_batch(&pks)
+}
+
+/// A random point, that no partial sum of a random keyset hits but with a negligible probability.
+pub fn seed<P: SWCurveConfig, R: Rng>(rng: &mut R) -> Affine<P> {
+    Affine::rand(rng)
+}
+
+/// `n` bits, each set with probability `participation`, a value in `[0, 1]`.
+pub fn bitmask<R: Rng>(n: usize, participation: f64, rng: &mut R) -> Bitmask {
+    Bitmask::from_bits((0..n).map(|_| rng.gen_bool(participation)).collect())
+}
+
+/// The inputs of an apk circuit, and the circuit itself.
+#[derive(Derivative)]
+#[derivative(Clone(bound = ""), Debug(bound = ""))]
+pub struct Fixture<C: Chain> {
+    pub keys: Vec<Affine<C::Curve>>,
+    pub seed: Affine<C::Curve>,
+    pub bitmask: Bitmask,
+    pub config: Config,
+}
+
+impl<C: Chain> Fixture<C> {
+    /// `n` random keys, with `participation` of them signing on average.
+    pub fn random<R: Rng>(n: usize, participation: f64, rng: &mut R) -> Self {
+        Self { keys: keys(n, rng), seed: seed(rng), bitmask: bitmask(n, participation, rng), config: Config::default() }
+    }
+
+    pub fn with_config(self, config: Config) -> Self {
+        Self { config, ..self }
+    }
+
+    pub fn circuit(&self) -> Result<ChainCircuit<C>> {
+        Ok(ChainCircuit::<C>::new(self.keys.clone(), self.seed, &self.bitmask)?.with_config(self.config.clone()))
+    }
+
+    /// The aggregate key of the signers.
+    pub fn apk(&self) -> Result<Affine<C::Curve>> {
+        crate::aggregation::aggregate(&self.keys, &self.bitmask)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_r1cs_std::fields::FieldOpsBounds;
+    use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystem};
+    use ark_std::test_rng;
+
+    use crate::chain::{Bls377InBw6, Bls381Emulated, KeyBaseField};
+
+    use super::*;
+
+    fn check<C: Chain>()
+        where for<'a> &'a C::FieldVar: FieldOpsBounds<'a, KeyBaseField<C>, C::FieldVar>
+    {
+        let rng = &mut test_rng();
+        let fixture = Fixture::<C>::random(6, 0.5, rng);
+        let cs = ConstraintSystem::new_ref();
+        fixture.circuit().unwrap().generate_constraints(cs.clone()).unwrap();
+        assert!(cs.is_satisfied().unwrap());
+        assert!(fixture.keys.iter().all(|k| k.is_on_curve() && k.is_in_correct_subgroup_assuming_on_curve()));
+    }
+
+    #[test]
+    fn test_fixture() {
+        check::<Bls377InBw6>();
+        check::<Bls381Emulated>();
+
+        let rng = &mut test_rng();
+        assert_eq!(bitmask(100, 0.0, rng).count_ones(), 0);
+        assert_eq!(bitmask(100, 1.0, rng).count_ones(), 100);
+    }
+}
@@ -0,0 +1,73 @@
+//! Measures the cost of a key in the apk circuit under the limb decompositions of an emulated field.
+//!
+//! `ark-r1cs-std` hardcodes two decompositions, one per optimization goal, chosen by a cost model
+//! of a single multiplication. `NonNativeFieldVar` calls `get_params` itself, so no other limb count or width
+//! can be plugged into its gadgets, and a search over them would need an emulated field gadget of our own.
+//! What is left to choose from is the two decompositions, and `Config::optimization` already does that,
+//! so there's no tuner on top of `probe`: it reports the numbers to choose by.
+//! For BLS12-381 emulated in itself, a key costs 3609 constraints of weight 45757 with the 32 limbs of 12 bits
+//! of `Optimization::Constraints`, and 4970 constraints of weight 19068 with the 8 limbs of 48 bits of `Optimization::Weight`,
+//! so each decomposition wins its own metric.
+
+use ark_ec::AffineRepr;
+use ark_ec::short_weierstrass::{Affine, SWCurveConfig};
+use ark_ff::PrimeField;
+use ark_r1cs_std::fields::nonnative::NonNativeFieldVar;
+
+use crate::apk_circuits::{ApkCircuit, limb_params, LimbParams};
+use crate::bitmask::Bitmask;
+use crate::config::{Config, Optimization};
+use crate::error::Result;
+use crate::witness::constraint_matrices;
+
+/// The cost of a key in the apk circuit, under a limb decomposition.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProbeCost {
+    pub optimization: Optimization,
+    pub limbs: LimbParams,
+    pub constraints: usize,
+    /// Non-zero entries of the constraint matrices.
+    pub weight: usize,
+}
+
+/// Synthesizes apk circuits with `1` and `3` keys, all of them selected, and returns the growth per key.
+pub fn probe<P: SWCurveConfig, CF: PrimeField>(optimization: Optimization) -> Result<ProbeCost>
+    where P::BaseField: PrimeField
+{
+    let g = Affine::<P>::generator();
+    let size = |n: usize| -> Result<(usize, usize)> {
+        // Multiples of the generator, none of the partial sums sharing `x` with the next key.
+        let keys = (1..=n as u64).map(|i| (g * P::ScalarField::from(i)).into()).collect();
+        let seed = (g * P::ScalarField::from(100u64)).into();
+        let circuit = ApkCircuit::<P, CF, NonNativeFieldVar<P::BaseField, CF>>::new(keys, seed, &Bitmask::from_bits(vec![true; n]))?
+            .with_config(Config { optimization, labels: false, ..Default::default() });
+        let m = constraint_matrices(circuit)?;
+        Ok((m.num_constraints, m.a_num_non_zero + m.b_num_non_zero + m.c_num_non_zero))
+    };
+    let (c1, w1) = size(1)?;
+    let (c3, w3) = size(3)?;
+    Ok(ProbeCost {
+        optimization,
+        limbs: limb_params::<P::BaseField, CF>(optimization.into()),
+        constraints: (c3 - c1) / 2,
+        weight: (w3 - w1) / 2,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe() {
+        type P = ark_bls12_381::g1::Config;
+        type CF = ark_bls12_381::Fr;
+        let constraints = probe::<P, CF>(Optimization::Constraints).unwrap();
+        let weight = probe::<P, CF>(Optimization::Weight).unwrap();
+        println!("{:?}\n{:?}", constraints, weight);
+        assert_eq!(constraints.limbs, LimbParams { num_limbs: 32, bits_per_limb: 12 });
+        assert_eq!(weight.limbs, LimbParams { num_limbs: 8, bits_per_limb: 48 });
+        assert!(constraints.constraints < weight.constraints);
+        assert!(weight.weight < constraints.weight);
+    }
+}
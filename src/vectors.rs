@@ -0,0 +1,169 @@
+//! Test vectors for implementations of the verifier in other languages: a keyset, a bitmask and a seed,
+//! the aggregate key they give, the instance vector, a verifying key and a proof, with the default config.
+//!
+//! The format is the concatenation of the canonical arkworks serializations of the fields, compressed, in order:
+//! - the version, a `u16`, little-endian, `VECTORS_VERSION`;
+//! - the name of the chain, its length as a little-endian `u64`, then its UTF-8 bytes;
+//! - the keys, their number as a little-endian `u64`, then each compressed point;
+//! - the bitmask, its length as a little-endian `u64`, then a byte per bit, `0` or `1`;
+//! - the seed, then the aggregate key of the signers, compressed points;
+//! - the instance vector, its length as a little-endian `u64`, then each element, little-endian;
+//! - the verifying key, then the proof, as the backend serializes them compressed.
+//!
+//! Compressed points are those of arkworks: the little-endian `x`, with the flags in the top bits of the last byte.
+
+use ark_ec::short_weierstrass::Affine;
+use ark_r1cs_std::fields::FieldOpsBounds;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_snark::SNARK;
+use derivative::Derivative;
+use rand::{CryptoRng, RngCore};
+
+use crate::aggregation::aggregate;
+use crate::apk_circuits::public_inputs;
+use crate::bitmask::Bitmask;
+use crate::chain::{Chain, ChainCircuit, KeyBaseField};
+use crate::config::Config;
+use crate::error::{Result, SnowballError};
+use crate::prover::{Prover, snark_error, Verifier};
+
+/// Bumped whenever the format of the vectors changes.
+pub const VECTORS_VERSION: u16 = 1;
+
+/// A proof of the apk circuit of the chain `C` with the backend `S`, with everything it's verified against.
+#[derive(Derivative)]
+#[derivative(Clone(bound = ""))]
+pub struct TestVector<C: Chain, S: SNARK<C::ConstraintField>> {
+    pub keys: Vec<Affine<C::Curve>>,
+    pub bitmask: Bitmask,
+    pub seed: Affine<C::Curve>,
+    /// The sum of the keys for which the bit is set.
+    pub apk: Affine<C::Curve>,
+    pub public_inputs: Vec<C::ConstraintField>,
+    pub vk: S::VerifyingKey,
+    pub proof: S::Proof,
+}
+
+impl<C: Chain, S: SNARK<C::ConstraintField>> TestVector<C, S> {
+    /// Runs a setup for the keyset and proves the circuit.
+    pub fn generate<R: RngCore + CryptoRng + Send>(keys: Vec<Affine<C::Curve>>, seed: Affine<C::Curve>, bitmask: Bitmask, rng: &mut R) -> Result<Self>
+        where for<'a> &'a C::FieldVar: FieldOpsBounds<'a, KeyBaseField<C>, C::FieldVar>,
+              S::ProvingKey: Sync,
+              S::Proof: Send,
+              S::Error: Send,
+    {
+        let circuit = || ChainCircuit::<C>::new(keys.clone(), seed, &bitmask);
+        let (pk, vk) = S::circuit_specific_setup(circuit()?, rng).map_err(snark_error)?;
+        let proof = Prover::<C, S>::new(pk).prove(circuit()?, rng)?;
+        let apk = aggregate(&keys, &bitmask)?;
+        let public_inputs = public_inputs::<C::Curve, C::ConstraintField, C::FieldVar>(&keys, &bitmask, &Config::default())?;
+        Ok(Self { keys, bitmask, seed, apk, public_inputs, vk, proof })
+    }
+
+    /// Recomputes the aggregate key and the instance vector, and verifies the proof against them,
+    /// `Integrity` if anything doesn't match.
+    pub fn check(&self) -> Result<()>
+        where S::ProcessedVerifyingKey: 'static,
+              S::Proof: 'static,
+    {
+        if self.apk != aggregate(&self.keys, &self.bitmask)? {
+            return Err(SnowballError::Integrity);
+        }
+        if self.public_inputs != public_inputs::<C::Curve, C::ConstraintField, C::FieldVar>(&self.keys, &self.bitmask, &Config::default())? {
+            return Err(SnowballError::Integrity);
+        }
+        if !Verifier::<C, S>::new(&self.vk)?.verify(&self.keys, &self.bitmask, &self.proof)? {
+            return Err(SnowballError::Integrity);
+        }
+        Ok(())
+    }
+
+    /// The vector in the format of the module.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = vec![];
+        VECTORS_VERSION.serialize_compressed(&mut bytes)?;
+        C::NAME.to_string().serialize_compressed(&mut bytes)?;
+        self.keys.serialize_compressed(&mut bytes)?;
+        self.bitmask.serialize_compressed(&mut bytes)?;
+        self.seed.serialize_compressed(&mut bytes)?;
+        self.apk.serialize_compressed(&mut bytes)?;
+        self.public_inputs.serialize_compressed(&mut bytes)?;
+        self.vk.serialize_compressed(&mut bytes)?;
+        self.proof.serialize_compressed(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Deserializes a vector, with full validation of the points, rejecting trailing bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut reader = bytes;
+        let version = u16::deserialize_compressed(&mut reader)?;
+        if version != VECTORS_VERSION {
+            return Err(SnowballError::IncompatibleEnvelope(format!("test vector version {}, expected {}", version, VECTORS_VERSION)));
+        }
+        let chain = String::deserialize_compressed(&mut reader)?;
+        if chain != C::NAME {
+            return Err(SnowballError::IncompatibleEnvelope(format!("test vector for chain {}, expected {}", chain, C::NAME)));
+        }
+        let vector = Self {
+            keys: CanonicalDeserialize::deserialize_compressed(&mut reader)?,
+            bitmask: CanonicalDeserialize::deserialize_compressed(&mut reader)?,
+            seed: CanonicalDeserialize::deserialize_compressed(&mut reader)?,
+            apk: CanonicalDeserialize::deserialize_compressed(&mut reader)?,
+            public_inputs: CanonicalDeserialize::deserialize_compressed(&mut reader)?,
+            vk: CanonicalDeserialize::deserialize_compressed(&mut reader)?,
+            proof: CanonicalDeserialize::deserialize_compressed(&mut reader)?,
+        };
+        if !reader.is_empty() {
+            return Err(SnowballError::Serialization(ark_serialize::SerializationError::InvalidData));
+        }
+        Ok(vector)
+    }
+
+    /// Deserializes a vector and checks it.
+    pub fn load(bytes: &[u8]) -> Result<Self>
+        where S::ProcessedVerifyingKey: 'static,
+              S::Proof: 'static,
+    {
+        let vector = Self::from_bytes(bytes)?;
+        vector.check()?;
+        Ok(vector)
+    }
+}
+
+#[cfg(all(test, feature = "bls12-377-bw6"))]
+mod tests {
+    use ark_ec::CurveGroup;
+    use ark_groth16::Groth16;
+    use ark_std::UniformRand;
+    use rand::rngs::OsRng;
+
+    use crate::chain::Bls377InBw6;
+
+    use super::*;
+
+    type Vector = TestVector<Bls377InBw6, Groth16<ark_bw6_761::BW6_761>>;
+
+    #[test]
+    fn test_vectors() {
+        let rng = &mut OsRng;
+        let keys: Vec<ark_bls12_377::G1Affine> = (0..3).map(|_| Affine::rand(rng)).collect();
+        let bitmask = Bitmask::from_bits(vec![true, false, true]);
+        let seed = Affine::rand(rng);
+        let vector = Vector::generate(keys.clone(), seed, bitmask, rng).unwrap();
+        assert_eq!(vector.apk, (keys[0] + keys[2]).into_affine());
+
+        let bytes = vector.to_bytes().unwrap();
+        assert_eq!(u16::from_le_bytes([bytes[0], bytes[1]]), VECTORS_VERSION);
+        let loaded = Vector::load(&bytes).unwrap();
+        assert_eq!((loaded.keys, loaded.apk, loaded.public_inputs), (vector.keys.clone(), vector.apk, vector.public_inputs.clone()));
+        assert!(Vector::from_bytes(&[&bytes[..], &[0]].concat()).is_err());
+
+        let mut forged = vector.clone();
+        forged.apk = keys[0];
+        assert!(matches!(forged.check(), Err(SnowballError::Integrity)));
+        let mut forged = vector;
+        forged.bitmask = Bitmask::from_bits(vec![true, true, true]);
+        forged.apk = (keys[0] + keys[1] + keys[2]).into_affine();
+        assert!(matches!(forged.check(), Err(SnowballError::Integrity)));
+    }
+}
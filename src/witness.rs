@@ -0,0 +1,92 @@
+//! Variable assignments and constraint matrices of circuits, for provers and checkers outside of arkworks.
+//!
+//! Both follow the arkworks convention: the variables are numbered instance first, then witness,
+//! each in allocation order, with the `0`-th instance variable being the constant `1`.
+//! A constraint `i` holds iff `<a_i, z> * <b_i, z> = <c_i, z>`, where `z = instance || witness`.
+
+use ark_ff::Field;
+use ark_relations::r1cs::{ConstraintMatrices, ConstraintSynthesizer, ConstraintSystem, OptimizationGoal, SynthesisError, SynthesisMode};
+
+use crate::error::Result;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Assignment<F: Field> {
+    /// The constant `1`, followed by the public inputs.
+    pub instance: Vec<F>,
+    pub witness: Vec<F>,
+}
+
+impl<F: Field> Assignment<F> {
+    /// The public inputs, as the verifier of a SNARK takes them.
+    pub fn public_inputs(&self) -> &[F] {
+        &self.instance[1..]
+    }
+
+    fn z(&self, i: usize) -> F {
+        if i < self.instance.len() {
+            self.instance[i]
+        } else {
+            self.witness[i - self.instance.len()]
+        }
+    }
+
+    /// Index of the first constraint the assignment doesn't satisfy, if any.
+    pub fn first_unsatisfied(&self, matrices: &ConstraintMatrices<F>) -> Option<usize> {
+        let eval = |row: &[(F, usize)]| row.iter().map(|(coeff, i)| *coeff * self.z(*i)).sum::<F>();
+        (0..matrices.num_constraints).find(|&i| eval(&matrices.a[i]) * eval(&matrices.b[i]) != eval(&matrices.c[i]))
+    }
+}
+
+/// Synthesizes the circuit in proving mode, without building the constraint matrices, and returns the assignment.
+pub fn extract_assignment<F: Field, C: ConstraintSynthesizer<F>>(circuit: C) -> Result<Assignment<F>> {
+    let cs = ConstraintSystem::<F>::new_ref();
+    cs.set_mode(SynthesisMode::Prove { construct_matrices: false });
+    // As the SNARK provers do.
+    cs.set_optimization_goal(OptimizationGoal::Constraints);
+    circuit.generate_constraints(cs.clone())?;
+    let cs = cs.into_inner().ok_or(SynthesisError::MissingCS)?;
+    Ok(Assignment { instance: cs.instance_assignment, witness: cs.witness_assignment })
+}
+
+/// Synthesizes the circuit in setup mode, and returns its constraint matrices.
+pub fn constraint_matrices<F: Field, C: ConstraintSynthesizer<F>>(circuit: C) -> Result<ConstraintMatrices<F>> {
+    let cs = ConstraintSystem::<F>::new_ref();
+    cs.set_mode(SynthesisMode::Setup);
+    cs.set_optimization_goal(OptimizationGoal::Constraints);
+    circuit.generate_constraints(cs.clone())?;
+    cs.finalize();
+    Ok(cs.to_matrices().ok_or(SynthesisError::MissingCS)?)
+}
+
+#[cfg(all(test, feature = "bls12-377-bw6"))]
+mod tests {
+    use ark_std::{test_rng, UniformRand};
+
+    use crate::apk_circuits::public_inputs;
+    use crate::bitmask::Bitmask;
+    use crate::chain::{Bls377InBw6, ChainCircuit};
+    use crate::config::Config;
+
+    use super::*;
+
+    #[test]
+    fn test_extract_assignment() {
+        let rng = &mut test_rng();
+        let keys: Vec<_> = (0..3).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+        let seed = ark_bls12_377::G1Affine::rand(rng);
+        let bitmask = Bitmask::from_bits(vec![true, false, true]);
+        let circuit = ChainCircuit::<Bls377InBw6>::new(keys.clone(), seed, &bitmask).unwrap();
+
+        let mut assignment = extract_assignment(circuit.clone()).unwrap();
+        let expected = public_inputs::<ark_bls12_377::g1::Config, _, ark_r1cs_std::fields::fp::FpVar<_>>(&keys, &bitmask, &Config::default()).unwrap();
+        assert_eq!(assignment.public_inputs(), expected);
+
+        let matrices = constraint_matrices(circuit).unwrap();
+        assert_eq!(matrices.num_instance_variables, assignment.instance.len());
+        assert_eq!(matrices.num_witness_variables, assignment.witness.len());
+        assert_eq!(assignment.first_unsatisfied(&matrices), None);
+
+        assignment.witness[0] += ark_bw6_761::Fr::from(1u8);
+        assert!(assignment.first_unsatisfied(&matrices).is_some());
+    }
+}
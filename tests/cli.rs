@@ -0,0 +1,47 @@
+//! `snowball-cli` end to end: `setup`, `prove` and `verify` on a keyset written as the binary reads it.
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+use ark_std::UniformRand;
+use rand::rngs::OsRng;
+
+use snowball::serialization::{to_bytes, SerializationMode};
+
+fn cli(command: &str, dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_snowball-cli"))
+        .current_dir(dir)
+        .args([command, "--chain", "bls12-377-bw6", "--keys", "keys", "--store", "store"])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_round_trip() {
+    let rng = &mut OsRng;
+    let dir = std::env::temp_dir().join(format!("snowball-cli-{}", u64::rand(rng)));
+    fs::create_dir_all(&dir).unwrap();
+    let keys: Vec<_> = (0..3).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+    fs::write(dir.join("keys"), to_bytes(&keys, SerializationMode::Compressed).unwrap()).unwrap();
+
+    // No keys, no proof: `prove` doesn't run a setup of its own.
+    let output = cli("prove", &dir, &["--bitmask", "101", "--out", "proof"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("run setup first"));
+    assert!(!dir.join("proof").exists() && !dir.join("store").exists());
+
+    assert!(cli("setup", &dir, &[]).status.success());
+    assert!(cli("prove", &dir, &["--bitmask", "101", "--out", "proof"]).status.success());
+    let output = cli("verify", &dir, &["--proof", "proof"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "valid proof, 2 of 3 keys");
+
+    // The proof is of the keys it was made for.
+    let other: Vec<_> = (0..3).map(|_| ark_bls12_377::G1Affine::rand(rng)).collect();
+    fs::write(dir.join("keys"), to_bytes(&other, SerializationMode::Compressed).unwrap()).unwrap();
+    assert!(!cli("verify", &dir, &["--proof", "proof"]).status.success());
+
+    fs::remove_dir_all(&dir).unwrap();
+}